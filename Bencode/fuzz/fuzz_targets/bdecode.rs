@@ -0,0 +1,26 @@
+#![no_main]
+
+use bencode::{bdecode, bdecode_ref, bencode, find_raw, Cursor, Event};
+use libfuzzer_sys::fuzz_target;
+
+// Any input either fails to decode or is canonical, so encoding it again gives the same bytes.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(value) = bdecode(data) {
+        assert_eq!(bencode(&value), data);
+        assert_eq!(bdecode_ref(data).unwrap().to_value(), value);
+
+        let mut cursor = Cursor::new(data);
+        if cursor.next_event().unwrap() == Some(Event::DictStart) {
+            while let Some(Event::Key(key)) = cursor.next_event().unwrap() {
+                let raw = cursor.skip_value().unwrap();
+                let key = std::str::from_utf8(key).unwrap_or_default();
+                if !key.is_empty() && value.get(key).is_some() {
+                    assert_eq!(find_raw(data, key).unwrap(), Some(raw));
+                }
+                assert_eq!(bencode(&bdecode(raw).unwrap()), raw);
+            }
+        }
+    } else {
+        assert!(bdecode_ref(data).is_err());
+    }
+});
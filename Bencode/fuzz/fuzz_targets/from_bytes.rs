@@ -0,0 +1,59 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde::Deserialize;
+
+// The shapes the client and the tracker read from the network and from torrent files.
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct AnnounceResponse {
+    #[serde(rename = "failure reason")]
+    failure_reason: Option<String>,
+    #[serde(default)]
+    interval: i64,
+    #[serde(default)]
+    peers: Vec<Peer>,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Peer {
+    #[serde(rename = "peer id", with = "serde_bytes", default)]
+    peer_id: Vec<u8>,
+    ip: String,
+    port: u16,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Metainfo {
+    announce: String,
+    info: Info,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Info {
+    name: String,
+    #[serde(rename = "piece length")]
+    piece_length: u64,
+    #[serde(with = "serde_bytes")]
+    pieces: Vec<u8>,
+    length: Option<u64>,
+    files: Option<Vec<File>>,
+    private: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct File {
+    length: u64,
+    path: Vec<String>,
+}
+
+fuzz_target!(|data: &[u8]| {
+    let _ = bencode::from_bytes::<AnnounceResponse>(data);
+    let _ = bencode::from_bytes::<Metainfo>(data);
+    let _ = bencode::from_bytes::<bencode::Value>(data);
+});
@@ -0,0 +1,302 @@
+use crate::{
+    cursor::{Cursor, Event, Limits},
+    errors::bdecoder_error::BDecoderError,
+    value::Value,
+    value_ref::{DictionaryRef, ValueRef},
+};
+
+pub fn from_vec_to_string(vec: &[u8]) -> String {
+    //! Converts a vector of bytes to a string.
+    vec.iter().fold(String::new(), |acc, byte| {
+        format!("{}{}", acc, *byte as char)
+    })
+}
+
+pub fn from_string_to_vec(str: &str) -> Vec<u8> {
+    //! Converts a string to a vector of bytes.
+    //! The string must be composed of ASCII characters.
+    str.as_bytes().to_vec()
+}
+
+pub fn bdecode(bytes: &[u8]) -> Result<Value, BDecoderError> {
+    //! Returns the chunk of bytes decoded by Bencoding format.
+    //! Only the canonical form is accepted: integers without leading zeros or -0, dictionary keys sorted
+    //! and without duplicates, and no data after the value. Any other input returns an Error with its offset.
+    //! The input must respect the default Limits.
+    bdecode_with_limits(bytes, Limits::default())
+}
+
+pub fn bdecode_with_limits(bytes: &[u8], limits: Limits) -> Result<Value, BDecoderError> {
+    //! Same as bdecode, but with the given bounds on the nesting depth and the size of the input.
+    Ok(decode(Cursor::with_limits(bytes, limits))?.to_value())
+}
+
+pub fn bdecode_ref(bytes: &[u8]) -> Result<ValueRef<'_>, BDecoderError> {
+    //! Same as bdecode, but the byte strings of the returned value are borrowed from bytes instead of copied.
+    decode(Cursor::new(bytes))
+}
+
+pub fn bdecode_ref_with_limits(
+    bytes: &[u8],
+    limits: Limits,
+) -> Result<ValueRef<'_>, BDecoderError> {
+    //! Same as bdecode_ref, but with the given bounds.
+    decode(Cursor::with_limits(bytes, limits))
+}
+
+fn decode(mut cursor: Cursor<'_>) -> Result<ValueRef<'_>, BDecoderError> {
+    //! Decodes the whole value and checks there is nothing after it.
+    let event = next(&mut cursor)?;
+    let value = build(&mut cursor, event)?;
+    cursor.next_event()?;
+    Ok(value)
+}
+
+fn next<'a>(cursor: &mut Cursor<'a>) -> Result<Event<'a>, BDecoderError> {
+    cursor
+        .next_event()?
+        .ok_or_else(|| cursor.error("unexpected end of input"))
+}
+
+fn build<'a>(cursor: &mut Cursor<'a>, event: Event<'a>) -> Result<ValueRef<'a>, BDecoderError> {
+    //! Builds the value that starts with event, reading the rest of it from the cursor.
+    match event {
+        Event::Int(int) => Ok(ValueRef::Int(int)),
+        Event::Bytes(bytes) => Ok(ValueRef::Bytes(bytes)),
+        Event::ListStart => {
+            let mut list = Vec::new();
+            loop {
+                match next(cursor)? {
+                    Event::End => return Ok(ValueRef::List(list)),
+                    event => list.push(build(cursor, event)?),
+                }
+            }
+        }
+        Event::DictStart => {
+            let mut dict = DictionaryRef::new();
+            while let Event::Key(key) = next(cursor)? {
+                let event = next(cursor)?;
+                dict.insert(key, build(cursor, event)?);
+            }
+            Ok(ValueRef::Dict(dict))
+        }
+        Event::Key(_) | Event::End => Err(cursor.error("expected a value")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dict(entries: Vec<(&str, Value)>) -> Value {
+        Value::Dict(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.as_bytes().to_vec(), value))
+                .collect(),
+        )
+    }
+
+    fn error(bencoded: &[u8]) -> String {
+        bdecode(bencoded).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_decode_positive_int() {
+        assert_eq!(bdecode(b"i1e").unwrap(), Value::Int(1));
+    }
+
+    #[test]
+    fn test_decode_negative_int() {
+        assert_eq!(bdecode(b"i-12e").unwrap(), Value::Int(-12));
+    }
+
+    #[test]
+    fn test_decode_big_int() {
+        assert_eq!(
+            bdecode(b"i12123124124124e").unwrap(),
+            Value::Int(12123124124124)
+        );
+        assert_eq!(
+            bdecode(b"i-9223372036854775808e").unwrap(),
+            Value::Int(i64::MIN)
+        );
+    }
+
+    #[test]
+    fn test_decode_string() {
+        assert_eq!(bdecode(b"6:espejo").unwrap(), Value::from("espejo"));
+        assert_eq!(bdecode(b"7: aa: bb").unwrap(), Value::from(" aa: bb"));
+        assert_eq!(bdecode(b"0:").unwrap(), Value::from(""));
+    }
+
+    #[test]
+    fn test_decode_list() {
+        assert_eq!(
+            bdecode(b"l2:sii22ei-441eli5000eee").unwrap(),
+            Value::List(vec![
+                Value::from("si"),
+                Value::Int(22),
+                Value::Int(-441),
+                Value::List(vec![Value::Int(5000)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_decode_tracker_response() {
+        let bencoded = b"d8:completei11e10:incompletei0e8:intervali1800e5:peersld2:ip12:91.189.95.217:peer id20:T03I--00LKG63z9lO3234:porti6883eeee";
+        assert_eq!(
+            bdecode(bencoded).unwrap(),
+            dict(vec![
+                ("complete", Value::Int(11)),
+                ("incomplete", Value::Int(0)),
+                ("interval", Value::Int(1800)),
+                (
+                    "peers",
+                    Value::List(vec![dict(vec![
+                        ("ip", Value::from("91.189.95.21")),
+                        ("peer id", Value::from("T03I--00LKG63z9lO323")),
+                        ("port", Value::Int(6883)),
+                    ])])
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_non_canonical_integers_are_rejected() {
+        assert_eq!(error(b"i03e"), "BDecoderError: leading zero at byte 1");
+        assert_eq!(error(b"i-0e"), "BDecoderError: negative zero at byte 3");
+        assert_eq!(error(b"i-e"), "BDecoderError: expected a digit at byte 2");
+        assert_eq!(error(b"ie"), "BDecoderError: expected a digit at byte 1");
+        assert_eq!(
+            error(b"i9223372036854775808e"),
+            "BDecoderError: integer overflow at byte 19"
+        );
+        assert_eq!(error(b"03:abc"), "BDecoderError: leading zero at byte 0");
+    }
+
+    #[test]
+    fn test_unsorted_and_duplicate_keys_are_rejected() {
+        assert_eq!(
+            error(b"d8:intervali1e8:completei2ee"),
+            "BDecoderError: unsorted dictionary key at byte 14"
+        );
+        assert_eq!(
+            error(b"d1:ai1e1:ai2ee"),
+            "BDecoderError: duplicate dictionary key at byte 7"
+        );
+        assert_eq!(
+            error(b"di1ei2ee"),
+            "BDecoderError: dictionary key is not a byte string at byte 1"
+        );
+    }
+
+    #[test]
+    fn test_truncated_and_trailing_input_are_rejected() {
+        assert_eq!(
+            error(b""),
+            "BDecoderError: unexpected end of input at byte 0"
+        );
+        assert_eq!(
+            error(b"l1:a"),
+            "BDecoderError: unexpected end of input at byte 4"
+        );
+        assert_eq!(
+            error(b"5:abc"),
+            "BDecoderError: byte string longer than the input at byte 2"
+        );
+        assert_eq!(
+            error(b"i1"),
+            "BDecoderError: unexpected end of input at byte 2"
+        );
+        assert_eq!(
+            error(b"i1ei2e"),
+            "BDecoderError: trailing data after the value at byte 3"
+        );
+        assert_eq!(
+            error(b"x"),
+            "BDecoderError: unexpected character 'x' at byte 0"
+        );
+    }
+
+    #[test]
+    fn test_nesting_is_limited() {
+        let limits = Limits {
+            max_depth: 2,
+            ..Limits::default()
+        };
+        assert_eq!(
+            bdecode_with_limits(b"lld1:ai1eeee", limits)
+                .unwrap_err()
+                .to_string(),
+            "BDecoderError: nesting deeper than 2 levels at byte 2"
+        );
+        assert!(bdecode_with_limits(b"llee", limits).is_ok());
+
+        let deep = [vec![b'l'; 100_000], vec![b'e'; 100_000]].concat();
+        assert_eq!(
+            error(&deep),
+            "BDecoderError: nesting deeper than 64 levels at byte 64"
+        );
+    }
+
+    #[test]
+    fn test_input_length_is_limited() {
+        let limits = Limits {
+            max_length: 4,
+            ..Limits::default()
+        };
+        assert_eq!(
+            bdecode_with_limits(b"5:abcde", limits)
+                .unwrap_err()
+                .to_string(),
+            "BDecoderError: input longer than 4 bytes at byte 4"
+        );
+        assert_eq!(
+            bdecode_with_limits(b"i12e", limits).unwrap(),
+            Value::Int(12)
+        );
+    }
+
+    #[test]
+    fn test_keys_in_any_order_when_not_canonical() {
+        let limits = Limits {
+            canonical: false,
+            ..Limits::default()
+        };
+        assert_eq!(
+            error(b"d1:bi2e1:ai1ee"),
+            "BDecoderError: unsorted dictionary key at byte 7"
+        );
+        assert_eq!(
+            bdecode_with_limits(b"d1:bi2e1:ai1ee", limits).unwrap(),
+            dict(vec![("a", Value::Int(1)), ("b", Value::Int(2))])
+        );
+        assert_eq!(
+            bdecode_with_limits(b"d1:bi2e1:ai1e1:bi3ee", limits)
+                .unwrap_err()
+                .to_string(),
+            "BDecoderError: duplicate dictionary key at byte 13"
+        );
+    }
+
+    #[test]
+    fn test_decode_ref_borrows_the_byte_strings() {
+        let bencoded = b"d4:infod6:pieces4:abcde4:spamli1eee";
+        let decoded = bdecode_ref(bencoded).unwrap();
+
+        let pieces = decoded.get("info").unwrap().get("pieces").unwrap();
+        assert_eq!(pieces.as_bytes().unwrap().as_ptr(), bencoded[18..].as_ptr());
+        assert_eq!(decoded.to_value(), bdecode(bencoded).unwrap());
+        assert_eq!(ValueRef::from(&decoded.to_value()), decoded);
+    }
+
+    #[test]
+    fn test_vector_to_string() {
+        let vec = vec![b'a', b'b', b'c'];
+        let str = from_vec_to_string(&vec);
+        assert_eq!(str, "abc");
+    }
+}
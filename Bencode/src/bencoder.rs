@@ -0,0 +1,100 @@
+use crate::value::Value;
+
+pub fn bencode(value: &Value) -> Vec<u8> {
+    //! Returns the chunk of bytes encoded by Bencoding format given a Value reference.
+    //! Dictionaries are always encoded with their keys sorted, so the output is canonical.
+    let mut encoded = Vec::new();
+    encode_into(value, &mut encoded);
+    encoded
+}
+
+pub fn encode_into(value: &Value, encoded: &mut Vec<u8>) {
+    //! Appends the value encoded by Bencoding format to encoded.
+    match value {
+        Value::Int(int) => {
+            encoded.push(b'i');
+            encoded.extend_from_slice(int.to_string().as_bytes());
+            encoded.push(b'e');
+        }
+        Value::Bytes(bytes) => encode_byte_string(bytes, encoded),
+        Value::List(list) => {
+            encoded.push(b'l');
+            for elem in list {
+                encode_into(elem, encoded);
+            }
+            encoded.push(b'e');
+        }
+        Value::Dict(dict) => {
+            encoded.push(b'd');
+            for (key, elem) in dict {
+                encode_byte_string(key, encoded);
+                encode_into(elem, encoded);
+            }
+            encoded.push(b'e');
+        }
+    }
+}
+
+fn encode_byte_string(bytes: &[u8], encoded: &mut Vec<u8>) {
+    //! Appends <length>:<bytes> to encoded.
+    encoded.extend_from_slice(bytes.len().to_string().as_bytes());
+    encoded.push(b':');
+    encoded.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bdecoder::bdecode, value::Dictionary};
+    use sha1::{Digest, Sha1};
+
+    #[test]
+    fn strings() {
+        assert_eq!(bencode(&Value::from("spam")), b"4:spam");
+        assert_eq!(bencode(&Value::from("MeDiuM")), b"6:MeDiuM");
+        assert_eq!(bencode(&Value::from("")), b"0:");
+        assert_eq!(bencode(&Value::Bytes(vec![0, 0xff])), b"2:\x00\xff");
+    }
+
+    #[test]
+    fn ints() {
+        assert_eq!(bencode(&Value::Int(52)), b"i52e");
+        assert_eq!(bencode(&Value::Int(-45)), b"i-45e");
+        assert_eq!(bencode(&Value::Int(0)), b"i0e");
+        assert_eq!(
+            bencode(&Value::Int(5200000000120312)),
+            b"i5200000000120312e"
+        );
+    }
+
+    #[test]
+    fn string_list() {
+        let list = Value::List(vec![Value::from("MeDiuM"), Value::from("spam")]);
+        assert_eq!(bencode(&list), b"l6:MeDiuM4:spame");
+    }
+
+    #[test]
+    fn dictionary_keys_are_sorted() {
+        let dict = Value::Dict(Dictionary::from([
+            (b"Venus".to_vec(), Value::from("ven")),
+            (b"Mercury".to_vec(), Value::from("mer")),
+            (b"Earth".to_vec(), Value::from("ear")),
+        ]));
+        assert_eq!(bencode(&dict), b"d5:Earth3:ear7:Mercury3:mer5:Venus3:vene");
+    }
+
+    #[test]
+    fn decoded_values_encode_to_the_same_bytes() {
+        let tracker_response = b"d8:completei4e10:incompletei0e8:intervali1800e5:peersld2:ip12:91.189.95.217:peer id20:T03I--00L0fMrxsYDws64:porti6892eeee";
+        for right in [
+            &b"8: AnElAp;"[..],
+            &b"l2:si3:sal4:ojos2:aee"[..],
+            &b"d8:intervali456e3:olai1ee"[..],
+            &tracker_response[..],
+        ] {
+            let encoded = bencode(&bdecode(right).unwrap());
+            assert_eq!(encoded, right);
+            assert_eq!(Sha1::digest(&encoded), Sha1::digest(right));
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use crate::errors::bdecoder_error::BDecoderError;
+use std::collections::BTreeSet;
+
+const DEFAULT_MAX_DEPTH: usize = 64;
+const DEFAULT_MAX_LENGTH: usize = 32 * 1024 * 1024;
+
+/// The bounds an input must respect to be decoded, so a hostile peer or tracker can not
+/// exhaust the memory or the stack of the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum number of nested lists and dictionaries.
+    pub max_depth: usize,
+    /// Maximum size of the whole input in bytes.
+    pub max_length: usize,
+    /// When false, dictionary keys are accepted in any order, for inputs written by other encoders such as
+    /// torrent files. Duplicate keys are still an Error.
+    pub canonical: bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_length: DEFAULT_MAX_LENGTH,
+            canonical: true,
+        }
+    }
+}
+
+/// Each step of the walk over a bencoded buffer. Byte strings are borrowed from the buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<'a> {
+    Int(i64),
+    Bytes(&'a [u8]),
+    /// A dictionary key, the next event starts its value.
+    Key(&'a [u8]),
+    ListStart,
+    DictStart,
+    /// The end of the innermost list or dictionary.
+    End,
+}
+
+/// A list or dictionary that has been opened and not closed yet.
+enum Frame<'a> {
+    List,
+    Dict {
+        last_key: Option<&'a [u8]>,
+        /// The keys read so far, only kept when the keys may come in any order.
+        keys: BTreeSet<&'a [u8]>,
+        has_key: bool,
+    },
+}
+
+/// Walks a bencoded buffer one event at a time without building any value.
+/// Only the canonical form is accepted: integers without leading zeros or -0, dictionary keys sorted
+/// and without duplicates, and no data after the value. Any other input returns an Error with its offset.
+/// Limits that are not canonical let the keys come in any order.
+pub struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    limits: Limits,
+    stack: Vec<Frame<'a>>,
+    started: bool,
+}
+
+impl<'a> Cursor<'a> {
+    /// Creates a cursor at the start of bytes with the default Limits.
+    pub fn new(bytes: &'a [u8]) -> Cursor<'a> {
+        Cursor::with_limits(bytes, Limits::default())
+    }
+
+    /// Creates a cursor at the start of bytes with the given bounds.
+    pub fn with_limits(bytes: &'a [u8], limits: Limits) -> Cursor<'a> {
+        Cursor {
+            bytes,
+            pos: 0,
+            limits,
+            stack: Vec::new(),
+            started: false,
+        }
+    }
+
+    /// Returns the offset of the next byte to read.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns the number of lists and dictionaries the cursor is in.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Returns the next event, or None once the whole value was read.
+    /// Data after the value is an Error.
+    pub fn next_event(&mut self) -> Result<Option<Event<'a>>, BDecoderError> {
+        if !self.started {
+            self.started = true;
+            if self.bytes.len() > self.limits.max_length {
+                self.pos = self.limits.max_length;
+                return Err(self.error(&format!(
+                    "input longer than {} bytes",
+                    self.limits.max_length
+                )));
+            }
+        } else if self.stack.is_empty() {
+            if self.pos != self.bytes.len() {
+                return Err(self.error("trailing data after the value"));
+            }
+            return Ok(None);
+        }
+
+        let expecting_key = matches!(self.stack.last(), Some(Frame::Dict { has_key: false, .. }));
+        let byte = self.peek()?;
+        if byte == b'e' && (expecting_key || matches!(self.stack.last(), Some(Frame::List))) {
+            self.pos += 1;
+            self.stack.pop();
+            self.value_read();
+            return Ok(Some(Event::End));
+        }
+        if expecting_key {
+            return self.parse_key().map(Some);
+        }
+        let event = match byte {
+            b'0'..=b'9' => Event::Bytes(self.parse_bytes()?),
+            b'i' => Event::Int(self.parse_int()?),
+            b'l' => {
+                self.enter(Frame::List)?;
+                Event::ListStart
+            }
+            b'd' => {
+                self.enter(Frame::Dict {
+                    last_key: None,
+                    keys: BTreeSet::new(),
+                    has_key: false,
+                })?;
+                Event::DictStart
+            }
+            byte => return Err(self.error(&format!("unexpected character {:?}", byte as char))),
+        };
+        if let Event::Int(_) | Event::Bytes(_) = event {
+            self.value_read();
+        }
+        Ok(Some(event))
+    }
+
+    /// Reads the next value without decoding it and returns its bytes as they are in the buffer.
+    pub fn skip_value(&mut self) -> Result<&'a [u8], BDecoderError> {
+        let start = self.pos;
+        let depth = self.depth();
+        match self.next_event()? {
+            Some(Event::Int(_)) | Some(Event::Bytes(_)) => {}
+            Some(Event::ListStart) | Some(Event::DictStart) => {
+                while self.depth() > depth {
+                    self.next_event()?;
+                }
+            }
+            _ => {
+                self.pos = start;
+                return Err(self.error("expected a value"));
+            }
+        }
+        Ok(&self.bytes[start..self.pos])
+    }
+
+    pub(crate) fn error(&self, reason: &str) -> BDecoderError {
+        BDecoderError::new(format!("BDecoderError: {} at byte {}", reason, self.pos))
+    }
+
+    fn peek(&self) -> Result<u8, BDecoderError> {
+        self.bytes
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| self.error("unexpected end of input"))
+    }
+
+    fn value_read(&mut self) {
+        //! Marks the value of the current dictionary entry as read, so a key comes next.
+        if let Some(Frame::Dict { has_key, .. }) = self.stack.last_mut() {
+            *has_key = false;
+        }
+    }
+
+    fn enter(&mut self, frame: Frame<'a>) -> Result<(), BDecoderError> {
+        //! Consumes the first byte of a list or a dictionary, which nests one more level.
+        if self.depth() == self.limits.max_depth {
+            return Err(self.error(&format!(
+                "nesting deeper than {} levels",
+                self.limits.max_depth
+            )));
+        }
+        self.pos += 1;
+        self.stack.push(frame);
+        Ok(())
+    }
+
+    fn parse_key(&mut self) -> Result<Event<'a>, BDecoderError> {
+        //! Decodes a dictionary key, which must be a byte string greater than the previous key, or only
+        //! different from the previous keys when the limits are not canonical.
+        let key_start = self.pos;
+        if !self.peek()?.is_ascii_digit() {
+            return Err(self.error("dictionary key is not a byte string"));
+        }
+        let key = self.parse_bytes()?;
+        let canonical = self.limits.canonical;
+        if let Some(Frame::Dict {
+            last_key,
+            keys,
+            has_key,
+        }) = self.stack.last_mut()
+        {
+            if !canonical {
+                if !keys.insert(key) {
+                    self.pos = key_start;
+                    return Err(self.error("duplicate dictionary key"));
+                }
+            } else if let Some(last) = *last_key {
+                if last >= key {
+                    let reason = match last == key {
+                        true => "duplicate dictionary key",
+                        false => "unsorted dictionary key",
+                    };
+                    self.pos = key_start;
+                    return Err(self.error(reason));
+                }
+            }
+            *last_key = Some(key);
+            *has_key = true;
+        }
+        Ok(Event::Key(key))
+    }
+
+    fn parse_digits(&mut self, negative: bool) -> Result<i64, BDecoderError> {
+        //! Reads a canonical decimal number: at least one digit and no leading zeros.
+        let start = self.pos;
+        let mut num: i64 = 0;
+        while let Some(byte) = self
+            .bytes
+            .get(self.pos)
+            .filter(|byte| byte.is_ascii_digit())
+        {
+            let digit = (byte - b'0') as i64;
+            num = num
+                .checked_mul(10)
+                .and_then(|num| match negative {
+                    true => num.checked_sub(digit),
+                    false => num.checked_add(digit),
+                })
+                .ok_or_else(|| self.error("integer overflow"))?;
+            self.pos += 1;
+        }
+        match self.pos - start {
+            0 => Err(self.error("expected a digit")),
+            1 => Ok(num),
+            _ if self.bytes[start] == b'0' => {
+                self.pos = start;
+                Err(self.error("leading zero"))
+            }
+            _ => Ok(num),
+        }
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), BDecoderError> {
+        if self.peek()? != expected {
+            return Err(self.error(&format!("expected {:?}", expected as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn parse_int(&mut self) -> Result<i64, BDecoderError> {
+        //! Decodes i<number>e.
+        self.expect(b'i')?;
+        let negative = self.peek()? == b'-';
+        if negative {
+            self.pos += 1;
+        }
+        let num = self.parse_digits(negative)?;
+        if negative && num == 0 {
+            return Err(self.error("negative zero"));
+        }
+        self.expect(b'e')?;
+        Ok(num)
+    }
+
+    fn parse_bytes(&mut self) -> Result<&'a [u8], BDecoderError> {
+        //! Decodes <length>:<bytes>.
+        let len = self.parse_digits(false)? as usize;
+        self.expect(b':')?;
+        let end = match self.pos.checked_add(len) {
+            Some(end) if end <= self.bytes.len() => end,
+            _ => return Err(self.error("byte string longer than the input")),
+        };
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+}
+
+pub fn find_raw<'a>(bytes: &'a [u8], key: &str) -> Result<Option<&'a [u8]>, BDecoderError> {
+    //! Returns the bytes of the value stored under key in the bencoded dictionary, exactly as they are in the buffer,
+    //! so it can be hashed without decoding it (e.g. the info dictionary of a torrent).
+    //! Returns None if bytes is not a dictionary or it does not have the key.
+    find_raw_with_limits(bytes, key, Limits::default())
+}
+
+pub fn find_raw_with_limits<'a>(
+    bytes: &'a [u8],
+    key: &str,
+    limits: Limits,
+) -> Result<Option<&'a [u8]>, BDecoderError> {
+    //! Same as find_raw, but with the given bounds.
+    let mut cursor = Cursor::with_limits(bytes, limits);
+    if cursor.next_event()? != Some(Event::DictStart) {
+        return Ok(None);
+    }
+    while let Some(Event::Key(found)) = cursor.next_event()? {
+        let value = cursor.skip_value()?;
+        if found == key.as_bytes() {
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(bencoded: &[u8]) -> Vec<Event<'_>> {
+        let mut cursor = Cursor::new(bencoded);
+        let mut events = Vec::new();
+        while let Some(event) = cursor.next_event().unwrap() {
+            events.push(event);
+        }
+        events
+    }
+
+    #[test]
+    fn test_events_of_nested_values() {
+        assert_eq!(
+            events(b"d5:peersld2:ip3:1.24:porti80eee4:spami-3ee"),
+            vec![
+                Event::DictStart,
+                Event::Key(b"peers"),
+                Event::ListStart,
+                Event::DictStart,
+                Event::Key(b"ip"),
+                Event::Bytes(b"1.2"),
+                Event::Key(b"port"),
+                Event::Int(80),
+                Event::End,
+                Event::End,
+                Event::Key(b"spam"),
+                Event::Int(-3),
+                Event::End,
+            ]
+        );
+        assert_eq!(events(b"0:"), vec![Event::Bytes(b"")]);
+    }
+
+    #[test]
+    fn test_skip_value_returns_the_raw_bytes() {
+        let mut cursor = Cursor::new(b"l1:ad1:bli1eee3:fooe");
+        assert_eq!(cursor.next_event().unwrap(), Some(Event::ListStart));
+        assert_eq!(cursor.skip_value().unwrap(), b"1:a");
+        assert_eq!(cursor.skip_value().unwrap(), b"d1:bli1eee");
+        assert_eq!(cursor.depth(), 1);
+        assert_eq!(cursor.skip_value().unwrap(), b"3:foo");
+        assert_eq!(
+            cursor.skip_value().unwrap_err().to_string(),
+            "BDecoderError: expected a value at byte 19"
+        );
+    }
+
+    #[test]
+    fn test_find_raw() {
+        let torrent = b"d8:announce3:url4:infod6:lengthi5e4:name1:ae4:spami1ee";
+        assert_eq!(
+            find_raw(torrent, "info").unwrap(),
+            Some(&b"d6:lengthi5e4:name1:ae"[..])
+        );
+        assert_eq!(find_raw(torrent, "announce").unwrap(), Some(&b"3:url"[..]));
+        assert_eq!(find_raw(torrent, "missing").unwrap(), None);
+        assert_eq!(find_raw(b"li1ee", "info").unwrap(), None);
+        assert!(find_raw(b"d4:infod6:lengthi05eee", "info").is_err());
+    }
+}
@@ -0,0 +1,449 @@
+use crate::{
+    bdecoder::bdecode,
+    errors::bdecoder_error::BDecoderError,
+    value::{Dictionary, Value},
+};
+use serde::de::{
+    self, value::BytesDeserializer, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor,
+};
+use std::{collections::btree_map, vec};
+
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, BDecoderError> {
+    //! Decodes bytes in canonical Bencoding format into any deserializable type.
+    from_value(bdecode(bytes)?)
+}
+
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, BDecoderError> {
+    //! Turns a bencoded Value into any deserializable type.
+    //! Byte strings can be read as strings when they are valid UTF-8, integers 0 and 1 as booleans,
+    //! and dictionary keys that the type does not know are ignored unless it flattens them into a map.
+    T::deserialize(value)
+}
+
+fn unexpected(value: &Value) -> de::Unexpected<'_> {
+    match value {
+        Value::Int(int) => de::Unexpected::Signed(*int),
+        Value::Bytes(bytes) => de::Unexpected::Bytes(bytes),
+        Value::List(_) => de::Unexpected::Seq,
+        Value::Dict(_) => de::Unexpected::Map,
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = BDecoderError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BDecoderError> {
+        match self {
+            Value::Int(int) => visitor.visit_i64(int),
+            Value::Bytes(bytes) => visitor.visit_byte_buf(bytes),
+            Value::List(list) => visitor.visit_seq(SeqDeserializer {
+                iter: list.into_iter(),
+            }),
+            Value::Dict(dict) => visitor.visit_map(MapDeserializer {
+                iter: dict.into_iter(),
+                value: None,
+            }),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BDecoderError> {
+        match self {
+            Value::Int(0) => visitor.visit_bool(false),
+            Value::Int(1) => visitor.visit_bool(true),
+            other => Err(de::Error::invalid_type(unexpected(&other), &visitor)),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BDecoderError> {
+        Err(de::Error::invalid_type(unexpected(&self), &visitor))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BDecoderError> {
+        Err(de::Error::invalid_type(unexpected(&self), &visitor))
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BDecoderError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BDecoderError> {
+        match self {
+            Value::Bytes(bytes) => match String::from_utf8(bytes) {
+                Ok(string) => visitor.visit_string(string),
+                Err(error) => visitor.visit_byte_buf(error.into_bytes()),
+            },
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BDecoderError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BDecoderError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BDecoderError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, BDecoderError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, BDecoderError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, BDecoderError> {
+        match self {
+            Value::Bytes(variant) => visitor.visit_enum(EnumDeserializer {
+                variant,
+                value: None,
+            }),
+            Value::Dict(dict) => {
+                let len = dict.len();
+                match dict.into_iter().next() {
+                    Some((variant, value)) if len == 1 => visitor.visit_enum(EnumDeserializer {
+                        variant,
+                        value: Some(value),
+                    }),
+                    _ => Err(de::Error::invalid_length(len, &"a single variant")),
+                }
+            }
+            other => Err(de::Error::invalid_type(unexpected(&other), &visitor)),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BDecoderError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BDecoderError> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 bytes byte_buf seq tuple tuple_struct map struct
+    }
+}
+
+impl<'de> IntoDeserializer<'de, BDecoderError> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
+
+/// Hands the elements of a list to a visitor.
+struct SeqDeserializer {
+    iter: vec::IntoIter<Value>,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqDeserializer {
+    type Error = BDecoderError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, BDecoderError> {
+        match self.iter.next() {
+            Some(value) => seed.deserialize(value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+/// Hands the entries of a dictionary to a visitor, keys as byte strings.
+struct MapDeserializer {
+    iter: btree_map::IntoIter<Vec<u8>, Value>,
+    value: Option<Value>,
+}
+
+impl<'de> de::MapAccess<'de> for MapDeserializer {
+    type Error = BDecoderError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, BDecoderError> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Value::Bytes(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, BDecoderError> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::custom("dictionary value without a key")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+/// An enum is either the name of a unit variant or a dictionary with the variant name as its only key.
+struct EnumDeserializer {
+    variant: Vec<u8>,
+    value: Option<Value>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+    type Error = BDecoderError;
+    type Variant = VariantDeserializer;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantDeserializer), BDecoderError> {
+        let variant = seed.deserialize(BytesDeserializer::<BDecoderError>::new(&self.variant))?;
+        Ok((variant, VariantDeserializer { value: self.value }))
+    }
+}
+
+struct VariantDeserializer {
+    value: Option<Value>,
+}
+
+impl VariantDeserializer {
+    fn content(self) -> Result<Value, BDecoderError> {
+        self.value
+            .ok_or_else(|| de::Error::invalid_type(de::Unexpected::UnitVariant, &"variant content"))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantDeserializer {
+    type Error = BDecoderError;
+
+    fn unit_variant(self) -> Result<(), BDecoderError> {
+        match self.value {
+            None => Ok(()),
+            Some(value) => Err(de::Error::invalid_type(unexpected(&value), &"unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, BDecoderError> {
+        seed.deserialize(self.content()?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, BDecoderError> {
+        de::Deserializer::deserialize_seq(self.content()?, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, BDecoderError> {
+        de::Deserializer::deserialize_map(self.content()?, visitor)
+    }
+}
+
+/// Builds a Value out of whatever the deserializer holds.
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a bencoded value")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Int(v as i64))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Int(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        i64::try_from(v)
+            .map(Value::Int)
+            .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Value, E> {
+        Ok(Value::Bytes(v))
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut list = Vec::new();
+        while let Some(elem) = seq.next_element()? {
+            list.push(elem);
+        }
+        Ok(Value::List(list))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut dict = Dictionary::new();
+        while let Some((key, elem)) = map.next_entry::<Value, Value>()? {
+            match key {
+                Value::Bytes(key) => dict.insert(key, elem),
+                _ => return Err(de::Error::custom("dictionary keys must be byte strings")),
+            };
+        }
+        Ok(Value::Dict(dict))
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Value {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ser::to_bytes;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Peer {
+        ip: String,
+        port: u16,
+        #[serde(rename = "peer id", with = "serde_bytes")]
+        peer_id: Vec<u8>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct AnnounceResponse {
+        interval: i64,
+        #[serde(rename = "failure reason")]
+        failure_reason: Option<String>,
+        peers: Vec<Peer>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Info {
+        name: String,
+        #[serde(rename = "piece length")]
+        piece_length: u64,
+        #[serde(with = "serde_bytes")]
+        pieces: Vec<u8>,
+        #[serde(default)]
+        private: bool,
+        #[serde(flatten)]
+        extra: BTreeMap<String, Value>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Message {
+        Ping,
+        Error(i64),
+        Query { id: String },
+    }
+
+    #[test]
+    fn test_tracker_response_round_trip() {
+        let bencoded =
+            b"d8:intervali1800e5:peersld2:ip9:127.0.0.17:peer id2:\xff\x004:porti6881eeee";
+        let response: AnnounceResponse = from_bytes(bencoded).unwrap();
+
+        assert_eq!(
+            response,
+            AnnounceResponse {
+                interval: 1800,
+                failure_reason: None,
+                peers: vec![Peer {
+                    ip: "127.0.0.1".to_string(),
+                    port: 6881,
+                    peer_id: vec![0xff, 0],
+                }],
+            }
+        );
+        assert_eq!(to_bytes(&response).unwrap(), bencoded);
+    }
+
+    #[test]
+    fn test_unknown_keys_are_flattened() {
+        let bencoded = b"d4:name1:a12:piece lengthi8e6:pieces2:\x01\x027:privatei1e6:sourcei3ee";
+        let info: Info = from_bytes(bencoded).unwrap();
+
+        assert!(info.private);
+        assert_eq!(info.pieces, vec![1, 2]);
+        assert_eq!(info.extra["source"], Value::Int(3));
+        assert_eq!(to_bytes(&info).unwrap(), bencoded);
+    }
+
+    #[test]
+    fn test_enums() {
+        for (message, bencoded) in [
+            (Message::Ping, &b"4:ping"[..]),
+            (Message::Error(201), &b"d5:errori201ee"[..]),
+            (
+                Message::Query {
+                    id: "abc".to_string(),
+                },
+                &b"d5:queryd2:id3:abcee"[..],
+            ),
+        ] {
+            assert_eq!(to_bytes(&message).unwrap(), bencoded);
+            assert_eq!(from_bytes::<Message>(bencoded).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_type_errors() {
+        assert!(from_bytes::<AnnounceResponse>(b"d5:peerslee").is_err());
+        assert!(from_bytes::<u8>(b"i256e").is_err());
+        assert!(from_bytes::<bool>(b"i2e").is_err());
+        assert!(from_bytes::<String>(b"2:\xff\xfe").is_err());
+        assert!(to_bytes(&1.5).is_err());
+        assert!(to_bytes(&u64::MAX).is_err());
+        assert!(to_bytes(&vec![None, Some(1)]).is_err());
+        assert!(to_bytes(&BTreeMap::from([(1, 2)])).is_err());
+    }
+}
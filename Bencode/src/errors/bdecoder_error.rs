@@ -1,4 +1,4 @@
-use std::{fmt::Display, io::Error};
+use std::fmt::Display;
 
 #[derive(Debug)]
 pub struct BDecoderError {
@@ -17,16 +17,16 @@ impl Display for BDecoderError {
     }
 }
 
-impl From<Error> for BDecoderError {
-    fn from(error: Error) -> BDecoderError {
-        BDecoderError {
-            msg: format!("BDecoderError: unexpected character ({})", error),
-        }
-    }
-}
-
 impl Default for BDecoderError {
     fn default() -> Self {
         Self::new("BDecoderError: unexpected character".to_string())
     }
 }
+
+impl std::error::Error for BDecoderError {}
+
+impl serde::de::Error for BDecoderError {
+    fn custom<T: Display>(msg: T) -> BDecoderError {
+        BDecoderError::new(format!("BDecoderError: {}", msg))
+    }
+}
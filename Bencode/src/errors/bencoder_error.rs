@@ -0,0 +1,32 @@
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub struct BEncoderError {
+    msg: String,
+}
+
+impl BEncoderError {
+    pub fn new(message: String) -> BEncoderError {
+        BEncoderError { msg: message }
+    }
+}
+
+impl Display for BEncoderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl std::error::Error for BEncoderError {}
+
+impl serde::ser::Error for BEncoderError {
+    fn custom<T: Display>(msg: T) -> BEncoderError {
+        BEncoderError::new(format!("BEncoderError: {}", msg))
+    }
+}
+
+impl Default for BEncoderError {
+    fn default() -> Self {
+        Self::new("BEncoderError: value can not be encoded".to_string())
+    }
+}
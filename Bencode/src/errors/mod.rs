@@ -0,0 +1,2 @@
+pub mod bdecoder_error;
+pub mod bencoder_error;
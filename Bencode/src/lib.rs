@@ -0,0 +1,19 @@
+//! Bencoding shared by the client and the tracker.
+
+pub mod bdecoder;
+pub mod bencoder;
+pub mod cursor;
+pub mod de;
+pub mod errors;
+pub mod ser;
+pub mod value;
+pub mod value_ref;
+
+pub use bdecoder::{bdecode, bdecode_ref, bdecode_ref_with_limits, bdecode_with_limits};
+pub use bencoder::bencode;
+pub use cursor::{find_raw, find_raw_with_limits, Cursor, Event, Limits};
+pub use de::{from_bytes, from_value};
+pub use errors::{bdecoder_error::BDecoderError, bencoder_error::BEncoderError};
+pub use ser::{to_bytes, to_value};
+pub use value::{Dictionary, Value};
+pub use value_ref::{DictionaryRef, ValueRef};
@@ -0,0 +1,400 @@
+use crate::{
+    bencoder::bencode,
+    errors::bencoder_error::BEncoderError,
+    value::{Dictionary, Value},
+};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq};
+
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, BEncoderError> {
+    //! Returns the value encoded by Bencoding format.
+    Ok(bencode(&to_value(value)?))
+}
+
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, BEncoderError> {
+    //! Returns the bencoded Value of any serializable type.
+    //! Structs and maps become dictionaries (fields set to None are left out), sequences become lists,
+    //! booleans become 0 or 1 and strings, chars and bytes become byte strings. Floats can not be encoded.
+    value
+        .serialize(Serializer)?
+        .ok_or_else(|| error("a missing value can only be a dictionary entry"))
+}
+
+fn error(reason: &str) -> BEncoderError {
+    BEncoderError::new(format!("BEncoderError: {}", reason))
+}
+
+/// Turns values into bencoded Values. None (and unit) serialize to Ok(None), so dictionaries can skip them.
+struct Serializer;
+
+/// Collects the elements of a sequence, a tuple or a tuple variant.
+struct SeqSerializer {
+    variant: Option<&'static str>,
+    list: Vec<Value>,
+}
+
+/// Collects the entries of a map, a struct or a struct variant.
+struct MapSerializer {
+    variant: Option<&'static str>,
+    dict: Dictionary,
+    key: Option<Vec<u8>>,
+}
+
+/// Wraps value in a dictionary with a single entry named after the variant.
+fn with_variant(variant: Option<&'static str>, value: Value) -> Value {
+    match variant {
+        Some(variant) => Value::Dict(Dictionary::from([(variant.as_bytes().to_vec(), value)])),
+        None => value,
+    }
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Option<Value>;
+    type Error = BEncoderError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Option<Value>, BEncoderError> {
+        Ok(Some(Value::Int(v as i64)))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Option<Value>, BEncoderError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Option<Value>, BEncoderError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Option<Value>, BEncoderError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Option<Value>, BEncoderError> {
+        Ok(Some(Value::Int(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Option<Value>, BEncoderError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Option<Value>, BEncoderError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Option<Value>, BEncoderError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Option<Value>, BEncoderError> {
+        match i64::try_from(v) {
+            Ok(v) => self.serialize_i64(v),
+            Err(_) => Err(error(&format!("{} does not fit in an integer", v))),
+        }
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Option<Value>, BEncoderError> {
+        Err(error("floats can not be encoded"))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Option<Value>, BEncoderError> {
+        Err(error("floats can not be encoded"))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Option<Value>, BEncoderError> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Option<Value>, BEncoderError> {
+        Ok(Some(Value::from(v)))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Option<Value>, BEncoderError> {
+        Ok(Some(Value::from(v)))
+    }
+
+    fn serialize_none(self) -> Result<Option<Value>, BEncoderError> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(
+        self,
+        value: &T,
+    ) -> Result<Option<Value>, BEncoderError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Option<Value>, BEncoderError> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Option<Value>, BEncoderError> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Option<Value>, BEncoderError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Option<Value>, BEncoderError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Option<Value>, BEncoderError> {
+        Ok(Some(with_variant(Some(variant), to_value(value)?)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, BEncoderError> {
+        Ok(SeqSerializer {
+            variant: None,
+            list: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, BEncoderError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, BEncoderError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, BEncoderError> {
+        Ok(SeqSerializer {
+            variant: Some(variant),
+            list: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, BEncoderError> {
+        Ok(MapSerializer {
+            variant: None,
+            dict: Dictionary::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<MapSerializer, BEncoderError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapSerializer, BEncoderError> {
+        Ok(MapSerializer {
+            variant: Some(variant),
+            dict: Dictionary::new(),
+            key: None,
+        })
+    }
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BEncoderError> {
+        self.list.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<Value>, BEncoderError> {
+        Ok(Some(with_variant(self.variant, Value::List(self.list))))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = BEncoderError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BEncoderError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<Value>, BEncoderError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = BEncoderError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BEncoderError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<Value>, BEncoderError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = BEncoderError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BEncoderError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<Value>, BEncoderError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = BEncoderError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BEncoderError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<Value>, BEncoderError> {
+        self.finish()
+    }
+}
+
+impl MapSerializer {
+    fn insert<T: Serialize + ?Sized>(
+        &mut self,
+        key: Vec<u8>,
+        value: &T,
+    ) -> Result<(), BEncoderError> {
+        if let Some(value) = value.serialize(Serializer)? {
+            self.dict.insert(key, value);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<Value>, BEncoderError> {
+        Ok(Some(with_variant(self.variant, Value::Dict(self.dict))))
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Option<Value>;
+    type Error = BEncoderError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), BEncoderError> {
+        match to_value(key)? {
+            Value::Bytes(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(error("dictionary keys must be strings or bytes")),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BEncoderError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| error("dictionary value without a key"))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Option<Value>, BEncoderError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Option<Value>;
+    type Error = BEncoderError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), BEncoderError> {
+        self.insert(key.as_bytes().to_vec(), value)
+    }
+
+    fn end(self) -> Result<Option<Value>, BEncoderError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = Option<Value>;
+    type Error = BEncoderError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), BEncoderError> {
+        self.insert(key.as_bytes().to_vec(), value)
+    }
+
+    fn end(self) -> Result<Option<Value>, BEncoderError> {
+        self.finish()
+    }
+}
+
+/// Serializes the keys of a dictionary as byte strings.
+struct Key<'a>(&'a [u8]);
+
+impl Serialize for Key<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Int(int) => serializer.serialize_i64(*int),
+            Value::Bytes(bytes) => serializer.serialize_bytes(bytes),
+            Value::List(list) => {
+                let mut seq = serializer.serialize_seq(Some(list.len()))?;
+                for elem in list {
+                    seq.serialize_element(elem)?;
+                }
+                seq.end()
+            }
+            Value::Dict(dict) => {
+                let mut map = serializer.serialize_map(Some(dict.len()))?;
+                for (key, elem) in dict {
+                    map.serialize_entry(&Key(key), elem)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
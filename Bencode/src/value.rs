@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+
+/// A bencoded dictionary. Keys are kept sorted, which is the order bencoding requires.
+pub type Dictionary = BTreeMap<Vec<u8>, Value>;
+
+/// This enum represents any bencoded value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+    Dict(Dictionary),
+}
+
+impl Value {
+    /// Returns the integer if the value is one.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(int) => Some(*int),
+            _ => None,
+        }
+    }
+
+    /// Returns the byte string if the value is one.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Returns the byte string if the value is one and it is valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        self.as_bytes()
+            .and_then(|bytes| std::str::from_utf8(bytes).ok())
+    }
+
+    /// Returns the list if the value is one.
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    /// Returns the dictionary if the value is one.
+    pub fn as_dict(&self) -> Option<&Dictionary> {
+        match self {
+            Value::Dict(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    /// Returns the value stored under key if this value is a dictionary.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.as_dict().and_then(|dict| dict.get(key.as_bytes()))
+    }
+}
+
+impl From<i64> for Value {
+    fn from(int: i64) -> Value {
+        Value::Int(int)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(string: &str) -> Value {
+        Value::Bytes(string.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for Value {
+    fn from(string: String) -> Value {
+        Value::Bytes(string.into_bytes())
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(bytes: &[u8]) -> Value {
+        Value::Bytes(bytes.to_vec())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(bytes: Vec<u8>) -> Value {
+        Value::Bytes(bytes)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(list: Vec<Value>) -> Value {
+        Value::List(list)
+    }
+}
+
+impl From<Dictionary> for Value {
+    fn from(dict: Dictionary) -> Value {
+        Value::Dict(dict)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accessors() {
+        let value = Value::Dict(Dictionary::from([
+            (b"interval".to_vec(), Value::Int(1800)),
+            (b"peers".to_vec(), Value::List(vec![Value::from("peer")])),
+            (b"raw".to_vec(), Value::Bytes(vec![0xff, 0xfe])),
+        ]));
+
+        assert_eq!(value.get("interval").and_then(Value::as_int), Some(1800));
+        assert_eq!(
+            value.get("peers").and_then(Value::as_list),
+            Some(&[Value::from("peer")][..])
+        );
+        assert_eq!(value.get("raw").and_then(Value::as_str), None);
+        assert_eq!(
+            value.get("raw").and_then(Value::as_bytes),
+            Some(&[0xff, 0xfe][..])
+        );
+        assert_eq!(value.get("missing"), None);
+        assert_eq!(Value::Int(1).get("interval"), None);
+    }
+}
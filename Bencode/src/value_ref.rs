@@ -0,0 +1,89 @@
+use crate::value::{Dictionary, Value};
+use std::collections::BTreeMap;
+
+/// A bencoded dictionary that borrows its keys from the bencoded buffer.
+pub type DictionaryRef<'a> = BTreeMap<&'a [u8], ValueRef<'a>>;
+
+/// This enum represents any bencoded value, borrowing its byte strings from the buffer it was decoded from
+/// instead of copying them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueRef<'a> {
+    Int(i64),
+    Bytes(&'a [u8]),
+    List(Vec<ValueRef<'a>>),
+    Dict(DictionaryRef<'a>),
+}
+
+impl<'a> ValueRef<'a> {
+    /// Returns the integer if the value is one.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            ValueRef::Int(int) => Some(*int),
+            _ => None,
+        }
+    }
+
+    /// Returns the byte string if the value is one.
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match self {
+            ValueRef::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Returns the byte string if the value is one and it is valid UTF-8.
+    pub fn as_str(&self) -> Option<&'a str> {
+        self.as_bytes()
+            .and_then(|bytes| std::str::from_utf8(bytes).ok())
+    }
+
+    /// Returns the list if the value is one.
+    pub fn as_list(&self) -> Option<&[ValueRef<'a>]> {
+        match self {
+            ValueRef::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    /// Returns the dictionary if the value is one.
+    pub fn as_dict(&self) -> Option<&DictionaryRef<'a>> {
+        match self {
+            ValueRef::Dict(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    /// Returns the value stored under key if this value is a dictionary.
+    pub fn get(&self, key: &str) -> Option<&ValueRef<'a>> {
+        self.as_dict().and_then(|dict| dict.get(key.as_bytes()))
+    }
+
+    /// Returns an owned copy of the value.
+    pub fn to_value(&self) -> Value {
+        match self {
+            ValueRef::Int(int) => Value::Int(*int),
+            ValueRef::Bytes(bytes) => Value::from(*bytes),
+            ValueRef::List(list) => Value::List(list.iter().map(ValueRef::to_value).collect()),
+            ValueRef::Dict(dict) => Value::Dict(
+                dict.iter()
+                    .map(|(key, value)| (key.to_vec(), value.to_value()))
+                    .collect::<Dictionary>(),
+            ),
+        }
+    }
+}
+
+impl<'a> From<&'a Value> for ValueRef<'a> {
+    fn from(value: &'a Value) -> ValueRef<'a> {
+        match value {
+            Value::Int(int) => ValueRef::Int(*int),
+            Value::Bytes(bytes) => ValueRef::Bytes(bytes),
+            Value::List(list) => ValueRef::List(list.iter().map(ValueRef::from).collect()),
+            Value::Dict(dict) => ValueRef::Dict(
+                dict.iter()
+                    .map(|(key, value)| (key.as_slice(), ValueRef::from(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Connects hundreds of peers on loopback to a seeder, both sides run by one event loop each, and measures
+//! how long every peer takes to get its blocks. Run with `cargo bench --bench loopback_peers`.
+use crabrave::peer_entities::communication_method::CommunicationMethod;
+use crabrave::peer_entities::event_loop::{ConnectionHandler, EventLoop, LoopStream};
+use crabrave::utilities::constants::{
+    CHUNK_SIZE, INFO_HASH_LEN, PIECE_ID, PSTR, REQUEST_ID, REQUEST_MESSAGE, RESERVED_SPACE_LEN,
+};
+use crabrave::utilities::utils::vecu8_to_u32;
+use std::net::TcpListener;
+use std::sync::mpsc::{channel, Sender};
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+const PEERS: [usize; 3] = [100, 250, 500];
+const BLOCKS_PER_PEER: u32 = 16;
+
+fn handshake() -> Vec<u8> {
+    let mut handshake = vec![PSTR.len() as u8];
+    handshake.extend(PSTR.as_bytes());
+    handshake.extend([0; RESERVED_SPACE_LEN as usize]);
+    handshake.extend([5; INFO_HASH_LEN]);
+    handshake.extend(b"-CR0001-123456789012");
+    handshake
+}
+
+/// Answers the handshake, and each request with a block of the length asked.
+struct Seeder {
+    stream: LoopStream,
+}
+
+impl ConnectionHandler for Seeder {
+    fn frame(&mut self) -> bool {
+        let frame = self.stream.take_frame();
+        if frame[0] as usize == PSTR.len() {
+            return self.stream.write_all(&handshake()).is_ok();
+        }
+        if frame.len() < 17 || frame[4] != REQUEST_ID {
+            return true;
+        }
+        let length = vecu8_to_u32(&frame[13..17]);
+        let mut piece = (9 + length).to_be_bytes().to_vec();
+        piece.push(PIECE_ID);
+        piece.extend(&frame[5..13]);
+        piece.extend(vec![7; length as usize]);
+        self.stream.write_all(&piece).is_ok()
+    }
+}
+
+/// Requests every block at once after the handshake, and tells when they all arrived.
+struct Leecher {
+    stream: LoopStream,
+    received: u32,
+    done: Sender<Duration>,
+    start: Instant,
+}
+
+impl ConnectionHandler for Leecher {
+    fn frame(&mut self) -> bool {
+        let frame = self.stream.take_frame();
+        if frame[0] as usize == PSTR.len() {
+            for block in 0..BLOCKS_PER_PEER {
+                let mut request = REQUEST_MESSAGE.to_vec();
+                request.extend(0u32.to_be_bytes());
+                request.extend((block * CHUNK_SIZE).to_be_bytes());
+                request.extend(CHUNK_SIZE.to_be_bytes());
+                if self.stream.write_all(&request).is_err() {
+                    return false;
+                }
+            }
+            return true;
+        }
+        if frame.len() > 4 && frame[4] == PIECE_ID {
+            self.received += 1;
+        }
+        if self.received < BLOCKS_PER_PEER {
+            return true;
+        }
+        let _r = self.done.send(self.start.elapsed());
+        false
+    }
+}
+
+fn run(peers: usize) {
+    let seeder_loop = EventLoop::new().unwrap();
+    let leecher_loop = EventLoop::new().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let acceptor = spawn(move || {
+        for _ in 0..peers {
+            let (socket, _) = listener.accept().unwrap();
+            let stream = seeder_loop.adopt(socket).unwrap();
+            let seeder = Seeder {
+                stream: stream.clone(),
+            };
+            stream.handle(Box::new(seeder)).unwrap();
+        }
+    });
+
+    let (done_sender, done_receiver) = channel();
+    let start = Instant::now();
+    for _ in 0..peers {
+        let mut stream = leecher_loop.stream();
+        stream.connect("127.0.0.1", port).unwrap();
+        stream.write_all(&handshake()).unwrap();
+        let leecher = Leecher {
+            stream: stream.clone(),
+            received: 0,
+            done: done_sender.clone(),
+            start,
+        };
+        stream.handle(Box::new(leecher)).unwrap();
+    }
+    acceptor.join().unwrap();
+    let mut slowest = Duration::ZERO;
+    for _ in 0..peers {
+        let elapsed = done_receiver
+            .recv_timeout(Duration::from_secs(60))
+            .expect("a peer did not get its blocks");
+        slowest = slowest.max(elapsed);
+    }
+    let megabytes = (peers as u64 * BLOCKS_PER_PEER as u64 * CHUNK_SIZE as u64) as f64 / 1e6;
+    println!(
+        "{:>4} peers: {:>6.1} MB in {:>8.2?}, {:>7.1} MB/s, 2 event loop threads",
+        peers,
+        megabytes,
+        slowest,
+        megabytes / slowest.as_secs_f64()
+    );
+}
+
+fn main() {
+    for peers in PEERS {
+        run(peers);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{
+    download_manager::PieceStatus,
+    rate_estimator::{TransferMeter, TransferRates},
+};
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// The peers that are uploaded to at the same time, besides the optimistic one.
+const UNCHOKE_SLOTS: usize = 4;
+const CHOKE_INTERVAL: Duration = Duration::from_secs(10);
+/// Every this many intervals another choked peer is tried.
+const OPTIMISTIC_INTERVALS: u64 = 3;
+
+/// Decides which of the peers connected to a torrent are uploaded to. While downloading the peers that
+/// send us the most are unchoked, like in tit-for-tat; once seeding, the ones that take the most from us.
+/// One more peer is unchoked in turns, so new peers get the chance to show their rate.
+#[derive(Debug)]
+pub struct Choker {
+    bitfield: Arc<Vec<Mutex<PieceStatus>>>,
+    state: Mutex<ChokerState>,
+}
+
+#[derive(Debug, Default)]
+struct ChokerState {
+    /// In the order they connected.
+    peers: Vec<(String, Arc<TransferMeter>)>,
+    unchoked: HashSet<String>,
+    optimistic: Option<String>,
+    rounds: u64,
+    last_update: Option<Instant>,
+}
+
+impl Choker {
+    pub fn new(bitfield: Arc<Vec<Mutex<PieceStatus>>>) -> Arc<Choker> {
+        Arc::new(Choker {
+            bitfield,
+            state: Mutex::new(ChokerState::default()),
+        })
+    }
+
+    /// Starts choosing for the peer, it is unchoked right away if there is a free slot.
+    pub fn add_peer(&self, peer_id: &str, meter: Arc<TransferMeter>) {
+        let mut state = self.lock();
+        state.peers.retain(|(id, _)| id != peer_id);
+        state.peers.push((peer_id.to_string(), meter));
+        if state.unchoked.len() < UNCHOKE_SLOTS {
+            state.unchoked.insert(peer_id.to_string());
+        }
+    }
+
+    pub fn remove_peer(&self, peer_id: &str) {
+        let mut state = self.lock();
+        state.peers.retain(|(id, _)| id != peer_id);
+        state.unchoked.remove(peer_id);
+        if state.optimistic.as_deref() == Some(peer_id) {
+            state.optimistic = None;
+        }
+    }
+
+    /// Returns whether the peer is choked, the choice is made again every CHOKE_INTERVAL.
+    /// Peers the Choker does not know of are not choked by it.
+    pub fn is_choked(&self, peer_id: &str) -> bool {
+        let seeding = self.seeding();
+        let mut state = self.lock();
+        if state
+            .last_update
+            .is_none_or(|last| last.elapsed() >= CHOKE_INTERVAL)
+        {
+            state.update(seeding);
+            state.last_update = Some(Instant::now());
+        }
+        state.peers.iter().any(|(id, _)| id == peer_id) && !state.unchoked.contains(peer_id)
+    }
+
+    fn seeding(&self) -> bool {
+        self.bitfield.iter().all(|piece| {
+            *piece.lock().unwrap_or_else(PoisonError::into_inner) == PieceStatus::Downloaded
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ChokerState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl ChokerState {
+    fn update(&mut self, seeding: bool) {
+        let rate = |rates: TransferRates| match seeding {
+            true => rates.upload_payload,
+            false => rates.download_payload,
+        };
+        let mut ranked: Vec<(u64, &String)> = self
+            .peers
+            .iter()
+            .map(|(id, meter)| (rate(meter.rates()), id))
+            .collect();
+        // the sort is stable, peers with the same rate keep the order they connected in
+        ranked.sort_by_key(|(rate, _)| Reverse(*rate));
+        self.unchoked = ranked
+            .iter()
+            .take(UNCHOKE_SLOTS)
+            .map(|(_, id)| (*id).clone())
+            .collect();
+
+        if self.rounds.is_multiple_of(OPTIMISTIC_INTERVALS) {
+            // the next choked peer after the last optimistic one, in the order they connected
+            let choked: Vec<&String> = self
+                .peers
+                .iter()
+                .map(|(id, _)| id)
+                .filter(|id| !self.unchoked.contains(*id))
+                .collect();
+            let last = self
+                .optimistic
+                .as_ref()
+                .and_then(|last| self.peers.iter().position(|(id, _)| id == last));
+            self.optimistic = choked
+                .iter()
+                .find(|id| {
+                    let position = self.peers.iter().position(|(peer, _)| peer == **id);
+                    last.is_none_or(|last| position > Some(last))
+                })
+                .or(choked.first())
+                .map(|id| (*id).clone());
+        }
+        if let Some(optimistic) = &self.optimistic {
+            if self.peers.iter().any(|(id, _)| id == optimistic) {
+                self.unchoked.insert(optimistic.clone());
+            }
+        }
+        self.rounds += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{events::Direction, rate_estimator::Traffic};
+
+    fn peers(choker: &Choker, rates: &[usize]) -> Vec<Arc<TransferMeter>> {
+        let mut meters = Vec::new();
+        for (i, rate) in rates.iter().enumerate() {
+            let meter = TransferMeter::new();
+            meter.record(Direction::Download, Traffic::Payload, *rate);
+            choker.add_peer(&format!("peer{}", i), meter.clone());
+            meters.push(meter);
+        }
+        meters
+    }
+
+    #[test]
+    fn test_the_fastest_peers_and_an_optimistic_one_are_unchoked() {
+        let bitfield = Arc::new(vec![Mutex::new(PieceStatus::NotDownloaded)]);
+        let choker = Choker::new(bitfield);
+        let _meters = peers(&choker, &[10, 600, 500, 20, 400, 300]);
+
+        // peer1 to peer5 are the fastest, peer0 is the first of the choked ones
+        let choked: Vec<bool> = (0..6)
+            .map(|i| choker.is_choked(&format!("peer{}", i)))
+            .collect();
+        assert_eq!(choked, vec![false, false, false, true, false, false]);
+        assert!(!choker.is_choked("unknown"));
+
+        choker.remove_peer("peer1");
+        choker.lock().update(false);
+        // peer3 takes the slot, the optimistic one stays until the next turn
+        assert!(!choker.is_choked("peer3"));
+        assert!(!choker.is_choked("peer0"));
+    }
+
+    #[test]
+    fn test_optimistic_unchoke_rotates() {
+        let bitfield = Arc::new(vec![Mutex::new(PieceStatus::Downloaded)]);
+        let choker = Choker::new(bitfield);
+        let meters = peers(&choker, &[0, 0, 0, 0, 0, 0, 0]);
+        for meter in &meters[..4] {
+            meter.record(Direction::Upload, Traffic::Payload, 1000);
+        }
+        // seeding: the ones we upload the most to are kept, the three others take turns
+        let mut optimistic = Vec::new();
+        for _ in 0..4 {
+            let mut state = choker.lock();
+            state.rounds = 0;
+            state.update(true);
+            assert_eq!(state.unchoked.len(), UNCHOKE_SLOTS + 1);
+            optimistic.push(state.optimistic.clone().unwrap());
+        }
+        assert_eq!(optimistic, vec!["peer4", "peer5", "peer6", "peer4"]);
+    }
+}
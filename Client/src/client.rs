@@ -1,33 +1,115 @@
 use crate::{
+    choker::Choker,
+    config::{Config, EncryptionPolicy},
+    connection_manager::ConnectionManager,
+    disk_cache::DiskCache,
     download_manager::DownloadManager,
     download_manager::DownloaderInfo,
     errors::client_error::ClientError,
+    events::{ClientEvent, EventBus, TrackerStatus},
+    file_selection::FileSelection,
     listener::Listener,
     logger::LogMsg,
     logger::Logger,
+    parsing::torrent_parser::Metainfo,
     peer_entities::communication_method::CommunicationMethod,
-    peer_entities::communication_method::TCP,
+    peer_entities::encrypted_stream::EncryptedStream,
+    peer_entities::event_loop::EventLoop,
     peer_entities::peer::Peer,
     peer_entities::peer_connection::PeerConnection,
-    tracker::Tracker,
-    tracker::TrackerInterface,
-    ui::ui_codes::*,
+    piece_picker::PiecePicker,
+    rate_estimator::TransferMeter,
+    rate_limiter::RateLimiter,
+    session::{IncomingConnection, SessionContext, TorrentState},
+    tracker::{AnnounceRequest, TrackerInterface},
     upload_manager::PieceRequest,
     upload_manager::UploadManager,
-    utilities::utils::{create_id, vecu8_to_string, vecu8_to_u64, UiParams},
+    utilities::utils::pieces_path,
 };
-use glib::Sender as UISender;
 use std::{
-    collections::HashMap,
     sync::mpsc,
     sync::mpsc::Receiver,
     sync::mpsc::{channel, Sender},
     sync::RwLock,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, PoisonError},
     thread::spawn,
     thread::JoinHandle,
 };
 
+/// The peers of a torrent, the ones the DownloadManager connects to. Peers found other than through the
+/// tracker, on the local network for example, are added to it while the torrent runs.
+#[derive(Clone)]
+#[allow(clippy::type_complexity)]
+pub struct PeerList {
+    peers: Arc<RwLock<Vec<Arc<PeerConnection<Peer>>>>>,
+    info_hash: Vec<u8>,
+    client_id: String,
+    event_loop: Arc<EventLoop>,
+    encryption: EncryptionPolicy,
+    rate_limiter: Arc<RateLimiter>,
+    rate_meter: Arc<TransferMeter>,
+    logger_sender: Sender<LogMsg>,
+    upload_sender: Sender<Option<PieceRequest>>,
+}
+
+impl PeerList {
+    /// Creates an empty list, its streams will run on the event loop of the session and be encrypted as
+    /// its policy says.
+    pub fn new(
+        info_hash: Vec<u8>,
+        client_id: String,
+        session: &SessionContext,
+        rate_limiter: Arc<RateLimiter>,
+        rate_meter: Arc<TransferMeter>,
+        logger_sender: Sender<LogMsg>,
+        upload_sender: Sender<Option<PieceRequest>>,
+    ) -> PeerList {
+        PeerList {
+            peers: Arc::new(RwLock::new(Vec::new())),
+            info_hash,
+            client_id,
+            event_loop: session.event_loop.clone(),
+            encryption: session.encryption,
+            rate_limiter,
+            rate_meter,
+            logger_sender,
+            upload_sender,
+        }
+    }
+
+    /// Adds the peer unless it is in the list already, returns whether it was added. Its stream runs on the
+    /// event loop of the session, and is limited and measured within the torrent.
+    pub fn add(&self, ip: String, port: u16) -> bool {
+        let mut peers = self.peers.write().unwrap_or_else(PoisonError::into_inner);
+        let known = peers.iter().any(|peer_connection| {
+            let peer = peer_connection
+                .peer
+                .read()
+                .unwrap_or_else(PoisonError::into_inner);
+            peer.ip == ip && peer.port == port
+        });
+        if known {
+            return false;
+        }
+        let mut stream = self.event_loop.stream();
+        stream.set_rate_limiter(self.rate_limiter.child());
+        stream.set_rate_meter(self.rate_meter.child());
+        peers.push(Arc::new(PeerConnection::new(
+            Peer::new("default_id".to_string(), ip, port),
+            self.info_hash.clone(),
+            self.client_id.clone(),
+            Arc::new(Mutex::new(Box::new(EncryptedStream::new(
+                Box::new(stream),
+                self.info_hash.clone(),
+                self.encryption,
+            )))),
+            Arc::new(Mutex::new(self.logger_sender.clone())),
+            Arc::new(Mutex::new(self.upload_sender.clone())),
+        )));
+        true
+    }
+}
+
 /// This struct is the responsible of creating the different parts of the application, such as the logger, listener, tracker, upload manager and download manager.
 #[allow(clippy::type_complexity)]
 pub struct Client {
@@ -47,20 +129,30 @@ pub struct Client {
     pub pieces: Vec<u8>,
     pub pieces_length: RwLock<u64>,
     pub file_length: RwLock<u64>,
-    pub sender_client: Arc<Mutex<UISender<Vec<(usize, UiParams, String)>>>>,
+    pub events: Arc<EventBus>,
     pub upload_sender: Arc<Mutex<Sender<Option<PieceRequest>>>>,
     upload_receiver: Arc<Mutex<Receiver<Option<PieceRequest>>>>,
-    pub port_listener: u16,
+    incoming_sender: Sender<IncomingConnection>,
+    incoming_receiver: Arc<Mutex<Receiver<IncomingConnection>>>,
+    pub state: Arc<RwLock<TorrentState>>,
+    pub session: SessionContext,
+    pub disk_cache: Arc<DiskCache>,
+    pub file_selection: Arc<FileSelection>,
+    pub piece_picker: Arc<RwLock<PiecePicker>>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub rate_meter: Arc<TransferMeter>,
+    pub connection_manager: Arc<ConnectionManager>,
+    peer_list: PeerList,
 }
 
 #[allow(clippy::type_complexity)]
 pub trait ClientInterface {
     fn create(
-        config: HashMap<String, String>,
-        sender_client: Arc<Mutex<UISender<Vec<(usize, UiParams, String)>>>>,
+        config: Config,
+        events: Arc<EventBus>,
         torrent_name: String,
-        port_listener: u16,
-        torrent_data: HashMap<String, Vec<u8>>,
+        session: SessionContext,
+        metainfo: Metainfo,
     ) -> Result<(Arc<dyn ClientInterface>, JoinHandle<()>), ClientError>
     where
         Self: Sized;
@@ -71,104 +163,133 @@ pub trait ClientInterface {
     ) -> Result<(JoinHandle<()>, JoinHandle<()>, JoinHandle<()>), ClientError>;
 
     fn get_info_hash(&self) -> Vec<u8>;
+
+    fn get_state(&self) -> Arc<RwLock<TorrentState>>;
+
+    fn get_incoming_sender(&self) -> Sender<IncomingConnection>;
+
+    fn get_file_selection(&self) -> Arc<FileSelection>;
+
+    fn get_rate_limiter(&self) -> Arc<RateLimiter>;
+
+    fn get_rate_meter(&self) -> Arc<TransferMeter>;
+
+    fn get_peer_list(&self) -> PeerList;
+
+    fn get_piece_picker(&self) -> Arc<RwLock<PiecePicker>>;
 }
 
 #[allow(clippy::type_complexity)]
 impl ClientInterface for Client {
     /// This function is responsible for creating the client and connect with the tracker.
+    /// The peer id, the ip and the port announced are the ones of the session the torrent belongs to,
+    /// torrent_name is the path of the torrent file.
     fn create(
-        config: HashMap<String, String>,
-        sender_client: Arc<Mutex<UISender<Vec<(usize, UiParams, String)>>>>,
+        config: Config,
+        events: Arc<EventBus>,
         torrent_name: String,
-        port_listener: u16,
-        torrent_data: HashMap<String, Vec<u8>>,
+        session: SessionContext,
+        metainfo: Metainfo,
     ) -> Result<(Arc<(dyn ClientInterface + 'static)>, JoinHandle<()>), ClientError> {
-        let id = create_id();
-        let log_path = config["log_path"].clone();
-        let download_path = config["download_path"].clone();
-        let mut download_pieces_path = "src/downloaded_pieces".to_string();
-        let torrent_path = config["torrent_path"].clone();
-        let port = config["port"].clone().parse::<u16>()?;
+        let id = session.peer_id.clone();
+        let log_path = config.storage.log_path.clone();
+        let download_path = config.storage.download_path.clone();
+        let torrent_path = torrent_name.clone();
+        let announced = session.announced();
+        let cache_size = config.storage.cache_size;
+        let download_mode = config.download_mode();
 
         let uploaded = 0;
         let downloaded = 0;
         let left = 0;
         let event = "started".to_string();
 
-        let torrent_name_aux1 = torrent_name.clone();
-        let torrent_name_aux2 = torrent_name.clone();
-
         // create directory for the torrent if it doesn't exist
         let mut split_filename = torrent_path.split('/');
         let real_name = split_filename
             .next_back()
             .ok_or_else(|| ClientError::new("Error spliting name of filename".to_string()))?;
-
-        download_pieces_path.push('/');
-
-        download_pieces_path.push_str(real_name);
+        let download_pieces_path = pieces_path(&torrent_path);
         if !std::path::Path::new(&download_pieces_path).exists() {
             std::fs::create_dir_all(&download_pieces_path)?;
         }
 
-        sender_client.lock()?.send(vec![(
-            UPDATE_TOTAL_SIZE,
-            UiParams::U64(vecu8_to_u64(&torrent_data["length"])),
-            torrent_name,
-        )])?;
-
-        sender_client.lock()?.send(vec![(
-            UPDATE_FILENAME,
-            UiParams::String(vecu8_to_string(&torrent_data["name"])),
-            torrent_name_aux1,
-        )])?;
+        // the priorities of the files are saved next to the pieces of the torrent
+        let file_selection = Arc::new(FileSelection::new(
+            metainfo.info.torrent_files(),
+            metainfo.info.piece_length,
+            metainfo.info.is_multi_file(),
+            format!("{}.resume", download_pieces_path),
+        )?);
+        events.publish(
+            &torrent_name,
+            ClientEvent::Metadata {
+                name: metainfo.info.name.clone(),
+                total_length: metainfo.info.total_length(),
+                files: file_selection.clone(),
+            },
+        );
 
-        let announce_url = String::from_utf8(torrent_data["url"].clone())?;
+        let announce_url = metainfo
+            .announce_url()
+            .ok_or_else(|| ClientError::new("The torrent has no tracker".to_string()))?
+            .to_string();
 
-        let mut info = HashMap::new();
-        info.insert(String::from("URL"), announce_url);
-        info.insert(String::from("peer_id"), id.clone());
-        info.insert(String::from("port"), format!("{}", port));
-        info.insert(String::from("uploaded"), format!("{}", uploaded));
-        info.insert(String::from("downloaded"), format!("{}", downloaded));
-        info.insert(String::from("left"), format!("{}", left));
-        info.insert(String::from("event"), event.clone());
+        let request = AnnounceRequest {
+            url: announce_url,
+            info_hash: metainfo.info_hash(),
+            uploaded,
+            downloaded,
+            left,
+            event: event.clone(),
+        };
 
         let log_path_aux = format!("{}/{}_log.txt", log_path, real_name);
 
         let (logger_sender, logger_receiver) = channel();
-        let mut logger = Logger::new(log_path_aux, logger_receiver)?;
+        let mut logger =
+            Logger::new(log_path_aux, logger_receiver)?.with_level(config.storage.log_level);
         let _logger_handler = spawn(move || {
             let _r = logger.start();
         });
 
-        let tracker = Tracker::create(
-            info,
-            torrent_data["info_hash"].clone(),
-            logger_sender.clone(),
-        )?;
+        // the announcer of the session adds the peer id and the address announced
+        let tracker = match session.announcer.announce(request, logger_sender.clone()) {
+            Ok(tracker) => tracker,
+            Err(e) => {
+                events.publish(
+                    &torrent_name,
+                    ClientEvent::Tracker(TrackerStatus::Failed(e.to_string())),
+                );
+                return Err(ClientError::from(e));
+            }
+        };
 
         let (upload_sender, upload_receiver) = channel();
+        let (incoming_sender, incoming_receiver) = channel();
 
         let peers = tracker.get_peers()?;
 
-        sender_client.lock()?.send(vec![(
-            UPDATE_PEERS_NUMBER,
-            UiParams::Usize(peers.len()),
-            torrent_name_aux2.clone(),
-        )])?;
+        events.publish(
+            &torrent_name,
+            ClientEvent::Tracker(TrackerStatus::Announced { peers: peers.len() }),
+        );
 
-        let mut peers_conn = Vec::new();
+        // the limiter of the torrent is nested in the one of the session, and the one of each peer in it
+        let rate_limiter = session.rate_limiter.child();
+        // the meters are nested the same way
+        let rate_meter = session.rate_meter.child();
+        let peer_list = PeerList::new(
+            tracker.get_info_hash(),
+            id.clone(),
+            &session,
+            rate_limiter.clone(),
+            rate_meter.clone(),
+            logger_sender.clone(),
+            upload_sender.clone(),
+        );
         for peer in peers {
-            let peer_conn = Arc::new(PeerConnection::new(
-                peer.clone(),
-                tracker.get_info_hash().clone(),
-                id.clone(),
-                Arc::new(Mutex::new(TCP::create())),
-                Arc::new(Mutex::new(logger_sender.clone())),
-                Arc::new(Mutex::new(upload_sender.clone())),
-            ));
-            peers_conn.push(peer_conn);
+            peer_list.add(peer.ip, peer.port);
         }
 
         let client = Arc::new(Client {
@@ -180,18 +301,35 @@ impl ClientInterface for Client {
             downloaded,
             left,
             logger_sender: Arc::new(Mutex::new(logger_sender)),
-            torrent_name: torrent_name_aux2,
+            torrent_name,
             event,
-            port,
+            port: announced.port,
             tracker,
-            peers: Arc::new(RwLock::new(peers_conn)),
-            pieces: torrent_data["pieces"].clone(),
-            pieces_length: RwLock::new(vecu8_to_u64(&torrent_data["piece length"])),
-            file_length: RwLock::new(vecu8_to_u64(&torrent_data["length"]) as u64),
-            sender_client,
+            peers: peer_list.peers.clone(),
+            pieces: metainfo.info.pieces_hash(),
+            pieces_length: RwLock::new(metainfo.info.piece_length),
+            file_length: RwLock::new(metainfo.info.total_length()),
+            events,
             upload_sender: Arc::new(Mutex::new(upload_sender)),
             upload_receiver: Arc::new(Mutex::new(upload_receiver)),
-            port_listener,
+            incoming_sender,
+            incoming_receiver: Arc::new(Mutex::new(incoming_receiver)),
+            state: Arc::new(RwLock::new(TorrentState::Running)),
+            disk_cache: Arc::new(DiskCache::new(
+                download_pieces_path,
+                metainfo.info.piece_length,
+                cache_size,
+            )),
+            file_selection,
+            piece_picker: Arc::new(RwLock::new(PiecePicker::new(download_mode))),
+            rate_limiter,
+            rate_meter,
+            connection_manager: ConnectionManager::new(
+                config.network.max_connect_attempts,
+                session.half_open.clone(),
+            ),
+            session,
+            peer_list,
         });
         Ok((client, _logger_handler))
     }
@@ -213,28 +351,41 @@ impl ClientInterface for Client {
             upload_sender: self.upload_sender.clone(),
             torrent_name: self.torrent_name.clone(),
             file_length: *self.file_length.read()?,
-            ui_sender: self.sender_client.clone(),
+            events: self.events.clone(),
+            disk_cache: self.disk_cache.clone(),
+            file_selection: self.file_selection.clone(),
+            piece_picker: self.piece_picker.clone(),
+            state: self.state.clone(),
+            connections: self.session.connections.clone(),
+            connection_manager: self.connection_manager.clone(),
         };
         let download_manager = DownloadManager::new(downloader_info)?;
         let listener_channel = mpsc::channel();
         let bitfield = download_manager.bitfield.clone();
+        let choker = Choker::new(bitfield.clone());
         let listener = Listener::new(
-            format!("127.0.0.1:{}", self.port_listener).as_str(),
+            self.incoming_receiver.clone(),
+            self.state.clone(),
             bitfield.clone(),
             Arc::new(Mutex::new(listener_channel.1)),
             self.logger_sender.clone(),
             self.upload_sender.clone(),
             self.id.clone(),
             self.get_info_hash(),
-            self.sender_client.clone(),
+            self.events.clone(),
             self.torrent_name.clone(),
+            self.rate_limiter.clone(),
+            self.rate_meter.clone(),
+            choker.clone(),
+            self.session.event_loop.clone(),
         )?;
         let upload_manager = UploadManager::new(
             self.logger_sender.clone().lock()?.clone(),
-            self.download_pieces_path.lock()?.clone(),
+            self.disk_cache.clone(),
             bitfield,
             self.upload_receiver.clone(),
             Arc::new(Mutex::new(listener_channel.0)),
+            choker,
         );
 
         let download_handle = spawn(move || {
@@ -244,11 +395,11 @@ impl ClientInterface for Client {
             let _r = listener.listen();
         });
 
-        let sender_client_cp = self.sender_client.clone();
+        let events_cp = self.events.clone();
         let torrent_name_cp = self.torrent_name.clone();
 
         let upload_handle = spawn(move || {
-            let _r = upload_manager.start_uploader(sender_client_cp, torrent_name_cp);
+            let _r = upload_manager.start_uploader(events_cp, torrent_name_cp);
         });
 
         Ok((download_handle, listener_handle, upload_handle))
@@ -257,4 +408,39 @@ impl ClientInterface for Client {
     fn get_info_hash(&self) -> Vec<u8> {
         self.tracker.get_info_hash()
     }
+
+    /// Returns the state shared with the session, which pauses or stops the torrent.
+    fn get_state(&self) -> Arc<RwLock<TorrentState>> {
+        self.state.clone()
+    }
+
+    /// Returns where the session sends the incoming connections of this torrent.
+    fn get_incoming_sender(&self) -> Sender<IncomingConnection> {
+        self.incoming_sender.clone()
+    }
+
+    /// Returns the priorities of the files, which the session changes while downloading.
+    fn get_file_selection(&self) -> Arc<FileSelection> {
+        self.file_selection.clone()
+    }
+
+    /// Returns the limiter of the torrent, the parent of the limiters of its peers.
+    fn get_rate_limiter(&self) -> Arc<RateLimiter> {
+        self.rate_limiter.clone()
+    }
+
+    /// Returns the meter of the torrent, the parent of the meters of its peers.
+    fn get_rate_meter(&self) -> Arc<TransferMeter> {
+        self.rate_meter.clone()
+    }
+
+    /// Returns the peers of the torrent, which the session adds the ones it finds to.
+    fn get_peer_list(&self) -> PeerList {
+        self.peer_list.clone()
+    }
+
+    /// Returns the piece picker of the torrent, the session changes its mode and playback position.
+    fn get_piece_picker(&self) -> Arc<RwLock<PiecePicker>> {
+        self.piece_picker.clone()
+    }
 }
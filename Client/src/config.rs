@@ -0,0 +1,666 @@
+use crate::{
+    errors::config_error::ConfigError,
+    parsing::config_parser::config_parse,
+    piece_picker::DownloadMode,
+    utilities::constants::{
+        DISK_CACHE_SIZE, LISTENING_PORT, LOOKAHEAD_WINDOW, MAX_CONNECTIONS, MAX_CONNECT_ATTEMPTS,
+        MAX_HALF_OPEN, MAX_TORRENT_SIZE, NUMBER_OF_PEERS_TO_ORDER, PORT_RETRIES, TORRENTS_PATH,
+    },
+};
+use std::{
+    env,
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::PathBuf,
+    str::FromStr,
+};
+
+/// Environment variables starting with it override the file: CRABRAVE_NETWORK_PORT sets network.port.
+const ENV_PREFIX: &str = "CRABRAVE_";
+
+/// The keys of the configuration, in their sections. Configuration files written before the sections
+/// existed, and the flags of the command line, use the key without its section.
+const KEYS: [&str; 34] = [
+    "network.port",
+    "network.bind_addresses",
+    "network.port_retries",
+    "network.external_ip",
+    "network.external_port",
+    "network.port_mapping",
+    "network.local_discovery",
+    "network.encryption",
+    "network.max_connections",
+    "network.max_half_open",
+    "network.max_connect_attempts",
+    "network.rpc_port",
+    "network.rpc_token",
+    "limits.max_download_rate",
+    "limits.max_upload_rate",
+    "limits.max_peer_download_rate",
+    "limits.max_peer_upload_rate",
+    "limits.schedule",
+    "limits.scheduled_download_rate",
+    "limits.scheduled_upload_rate",
+    "storage.download_path",
+    "storage.log_path",
+    "storage.log_level",
+    "storage.torrents_path",
+    "storage.cache_size",
+    "storage.max_torrent_size",
+    "download.mode",
+    "download.lookahead_window",
+    "seeding.share_ratio",
+    "seeding.seed_time",
+    "seeding.goal_action",
+    "seeding.max_active_downloads",
+    "seeding.max_active_seeds",
+    "tracker.numwant",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkConfig {
+    /// The port peers connect to, 0 picks a free one.
+    pub port: u16,
+    /// The addresses listened on, all with the same port. `0.0.0.0` is every IPv4 interface and `::`
+    /// every IPv6 one.
+    pub bind_addresses: Vec<IpAddr>,
+    /// Times a free port is picked instead when the port is taken, 0 fails at once.
+    pub port_retries: usize,
+    /// What the trackers are told instead of the address and the port listened on, when a NAT forwards
+    /// them to the client.
+    pub external_ip: Option<IpAddr>,
+    pub external_port: Option<u16>,
+    /// Whether the port is mapped on the gateway with UPnP, NAT-PMP or PCP.
+    pub port_mapping: bool,
+    /// Whether the torrents are announced to the local network, and the peers announced there connected to.
+    pub local_discovery: bool,
+    /// Whether the peer connections are encrypted with MSE.
+    pub encryption: EncryptionPolicy,
+    /// The peer connections of every torrent of the session together.
+    pub max_connections: usize,
+    /// The connections of the session being opened at once, from the TCP connect to the end of the handshake.
+    pub max_half_open: usize,
+    /// The peers each torrent tries to connect to at once.
+    pub max_connect_attempts: usize,
+    /// The JSON-RPC server only starts if it is set, and it requires the token.
+    pub rpc_port: Option<u16>,
+    pub rpc_token: Option<String>,
+}
+
+/// Bytes per second, 0 is unlimited. They are reloaded while the client runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LimitsConfig {
+    /// The whole session, out of the schedule.
+    pub max_download_rate: u64,
+    pub max_upload_rate: u64,
+    /// Each peer connection.
+    pub max_peer_download_rate: u64,
+    pub max_peer_upload_rate: u64,
+    /// While it lasts the scheduled rates replace the max rates of the session.
+    pub schedule: Option<LimitSchedule>,
+    pub scheduled_download_rate: u64,
+    pub scheduled_upload_rate: u64,
+}
+
+/// A part of the day in local time, written as 08:00-18:00. It ends on the next day if the end is
+/// before the start, like 22:00-07:00.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitSchedule {
+    /// Minutes since midnight.
+    pub start: u32,
+    pub end: u32,
+}
+
+impl LimitSchedule {
+    /// Returns whether the minute of the day is in the schedule.
+    pub fn contains(&self, minute: u32) -> bool {
+        match self.start <= self.end {
+            true => self.start <= minute && minute < self.end,
+            false => minute >= self.start || minute < self.end,
+        }
+    }
+}
+
+impl LimitsConfig {
+    /// Returns the download and upload rates of the session at the minute of the day.
+    pub fn session_rates(&self, minute: u32) -> (u64, u64) {
+        match self.schedule {
+            Some(schedule) if schedule.contains(minute) => {
+                (self.scheduled_download_rate, self.scheduled_upload_rate)
+            }
+            _ => (self.max_download_rate, self.max_upload_rate),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageConfig {
+    pub download_path: String,
+    pub log_path: String,
+    /// 0 turns the logs of the torrents off.
+    pub log_level: u8,
+    /// Where the torrent files added through the RPC server are saved.
+    pub torrents_path: String,
+    /// Bytes of pieces kept in memory by the disk cache of each torrent.
+    pub cache_size: usize,
+    /// Torrent files bigger than it are not read.
+    pub max_torrent_size: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadConfig {
+    /// The lookahead of Sequential is the lookahead_window.
+    pub mode: DownloadMode,
+    pub lookahead_window: usize,
+}
+
+/// When complete torrents stop seeding and how many torrents run at once. 0 is no goal or no limit.
+/// They are reloaded while the client runs.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SeedingConfig {
+    /// Bytes uploaded over the length of the torrent.
+    pub share_ratio: f64,
+    /// Minutes spent seeding.
+    pub seed_time: u64,
+    /// What is done with a torrent once it reaches one of the goals.
+    pub goal_action: GoalAction,
+    /// The torrents beyond them are queued until one of the active ones ends.
+    pub max_active_downloads: usize,
+    pub max_active_seeds: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GoalAction {
+    #[default]
+    Pause,
+    Remove,
+}
+
+/// How the peer connections are encrypted (MSE). Disabled ones are plaintext; enabled ones are encrypted
+/// when the peer can, falling back to plaintext; forced ones are always encrypted, peers that can not are
+/// not talked to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EncryptionPolicy {
+    Disabled,
+    #[default]
+    Enabled,
+    Forced,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackerConfig {
+    /// How many peers are asked for in each announce.
+    pub numwant: usize,
+}
+
+/// The configuration of the client. Every value has a default, which the configuration file,
+/// the environment and the flags of the command line override in that order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub network: NetworkConfig,
+    pub limits: LimitsConfig,
+    pub storage: StorageConfig,
+    pub download: DownloadConfig,
+    pub seeding: SeedingConfig,
+    pub tracker: TrackerConfig,
+    source: Option<PathBuf>,
+    overrides: Vec<(String, String)>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            network: NetworkConfig {
+                port: LISTENING_PORT,
+                bind_addresses: vec![
+                    IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                    IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+                ],
+                port_retries: PORT_RETRIES,
+                external_ip: None,
+                external_port: None,
+                port_mapping: true,
+                local_discovery: true,
+                encryption: EncryptionPolicy::Enabled,
+                max_connections: MAX_CONNECTIONS,
+                max_half_open: MAX_HALF_OPEN,
+                max_connect_attempts: MAX_CONNECT_ATTEMPTS,
+                rpc_port: None,
+                rpc_token: None,
+            },
+            limits: LimitsConfig::default(),
+            storage: StorageConfig {
+                download_path: "src/downloads".to_string(),
+                log_path: "src/reports".to_string(),
+                log_level: 1,
+                torrents_path: TORRENTS_PATH.to_string(),
+                cache_size: DISK_CACHE_SIZE,
+                max_torrent_size: MAX_TORRENT_SIZE,
+            },
+            download: DownloadConfig {
+                mode: DownloadMode::RarestFirst,
+                lookahead_window: LOOKAHEAD_WINDOW,
+            },
+            seeding: SeedingConfig::default(),
+            tracker: TrackerConfig {
+                numwant: NUMBER_OF_PEERS_TO_ORDER,
+            },
+            source: None,
+            overrides: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration file at path and then the environment.
+    pub fn load(path: &str) -> Result<Config, ConfigError> {
+        let mut config = Config {
+            source: Some(PathBuf::from(path)),
+            ..Config::default()
+        };
+        config.read_source()?;
+        config.read_env(env::vars())?;
+        Ok(config)
+    }
+
+    /// Reads the environment over the defaults, for when there is no configuration file.
+    pub fn from_env() -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        config.read_env(env::vars())?;
+        Ok(config)
+    }
+
+    /// Reads the configuration again from the same file and environment, and keeps the values set with set.
+    pub fn reload(&self) -> Result<Config, ConfigError> {
+        let mut config = Config {
+            source: self.source.clone(),
+            ..Config::default()
+        };
+        config.read_source()?;
+        config.read_env(env::vars())?;
+        for (key, value) in &self.overrides {
+            config.set(key, value)?;
+        }
+        Ok(config)
+    }
+
+    /// Returns the configuration file it was loaded from.
+    pub fn source(&self) -> Option<&PathBuf> {
+        self.source.as_ref()
+    }
+
+    /// Sets a value with or without its section, `network.port` or `port`. The value is kept on reload.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        self.apply(key, value)?;
+        self.overrides.retain(|(set_key, _)| set_key != key);
+        self.overrides.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+
+    /// Returns the order the pieces are requested in.
+    pub fn download_mode(&self) -> DownloadMode {
+        match self.download.mode {
+            DownloadMode::Sequential { .. } => DownloadMode::Sequential {
+                lookahead: self.download.lookahead_window,
+            },
+            mode => mode,
+        }
+    }
+
+    fn read_source(&mut self) -> Result<(), ConfigError> {
+        if let Some(path) = &self.source {
+            let values = config_parse(path.to_string_lossy().to_string())?;
+            for (key, value) in values {
+                self.apply(&key, &value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_env(
+        &mut self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<(), ConfigError> {
+        for (name, value) in vars {
+            let key = match name.strip_prefix(ENV_PREFIX) {
+                Some(key) => key.to_lowercase(),
+                None => continue,
+            };
+            let key = KEYS
+                .iter()
+                .find(|known| known.replacen('.', "_", 1) == key)
+                .ok_or_else(|| {
+                    ConfigError::new(format!("ConfigError: unknown variable {}", name))
+                })?;
+            self.apply(key, &value)?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let key = full_key(key)?;
+        match key {
+            "network.port" => self.network.port = parse(key, value)?,
+            "network.bind_addresses" => self.network.bind_addresses = parse_addresses(key, value)?,
+            "network.port_retries" => self.network.port_retries = parse(key, value)?,
+            "network.external_ip" => self.network.external_ip = Some(parse(key, value)?),
+            "network.external_port" => self.network.external_port = Some(parse(key, value)?),
+            "network.port_mapping" => self.network.port_mapping = parse(key, value)?,
+            "network.local_discovery" => self.network.local_discovery = parse(key, value)?,
+            "network.encryption" => {
+                self.network.encryption = match value {
+                    "disabled" => EncryptionPolicy::Disabled,
+                    "enabled" => EncryptionPolicy::Enabled,
+                    "forced" => EncryptionPolicy::Forced,
+                    _ => return Err(invalid(key, value, "expected disabled, enabled or forced")),
+                }
+            }
+            "network.max_connections" => self.network.max_connections = parse_positive(key, value)?,
+            "network.max_half_open" => self.network.max_half_open = parse_positive(key, value)?,
+            "network.max_connect_attempts" => {
+                self.network.max_connect_attempts = parse_positive(key, value)?
+            }
+            "network.rpc_port" => self.network.rpc_port = Some(parse(key, value)?),
+            "network.rpc_token" => self.network.rpc_token = Some(not_empty(key, value)?),
+            "limits.max_download_rate" => self.limits.max_download_rate = parse(key, value)?,
+            "limits.max_upload_rate" => self.limits.max_upload_rate = parse(key, value)?,
+            "limits.max_peer_download_rate" => {
+                self.limits.max_peer_download_rate = parse(key, value)?
+            }
+            "limits.max_peer_upload_rate" => self.limits.max_peer_upload_rate = parse(key, value)?,
+            "limits.schedule" => self.limits.schedule = Some(parse_schedule(key, value)?),
+            "limits.scheduled_download_rate" => {
+                self.limits.scheduled_download_rate = parse(key, value)?
+            }
+            "limits.scheduled_upload_rate" => {
+                self.limits.scheduled_upload_rate = parse(key, value)?
+            }
+            "storage.download_path" => self.storage.download_path = not_empty(key, value)?,
+            "storage.log_path" => self.storage.log_path = not_empty(key, value)?,
+            "storage.log_level" => self.storage.log_level = parse(key, value)?,
+            "storage.torrents_path" => self.storage.torrents_path = not_empty(key, value)?,
+            "storage.cache_size" => self.storage.cache_size = parse_positive(key, value)?,
+            "storage.max_torrent_size" => {
+                self.storage.max_torrent_size = parse_positive(key, value)?
+            }
+            "download.mode" => {
+                self.download.mode = match value {
+                    "rarest_first" => DownloadMode::RarestFirst,
+                    "sequential" => DownloadMode::Sequential { lookahead: 0 },
+                    "first_last" => DownloadMode::FirstAndLast,
+                    _ => {
+                        return Err(invalid(
+                            key,
+                            value,
+                            "expected rarest_first, sequential or first_last",
+                        ))
+                    }
+                }
+            }
+            "download.lookahead_window" => {
+                self.download.lookahead_window = parse_positive(key, value)?
+            }
+            "seeding.share_ratio" => {
+                self.seeding.share_ratio = match parse::<f64>(key, value)? {
+                    ratio if ratio.is_finite() && ratio >= 0.0 => ratio,
+                    _ => return Err(invalid(key, value, "it must be 0 or more")),
+                }
+            }
+            "seeding.seed_time" => self.seeding.seed_time = parse(key, value)?,
+            "seeding.goal_action" => {
+                self.seeding.goal_action = match value {
+                    "pause" => GoalAction::Pause,
+                    "remove" => GoalAction::Remove,
+                    _ => return Err(invalid(key, value, "expected pause or remove")),
+                }
+            }
+            "seeding.max_active_downloads" => {
+                self.seeding.max_active_downloads = parse(key, value)?
+            }
+            "seeding.max_active_seeds" => self.seeding.max_active_seeds = parse(key, value)?,
+            "tracker.numwant" => self.tracker.numwant = parse_positive(key, value)?,
+            _ => {
+                return Err(ConfigError::new(format!(
+                    "ConfigError: unknown key {}",
+                    key
+                )))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the key with its section, failing for keys that do not exist.
+fn full_key(key: &str) -> Result<&'static str, ConfigError> {
+    KEYS.iter()
+        .find(|known| **known == key || known.split_once('.').map(|(_, name)| name) == Some(key))
+        .copied()
+        .ok_or_else(|| ConfigError::new(format!("ConfigError: unknown key {}", key)))
+}
+
+fn invalid(key: &str, value: &str, reason: &str) -> ConfigError {
+    ConfigError::new(format!(
+        "ConfigError: invalid {} \"{}\" ({})",
+        key, value, reason
+    ))
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, ConfigError>
+where
+    T::Err: Display,
+{
+    value
+        .parse::<T>()
+        .map_err(|e| invalid(key, value, &e.to_string()))
+}
+
+fn parse_positive(key: &str, value: &str) -> Result<usize, ConfigError> {
+    match parse::<usize>(key, value)? {
+        0 => Err(invalid(key, value, "it must be greater than 0")),
+        number => Ok(number),
+    }
+}
+
+/// Parses a schedule written as HH:MM-HH:MM.
+fn parse_schedule(key: &str, value: &str) -> Result<LimitSchedule, ConfigError> {
+    let minute = |time: &str| -> Option<u32> {
+        let (hours, minutes) = time.trim().split_once(':')?;
+        let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+        (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+    };
+    let schedule = value
+        .split_once('-')
+        .and_then(|(start, end)| Some((minute(start)?, minute(end)?)));
+    match schedule {
+        Some((start, end)) if start != end => Ok(LimitSchedule { start, end }),
+        _ => Err(invalid(
+            key,
+            value,
+            "expected a time of the day like 22:00-07:00",
+        )),
+    }
+}
+
+/// Parses a list of ip addresses separated by commas, IPv6 ones with or without brackets.
+fn parse_addresses(key: &str, value: &str) -> Result<Vec<IpAddr>, ConfigError> {
+    value
+        .split(',')
+        .map(|address| {
+            let address = address.trim();
+            parse(key, address.trim_start_matches('[').trim_end_matches(']'))
+        })
+        .collect()
+}
+
+fn not_empty(key: &str, value: &str) -> Result<String, ConfigError> {
+    match value.is_empty() {
+        true => Err(invalid(key, value, "it can not be empty")),
+        false => Ok(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::test_helper::test_dir;
+    use std::fs;
+
+    fn config_file(name: &str, contents: &str) -> String {
+        let path = test_dir("config", name).join("config.yml");
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_sections_and_keys_without_section() {
+        let path = config_file(
+            "sections",
+            "port: 443\nlog_level: 5\nnetwork:\n  max_connections: 20\nlimits:\n  max_upload_rate: 1024\ndownload:\n  mode: sequential\n  lookahead_window: 5\n",
+        );
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.network.port, 443);
+        assert_eq!(config.network.max_connections, 20);
+        assert_eq!(config.storage.log_level, 5);
+        assert_eq!(config.limits.max_upload_rate, 1024);
+        assert_eq!(config.limits.max_download_rate, 0);
+        assert_eq!(
+            config.download_mode(),
+            DownloadMode::Sequential { lookahead: 5 }
+        );
+        assert_eq!(config.storage.cache_size, DISK_CACHE_SIZE);
+        assert_eq!(config.storage.max_torrent_size, MAX_TORRENT_SIZE);
+    }
+
+    #[test]
+    fn test_errors_name_the_key() {
+        let path = config_file("bad_port", "network:\n  port: 70000\n");
+        let error = Config::load(&path).unwrap_err().to_string();
+        assert!(error.contains("network.port \"70000\""));
+
+        let path = config_file("unknown", "storage:\n  download_pth: a\n");
+        let error = Config::load(&path).unwrap_err().to_string();
+        assert!(error.contains("unknown key storage.download_pth"));
+
+        let mut config = Config::default();
+        assert!(config.set("cache_size", "0").is_err());
+        assert!(config.set("mode", "random").is_err());
+    }
+
+    #[test]
+    fn test_every_key_is_applied() {
+        let mut config = Config::default();
+        for key in KEYS {
+            if let Err(error) = config.apply(key, "") {
+                assert!(!error.to_string().contains("unknown key"), "{}", key);
+            }
+        }
+        config.apply("numwant", "30").unwrap();
+        assert_eq!(config.tracker.numwant, 30);
+    }
+
+    #[test]
+    fn test_environment_overrides_the_file() {
+        let mut config = Config::default();
+        config
+            .read_env(
+                [
+                    ("HOME".to_string(), "/root".to_string()),
+                    ("CRABRAVE_NETWORK_PORT".to_string(), "7000".to_string()),
+                    ("CRABRAVE_STORAGE_LOG_PATH".to_string(), "logs".to_string()),
+                ]
+                .into_iter(),
+            )
+            .unwrap();
+        assert_eq!(config.network.port, 7000);
+        assert_eq!(config.storage.log_path, "logs");
+
+        let unknown = [("CRABRAVE_PORT".to_string(), "7000".to_string())];
+        assert!(config.read_env(unknown.into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_scheduled_limits() {
+        let mut config = Config::default();
+        config.set("max_download_rate", "1000").unwrap();
+        config.set("schedule", "22:00-07:30").unwrap();
+        config.set("scheduled_download_rate", "5000").unwrap();
+        let limits = config.limits;
+        assert_eq!(limits.session_rates(21 * 60 + 59), (1000, 0));
+        assert_eq!(limits.session_rates(23 * 60), (5000, 0));
+        assert_eq!(limits.session_rates(7 * 60 + 29), (5000, 0));
+        assert_eq!(limits.session_rates(7 * 60 + 30), (1000, 0));
+
+        assert!(config.set("schedule", "25:00-07:00").is_err());
+        assert!(config.set("schedule", "08:00").is_err());
+        assert!(config.set("schedule", "08:00-08:00").is_err());
+    }
+
+    #[test]
+    fn test_seeding_section() {
+        let path = config_file(
+            "seeding",
+            "seeding:\n  share_ratio: 1.5\n  seed_time: 90\n  goal_action: remove\n  max_active_seeds: 2\n",
+        );
+        let seeding = Config::load(&path).unwrap().seeding;
+        assert_eq!(seeding.share_ratio, 1.5);
+        assert_eq!(seeding.seed_time, 90);
+        assert_eq!(seeding.goal_action, GoalAction::Remove);
+        assert_eq!(seeding.max_active_downloads, 0);
+        assert_eq!(seeding.max_active_seeds, 2);
+
+        let mut config = Config::default();
+        assert!(config.set("share_ratio", "-1").is_err());
+        assert!(config.set("goal_action", "delete").is_err());
+    }
+
+    #[test]
+    fn test_network_addresses() {
+        let path = config_file(
+            "network",
+            "network:\n  bind_addresses: 0.0.0.0, [::1]\n  external_ip: 203.0.113.7\n  external_port: 51413\n",
+        );
+        let network = Config::load(&path).unwrap().network;
+        assert_eq!(
+            network.bind_addresses,
+            vec![
+                IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                IpAddr::V6(Ipv6Addr::LOCALHOST)
+            ]
+        );
+        assert_eq!(network.external_ip, Some(IpAddr::from([203, 0, 113, 7])));
+        assert_eq!(network.external_port, Some(51413));
+        assert_eq!(network.port_retries, PORT_RETRIES);
+        assert!(network.port_mapping);
+        assert!(network.local_discovery);
+        assert_eq!(network.encryption, EncryptionPolicy::Enabled);
+
+        let mut config = Config::default();
+        assert!(config.set("bind_addresses", "0.0.0.0,").is_err());
+        assert!(config.set("bind_addresses", "localhost").is_err());
+        assert!(config.set("external_ip", "1.2.3").is_err());
+        config.set("port_mapping", "false").unwrap();
+        assert!(!config.network.port_mapping);
+        assert!(config.set("port_mapping", "no").is_err());
+        config.set("local_discovery", "false").unwrap();
+        assert!(!config.network.local_discovery);
+        config.set("encryption", "forced").unwrap();
+        assert_eq!(config.network.encryption, EncryptionPolicy::Forced);
+        assert!(config.set("encryption", "rc4").is_err());
+    }
+
+    #[test]
+    fn test_reload_keeps_the_values_set() {
+        let path = config_file("reload", "limits:\n  max_download_rate: 100\n");
+        let mut config = Config::load(&path).unwrap();
+        config.set("max_upload_rate", "50").unwrap();
+
+        fs::write(
+            &path,
+            "limits:\n  max_download_rate: 200\n  max_upload_rate: 10\n",
+        )
+        .unwrap();
+        let reloaded = config.reload().unwrap();
+        assert_eq!(reloaded.limits.max_download_rate, 200);
+        assert_eq!(reloaded.limits.max_upload_rate, 50);
+    }
+}
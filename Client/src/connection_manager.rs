@@ -0,0 +1,273 @@
+use crate::session::{ConnectionLimit, ConnectionSlot};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The wait after the first failure to connect to a peer, it doubles with each failure after it.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(30 * 60);
+const HALF_OPEN_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What the ConnectionManager remembers of a peer of the torrent.
+#[derive(Debug, Default)]
+struct PeerRecord {
+    failures: u32,
+    retry_at: Option<Instant>,
+    /// It sent a piece that did not match its hash.
+    banned: bool,
+    /// Being connected to or connected.
+    busy: bool,
+    /// The best download rate of the connections with the peer, bytes per second.
+    best_rate: u64,
+}
+
+#[derive(Debug, Default)]
+struct ManagerState {
+    /// By ip:port.
+    peers: HashMap<String, PeerRecord>,
+    attempts: usize,
+    connected: usize,
+}
+
+/// Chooses which peers of a torrent to connect to. A peer that fails waits longer after each failure,
+/// a peer that sent a bad piece is never connected to again and the peers that were fast before are tried
+/// first. The attempts of the torrent are limited, and the half-open connections of the whole session too:
+/// the ones between the TCP connect and the end of the handshake.
+#[derive(Debug)]
+pub struct ConnectionManager {
+    max_attempts: usize,
+    half_open: Arc<ConnectionLimit>,
+    state: Mutex<ManagerState>,
+}
+
+/// An attempt counted by the ConnectionManager. It is a failure of the peer unless it is connected or abandoned.
+#[derive(Debug)]
+pub struct ConnectAttempt {
+    manager: Arc<ConnectionManager>,
+    address: String,
+    ended: bool,
+}
+
+/// A connected peer, the peer can be connected to again once it is dropped.
+#[derive(Debug)]
+pub struct ActiveConnection {
+    manager: Arc<ConnectionManager>,
+    address: String,
+}
+
+impl ConnectionManager {
+    /// Creates a manager that makes at most max_attempts at once, sharing the half-open limit of the session.
+    pub fn new(max_attempts: usize, half_open: Arc<ConnectionLimit>) -> Arc<ConnectionManager> {
+        Arc::new(ConnectionManager {
+            max_attempts,
+            half_open,
+            state: Mutex::new(ManagerState::default()),
+        })
+    }
+
+    /// Returns the indexes of the addresses worth connecting to now, the best first. Banned peers, the ones
+    /// being connected to or connected and the ones waiting after a failure are left out.
+    pub fn candidates(&self, addresses: &[String]) -> Vec<usize> {
+        self.candidates_at(addresses, Instant::now())
+    }
+
+    /// Returns the peers being connected to and the connected ones.
+    pub fn active(&self) -> usize {
+        let state = self.lock();
+        state.attempts + state.connected
+    }
+
+    /// Starts an attempt to connect to the peer, None if the torrent already makes max_attempts.
+    pub fn start_attempt(self: &Arc<Self>, address: &str) -> Option<ConnectAttempt> {
+        let mut state = self.lock();
+        if state.attempts >= self.max_attempts {
+            return None;
+        }
+        state.attempts += 1;
+        state.peers.entry(address.to_string()).or_default().busy = true;
+        Some(ConnectAttempt {
+            manager: self.clone(),
+            address: address.to_string(),
+            ended: false,
+        })
+    }
+
+    /// Waits up to timeout for a half-open slot of the session.
+    pub fn wait_half_open(&self, timeout: Duration) -> Option<ConnectionSlot> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(slot) = self.half_open.try_acquire() {
+                return Some(slot);
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            thread::sleep(HALF_OPEN_CHECK_INTERVAL);
+        }
+    }
+
+    /// Never connects to the peer again.
+    pub fn ban(&self, address: &str) {
+        self.lock()
+            .peers
+            .entry(address.to_string())
+            .or_default()
+            .banned = true;
+    }
+
+    pub fn is_banned(&self, address: &str) -> bool {
+        self.lock()
+            .peers
+            .get(address)
+            .is_some_and(|peer| peer.banned)
+    }
+
+    fn candidates_at(&self, addresses: &[String], now: Instant) -> Vec<usize> {
+        let state = self.lock();
+        let mut candidates: Vec<(usize, u64, u32)> = addresses
+            .iter()
+            .enumerate()
+            .filter_map(|(index, address)| match state.peers.get(address) {
+                None => Some((index, 0, 0)),
+                Some(peer) if peer.banned || peer.busy => None,
+                Some(peer) if peer.retry_at.is_some_and(|retry_at| retry_at > now) => None,
+                Some(peer) => Some((index, peer.best_rate, peer.failures)),
+            })
+            .collect();
+        // the sort is stable, peers that are alike keep the order of the addresses
+        candidates.sort_by_key(|(_, rate, failures)| (Reverse(*rate), *failures));
+        candidates.into_iter().map(|(index, _, _)| index).collect()
+    }
+
+    fn failed(&self, address: &str, now: Instant) {
+        let mut state = self.lock();
+        state.attempts -= 1;
+        let peer = state.peers.entry(address.to_string()).or_default();
+        peer.busy = false;
+        peer.failures += 1;
+        peer.retry_at = Some(now + backoff(peer.failures));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ManagerState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl ConnectAttempt {
+    /// The handshake went through, the failures of the peer are forgotten.
+    pub fn connected(mut self) -> ActiveConnection {
+        self.ended = true;
+        let mut state = self.manager.lock();
+        state.attempts -= 1;
+        state.connected += 1;
+        let peer = state.peers.entry(self.address.clone()).or_default();
+        peer.failures = 0;
+        peer.retry_at = None;
+        drop(state);
+        ActiveConnection {
+            manager: self.manager.clone(),
+            address: self.address.clone(),
+        }
+    }
+
+    /// Ends the attempt without blaming the peer, like when the session has no half-open slot for it.
+    pub fn abandon(mut self) {
+        self.ended = true;
+        let mut state = self.manager.lock();
+        state.attempts -= 1;
+        state.peers.entry(self.address.clone()).or_default().busy = false;
+    }
+}
+
+impl Drop for ConnectAttempt {
+    fn drop(&mut self) {
+        if !self.ended {
+            self.manager.failed(&self.address, Instant::now());
+        }
+    }
+}
+
+impl ActiveConnection {
+    /// Records the download rate of the connection, the best one of the peer is kept to rank it.
+    pub fn record_rate(&self, bytes_per_second: u64) {
+        let mut state = self.manager.lock();
+        let peer = state.peers.entry(self.address.clone()).or_default();
+        peer.best_rate = peer.best_rate.max(bytes_per_second);
+    }
+}
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        let mut state = self.manager.lock();
+        state.connected -= 1;
+        state.peers.entry(self.address.clone()).or_default().busy = false;
+    }
+}
+
+/// Returns the wait after the failures in a row of a peer.
+fn backoff(failures: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(1 << failures.saturating_sub(1).min(16))
+        .min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addresses() -> Vec<String> {
+        (1..=4).map(|i| format!("10.0.0.{}:6881", i)).collect()
+    }
+
+    #[test]
+    fn test_failed_peers_wait_longer_each_time() {
+        assert_eq!(backoff(1), INITIAL_BACKOFF);
+        assert_eq!(backoff(3), INITIAL_BACKOFF * 4);
+        assert_eq!(backoff(40), MAX_BACKOFF);
+
+        let manager = ConnectionManager::new(10, ConnectionLimit::new(10));
+        let addresses = addresses();
+        // abandoned attempts do not count
+        manager.start_attempt(&addresses[0]).unwrap().abandon();
+        assert_eq!(manager.candidates(&addresses), vec![0, 1, 2, 3]);
+        let start = Instant::now();
+        drop(manager.start_attempt(&addresses[0]).unwrap());
+        assert_eq!(manager.candidates_at(&addresses, start), vec![1, 2, 3]);
+        let later = start + INITIAL_BACKOFF + Duration::from_secs(1);
+        assert_eq!(manager.candidates_at(&addresses, later), vec![1, 2, 3, 0]);
+        assert_eq!(manager.active(), 0);
+    }
+
+    #[test]
+    fn test_fast_peers_first_and_banned_peers_never() {
+        let manager = ConnectionManager::new(10, ConnectionLimit::new(10));
+        let addresses = addresses();
+        let connection = manager.start_attempt(&addresses[2]).unwrap().connected();
+        connection.record_rate(5000);
+        assert_eq!(manager.candidates(&addresses), vec![0, 1, 3]);
+        assert_eq!(manager.active(), 1);
+        drop(connection);
+
+        manager.ban(&addresses[1]);
+        assert!(manager.is_banned(&addresses[1]));
+        assert_eq!(manager.candidates(&addresses), vec![2, 0, 3]);
+    }
+
+    #[test]
+    fn test_attempts_are_limited() {
+        let manager = ConnectionManager::new(2, ConnectionLimit::new(1));
+        let addresses = addresses();
+        let _first = manager.start_attempt(&addresses[0]).unwrap();
+        let _second = manager.start_attempt(&addresses[1]).unwrap();
+        assert!(manager.start_attempt(&addresses[2]).is_none());
+
+        let slot = manager.wait_half_open(Duration::ZERO).unwrap();
+        assert!(manager.wait_half_open(Duration::ZERO).is_none());
+        drop(slot);
+        assert!(manager.wait_half_open(Duration::ZERO).is_some());
+    }
+}
@@ -0,0 +1,432 @@
+use crate::errors::disk_cache_error::DiskCacheError;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// The pieces are stored one after the other in this file of the pieces directory, each one at its index
+/// times the piece length, so adjacent pieces are written together.
+const PIECES_FILE: &str = "pieces.dat";
+/// The indexes of the stored pieces, appended as 4 big endian bytes once their data is written.
+const STORED_FILE: &str = "pieces.stored";
+const INDEX_LEN: usize = 4;
+
+/// The DiskCache keeps pieces in memory between the peers and the pieces stored on disk.
+/// Downloaded pieces are kept as dirty entries and written back in batches, and uploads are served from memory whenever possible.
+pub struct DiskCache {
+    pieces_path: String,
+    piece_length: u64,
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+/// This struct contains the counters exposed to the stats view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub cached_bytes: usize,
+    pub dirty_bytes: usize,
+}
+
+/// A piece stored in the cache. Dirty pieces have not been written to disk yet.
+struct CachedPiece {
+    /// Shared with the write back, which writes it without holding the lock.
+    data: Arc<Vec<u8>>,
+    dirty: bool,
+    last_access: u64,
+}
+
+struct CacheState {
+    pieces: HashMap<u32, CachedPiece>,
+    clock: u64,
+    cached_bytes: usize,
+    dirty_bytes: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl DiskCache {
+    /// Creates a new DiskCache for the pieces stored at pieces_path, holding at most capacity bytes in memory.
+    pub fn new(pieces_path: String, piece_length: u64, capacity: usize) -> DiskCache {
+        DiskCache {
+            pieces_path,
+            piece_length,
+            capacity,
+            state: Mutex::new(CacheState {
+                pieces: HashMap::new(),
+                clock: 0,
+                cached_bytes: 0,
+                dirty_bytes: 0,
+                hits: 0,
+                misses: 0,
+            }),
+        }
+    }
+
+    /// Stores a downloaded piece in memory. Dirty pieces are written back once they fill half of the cache.
+    pub fn write_piece(&self, piece_index: u32, data: Vec<u8>) -> Result<(), DiskCacheError> {
+        let mut state = self.state.lock()?;
+        state.clock += 1;
+        let last_access = state.clock;
+        let len = data.len();
+        if let Some(old) = state.pieces.insert(
+            piece_index,
+            CachedPiece {
+                data: Arc::new(data),
+                dirty: true,
+                last_access,
+            },
+        ) {
+            state.cached_bytes -= old.data.len();
+            if old.dirty {
+                state.dirty_bytes -= old.data.len();
+            }
+        }
+        state.cached_bytes += len;
+        state.dirty_bytes += len;
+        let write_back = state.dirty_bytes >= self.capacity / 2;
+        drop(state);
+
+        if write_back {
+            self.write_back()?;
+        }
+        self.evict()
+    }
+
+    /// Returns length bytes of the piece starting at offset.
+    /// When the first block of a piece is requested and the piece is not cached, the whole piece is read ahead.
+    /// The disk is read without holding the lock.
+    pub fn read_block(
+        &self,
+        piece_index: u32,
+        offset: u32,
+        length: u32,
+    ) -> Result<Vec<u8>, DiskCacheError> {
+        let begin = offset as usize;
+        let end = begin + length as usize;
+        {
+            let mut state = self.state.lock()?;
+            state.clock += 1;
+            let clock = state.clock;
+            if let Some(piece) = state.pieces.get_mut(&piece_index) {
+                piece.last_access = clock;
+                let block = slice_block(&piece.data, begin, end, piece_index)?;
+                state.hits += 1;
+                return Ok(block);
+            }
+            state.misses += 1;
+        }
+
+        if offset != 0 {
+            let mut piece_file = File::open(self.pieces_file())?;
+            // the block ends with its piece, the next one follows it in the file
+            let left = self.piece_length.saturating_sub(offset as u64);
+            let mut block = vec![0; (length as u64).min(left) as usize];
+            piece_file.seek(SeekFrom::Start(
+                piece_index as u64 * self.piece_length + offset as u64,
+            ))?;
+            let read = piece_file.read(&mut block)?;
+            block.truncate(read);
+            return Ok(block);
+        }
+
+        let data = read_piece(&self.pieces_path, self.piece_length, piece_index)?;
+        let block = slice_block(&data, begin, end, piece_index)?;
+        let mut state = self.state.lock()?;
+        // a write of the piece meanwhile is newer than the disk
+        if !state.pieces.contains_key(&piece_index) {
+            state.clock += 1;
+            let last_access = state.clock;
+            state.cached_bytes += data.len();
+            state.pieces.insert(
+                piece_index,
+                CachedPiece {
+                    data: Arc::new(data),
+                    dirty: false,
+                    last_access,
+                },
+            );
+        }
+        drop(state);
+        self.evict()?;
+        Ok(block)
+    }
+
+    /// Writes every dirty piece to disk.
+    pub fn flush(&self) -> Result<(), DiskCacheError> {
+        self.write_back()
+    }
+
+    /// Returns the current hit and miss counters.
+    pub fn stats(&self) -> Result<CacheStats, DiskCacheError> {
+        let state = self.state.lock()?;
+        Ok(CacheStats {
+            hits: state.hits,
+            misses: state.misses,
+            cached_bytes: state.cached_bytes,
+            dirty_bytes: state.dirty_bytes,
+        })
+    }
+
+    /// Writes the dirty pieces to disk, every run of adjacent pieces with a single write. The pieces are
+    /// taken under the lock and written once it is released, reads and writes of the cache go on meanwhile.
+    fn write_back(&self) -> Result<(), DiskCacheError> {
+        let mut dirty: Vec<(u32, Arc<Vec<u8>>)> = self
+            .state
+            .lock()?
+            .pieces
+            .iter()
+            .filter(|(_, piece)| piece.dirty)
+            .map(|(index, piece)| (*index, piece.data.clone()))
+            .collect();
+        dirty.sort_unstable_by_key(|(index, _)| *index);
+
+        let mut run_start = 0;
+        for end in 1..=dirty.len() {
+            // only the last piece of the torrent is shorter, a run can not go on after it
+            let adjacent = end < dirty.len()
+                && dirty[end].0 == dirty[end - 1].0 + 1
+                && dirty[end - 1].1.len() as u64 == self.piece_length;
+            if !adjacent {
+                let run: Vec<&[u8]> = dirty[run_start..end]
+                    .iter()
+                    .map(|(_, data)| data.as_slice())
+                    .collect();
+                store_pieces(
+                    &self.pieces_path,
+                    self.piece_length,
+                    dirty[run_start].0,
+                    &run,
+                )?;
+                run_start = end;
+            }
+        }
+
+        let mut guard = self.state.lock()?;
+        let state = &mut *guard;
+        for (index, data) in dirty {
+            if let Some(piece) = state.pieces.get_mut(&index) {
+                // a piece written again meanwhile is still dirty
+                if piece.dirty && Arc::ptr_eq(&piece.data, &data) {
+                    piece.dirty = false;
+                    state.dirty_bytes -= data.len();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the least recently used pieces until the cache fits in its capacity. The dirty pieces are
+    /// written back if there are no clean ones left to remove.
+    fn evict(&self) -> Result<(), DiskCacheError> {
+        loop {
+            let mut state = self.state.lock()?;
+            while state.cached_bytes > self.capacity {
+                let lru = state
+                    .pieces
+                    .iter()
+                    .filter(|(_, piece)| !piece.dirty)
+                    .min_by_key(|(_, piece)| piece.last_access)
+                    .map(|(index, _)| *index);
+                match lru.and_then(|index| state.pieces.remove(&index)) {
+                    Some(piece) => state.cached_bytes -= piece.data.len(),
+                    None => break,
+                }
+            }
+            if state.cached_bytes <= self.capacity || state.dirty_bytes == 0 {
+                return Ok(());
+            }
+            drop(state);
+            self.write_back()?;
+        }
+    }
+
+    /// Returns the path of the file that stores the pieces.
+    fn pieces_file(&self) -> String {
+        format!("{}/{}", self.pieces_path, PIECES_FILE)
+    }
+}
+
+/// Returns the indexes of the pieces stored at pieces_path, none if nothing was stored yet.
+pub fn stored_pieces(pieces_path: &str) -> Result<HashSet<u32>, DiskCacheError> {
+    let mut indexes = Vec::new();
+    match File::open(Path::new(pieces_path).join(STORED_FILE)) {
+        Ok(mut file) => file.read_to_end(&mut indexes)?,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(DiskCacheError::from(e)),
+    };
+    // an index cut short by a crash was never stored
+    Ok(indexes
+        .chunks_exact(INDEX_LEN)
+        .map(|index| u32::from_be_bytes([index[0], index[1], index[2], index[3]]))
+        .collect())
+}
+
+/// Reads a stored piece, the last piece of the torrent is shorter than piece_length.
+pub fn read_piece(
+    pieces_path: &str,
+    piece_length: u64,
+    piece_index: u32,
+) -> Result<Vec<u8>, DiskCacheError> {
+    let mut file = File::open(Path::new(pieces_path).join(PIECES_FILE))?;
+    file.seek(SeekFrom::Start(piece_index as u64 * piece_length))?;
+    let mut data = Vec::new();
+    file.take(piece_length).read_to_end(&mut data)?;
+    if data.is_empty() {
+        return Err(DiskCacheError::new(format!(
+            "DiskCacheError: piece {} is not stored",
+            piece_index
+        )));
+    }
+    Ok(data)
+}
+
+/// Stores adjacent pieces from first_index on with a single write, then records them as stored.
+pub fn store_pieces(
+    pieces_path: &str,
+    piece_length: u64,
+    first_index: u32,
+    pieces: &[&[u8]],
+) -> Result<(), DiskCacheError> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(Path::new(pieces_path).join(PIECES_FILE))?;
+    file.seek(SeekFrom::Start(first_index as u64 * piece_length))?;
+    file.write_all(&pieces.concat())?;
+    file.flush()?;
+
+    let mut stored = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(Path::new(pieces_path).join(STORED_FILE))?;
+    let mut indexes = Vec::with_capacity(pieces.len() * INDEX_LEN);
+    for index in first_index..first_index + pieces.len() as u32 {
+        indexes.extend(index.to_be_bytes());
+    }
+    stored.write_all(&indexes)?;
+    stored.flush()?;
+    Ok(())
+}
+
+/// Returns a copy of data[begin..end], failing if the block is outside of the piece.
+fn slice_block(
+    data: &[u8],
+    begin: usize,
+    end: usize,
+    piece_index: u32,
+) -> Result<Vec<u8>, DiskCacheError> {
+    if begin > data.len() {
+        return Err(DiskCacheError::new(format!(
+            "DiskCacheError: offset {} out of piece {}",
+            begin, piece_index
+        )));
+    }
+    Ok(data[begin..end.min(data.len())].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::test_helper::test_dir;
+
+    #[test]
+    fn test_read_ahead_counts_hits_and_misses() {
+        let dir = test_dir("disk_cache", "read_ahead").display().to_string();
+        store_pieces(&dir, 64, 0, &[&[7u8; 64]]).unwrap();
+        let cache = DiskCache::new(dir, 64, 1024);
+
+        assert_eq!(cache.read_block(0, 0, 16).unwrap(), vec![7u8; 16]);
+        assert_eq!(cache.read_block(0, 16, 16).unwrap(), vec![7u8; 16]);
+        assert_eq!(cache.read_block(0, 48, 32).unwrap(), vec![7u8; 16]);
+
+        let stats = cache.stats().unwrap();
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.cached_bytes, 64);
+    }
+
+    #[test]
+    fn test_blocks_read_from_disk_end_with_their_piece() {
+        let dir = test_dir("disk_cache", "block_end").display().to_string();
+        store_pieces(&dir, 8, 0, &[&[0u8; 8], &[1u8; 8]]).unwrap();
+        let cache = DiskCache::new(dir, 8, 1024);
+
+        assert_eq!(cache.read_block(0, 4, 16).unwrap(), vec![0u8; 4]);
+        assert_eq!(cache.read_block(1, 4, 2).unwrap(), vec![1u8; 2]);
+    }
+
+    #[test]
+    fn test_dirty_pieces_are_served_before_flush() {
+        let dir = test_dir("disk_cache", "dirty").display().to_string();
+        let cache = DiskCache::new(dir.clone(), 4, 1024);
+        cache.write_piece(3, vec![1, 2, 3, 4]).unwrap();
+
+        assert!(stored_pieces(&dir).unwrap().is_empty());
+        assert_eq!(cache.read_block(3, 1, 2).unwrap(), vec![2, 3]);
+
+        cache.flush().unwrap();
+        assert_eq!(read_piece(&dir, 4, 3).unwrap(), vec![1, 2, 3, 4]);
+        assert_eq!(cache.stats().unwrap().dirty_bytes, 0);
+    }
+
+    #[test]
+    fn test_write_back_when_half_full() {
+        let dir = test_dir("disk_cache", "write_back").display().to_string();
+        let cache = DiskCache::new(dir.clone(), 4, 16);
+        cache.write_piece(0, vec![0; 4]).unwrap();
+        assert_eq!(cache.stats().unwrap().dirty_bytes, 4);
+        cache.write_piece(1, vec![1; 4]).unwrap();
+
+        assert_eq!(cache.stats().unwrap().dirty_bytes, 0);
+        assert_eq!(stored_pieces(&dir).unwrap(), HashSet::from([0, 1]));
+    }
+
+    #[test]
+    fn test_adjacent_pieces_are_written_together() {
+        let dir = test_dir("disk_cache", "coalesce").display().to_string();
+        let cache = DiskCache::new(dir.clone(), 4, 1024);
+        // pieces 0 to 2 are one run, the last piece of the torrent is shorter
+        for (index, data) in [
+            (2, vec![2; 4]),
+            (0, vec![0; 4]),
+            (5, vec![5; 2]),
+            (1, vec![1; 4]),
+        ] {
+            cache.write_piece(index, data).unwrap();
+        }
+        cache.flush().unwrap();
+
+        assert_eq!(
+            std::fs::read(format!("{}/{}", dir, PIECES_FILE)).unwrap(),
+            [vec![0; 4], vec![1; 4], vec![2; 4], vec![0; 8], vec![5; 2]].concat()
+        );
+        assert_eq!(stored_pieces(&dir).unwrap(), HashSet::from([0, 1, 2, 5]));
+        assert_eq!(read_piece(&dir, 4, 5).unwrap(), vec![5; 2]);
+        assert!(read_piece(&dir, 4, 6).is_err());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let dir = test_dir("disk_cache", "lru").display().to_string();
+        for i in 0..3 {
+            store_pieces(&dir, 8, i, &[&[i as u8; 8]]).unwrap();
+        }
+        let cache = DiskCache::new(dir, 8, 16);
+        cache.read_block(0, 0, 8).unwrap();
+        cache.read_block(1, 0, 8).unwrap();
+        cache.read_block(0, 0, 8).unwrap(); // piece 0 is now the most recently used
+        cache.read_block(2, 0, 8).unwrap(); // evicts piece 1
+
+        cache.read_block(0, 0, 8).unwrap();
+        let stats = cache.stats().unwrap();
+        assert_eq!(stats.hits, 2);
+        cache.read_block(1, 0, 8).unwrap();
+        assert_eq!(cache.stats().unwrap().misses, stats.misses + 1);
+    }
+}
@@ -1,31 +1,41 @@
 use crate::{
+    connection_manager::{ActiveConnection, ConnectAttempt, ConnectionManager},
+    disk_cache::{stored_pieces, DiskCache},
     errors::download_manager_error::DownloadManagerError,
     errors::peer_connection_error::PeerConnectionError,
+    events::{ClientEvent, Direction, DisconnectReason, EventBus},
+    file_selection::{FilePriority, FileSelection},
     logger::LogMsg,
     peer_entities::peer::{Peer, PeerInterface},
     peer_entities::peer_connection::PeerConnection,
-    ui::ui_codes::*,
+    piece_picker::PiecePicker,
+    session::{ConnectionLimit, TorrentState},
     upload_manager::PieceRequest,
     utilities::constants::*,
-    utilities::file_assembler::assemble,
-    utilities::utils::UiParams,
+    utilities::file_assembler::{assemble, assemble_files},
 };
-use chrono::{offset::Utc, DateTime};
 use core::hash::Hash;
-use glib::Sender as UISender;
 use sha1::{Digest, Sha1};
 use std::{
-    collections::HashSet,
     fs::File,
-    io::{Read, Write},
+    io::Read,
+    net::{IpAddr, SocketAddr},
     path::Path,
     sync::mpsc::{channel, Receiver, Sender},
     sync::{Arc, Mutex, RwLock},
     thread::{self, spawn},
-    time::Duration,
-    time::SystemTime,
+    time::{Duration, Instant},
 };
 
+/// How often a complete torrent checks whether it was removed or resumed.
+const SEED_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// The peers each torrent downloads from at once, counting the ones being connected to.
+const PEER_CONNECTIONS: usize = 50;
+/// How often more peers are connected to while downloading.
+const CONNECT_INTERVAL: Duration = Duration::from_secs(2);
+/// How long an attempt waits for the other half-open connections of the session before giving up.
+const HALF_OPEN_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// The DownloadManager is responsible for downloading pieces from other peers.
 #[allow(clippy::type_complexity)]
 pub struct DownloadManager {
@@ -35,9 +45,9 @@ pub struct DownloadManager {
     pieces_receiver: Arc<Mutex<Receiver<PieceInfo>>>,
     pieces_sender: Arc<Mutex<Sender<PieceInfo>>>,
     logger_sender: Arc<Mutex<Sender<LogMsg>>>,
-    sender_client: Arc<Mutex<UISender<Vec<(usize, UiParams, String)>>>>,
-    active_threads_quantity: Arc<Mutex<usize>>,
+    events: Arc<EventBus>,
     threads_handles: Arc<Mutex<Vec<thread::JoinHandle<()>>>>,
+    piece_picker: Arc<RwLock<PiecePicker>>,
 }
 
 /// The enum PieceStatus represents the status of a piece that we want to download.
@@ -84,18 +94,20 @@ pub struct DownloaderInfo {
     pub upload_sender: Arc<Mutex<Sender<Option<PieceRequest>>>>,
     pub torrent_name: String,
     pub file_length: u64,
-    pub ui_sender: Arc<Mutex<glib::Sender<Vec<(usize, UiParams, String)>>>>,
+    pub events: Arc<EventBus>,
+    pub disk_cache: Arc<DiskCache>,
+    pub file_selection: Arc<FileSelection>,
+    /// Shared with the session, which changes the download mode and the playback position.
+    pub piece_picker: Arc<RwLock<PiecePicker>>,
+    pub state: Arc<RwLock<TorrentState>>,
+    pub connections: Arc<ConnectionLimit>,
+    pub connection_manager: Arc<ConnectionManager>,
 }
 
 impl DownloadManager {
     /// Creates a download manager and the corresponding bitfield.
     pub fn new(info: DownloaderInfo) -> Result<Arc<DownloadManager>, DownloadManagerError> {
         let pieces_quantity = (info.file_length / info.piece_length) as usize;
-        info.ui_sender.lock()?.send(vec![(
-            GET_PIECES_QUANTITY,
-            UiParams::Integer(pieces_quantity as i64),
-            info.torrent_name.clone(),
-        )])?;
         info.logger_sender
             .lock()?
             .send(LogMsg::Info("Reading disk, please wait...".to_string()))?;
@@ -105,11 +117,13 @@ impl DownloadManager {
             .iter()
             .filter(|x| PieceStatus::Downloaded == x.lock().unwrap().to_owned())
             .count();
-        info.ui_sender.lock()?.send(vec![(
-            UPDATE_INITIAL_DOWNLOADED_PIECES,
-            UiParams::Integer(current_downloaded_pieces as i64),
-            info.torrent_name.clone(),
-        )])?;
+        info.events.publish(
+            &info.torrent_name,
+            ClientEvent::PiecesChecked {
+                pieces: pieces_quantity,
+                verified: current_downloaded_pieces,
+            },
+        );
 
         let downloader_info = Arc::new(RwLock::new(info.clone()));
         let (pieces_sender, pieces_receiver) = channel();
@@ -120,14 +134,25 @@ impl DownloadManager {
             pieces_receiver: Arc::new(Mutex::new(pieces_receiver)),
             pieces_sender: Arc::new(Mutex::new(pieces_sender)),
             logger_sender: info.logger_sender.clone(),
-            sender_client: info.ui_sender,
-            active_threads_quantity: Arc::new(Mutex::new(0)),
+            events: info.events,
             threads_handles: Arc::new(Mutex::new(Vec::new())),
+            piece_picker: info.piece_picker.clone(),
         }))
     }
 
-    /// Starts the download process. Once the download is finished, assembles the downloaded pieces into a file.
+    /// Starts the download process. Once the download is finished, assembles the downloaded pieces into a file
+    /// and seeds them until the torrent is removed from the session.
     pub fn start_download(self: Arc<Self>) -> Result<(), DownloadManagerError> {
+        let result = self.clone().download();
+        self.seed()?;
+        // the uploader stops the listener too
+        self.info.read()?.upload_sender.lock()?.send(None)?;
+        self.logger_sender.lock()?.send(LogMsg::End)?;
+        result
+    }
+
+    /// Downloads the wanted pieces and assembles them.
+    fn download(self: Arc<Self>) -> Result<(), DownloadManagerError> {
         let mut pretty_torrent_name = self.info.read()?.torrent_name.clone();
         pretty_torrent_name = pretty_torrent_name.split('/').last().unwrap().to_string();
         pretty_torrent_name = pretty_torrent_name.rsplit_once('.').unwrap().0.to_string();
@@ -139,37 +164,36 @@ impl DownloadManager {
         );
         if Path::new(&assembled_file_path).exists() {
             self.logger_sender.lock()?.send(LogMsg::Info(format!(
-                "File {} is already downloaded, exiting...",
+                "File {} is already downloaded, seeding...",
                 assembled_file_path
             )))?;
-            self.info.read()?.upload_sender.lock()?.send(None)?;
-            self.logger_sender.lock()?.send(LogMsg::End)?;
+            self.publish(ClientEvent::Completed {
+                path: assembled_file_path,
+            })?;
             return Ok(());
         }
 
-        if self
-            .bitfield
-            .iter()
-            .all(|x| PieceStatus::Downloaded == x.lock().unwrap().to_owned())
-        {
-            self.info.read()?.upload_sender.lock()?.send(None)?;
-            self.logger_sender.lock()?.send(LogMsg::End)?;
+        if !self.info.read()?.file_selection.has_wanted_files()? {
+            self.logger_sender.lock()?.send(LogMsg::Info(format!(
+                "Every file of {} is skipped, nothing to download...",
+                pretty_torrent_name
+            )))?;
+            return Ok(());
+        }
+
+        if self.wanted_pieces_downloaded()? {
             self.logger_sender.lock()?.send(LogMsg::Info(format!(
                 "All pieces downloaded, assembling file {}...",
                 pretty_torrent_name
             )))?;
-            assemble(
-                self.info.read()?.download_pieces_path.clone(),
-                assembled_file_path.clone(),
-                self.pieces_quantity,
-                self.info.read()?.piece_length as usize,
-            )?;
+            self.assemble_torrent(assembled_file_path.clone())?;
             self.logger_sender.lock()?.send(LogMsg::Info(format!(
-                "File {} assembled, exiting...",
+                "File {} assembled, seeding...",
                 assembled_file_path
             )))?;
-            self.info.read()?.upload_sender.lock()?.send(None)?;
-            self.logger_sender.lock()?.send(LogMsg::End)?;
+            self.publish(ClientEvent::Completed {
+                path: assembled_file_path,
+            })?;
             return Ok(());
         }
         let mut current_time = chrono::Utc::now();
@@ -183,35 +207,30 @@ impl DownloadManager {
             let _r = self_copy.listen_for_pieces();
         }));
 
-        // Init the threads to download pieces
-        let _r = self.clone().init_peers_connnections(50);
-        thread::sleep(Duration::from_secs(3));
         self.logger_sender.lock()?.send(LogMsg::Info(
-            "Jobs spawned, waiting for downloads to end...".to_string(),
+            "Connecting to peers, waiting for downloads to end...".to_string(),
         ))?;
         self.logger_sender.lock()?.send(LogMsg::Info(format!(
             "PIECES LEFT: {}",
-            self.pieces_quantity
-                - self
-                    .bitfield
-                    .iter()
-                    .filter(|x| PieceStatus::Downloaded == x.lock().unwrap().to_owned())
-                    .count()
+            self.wanted_pieces_with_status(PieceStatus::NotDownloaded)?
+                .len()
         )))?;
-        while !self
-            .bitfield
-            .iter()
-            .all(|x| PieceStatus::Downloaded == x.lock().unwrap().to_owned())
-        {
-            if self.active_threads_quantity.lock()?.to_owned() == 0 {
-                let _r = self.clone().init_peers_connnections(1);
+        // skipped pieces are never downloaded, so only the wanted ones are waited for
+        while !self.wanted_pieces_downloaded()? {
+            match self.state()? {
+                TorrentState::Stopped => return self.stop(),
+                // paused and queued torrents wait here until they are resumed
+                TorrentState::Paused | TorrentState::Queued | TorrentState::Seeding => {}
+                // the peers that left are replaced, the ones that failed are tried again after their backoff
+                TorrentState::Running => {
+                    let _r = self.clone().init_peers_connnections(PEER_CONNECTIONS);
+                }
             }
-            thread::sleep(Duration::from_secs(10));
+            thread::sleep(CONNECT_INTERVAL);
         }
         self.logger_sender.lock()?.send(LogMsg::Info(
             "Finished waiting for downloads to end :)".to_string(),
         ))?;
-        self.info.read()?.upload_sender.lock()?.send(None)?;
         self.pieces_sender.lock()?.send(PieceInfo {
             // Exit the thread when all pieces are downloaded
             piece_index: 0,
@@ -236,28 +255,29 @@ impl DownloadManager {
         self.logger_sender
             .lock()?
             .send(LogMsg::Info("Assembling file...".to_string()))?;
-        match assemble(
-            self.info.read()?.download_pieces_path.clone(),
-            assembled_file_path.clone(),
-            self.pieces_quantity,
-            self.info.read()?.piece_length as usize,
-        ) {
+        match self.assemble_torrent(assembled_file_path.clone()) {
             Ok(_) => {
                 self.logger_sender
                     .lock()?
                     .send(LogMsg::Info("File assembled successfully...".to_string()))?;
+                self.publish(ClientEvent::Completed {
+                    path: assembled_file_path.clone(),
+                })?;
             }
             Err(e) => {
                 self.logger_sender
                     .lock()?
                     .send(LogMsg::Info("Error assembling the file".to_string()))?;
-                self.logger_sender.lock()?.send(LogMsg::End)?;
                 return Err(DownloadManagerError::new(format!(
                     "Error assembling the file:{}",
                     e
                 )));
             }
         }
+        if self.info.read()?.file_selection.is_multi_file() {
+            // every piece was verified when it was downloaded, the files only hold part of them
+            return Ok(());
+        }
         self.logger_sender
             .lock()?
             .send(LogMsg::Info("Verifying file integrity...".to_string()))?;
@@ -272,7 +292,6 @@ impl DownloadManager {
                 self.logger_sender
                     .lock()?
                     .send(LogMsg::Info("File verified successfully...".to_string()))?;
-                self.logger_sender.lock()?.send(LogMsg::End)?;
                 Ok(())
             }
             Err(e) => {
@@ -280,7 +299,6 @@ impl DownloadManager {
                     "Error verifying the file, error:{}",
                     e
                 )))?;
-                self.logger_sender.lock()?.send(LogMsg::End)?;
                 Err(DownloadManagerError::new(
                     "Error verifying the file".to_string(),
                 ))
@@ -288,75 +306,190 @@ impl DownloadManager {
         }
     }
 
-    /// Initializes the struct peer connection for each peer.
+    /// Waits until the torrent is removed while the listener and the uploader serve its pieces.
+    /// A torrent that runs once the download is over, like one resumed by the session, is seeding.
+    fn seed(&self) -> Result<(), DownloadManagerError> {
+        loop {
+            match self.state()? {
+                TorrentState::Stopped => return Ok(()),
+                TorrentState::Running => self.start_seeding()?,
+                _ => {}
+            }
+            thread::sleep(SEED_CHECK_INTERVAL);
+        }
+    }
+
+    /// Turns the running torrent into a seeding one, unless the session changed its state meanwhile.
+    fn start_seeding(&self) -> Result<(), DownloadManagerError> {
+        let info = self.info.read()?;
+        let mut state = info.state.write()?;
+        if *state != TorrentState::Running {
+            return Ok(());
+        }
+        *state = TorrentState::Seeding;
+        drop(state);
+        self.events.publish(
+            &info.torrent_name,
+            ClientEvent::StateChanged(TorrentState::Seeding),
+        );
+        Ok(())
+    }
+
+    /// Returns whether the session wants the torrent running, paused or stopped.
+    fn state(&self) -> Result<TorrentState, DownloadManagerError> {
+        let state = *self.info.read()?.state.read()?;
+        Ok(state)
+    }
+
+    /// Publishes an event of this torrent.
+    fn publish(&self, event: ClientEvent) -> Result<(), DownloadManagerError> {
+        self.events.publish(&self.info.read()?.torrent_name, event);
+        Ok(())
+    }
+
+    /// Publishes that the connection with the peer was closed.
+    fn disconnected(
+        &self,
+        peer_connection: &PeerConnection<Peer>,
+        reason: DisconnectReason,
+    ) -> Result<(), DownloadManagerError> {
+        self.publish(ClientEvent::PeerDisconnected {
+            peer_id: peer_connection.peer.read()?.id.clone(),
+            reason,
+        })
+    }
+
+    /// Ends a download that was removed from the session without assembling it.
+    /// The downloaded pieces are kept, so the download goes on if the torrent is added again.
+    fn stop(self: Arc<Self>) -> Result<(), DownloadManagerError> {
+        self.logger_sender.lock()?.send(LogMsg::Info(
+            "Torrent removed, stopping the download...".to_string(),
+        ))?;
+        self.pieces_sender.lock()?.send(PieceInfo {
+            piece_index: 0,
+            piece_status: PieceStatus::End,
+            piece_data: vec![],
+        })?;
+        let mut handles = self.threads_handles.lock()?;
+        while let Some(handle) = handles.pop() {
+            handle.join()?;
+        }
+        Ok(())
+    }
+
+    /// Connects to the best peers the ConnectionManager gives until job_quantity peers are being connected to
+    /// or connected. Every connection takes a slot of the session, no more are opened once the limit is reached.
+    /// The downloads still block: each peer is connected to and downloaded from in its own thread, only the
+    /// uploads run on the EventLoop. The threads of the connections that ended are joined first. Returns the
+    /// connections started.
     fn init_peers_connnections(
         self: Arc<Self>,
         job_quantity: usize,
     ) -> Result<usize, DownloadManagerError> {
+        let mut handles = self.threads_handles.lock()?;
+        join_finished(&mut handles)?;
+        drop(handles);
         let mut job_counter: usize = 0;
-        let mut job_quantity = job_quantity;
-        let peers_quantity = self.info.read()?.peers.read()?.iter().count();
-        if peers_quantity < job_quantity {
-            job_quantity = peers_quantity;
-        }
         let info = self.info.read()?;
-        let peers = info.peers.clone();
-        for peer in peers.read()?.iter() {
-            let peer_copy = peer.clone();
-            let self_copy = self.clone();
+        let manager = info.connection_manager.clone();
+        let peers = info.peers.read()?.clone();
+        let mut addresses = Vec::new();
+        for peer in peers.iter() {
+            addresses.push(address(peer)?);
+        }
+        for index in manager.candidates(&addresses) {
             // exits if there are no more pieces to download or if there are enough jobs spawned
-            if job_counter >= job_quantity
-                || !self
-                    .bitfield
-                    .iter()
-                    .any(|x| PieceStatus::NotDownloaded == x.lock().unwrap().to_owned())
+            if manager.active() >= job_quantity
+                || self
+                    .wanted_pieces_with_status(PieceStatus::NotDownloaded)?
+                    .is_empty()
             {
                 break;
             }
+            let slot = match info.connections.try_acquire() {
+                Some(slot) => slot,
+                None => break,
+            };
+            let attempt = match manager.start_attempt(&addresses[index]) {
+                Some(attempt) => attempt,
+                None => break,
+            };
+            let peer = peers[index].clone();
+            let self_copy = self.clone();
+            self.threads_handles.lock()?.push(spawn(move || {
+                let _slot = slot;
+                let _r = self_copy.connect_and_download(peer, attempt);
+            }));
+            job_counter += 1;
+        }
+        Ok(job_counter)
+    }
 
-            if self.clone().try_peer_connection(peer.clone()).is_ok() {
-                self.threads_handles.lock()?.push(spawn(move || {
-                    let _r = self_copy.download_pieces(peer_copy);
-                }));
-                job_counter += 1;
-            } else {
-                self.logger_sender.lock()?.send(LogMsg::Info(format!(
-                    "try peer connection failed, peer:{}",
-                    peer.peer.read()?.ip
-                )))?;
+    /// Connects to the peer while holding a half-open slot of the session, and downloads from it.
+    /// The ConnectionManager learns how the attempt went and how fast the peer was.
+    fn connect_and_download(
+        self: Arc<Self>,
+        peer_connection: Arc<PeerConnection<Peer>>,
+        attempt: ConnectAttempt,
+    ) -> Result<(), DownloadManagerError> {
+        let manager = self.info.read()?.connection_manager.clone();
+        let half_open = match manager.wait_half_open(HALF_OPEN_TIMEOUT) {
+            Some(half_open) => half_open,
+            None => {
+                attempt.abandon();
+                return Ok(());
             }
+        };
+        if let Err(e) = self.clone().try_peer_connection(peer_connection.clone()) {
+            self.logger_sender.lock()?.send(LogMsg::Info(format!(
+                "try peer connection failed, peer:{}",
+                peer_connection.peer.read()?.ip
+            )))?;
+            peer_connection.stream.lock()?.disconnect();
+            return Err(DownloadManagerError::from(e));
         }
-        Ok(job_counter)
+        drop(half_open);
+        let connection = attempt.connected();
+
+        let meter = peer_connection.stream.lock()?.rate_meter();
+        let downloaded_before = meter
+            .as_ref()
+            .map(|meter| meter.payload_total(Direction::Download));
+        let start = Instant::now();
+        let result = self.clone().download_pieces(peer_connection.clone());
+        if let (Some(meter), Some(before)) = (meter, downloaded_before) {
+            record_average_rate(
+                &connection,
+                meter.payload_total(Direction::Download) - before,
+                start,
+            );
+        }
+        // a later attempt connects again
+        peer_connection.stream.lock()?.disconnect();
+        result
     }
 
-    /// It waits for pieces and stores them in the disk, it is intended to run in a separate thread.
+    /// It waits for pieces and stores them in the disk cache, it is intended to run in a separate thread.
+    /// The cache is flushed to disk once all pieces are downloaded.
     fn listen_for_pieces(self: Arc<Self>) -> Result<(), DownloadManagerError> {
         let pieces_receiver = self.pieces_receiver.lock()?;
+        let disk_cache = self.info.read()?.disk_cache.clone();
         loop {
             let piece = pieces_receiver.recv()?;
             if piece.piece_status == PieceStatus::End {
+                disk_cache.flush()?;
                 return Ok(());
             }
-            let mut file = File::create(format!(
-                "{}/piece_{}.txt",
-                self.info.read()?.download_pieces_path,
-                piece.piece_index
-            ))?;
-            file.write_all(&piece.piece_data)?;
-            file.flush()?;
+            disk_cache.write_piece(piece.piece_index as u32, piece.piece_data)?;
         }
     }
 
     /// Starts with the exchange of messagges until unchoke is detected, then start to request pieces by checking the bitfield.
-    /// Also send messages to UI to update data in real time.
+    /// Also publishes the events of the connection as they happen.
     fn download_pieces(
         self: Arc<Self>,
         peer_connection: Arc<PeerConnection<Peer>>,
     ) -> Result<(), DownloadManagerError> {
-        let mut active_threads = self.active_threads_quantity.lock()?;
-        *active_threads += 1;
-        drop(active_threads);
-
         self.info
             .read()?
             .logger_sender
@@ -365,20 +498,11 @@ impl DownloadManager {
                 "PEER CONNECTION ESTABLISHED WITH PEER {:?}",
                 peer_connection.peer.read()?.ip
             )))?;
-        self.sender_client.lock()?.send(vec![(
-            UPDATE_ACTIVE_CONNS,
-            UiParams::Usize(1),
-            self.info.read()?.torrent_name.clone(),
-        )])?;
-        self.sender_client.lock()?.send(vec![(
-            UPDATE_PEER_ID_IP_PORT,
-            UiParams::Vector(vec![
-                peer_connection.peer.read()?.id.clone(),
-                peer_connection.peer.read()?.ip.clone(),
-                format!("{}", peer_connection.peer.read()?.port.clone()),
-            ]),
-            self.info.read()?.torrent_name.clone(),
-        )])?;
+        self.publish(ClientEvent::PeerConnected {
+            peer_id: peer_connection.peer.read()?.id.clone(),
+            ip: peer_connection.peer.read()?.ip.clone(),
+            port: peer_connection.peer.read()?.port,
+        })?;
 
         // Start iterating until unchoked received
         let mut message_type = ERROR_ID;
@@ -388,21 +512,12 @@ impl DownloadManager {
             message_type = match peer_connection.clone().read_detect_message() {
                 Ok(message) => message,
                 Err(e) => {
-                    self.sender_client.lock()?.send(vec![(
-                        DELETE_ONE_ACTIVE_CONNECTION,
-                        UiParams::Vector(vec![
-                            peer_connection.peer.read()?.id.clone(),
-                            "Disconnected".to_string(),
-                        ]),
-                        self.info.read()?.torrent_name.clone(),
-                    )])?;
+                    self.disconnected(&peer_connection, DisconnectReason::Error(e.to_string()))?;
                     self.logger_sender.lock()?.send(LogMsg::Info(format!(
                         "SUBSTRACTING THREAD, PEER:{}, ERROR:{}",
                         peer_connection.peer.read()?.ip,
                         e
                     )))?;
-                    let mut active_threads = self.active_threads_quantity.lock()?;
-                    *active_threads -= 1;
                     return Err(DownloadManagerError::from(e));
                 }
             };
@@ -410,14 +525,9 @@ impl DownloadManager {
             if message_type == BITFIELD_ID {
                 peer_connection.clone().unchoke()?;
                 peer_connection.clone().interested()?;
-                self.sender_client.lock()?.send(vec![(
-                    UPDATE_INTERESTED,
-                    UiParams::Vector(vec![
-                        peer_connection.peer.read()?.id.clone(),
-                        "Interested".to_string(),
-                    ]),
-                    self.info.read()?.torrent_name.clone(),
-                )])?;
+                self.publish(ClientEvent::PeerInterested {
+                    peer_id: peer_connection.peer.read()?.id.clone(),
+                })?;
             }
 
             if message_type == CHOKE_ID {
@@ -425,38 +535,26 @@ impl DownloadManager {
                     "PEER {} CHOKED US, BEFORE SENDING PIECE REQUEST",
                     peer_connection.peer.read()?.ip
                 )))?;
-                self.sender_client.lock()?.send(vec![(
-                    DELETE_ONE_ACTIVE_CONNECTION,
-                    UiParams::Vector(vec![
-                        peer_connection.peer.read()?.id.clone(),
-                        "Disconnected".to_string(),
-                    ]),
-                    self.info.read()?.torrent_name.clone(),
-                )])?;
-                let mut active_threads = self.active_threads_quantity.lock()?;
-                *active_threads -= 1;
+                self.disconnected(&peer_connection, DisconnectReason::Choked)?;
                 return Err(DownloadManagerError::new("Peer choked us".to_string()));
             }
         }
 
-        self.sender_client.lock()?.send(vec![(
-            UPDATE_UNCHOKE,
-            UiParams::Vector(vec![
-                peer_connection.peer.read()?.id.clone(),
-                "Unchoked".to_string(),
-            ]),
-            self.info.read()?.torrent_name.clone(),
-        )])?;
+        self.publish(ClientEvent::PeerUnchoked {
+            peer_id: peer_connection.peer.read()?.id.clone(),
+        })?;
 
         // Start downloading pieces
         loop {
+            if self.state()? != TorrentState::Running {
+                self.disconnected(&peer_connection, DisconnectReason::TorrentNotRunning)?;
+                return Ok(());
+            }
             let (pieces_indexes, mut pieces_to_download) = self
                 .clone()
                 .select_pieces_to_download(peer_connection.clone())?;
 
             if pieces_indexes.is_empty() || pieces_to_download.is_empty() {
-                let mut active_threads = self.active_threads_quantity.lock()?;
-                *active_threads -= 1;
                 return Ok(());
             }
 
@@ -468,18 +566,15 @@ impl DownloadManager {
         }
     }
 
-    /// Selects the pieces to download from the bitfield of the peer.
+    /// Selects the pieces to download from the bitfield of the peer, in the order given by the piece picker.
     fn select_pieces_to_download(
         self: Arc<Self>,
         peer_connection: Arc<PeerConnection<Peer>>,
     ) -> Result<(Vec<usize>, Vec<PieceInfo>), DownloadManagerError> {
         let mut pieces_indexes: Vec<usize> = Vec::new();
         let mut pieces_to_download: Vec<PieceInfo> = Vec::new();
-        let quantity_not_downloaded = self
-            .bitfield
-            .iter()
-            .filter(|x| PieceStatus::NotDownloaded == x.lock().unwrap().to_owned())
-            .count();
+        let wanted_pieces = self.pieces_in_download_order()?;
+        let quantity_not_downloaded = wanted_pieces.len();
         let quantity_to_download = if quantity_not_downloaded < MAX_PIECES_TO_DOWNLOAD {
             quantity_not_downloaded
         } else {
@@ -487,19 +582,13 @@ impl DownloadManager {
         };
 
         if quantity_not_downloaded == 0 {
-            self.sender_client.lock()?.send(vec![(
-                DELETE_ONE_ACTIVE_CONNECTION,
-                UiParams::Vector(vec![
-                    peer_connection.peer.read()?.id.clone(),
-                    "Disconnected".to_string(),
-                ]),
-                self.info.read()?.torrent_name.clone(),
-            )])?;
+            self.disconnected(&peer_connection, DisconnectReason::Completed)?;
             return Ok((pieces_indexes, pieces_to_download));
         }
 
         let peer_bitfield = peer_connection.peer.read()?.get_bitfield().clone();
-        for (i, piece) in self.bitfield.iter().enumerate() {
+        for i in wanted_pieces {
+            let piece = &self.bitfield[i];
             if !peer_bitfield.contains(&(i as u32)) {
                 continue;
             }
@@ -530,8 +619,6 @@ impl DownloadManager {
                         "SUBSTRACTING THREAD, PEER:{}",
                         peer_connection.peer.read()?.ip
                     )))?;
-                    let mut active_threads = self.active_threads_quantity.lock()?;
-                    *active_threads -= 1;
                     return Err(DownloadManagerError::new(
                         "Error trying to lock piece mutex".to_string(),
                     ));
@@ -549,17 +636,8 @@ impl DownloadManager {
                 .logger_sender
                 .lock()?
                 .send(LogMsg::Info("Ended idle job".to_string()))?;
-            self.sender_client.lock()?.send(vec![(
-                DELETE_ONE_ACTIVE_CONNECTION,
-                UiParams::Vector(vec![
-                    peer_connection.peer.read()?.id.clone(),
-                    "Disconnected".to_string(),
-                ]),
-                self.info.read()?.torrent_name.clone(),
-            )])?;
-
-            let mut active_threads = self.active_threads_quantity.lock()?;
-            *active_threads -= 1;
+            self.disconnected(&peer_connection, DisconnectReason::NothingToDownload)?;
+
             return Err(DownloadManagerError::new(
                 "Ended idle job, pieces to download with len 0".to_string(),
             ));
@@ -596,30 +674,20 @@ impl DownloadManager {
 
         for (iteration, piece) in pieces_to_download.iter_mut().enumerate() {
             let index = piece.piece_index;
-            let system_time = SystemTime::now();
-            let datetime: DateTime<Utc> = system_time.into();
-            let timestamp = datetime.timestamp();
             match self
                 .clone()
                 .request_piece(index as u32, peer_connection.clone())
             {
                 Ok(piece_data) => {
-                    let system_time2 = SystemTime::now();
-                    let datetime2: DateTime<Utc> = system_time2.into();
-                    let timestamp2 = datetime2.timestamp();
-                    let mut time_difference = timestamp2 - timestamp;
-                    if time_difference == 0 {
-                        time_difference = 8;
+                    // the rate of the last seconds, measured by the connection
+                    let meter = peer_connection.stream.lock()?.rate_meter();
+                    if let Some(meter) = meter {
+                        self.publish(ClientEvent::RateSample {
+                            peer_id: peer_connection.peer.read()?.id.clone(),
+                            direction: Direction::Download,
+                            bytes_per_second: meter.rates().download_payload,
+                        })?;
                     }
-                    let download_speed = 16384 / time_difference;
-                    self.sender_client.lock()?.send(vec![(
-                        UPDATE_DOWNSPEED,
-                        UiParams::Vector(vec![
-                            peer_connection.peer.read()?.id.clone(),
-                            format!("{} bytes / sec", download_speed),
-                        ]),
-                        self.info.read()?.torrent_name.clone(),
-                    )])?;
                     self.pieces_sender.lock()?.send(PieceInfo {
                         piece_index: index,
                         piece_status: PieceStatus::Downloaded,
@@ -637,11 +705,7 @@ impl DownloadManager {
                         .to_string(),
                     ))?;
 
-                    self.sender_client.lock()?.send(vec![(
-                        UPDATE_DOWNLOADED_PIECES,
-                        UiParams::U64(1),
-                        self.info.read()?.clone().torrent_name,
-                    )])?
+                    self.publish(ClientEvent::PieceDownloaded { index })?;
                 }
                 Err(e) => {
                     self.logger_sender.lock()?.send(LogMsg::Info(format!(
@@ -658,16 +722,7 @@ impl DownloadManager {
                         "SUBSTRACTING THREAD, PEER:{}",
                         peer_connection.peer.read()?.ip
                     )))?;
-                    self.sender_client.lock()?.send(vec![(
-                        DELETE_ONE_ACTIVE_CONNECTION,
-                        UiParams::Vector(vec![
-                            peer_connection.peer.read()?.id.clone(),
-                            "Disconnected".to_string(),
-                        ]),
-                        self.info.read()?.torrent_name.clone(),
-                    )])?;
-                    let mut active_threads = self.active_threads_quantity.lock()?;
-                    *active_threads -= 1;
+                    self.disconnected(&peer_connection, DisconnectReason::Error(e.to_string()))?;
                     return Err(DownloadManagerError::new(
                         "Error downloading piece".to_string(),
                     ));
@@ -713,12 +768,19 @@ impl DownloadManager {
             piece_data.extend(chunk);
             offset += CHUNK_SIZE;
         }
-        verify_piece(&self.info.read()?.pieces_hash, &piece_data, &piece_idx)?;
-        self.sender_client.lock()?.send(vec![(
-            UPDATE_VERIFIED_PIECES,
-            UiParams::Usize(1),
-            self.info.read()?.torrent_name.clone(),
-        )])?;
+        if let Err(e) = verify_piece(&self.info.read()?.pieces_hash, &piece_data, &piece_idx) {
+            // a peer that sends bad data is not trusted again
+            let manager = self.info.read()?.connection_manager.clone();
+            manager.ban(&address(&peer)?);
+            return Err(DownloadManagerError::new(format!(
+                "{}, peer {} banned",
+                e,
+                peer.peer.read()?.ip
+            )));
+        }
+        self.publish(ClientEvent::PieceVerified {
+            index: piece_idx as usize,
+        })?;
 
         Ok(piece_data)
     }
@@ -732,6 +794,88 @@ impl DownloadManager {
         peer.handshake(peer_id)
     }
 
+    /// Returns the indexes of the pieces that are not skipped and have the given status, ordered by priority.
+    fn wanted_pieces_with_status(
+        &self,
+        status: PieceStatus,
+    ) -> Result<Vec<usize>, DownloadManagerError> {
+        let wanted_pieces = self
+            .info
+            .read()?
+            .file_selection
+            .pieces_by_priority(self.pieces_quantity)?;
+        Ok(wanted_pieces
+            .into_iter()
+            .filter(|i| status == self.bitfield[*i].lock().unwrap().to_owned())
+            .collect())
+    }
+
+    /// Returns the missing pieces that are not skipped, in the order the piece picker wants them requested.
+    fn pieces_in_download_order(&self) -> Result<Vec<usize>, DownloadManagerError> {
+        let availability = self.pieces_availability()?;
+        let info = self.info.read()?;
+        let missing: Vec<(usize, FilePriority)> = info
+            .file_selection
+            .wanted_pieces(self.pieces_quantity)?
+            .into_iter()
+            .filter(|(i, _)| {
+                PieceStatus::NotDownloaded == self.bitfield[*i].lock().unwrap().to_owned()
+            })
+            .collect();
+        let edges = info.file_selection.file_edge_pieces()?;
+        Ok(self
+            .piece_picker
+            .read()?
+            .order(&missing, &availability, &edges))
+    }
+
+    /// Returns how many of the known peers have each piece.
+    fn pieces_availability(&self) -> Result<Vec<usize>, DownloadManagerError> {
+        let mut availability = vec![0; self.pieces_quantity];
+        let info = self.info.read()?;
+        for peer_connection in info.peers.read()?.iter() {
+            for index in peer_connection.peer.read()?.get_bitfield().iter() {
+                if let Some(peers) = availability.get_mut(*index as usize) {
+                    *peers += 1;
+                }
+            }
+        }
+        Ok(availability)
+    }
+
+    /// Returns true if every piece that is not skipped is downloaded.
+    fn wanted_pieces_downloaded(&self) -> Result<bool, DownloadManagerError> {
+        let wanted_pieces = self
+            .info
+            .read()?
+            .file_selection
+            .pieces_by_priority(self.pieces_quantity)?;
+        Ok(wanted_pieces
+            .iter()
+            .all(|i| PieceStatus::Downloaded == self.bitfield[*i].lock().unwrap().to_owned()))
+    }
+
+    /// Assembles the downloaded pieces. Single-file torrents are written to assembled_file_path,
+    /// multi-file torrents are written inside a directory with that path, leaving out the skipped files.
+    fn assemble_torrent(&self, assembled_file_path: String) -> Result<(), DownloadManagerError> {
+        let info = self.info.read()?;
+        if info.file_selection.is_multi_file() {
+            return assemble_files(
+                info.download_pieces_path.clone(),
+                assembled_file_path,
+                &info.file_selection,
+                self.pieces_quantity,
+                info.piece_length as usize,
+            );
+        }
+        assemble(
+            info.download_pieces_path.clone(),
+            assembled_file_path,
+            self.pieces_quantity,
+            info.piece_length as usize,
+        )
+    }
+
     /// It change status of given pieces in the bitfield to NotDownloaded.
     fn clean_bitfield_at(self: Arc<Self>, indexes: &[usize]) -> Result<(), DownloadManagerError> {
         for index in indexes.iter() {
@@ -742,6 +886,36 @@ impl DownloadManager {
     }
 }
 
+/// Returns the ip:port the ConnectionManager knows the peer by, an IPv6 address between brackets.
+fn address(peer_connection: &PeerConnection<Peer>) -> Result<String, DownloadManagerError> {
+    let peer = peer_connection.peer.read()?;
+    match peer.ip.parse::<IpAddr>() {
+        Ok(ip) => Ok(SocketAddr::new(ip, peer.port).to_string()),
+        Err(_) => Ok(format!("{}:{}", peer.ip, peer.port)),
+    }
+}
+
+/// Joins the threads that ended and keeps the rest, so the handles do not pile up over the download.
+fn join_finished(handles: &mut Vec<thread::JoinHandle<()>>) -> Result<(), DownloadManagerError> {
+    let mut index = 0;
+    while index < handles.len() {
+        if handles[index].is_finished() {
+            handles.swap_remove(index).join()?;
+        } else {
+            index += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Records the bytes downloaded from a connection over the time it lasted.
+fn record_average_rate(connection: &ActiveConnection, downloaded: u64, start: Instant) {
+    let seconds = start.elapsed().as_secs_f64();
+    if seconds > 0.0 {
+        connection.record_rate((downloaded as f64 / seconds) as u64);
+    }
+}
+
 /// Checks if the assembled file is the same as the original file comparing the sha1 of each piece.
 fn verify_assembled_file(
     pieces: &[u8],
@@ -783,34 +957,18 @@ fn verify_piece(
     Ok(())
 }
 
-/// Returns a Vec of PieceStatus acting as Bitfield. It scans the download path and build the bitfield, only works if pieces names are in the right format ["piece_{idx}.txt"]
+/// Returns a Vec of PieceStatus acting as Bitfield, the pieces the DiskCache stored at the download path are downloaded.
 fn build_bitfield(
     pieces_quantity: usize,
     download_pieces_path: String,
 ) -> Arc<Vec<Mutex<PieceStatus>>> {
-    let mut stored_indexes = HashSet::new();
     if !std::path::Path::new(&download_pieces_path).exists() {
         std::fs::create_dir_all(&download_pieces_path).unwrap();
     }
-
-    for entry in std::fs::read_dir(download_pieces_path).unwrap().flatten() {
-        if !entry.path().is_dir() {
-            let piece_idx: usize = entry
-                .file_name()
-                .to_str()
-                .unwrap()
-                .split('_')
-                .collect::<Vec<&str>>()[1]
-                .split('.')
-                .collect::<Vec<&str>>()[0]
-                .parse()
-                .unwrap();
-            stored_indexes.insert(piece_idx);
-        }
-    }
+    let stored_indexes = stored_pieces(&download_pieces_path).unwrap();
     let mut bitfield = Vec::with_capacity(pieces_quantity);
     for i in 0..pieces_quantity {
-        if stored_indexes.contains(&i) {
+        if stored_indexes.contains(&(i as u32)) {
             bitfield.push(Mutex::new(PieceStatus::Downloaded));
         } else {
             bitfield.push(Mutex::new(PieceStatus::NotDownloaded));
@@ -822,6 +980,7 @@ fn build_bitfield(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
     #[test]
     fn test_verify_piece_with_wrong_piece() {
         let mut hasher = Sha1::new();
@@ -933,6 +1092,24 @@ mod tests {
         assert!(!set.contains(&piece3)); // absent piece
     }
 
+    #[test]
+    fn test_only_finished_threads_are_joined() {
+        let (sender, receiver) = channel::<()>();
+        let mut handles = vec![
+            spawn(|| {}),
+            spawn(move || {
+                let _r = receiver.recv();
+            }),
+        ];
+        while !handles[0].is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        join_finished(&mut handles).unwrap();
+        assert_eq!(handles.len(), 1);
+        drop(sender);
+        handles.pop().unwrap().join().unwrap();
+    }
+
     #[test]
     fn test_verify_assembled_file() {
         let mut hasher = Sha1::new();
@@ -11,6 +11,11 @@ impl ArgsError {
             msg: "ArgsError: Invalid argument".to_string(),
         }
     }
+
+    /// Creates an error explaining what is wrong with the arguments.
+    pub fn with_msg(message: String) -> ArgsError {
+        ArgsError { msg: message }
+    }
 }
 
 impl Display for ArgsError {
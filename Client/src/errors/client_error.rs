@@ -1,16 +1,14 @@
 use super::listener_error::ListenerError;
 use crate::{
     errors::{
-        download_manager_error::DownloadManagerError, logger_error::LoggerError,
-        peer_connection_error::PeerConnectionError, torrent_parser_error::TorrentParserError,
-        tracker_error::TrackerError,
+        download_manager_error::DownloadManagerError, file_selection_error::FileSelectionError,
+        logger_error::LoggerError, peer_connection_error::PeerConnectionError,
+        torrent_parser_error::TorrentParserError, tracker_error::TrackerError,
     },
     logger::LogMsg,
     peer_entities::peer::Peer,
     upload_manager::PieceRequest,
-    utilities::utils::UiParams,
 };
-use glib::Sender as UISender;
 use std::{
     fmt::Display,
     io::Error,
@@ -53,6 +51,14 @@ impl From<ParseIntError> for ClientError {
     }
 }
 
+impl From<FileSelectionError> for ClientError {
+    fn from(error: FileSelectionError) -> ClientError {
+        ClientError {
+            msg: format!("ClientError: ({})", error),
+        }
+    }
+}
+
 impl From<TorrentParserError> for ClientError {
     fn from(error: TorrentParserError) -> ClientError {
         ClientError {
@@ -91,24 +97,6 @@ impl From<PeerConnectionError> for ClientError {
     }
 }
 
-impl From<PoisonError<MutexGuard<'_, UISender<Vec<(usize, UiParams, String)>>>>> for ClientError {
-    fn from(
-        error: PoisonError<MutexGuard<'_, UISender<Vec<(usize, UiParams, String)>>>>,
-    ) -> ClientError {
-        ClientError {
-            msg: format!("ClientError: poisoned error ({})", error),
-        }
-    }
-}
-
-impl From<SendError<Vec<(usize, UiParams, String)>>> for ClientError {
-    fn from(error: SendError<Vec<(usize, UiParams, String)>>) -> ClientError {
-        ClientError {
-            msg: format!("ClientError: send error ({})", error),
-        }
-    }
-}
-
 impl From<DownloadManagerError> for ClientError {
     fn from(error: DownloadManagerError) -> ClientError {
         ClientError {
@@ -176,14 +164,6 @@ impl From<PoisonError<MutexGuard<'_, Sender<Option<PieceRequest>>>>> for ClientE
         }
     }
 }
-impl From<SendError<Vec<(usize, UiParams)>>> for ClientError {
-    fn from(error: SendError<Vec<(usize, UiParams)>>) -> ClientError {
-        ClientError {
-            msg: format!("ClientError: error sending to ui ({})", error),
-        }
-    }
-}
-
 impl From<SendError<String>> for ClientError {
     fn from(error: SendError<String>) -> ClientError {
         ClientError {
@@ -207,11 +187,3 @@ impl From<SendError<Option<PieceRequest>>> for ClientError {
         }
     }
 }
-
-impl From<PoisonError<MutexGuard<'_, Sender<Vec<(usize, UiParams)>>>>> for ClientError {
-    fn from(error: PoisonError<MutexGuard<'_, Sender<Vec<(usize, UiParams)>>>>) -> ClientError {
-        ClientError {
-            msg: format!("ClientError: error creating ui channel ({})", error),
-        }
-    }
-}
@@ -1,3 +1,4 @@
+use super::encryption_error::EncryptionError;
 use std::{fmt::Display, io::Error};
 
 #[derive(Debug, Default)]
@@ -24,3 +25,11 @@ impl From<Error> for CommunicationMethodError {
         }
     }
 }
+
+impl From<EncryptionError> for CommunicationMethodError {
+    fn from(error: EncryptionError) -> CommunicationMethodError {
+        CommunicationMethodError {
+            msg: format!("CommunicationMethodError: ({})", error),
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use super::config_parser_error::ConfigParserError;
+use std::fmt::Display;
+
+/// An invalid configuration, the message names the key with the wrong value.
+#[derive(Debug)]
+pub struct ConfigError {
+    msg: String,
+}
+
+impl ConfigError {
+    pub fn new(message: String) -> ConfigError {
+        ConfigError { msg: message }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl From<ConfigParserError> for ConfigError {
+    fn from(error: ConfigParserError) -> ConfigError {
+        ConfigError {
+            msg: format!("ConfigError: ({})", error),
+        }
+    }
+}
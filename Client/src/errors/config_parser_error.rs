@@ -11,6 +11,11 @@ impl ConfigParserError {
             msg: "ConfigParserError: Expected field not found".to_string(),
         }
     }
+
+    /// Creates an error explaining what is wrong in the file.
+    pub fn with_msg(message: String) -> ConfigParserError {
+        ConfigParserError { msg: message }
+    }
 }
 
 impl Display for ConfigParserError {
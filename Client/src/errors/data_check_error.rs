@@ -0,0 +1,35 @@
+use crate::errors::disk_cache_error::DiskCacheError;
+use std::{fmt::Display, io::Error};
+
+#[derive(Debug)]
+pub struct DataCheckError {
+    msg: String,
+}
+
+impl DataCheckError {
+    pub fn new(message: String) -> DataCheckError {
+        DataCheckError { msg: message }
+    }
+}
+
+impl Display for DataCheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl From<Error> for DataCheckError {
+    fn from(error: Error) -> DataCheckError {
+        DataCheckError {
+            msg: format!("DataCheckError: ({})", error),
+        }
+    }
+}
+
+impl From<DiskCacheError> for DataCheckError {
+    fn from(error: DiskCacheError) -> DataCheckError {
+        DataCheckError {
+            msg: format!("DataCheckError: ({})", error),
+        }
+    }
+}
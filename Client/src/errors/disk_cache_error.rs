@@ -0,0 +1,44 @@
+use std::{
+    fmt::Display,
+    io::Error,
+    sync::{MutexGuard, PoisonError},
+};
+
+#[derive(Debug)]
+pub struct DiskCacheError {
+    msg: String,
+}
+
+impl DiskCacheError {
+    pub fn new(message: String) -> DiskCacheError {
+        DiskCacheError { msg: message }
+    }
+}
+
+impl Display for DiskCacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl From<Error> for DiskCacheError {
+    fn from(error: Error) -> DiskCacheError {
+        DiskCacheError {
+            msg: format!("DiskCacheError: ({})", error),
+        }
+    }
+}
+
+impl<T> From<PoisonError<MutexGuard<'_, T>>> for DiskCacheError {
+    fn from(error: PoisonError<MutexGuard<'_, T>>) -> DiskCacheError {
+        DiskCacheError {
+            msg: format!("DiskCacheError: poisoned thread ({})", error),
+        }
+    }
+}
+
+impl Default for DiskCacheError {
+    fn default() -> Self {
+        Self::new("DiskCacheError: error accessing the disk cache".to_string())
+    }
+}
@@ -1,14 +1,17 @@
 use super::client_error::ClientError;
 use crate::{
     download_manager::{DownloaderInfo, PieceInfo, PieceStatus},
+    errors::disk_cache_error::DiskCacheError,
+    errors::file_selection_error::FileSelectionError,
     errors::peer_connection_error::PeerConnectionError,
     logger::LogMsg,
+    peer_entities::communication_method::CommunicationMethod,
     peer_entities::peer::Peer,
     peer_entities::peer_connection::PeerConnection,
+    piece_picker::PiecePicker,
+    session::TorrentState,
     upload_manager::PieceRequest,
-    utilities::utils::UiParams,
 };
-use glib::Sender as UISender;
 use std::{
     any::Any,
     fmt::Display,
@@ -45,6 +48,54 @@ impl From<Error> for DownloadManagerError {
     }
 }
 
+impl From<DiskCacheError> for DownloadManagerError {
+    fn from(error: DiskCacheError) -> DownloadManagerError {
+        DownloadManagerError {
+            msg: format!("DownloadManagerError: ({})", error),
+        }
+    }
+}
+
+impl From<FileSelectionError> for DownloadManagerError {
+    fn from(error: FileSelectionError) -> DownloadManagerError {
+        DownloadManagerError {
+            msg: format!("DownloadManagerError: ({})", error),
+        }
+    }
+}
+
+impl From<PoisonError<RwLockReadGuard<'_, PiecePicker>>> for DownloadManagerError {
+    fn from(error: PoisonError<RwLockReadGuard<'_, PiecePicker>>) -> DownloadManagerError {
+        DownloadManagerError {
+            msg: format!("DownloadManagerError: poisoned piece picker ({})", error),
+        }
+    }
+}
+
+impl From<PoisonError<RwLockWriteGuard<'_, PiecePicker>>> for DownloadManagerError {
+    fn from(error: PoisonError<RwLockWriteGuard<'_, PiecePicker>>) -> DownloadManagerError {
+        DownloadManagerError {
+            msg: format!("DownloadManagerError: poisoned piece picker ({})", error),
+        }
+    }
+}
+
+impl From<PoisonError<RwLockReadGuard<'_, TorrentState>>> for DownloadManagerError {
+    fn from(error: PoisonError<RwLockReadGuard<'_, TorrentState>>) -> DownloadManagerError {
+        DownloadManagerError {
+            msg: format!("DownloadManagerError: poisoned torrent state ({})", error),
+        }
+    }
+}
+
+impl From<PoisonError<RwLockWriteGuard<'_, TorrentState>>> for DownloadManagerError {
+    fn from(error: PoisonError<RwLockWriteGuard<'_, TorrentState>>) -> DownloadManagerError {
+        DownloadManagerError {
+            msg: format!("DownloadManagerError: ({})", error),
+        }
+    }
+}
+
 impl From<ClientError> for DownloadManagerError {
     fn from(error: ClientError) -> DownloadManagerError {
         DownloadManagerError {
@@ -61,6 +112,18 @@ impl From<PoisonError<MutexGuard<'_, usize>>> for DownloadManagerError {
     }
 }
 
+impl From<PoisonError<MutexGuard<'_, Box<dyn CommunicationMethod + Send>>>>
+    for DownloadManagerError
+{
+    fn from(
+        error: PoisonError<MutexGuard<'_, Box<dyn CommunicationMethod + Send>>>,
+    ) -> DownloadManagerError {
+        DownloadManagerError {
+            msg: format!("DownloadManagerError: ({})", error),
+        }
+    }
+}
+
 impl From<PeerConnectionError> for DownloadManagerError {
     fn from(error: PeerConnectionError) -> DownloadManagerError {
         DownloadManagerError {
@@ -263,50 +326,6 @@ impl From<Box<dyn Any + Send>> for DownloadManagerError {
     }
 }
 
-impl From<SendError<Vec<(usize, UiParams)>>> for DownloadManagerError {
-    fn from(error: SendError<Vec<(usize, UiParams)>>) -> DownloadManagerError {
-        DownloadManagerError {
-            msg: format!("DownloadManagerError: error sending to ui ({})", error),
-        }
-    }
-}
-
-impl From<PoisonError<MutexGuard<'_, UISender<Vec<(usize, UiParams, String)>>>>>
-    for DownloadManagerError
-{
-    fn from(
-        error: PoisonError<MutexGuard<'_, UISender<Vec<(usize, UiParams, String)>>>>,
-    ) -> DownloadManagerError {
-        DownloadManagerError {
-            msg: format!("DownloadManagerError: error sending to ui ({})", error),
-        }
-    }
-}
-impl
-    From<
-        PoisonError<
-            MutexGuard<'_, std::sync::mpsc::Sender<Vec<(usize, UiParams, std::string::String)>>>,
-        >,
-    > for DownloadManagerError
-{
-    fn from(
-        error: PoisonError<
-            MutexGuard<'_, std::sync::mpsc::Sender<Vec<(usize, UiParams, std::string::String)>>>,
-        >,
-    ) -> DownloadManagerError {
-        DownloadManagerError {
-            msg: format!("DownloadManagerError: error sending to ui ({})", error),
-        }
-    }
-}
-
-impl From<SendError<Vec<(usize, UiParams, String)>>> for DownloadManagerError {
-    fn from(error: SendError<Vec<(usize, UiParams, String)>>) -> DownloadManagerError {
-        DownloadManagerError {
-            msg: format!("DownloadManagerError: error sending to ui ({})", error),
-        }
-    }
-}
 impl From<PoisonError<MutexGuard<'_, std::sync::mpsc::Sender<Option<PieceRequest>>>>>
     for DownloadManagerError
 {
@@ -0,0 +1,36 @@
+use super::communication_method_error::CommunicationMethodError;
+use std::{fmt::Display, io::Error};
+
+/// The encryption handshake with a peer failed, or the peer asked for a method the policy refuses.
+#[derive(Debug)]
+pub struct EncryptionError {
+    msg: String,
+}
+
+impl EncryptionError {
+    pub fn new(message: String) -> EncryptionError {
+        EncryptionError { msg: message }
+    }
+}
+
+impl Display for EncryptionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl From<Error> for EncryptionError {
+    fn from(error: Error) -> EncryptionError {
+        EncryptionError {
+            msg: format!("EncryptionError: ({})", error),
+        }
+    }
+}
+
+impl From<CommunicationMethodError> for EncryptionError {
+    fn from(error: CommunicationMethodError) -> EncryptionError {
+        EncryptionError {
+            msg: format!("EncryptionError: ({})", error),
+        }
+    }
+}
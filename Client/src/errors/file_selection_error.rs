@@ -0,0 +1,69 @@
+use bencode::{BDecoderError, BEncoderError};
+use std::{
+    fmt::Display,
+    io::Error,
+    sync::{PoisonError, RwLockReadGuard, RwLockWriteGuard},
+};
+
+#[derive(Debug)]
+pub struct FileSelectionError {
+    msg: String,
+}
+
+impl FileSelectionError {
+    pub fn new(message: String) -> FileSelectionError {
+        FileSelectionError { msg: message }
+    }
+}
+
+impl Display for FileSelectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl From<Error> for FileSelectionError {
+    fn from(error: Error) -> FileSelectionError {
+        FileSelectionError {
+            msg: format!("FileSelectionError: ({})", error),
+        }
+    }
+}
+
+impl From<BDecoderError> for FileSelectionError {
+    fn from(error: BDecoderError) -> FileSelectionError {
+        FileSelectionError {
+            msg: format!("FileSelectionError: ({})", error),
+        }
+    }
+}
+
+impl From<BEncoderError> for FileSelectionError {
+    fn from(error: BEncoderError) -> FileSelectionError {
+        FileSelectionError {
+            msg: format!("FileSelectionError: ({})", error),
+        }
+    }
+}
+
+impl<T> From<PoisonError<RwLockReadGuard<'_, T>>> for FileSelectionError {
+    fn from(error: PoisonError<RwLockReadGuard<'_, T>>) -> FileSelectionError {
+        FileSelectionError {
+            msg: format!("FileSelectionError: poisoned thread ({})", error),
+        }
+    }
+}
+
+impl<T> From<PoisonError<RwLockWriteGuard<'_, T>>> for FileSelectionError {
+    fn from(error: PoisonError<RwLockWriteGuard<'_, T>>) -> FileSelectionError {
+        FileSelectionError {
+            msg: format!("FileSelectionError: poisoned thread ({})", error),
+        }
+    }
+}
+
+impl Default for FileSelectionError {
+    fn default() -> Self {
+        Self::new("FileSelectionError: error accessing the file priorities".to_string())
+    }
+}
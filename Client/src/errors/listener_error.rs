@@ -3,11 +3,12 @@ use super::{
     peer_connection_error::PeerConnectionError,
 };
 use crate::{
-    download_manager::PieceStatus, logger::LogMsg,
-    peer_entities::communication_method::CommunicationMethod, peer_entities::peer::IncomingPeer,
-    utilities::utils::UiParams,
+    download_manager::PieceStatus,
+    logger::LogMsg,
+    peer_entities::communication_method::CommunicationMethod,
+    peer_entities::peer::IncomingPeer,
+    session::{IncomingConnection, TorrentState},
 };
-use glib::Sender as UISender;
 use std::{
     fmt::Display,
     io::Error,
@@ -55,6 +56,20 @@ impl From<PoisonError<MutexGuard<'_, Receiver<String>>>> for ListenerError {
         }
     }
 }
+impl From<PoisonError<MutexGuard<'_, Receiver<IncomingConnection>>>> for ListenerError {
+    fn from(error: PoisonError<MutexGuard<'_, Receiver<IncomingConnection>>>) -> ListenerError {
+        ListenerError {
+            msg: format!("ListenerError: poisoned thread ({})", error),
+        }
+    }
+}
+impl From<PoisonError<RwLockReadGuard<'_, TorrentState>>> for ListenerError {
+    fn from(error: PoisonError<RwLockReadGuard<'_, TorrentState>>) -> ListenerError {
+        ListenerError {
+            msg: format!("ListenerError: poisoned thread ({})", error),
+        }
+    }
+}
 impl From<SendError<String>> for ListenerError {
     fn from(error: SendError<String>) -> ListenerError {
         ListenerError {
@@ -123,24 +138,6 @@ impl From<PoisonError<RwLockReadGuard<'_, Vec<PieceStatus>>>> for ListenerError
     }
 }
 
-impl From<PoisonError<MutexGuard<'_, UISender<Vec<(usize, UiParams, String)>>>>> for ListenerError {
-    fn from(
-        error: PoisonError<MutexGuard<'_, UISender<Vec<(usize, UiParams, String)>>>>,
-    ) -> ListenerError {
-        ListenerError {
-            msg: format!("ListenerError: ({})", error),
-        }
-    }
-}
-
-impl From<SendError<Vec<(usize, UiParams, String)>>> for ListenerError {
-    fn from(error: SendError<Vec<(usize, UiParams, String)>>) -> ListenerError {
-        ListenerError {
-            msg: format!("ListenerError: ({})", error),
-        }
-    }
-}
-
 impl
     From<
         PoisonError<
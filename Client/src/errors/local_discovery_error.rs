@@ -0,0 +1,27 @@
+use std::{fmt::Display, io::Error};
+
+/// The multicast groups of the local peer discovery could not be joined or written to.
+#[derive(Debug)]
+pub struct LocalDiscoveryError {
+    msg: String,
+}
+
+impl LocalDiscoveryError {
+    pub fn new(message: String) -> LocalDiscoveryError {
+        LocalDiscoveryError { msg: message }
+    }
+}
+
+impl Display for LocalDiscoveryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl From<Error> for LocalDiscoveryError {
+    fn from(error: Error) -> LocalDiscoveryError {
+        LocalDiscoveryError {
+            msg: format!("LocalDiscoveryError: ({})", error),
+        }
+    }
+}
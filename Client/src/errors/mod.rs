@@ -1,12 +1,21 @@
 pub mod args_error;
-pub mod bdecoder_error;
 pub mod client_error;
 pub mod communication_method_error;
+pub mod config_error;
 pub mod config_parser_error;
+pub mod data_check_error;
+pub mod disk_cache_error;
 pub mod download_manager_error;
+pub mod encryption_error;
+pub mod file_selection_error;
 pub mod listener_error;
+pub mod local_discovery_error;
 pub mod logger_error;
 pub mod peer_connection_error;
+pub mod port_mapping_error;
+pub mod rpc_error;
+pub mod session_error;
+pub mod torrent_builder_error;
 pub mod torrent_parser_error;
 pub mod tracker_error;
 pub mod upload_manager_error;
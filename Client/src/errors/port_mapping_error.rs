@@ -0,0 +1,27 @@
+use std::{fmt::Display, io::Error};
+
+/// A gateway that could not be found or that refused a mapping.
+#[derive(Debug)]
+pub struct PortMappingError {
+    msg: String,
+}
+
+impl PortMappingError {
+    pub fn new(message: String) -> PortMappingError {
+        PortMappingError { msg: message }
+    }
+}
+
+impl Display for PortMappingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl From<Error> for PortMappingError {
+    fn from(error: Error) -> PortMappingError {
+        PortMappingError {
+            msg: format!("PortMappingError: ({})", error),
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use super::session_error::SessionError;
+use crate::rpc::{INTERNAL_ERROR, INVALID_PARAMS, PARSE_ERROR, SERVER_ERROR};
+use std::{
+    fmt::Display,
+    io::Error,
+    sync::{MutexGuard, PoisonError},
+};
+
+/// An error of the RPC server. The code is the one of the JSON-RPC error sent back to the caller.
+#[derive(Debug)]
+pub struct RpcError {
+    code: i64,
+    msg: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: String) -> RpcError {
+        RpcError { code, msg: message }
+    }
+
+    pub fn code(&self) -> i64 {
+        self.code
+    }
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(error: Error) -> RpcError {
+        RpcError {
+            code: INTERNAL_ERROR,
+            msg: format!("RpcError: ({})", error),
+        }
+    }
+}
+
+impl From<serde_json::Error> for RpcError {
+    fn from(error: serde_json::Error) -> RpcError {
+        RpcError {
+            code: PARSE_ERROR,
+            msg: format!("RpcError: invalid JSON ({})", error),
+        }
+    }
+}
+
+impl From<base64::DecodeError> for RpcError {
+    fn from(error: base64::DecodeError) -> RpcError {
+        RpcError {
+            code: INVALID_PARAMS,
+            msg: format!("RpcError: invalid base64 ({})", error),
+        }
+    }
+}
+
+impl From<SessionError> for RpcError {
+    fn from(error: SessionError) -> RpcError {
+        RpcError {
+            code: SERVER_ERROR,
+            msg: format!("RpcError: ({})", error),
+        }
+    }
+}
+
+impl From<PoisonError<MutexGuard<'_, bool>>> for RpcError {
+    fn from(error: PoisonError<MutexGuard<'_, bool>>) -> RpcError {
+        RpcError {
+            code: INTERNAL_ERROR,
+            msg: format!("RpcError: poisoned thread ({})", error),
+        }
+    }
+}
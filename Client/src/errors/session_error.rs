@@ -0,0 +1,173 @@
+use super::{
+    client_error::ClientError, communication_method_error::CommunicationMethodError,
+    encryption_error::EncryptionError, file_selection_error::FileSelectionError,
+    local_discovery_error::LocalDiscoveryError, torrent_parser_error::TorrentParserError,
+};
+use crate::session::{IncomingConnection, TorrentHandle, TorrentState};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::Error,
+    num::ParseIntError,
+    sync::mpsc::{SendError, Sender},
+    sync::{MutexGuard, PoisonError, RwLockReadGuard, RwLockWriteGuard},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+#[derive(Debug, Default)]
+pub struct SessionError {
+    msg: String,
+}
+
+impl SessionError {
+    pub fn new(message: String) -> SessionError {
+        SessionError { msg: message }
+    }
+}
+
+impl Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl From<Error> for SessionError {
+    fn from(error: Error) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: ({})", error),
+        }
+    }
+}
+
+impl From<ParseIntError> for SessionError {
+    fn from(error: ParseIntError) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: error parsing port number ({})", error),
+        }
+    }
+}
+
+impl From<ClientError> for SessionError {
+    fn from(error: ClientError) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: ({})", error),
+        }
+    }
+}
+
+impl From<CommunicationMethodError> for SessionError {
+    fn from(error: CommunicationMethodError) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: ({})", error),
+        }
+    }
+}
+
+impl From<EncryptionError> for SessionError {
+    fn from(error: EncryptionError) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: ({})", error),
+        }
+    }
+}
+
+impl From<LocalDiscoveryError> for SessionError {
+    fn from(error: LocalDiscoveryError) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: ({})", error),
+        }
+    }
+}
+
+impl From<TorrentParserError> for SessionError {
+    fn from(error: TorrentParserError) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: ({})", error),
+        }
+    }
+}
+
+impl From<FileSelectionError> for SessionError {
+    fn from(error: FileSelectionError) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: ({})", error),
+        }
+    }
+}
+
+impl From<PoisonError<RwLockReadGuard<'_, HashMap<Vec<u8>, TorrentHandle>>>> for SessionError {
+    fn from(
+        error: PoisonError<RwLockReadGuard<'_, HashMap<Vec<u8>, TorrentHandle>>>,
+    ) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: poisoned thread ({})", error),
+        }
+    }
+}
+
+impl From<PoisonError<RwLockWriteGuard<'_, HashMap<Vec<u8>, TorrentHandle>>>> for SessionError {
+    fn from(
+        error: PoisonError<RwLockWriteGuard<'_, HashMap<Vec<u8>, TorrentHandle>>>,
+    ) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: poisoned thread ({})", error),
+        }
+    }
+}
+
+impl From<PoisonError<RwLockReadGuard<'_, TorrentState>>> for SessionError {
+    fn from(error: PoisonError<RwLockReadGuard<'_, TorrentState>>) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: poisoned thread ({})", error),
+        }
+    }
+}
+
+impl From<PoisonError<RwLockWriteGuard<'_, TorrentState>>> for SessionError {
+    fn from(error: PoisonError<RwLockWriteGuard<'_, TorrentState>>) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: poisoned thread ({})", error),
+        }
+    }
+}
+
+impl From<PoisonError<MutexGuard<'_, Sender<IncomingConnection>>>> for SessionError {
+    fn from(error: PoisonError<MutexGuard<'_, Sender<IncomingConnection>>>) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: poisoned thread ({})", error),
+        }
+    }
+}
+
+impl From<PoisonError<MutexGuard<'_, Vec<JoinHandle<()>>>>> for SessionError {
+    fn from(error: PoisonError<MutexGuard<'_, Vec<JoinHandle<()>>>>) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: poisoned thread ({})", error),
+        }
+    }
+}
+
+impl From<PoisonError<MutexGuard<'_, bool>>> for SessionError {
+    fn from(error: PoisonError<MutexGuard<'_, bool>>) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: poisoned thread ({})", error),
+        }
+    }
+}
+
+impl From<PoisonError<MutexGuard<'_, Option<Duration>>>> for SessionError {
+    fn from(error: PoisonError<MutexGuard<'_, Option<Duration>>>) -> SessionError {
+        SessionError {
+            msg: format!("SessionError: poisoned thread ({})", error),
+        }
+    }
+}
+
+impl From<SendError<IncomingConnection>> for SessionError {
+    fn from(_error: SendError<IncomingConnection>) -> SessionError {
+        SessionError {
+            msg: "SessionError: the torrent stopped accepting connections".to_string(),
+        }
+    }
+}
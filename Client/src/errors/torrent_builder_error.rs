@@ -0,0 +1,40 @@
+use std::{any::Any, fmt::Display, io::Error};
+
+#[derive(Debug)]
+pub struct TorrentBuilderError {
+    msg: String,
+}
+
+impl TorrentBuilderError {
+    pub fn new(message: String) -> TorrentBuilderError {
+        TorrentBuilderError { msg: message }
+    }
+}
+
+impl Display for TorrentBuilderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl From<Error> for TorrentBuilderError {
+    fn from(error: Error) -> TorrentBuilderError {
+        TorrentBuilderError {
+            msg: format!("TorrentBuilderError: ({})", error),
+        }
+    }
+}
+
+impl From<Box<dyn Any + Send>> for TorrentBuilderError {
+    fn from(error: Box<dyn Any + Send>) -> TorrentBuilderError {
+        TorrentBuilderError {
+            msg: format!("TorrentBuilderError: hashing thread panicked ({:?})", error),
+        }
+    }
+}
+
+impl Default for TorrentBuilderError {
+    fn default() -> Self {
+        Self::new("TorrentBuilderError: error creating the torrent".to_string())
+    }
+}
@@ -1,4 +1,4 @@
-use crate::errors::bdecoder_error::BDecoderError;
+use bencode::BDecoderError;
 use std::{fmt::Display, io::Error};
 
 #[derive(Debug)]
@@ -1,4 +1,5 @@
-use crate::{errors::bdecoder_error::BDecoderError, logger::LogMsg};
+use crate::logger::LogMsg;
+use bencode::BDecoderError;
 use native_tls::HandshakeError;
 use std::{fmt::Display, io::Error, net::TcpStream, sync::mpsc::SendError};
 
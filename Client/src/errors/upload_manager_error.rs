@@ -1,7 +1,7 @@
 use crate::{
     download_manager::PieceStatus, errors::communication_method_error::CommunicationMethodError,
-    errors::peer_connection_error::PeerConnectionError, logger::LogMsg, peer_entities::peer::Peer,
-    utilities::utils::UiParams,
+    errors::disk_cache_error::DiskCacheError, errors::peer_connection_error::PeerConnectionError,
+    logger::LogMsg, peer_entities::peer::Peer,
 };
 use std::{
     fmt::Display,
@@ -60,14 +60,6 @@ impl From<PoisonError<RwLockReadGuard<'_, Vec<PieceStatus>>>> for UploadManagerE
     }
 }
 
-impl From<SendError<Vec<(usize, UiParams, String)>>> for UploadManagerError {
-    fn from(error: SendError<Vec<(usize, UiParams, String)>>) -> UploadManagerError {
-        UploadManagerError {
-            msg: format!("UploadManagerError: send error ({})", error),
-        }
-    }
-}
-
 impl From<PoisonError<RwLockReadGuard<'_, Vec<Peer>>>> for UploadManagerError {
     fn from(error: PoisonError<RwLockReadGuard<'_, Vec<Peer>>>) -> UploadManagerError {
         UploadManagerError {
@@ -84,6 +76,14 @@ impl From<CommunicationMethodError> for UploadManagerError {
     }
 }
 
+impl From<DiskCacheError> for UploadManagerError {
+    fn from(error: DiskCacheError) -> UploadManagerError {
+        UploadManagerError {
+            msg: format!("UploadManagerError: disk cache error ({})", error),
+        }
+    }
+}
+
 impl From<PoisonError<RwLockWriteGuard<'_, Vec<PieceStatus>>>> for UploadManagerError {
     fn from(error: PoisonError<RwLockWriteGuard<'_, Vec<PieceStatus>>>) -> UploadManagerError {
         UploadManagerError {
@@ -92,7 +92,7 @@ impl From<PoisonError<RwLockWriteGuard<'_, Vec<PieceStatus>>>> for UploadManager
     }
 }
 
-impl<T> From<PoisonError<MutexGuard<'_, T>>> for UploadManagerError {
+impl<T: ?Sized> From<PoisonError<MutexGuard<'_, T>>> for UploadManagerError {
     fn from(error: PoisonError<MutexGuard<'_, T>>) -> UploadManagerError {
         UploadManagerError {
             msg: format!("UploadManagerError: poisoned thread ({})", error),
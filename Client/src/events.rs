@@ -0,0 +1,387 @@
+use crate::{file_selection::FileSelection, logger::LogMsg, session::TorrentState};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::Write,
+    sync::mpsc::{SendError, Sender},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// What happened to a torrent. Every event is published to all the subscribers of the EventBus.
+#[derive(Debug, Clone)]
+pub enum ClientEvent {
+    /// The torrent file was read.
+    Metadata {
+        name: String,
+        total_length: u64,
+        files: Arc<FileSelection>,
+    },
+    /// The pieces already on disk were counted.
+    PiecesChecked {
+        pieces: usize,
+        verified: usize,
+    },
+    Tracker(TrackerStatus),
+    PeerConnected {
+        peer_id: String,
+        ip: String,
+        port: u16,
+    },
+    PeerDisconnected {
+        peer_id: String,
+        reason: DisconnectReason,
+    },
+    PeerUnchoked {
+        peer_id: String,
+    },
+    /// We stopped uploading to the peer, others upload more to us.
+    PeerChoked {
+        peer_id: String,
+    },
+    PeerInterested {
+        peer_id: String,
+    },
+    PieceDownloaded {
+        index: usize,
+    },
+    /// The hash of the piece matched the one of the torrent file.
+    PieceVerified {
+        index: usize,
+    },
+    RateSample {
+        peer_id: String,
+        direction: Direction,
+        bytes_per_second: u64,
+    },
+    CacheStats {
+        hits: u64,
+        misses: u64,
+    },
+    StateChanged(TorrentState),
+    /// The wanted files are complete at path, a file or a directory for multi-file torrents.
+    Completed {
+        path: String,
+    },
+    /// The session could not accept a connection, it keeps listening. Published without a torrent.
+    AcceptFailed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrackerStatus {
+    Announced { peers: usize },
+    Failed(String),
+}
+
+/// Why a peer connection was closed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The peer choked us.
+    Choked,
+    /// Every piece we want is already downloaded.
+    Completed,
+    /// The peer does not have any of the pieces we are missing.
+    NothingToDownload,
+    /// The torrent was paused or stopped.
+    TorrentNotRunning,
+    /// The connection failed or the peer sent something we could not read.
+    Error(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Download,
+    Upload,
+}
+
+/// A ClientEvent and the torrent it is about, the path of its torrent file. The events of the session
+/// have an empty torrent.
+#[derive(Debug, Clone)]
+pub struct TorrentEvent {
+    pub torrent: String,
+    pub event: ClientEvent,
+}
+
+/// Receives the events of every torrent: the GTK window, the console, the log file or a test.
+pub trait EventSink: Send {
+    /// Returns an Error once the subscriber is gone, then the bus stops sending to it.
+    fn send(&mut self, event: TorrentEvent) -> Result<(), SendError<TorrentEvent>>;
+}
+
+/// Delivers the events of the torrents to many subscribers. Publishing never fails, so a subscriber
+/// that went away does not stop a download.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Box<dyn EventSink>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Arc<EventBus> {
+        Arc::new(EventBus::default())
+    }
+
+    /// Adds a subscriber, it gets the events published from now on.
+    pub fn subscribe(&self, sink: Box<dyn EventSink>) {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(sink);
+    }
+
+    /// Sends the event to every subscriber and drops the ones that are gone.
+    pub fn publish(&self, torrent: &str, event: ClientEvent) {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        subscribers.retain_mut(|sink| {
+            sink.send(TorrentEvent {
+                torrent: torrent.to_string(),
+                event: event.clone(),
+            })
+            .is_ok()
+        });
+    }
+}
+
+impl EventSink for Sender<TorrentEvent> {
+    fn send(&mut self, event: TorrentEvent) -> Result<(), SendError<TorrentEvent>> {
+        Sender::send(self, event)
+    }
+}
+
+#[cfg(feature = "gui")]
+impl EventSink for glib::Sender<TorrentEvent> {
+    fn send(&mut self, event: TorrentEvent) -> Result<(), SendError<TorrentEvent>> {
+        glib::Sender::send(self, event)
+    }
+}
+
+/// Writes every event to a Logger.
+pub struct LogSink {
+    logger_sender: Sender<LogMsg>,
+}
+
+impl LogSink {
+    pub fn new(logger_sender: Sender<LogMsg>) -> LogSink {
+        LogSink { logger_sender }
+    }
+}
+
+impl EventSink for LogSink {
+    fn send(&mut self, event: TorrentEvent) -> Result<(), SendError<TorrentEvent>> {
+        let line = format!("{}: {}", event.torrent, event.event);
+        self.logger_sender
+            .send(LogMsg::Info(line))
+            .map_err(|_| SendError(event))
+    }
+}
+
+/// The pieces of a torrent counted by the ConsoleSink.
+#[derive(Debug, Default)]
+struct Progress {
+    pieces: usize,
+    verified: usize,
+}
+
+/// Writes the progress of every torrent to out: one line for each verified piece,
+/// and the answers of the tracker and the changes of state.
+pub struct ConsoleSink<W: Write + Send> {
+    out: W,
+    progress: HashMap<String, Progress>,
+}
+
+impl<W: Write + Send> ConsoleSink<W> {
+    pub fn new(out: W) -> ConsoleSink<W> {
+        ConsoleSink {
+            out,
+            progress: HashMap::new(),
+        }
+    }
+
+    fn update(&mut self, event: &TorrentEvent) -> Option<String> {
+        //! Returns the line to write for the event, if any.
+        let progress = self.progress.entry(event.torrent.clone()).or_default();
+        let line = match &event.event {
+            ClientEvent::PiecesChecked { pieces, verified } => {
+                progress.pieces = *pieces;
+                progress.verified = *verified;
+                format!("resuming with {} of {} pieces", verified, pieces)
+            }
+            ClientEvent::PieceVerified { .. } => {
+                progress.verified += 1;
+                let percentage = match progress.pieces {
+                    0 => 0.0,
+                    pieces => progress.verified as f64 * 100.0 / pieces as f64,
+                };
+                format!(
+                    "{} of {} pieces ({:.1}%)",
+                    progress.verified, progress.pieces, percentage
+                )
+            }
+            ClientEvent::Tracker(_)
+            | ClientEvent::StateChanged(_)
+            | ClientEvent::Completed { .. } => event.event.to_string(),
+            _ => return None,
+        };
+        Some(format!("{}: {}", event.torrent, line))
+    }
+}
+
+impl<W: Write + Send> EventSink for ConsoleSink<W> {
+    fn send(&mut self, event: TorrentEvent) -> Result<(), SendError<TorrentEvent>> {
+        if let Some(line) = self.update(&event) {
+            // the download goes on even if the console is gone
+            let _r = writeln!(self.out, "{}", line);
+        }
+        Ok(())
+    }
+}
+
+impl Display for ClientEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientEvent::Metadata {
+                name,
+                total_length,
+                files,
+            } => write!(
+                f,
+                "{}, {} bytes in {} files",
+                name,
+                total_length,
+                files.files().len()
+            ),
+            ClientEvent::PiecesChecked { pieces, verified } => {
+                write!(f, "{} of {} pieces already downloaded", verified, pieces)
+            }
+            ClientEvent::Tracker(TrackerStatus::Announced { peers }) => {
+                write!(f, "tracker returned {} peers", peers)
+            }
+            ClientEvent::Tracker(TrackerStatus::Failed(reason)) => {
+                write!(f, "tracker failed ({})", reason)
+            }
+            ClientEvent::PeerConnected { peer_id, ip, port } => {
+                write!(f, "peer {} connected from {}:{}", peer_id, ip, port)
+            }
+            ClientEvent::PeerDisconnected { peer_id, reason } => {
+                write!(f, "peer {} disconnected ({})", peer_id, reason)
+            }
+            ClientEvent::PeerUnchoked { peer_id } => write!(f, "peer {} unchoked", peer_id),
+            ClientEvent::PeerChoked { peer_id } => write!(f, "peer {} choked", peer_id),
+            ClientEvent::PeerInterested { peer_id } => write!(f, "peer {} interested", peer_id),
+            ClientEvent::PieceDownloaded { index } => write!(f, "piece {} downloaded", index),
+            ClientEvent::PieceVerified { index } => write!(f, "piece {} verified", index),
+            ClientEvent::RateSample {
+                peer_id,
+                direction,
+                bytes_per_second,
+            } => write!(
+                f,
+                "{:?} from peer {} at {} bytes / sec",
+                direction, peer_id, bytes_per_second
+            ),
+            ClientEvent::CacheStats { hits, misses } => {
+                write!(f, "disk cache: {} hits, {} misses", hits, misses)
+            }
+            ClientEvent::StateChanged(state) => write!(f, "{:?}", state),
+            ClientEvent::Completed { path } => write!(f, "completed at {}", path),
+            ClientEvent::AcceptFailed(reason) => {
+                write!(f, "accepting a connection failed ({})", reason)
+            }
+        }
+    }
+}
+
+impl Display for DisconnectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisconnectReason::Choked => write!(f, "choked us"),
+            DisconnectReason::Completed => write!(f, "download completed"),
+            DisconnectReason::NothingToDownload => write!(f, "has no missing piece"),
+            DisconnectReason::TorrentNotRunning => write!(f, "torrent not running"),
+            DisconnectReason::Error(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_every_subscriber_gets_the_events() {
+        let bus = EventBus::new();
+        let (first_sender, first_receiver) = channel();
+        let (second_sender, second_receiver) = channel();
+        bus.subscribe(Box::new(first_sender));
+        bus.subscribe(Box::new(second_sender));
+
+        bus.publish("test.torrent", ClientEvent::PieceVerified { index: 3 });
+        for receiver in [first_receiver, second_receiver] {
+            let event = receiver.recv().unwrap();
+            assert_eq!(event.torrent, "test.torrent");
+            assert!(matches!(
+                event.event,
+                ClientEvent::PieceVerified { index: 3 }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_subscribers_that_are_gone_are_dropped() {
+        let bus = EventBus::new();
+        let (gone_sender, gone_receiver) = channel();
+        let (sender, receiver) = channel();
+        bus.subscribe(Box::new(gone_sender));
+        bus.subscribe(Box::new(sender));
+        drop(gone_receiver);
+
+        bus.publish("test.torrent", ClientEvent::PieceDownloaded { index: 0 });
+        bus.publish("test.torrent", ClientEvent::PieceDownloaded { index: 1 });
+        assert_eq!(bus.subscribers.lock().unwrap().len(), 1);
+        assert_eq!(receiver.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn test_log_sink_writes_each_event() {
+        let (logger_sender, logger_receiver) = channel();
+        let mut sink = LogSink::new(logger_sender);
+        sink.send(TorrentEvent {
+            torrent: "test.torrent".to_string(),
+            event: ClientEvent::PeerDisconnected {
+                peer_id: "peer".to_string(),
+                reason: DisconnectReason::Choked,
+            },
+        })
+        .unwrap();
+        assert_eq!(
+            logger_receiver.recv().unwrap(),
+            LogMsg::Info("test.torrent: peer peer disconnected (choked us)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_console_sink_writes_the_progress() {
+        let mut sink = ConsoleSink::new(Vec::new());
+        for event in [
+            ClientEvent::PiecesChecked {
+                pieces: 4,
+                verified: 1,
+            },
+            ClientEvent::PieceDownloaded { index: 2 },
+            ClientEvent::PieceVerified { index: 2 },
+        ] {
+            sink.send(TorrentEvent {
+                torrent: "test.torrent".to_string(),
+                event,
+            })
+            .unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(sink.out).unwrap(),
+            "test.torrent: resuming with 1 of 4 pieces\ntest.torrent: 2 of 4 pieces (50.0%)\n"
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use crate::{
+    errors::file_selection_error::FileSelectionError, parsing::torrent_parser::TorrentFile,
+};
+use serde::{Deserialize, Serialize};
+use std::{fs, ops::Range, path::Path, sync::RwLock};
+
+/// The priority of a file of the torrent. Pieces that only belong to skipped files are never requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FilePriority {
+    Skip,
+    Low,
+    Normal,
+    High,
+}
+
+impl FilePriority {
+    /// Returns the priority stored with the given number, unknown numbers are read as Normal.
+    pub fn from_i64(value: i64) -> FilePriority {
+        match value {
+            0 => FilePriority::Skip,
+            1 => FilePriority::Low,
+            3 => FilePriority::High,
+            _ => FilePriority::Normal,
+        }
+    }
+
+    /// Returns the number used to store the priority, it is also its position in the UI selector.
+    pub fn to_i64(self) -> i64 {
+        match self {
+            FilePriority::Skip => 0,
+            FilePriority::Low => 1,
+            FilePriority::Normal => 2,
+            FilePriority::High => 3,
+        }
+    }
+}
+
+/// The FileSelection keeps the priority of every file of a torrent and translates it to pieces.
+/// A piece shared by a skipped file and a wanted file is still downloaded, the bytes of the skipped file
+/// stay in the pieces directory (the parts file of the torrent) and are never written to the download path.
+#[derive(Debug)]
+pub struct FileSelection {
+    files: Vec<TorrentFile>,
+    piece_length: u64,
+    multi_file: bool,
+    /// The files that have bytes inside each piece, computed once from the layout of the torrent.
+    piece_files: Vec<Range<usize>>,
+    resume_path: String,
+    priorities: RwLock<Vec<FilePriority>>,
+}
+
+impl FileSelection {
+    /// Creates a FileSelection for the given files, multi_file telling whether the info dictionary had a files list.
+    /// Priorities saved at resume_path are restored, otherwise every file is Normal.
+    pub fn new(
+        files: Vec<TorrentFile>,
+        piece_length: u64,
+        multi_file: bool,
+        resume_path: String,
+    ) -> Result<FileSelection, FileSelectionError> {
+        let mut priorities = vec![FilePriority::Normal; files.len()];
+        if Path::new(&resume_path).exists() {
+            let stored = load_priorities(&resume_path)?;
+            if stored.len() == files.len() {
+                priorities = stored;
+            }
+        }
+        let piece_files = piece_files(&files, piece_length);
+        Ok(FileSelection {
+            files,
+            piece_length,
+            multi_file,
+            piece_files,
+            resume_path,
+            priorities: RwLock::new(priorities),
+        })
+    }
+
+    /// Returns the files of the torrent.
+    pub fn files(&self) -> &[TorrentFile] {
+        &self.files
+    }
+
+    /// Returns true if the torrent lists its files, even if it has only one.
+    pub fn is_multi_file(&self) -> bool {
+        self.multi_file
+    }
+
+    /// Returns a copy of the priority of every file.
+    pub fn priorities(&self) -> Result<Vec<FilePriority>, FileSelectionError> {
+        Ok(self.priorities.read()?.clone())
+    }
+
+    /// Changes the priority of a file and saves the priorities with the resume data.
+    pub fn set_priority(
+        &self,
+        file_index: usize,
+        priority: FilePriority,
+    ) -> Result<(), FileSelectionError> {
+        let mut priorities = self.priorities.write()?;
+        if file_index >= priorities.len() {
+            return Err(FileSelectionError::new(format!(
+                "FileSelectionError: file {} does not exist",
+                file_index
+            )));
+        }
+        priorities[file_index] = priority;
+        save_priorities(&self.resume_path, &priorities)
+    }
+
+    /// Returns the priority of a piece, which is the highest priority of the files it overlaps.
+    pub fn piece_priority(&self, piece_index: usize) -> Result<FilePriority, FileSelectionError> {
+        let priorities = self.priorities.read()?;
+        Ok(self.priority_of(piece_index, &priorities))
+    }
+
+    /// Returns true if at least one file is not skipped.
+    pub fn has_wanted_files(&self) -> Result<bool, FileSelectionError> {
+        Ok(self
+            .priorities
+            .read()?
+            .iter()
+            .any(|priority| *priority != FilePriority::Skip))
+    }
+
+    /// Returns the pieces that must be downloaded with their priority, in index order.
+    pub fn wanted_pieces(
+        &self,
+        pieces_quantity: usize,
+    ) -> Result<Vec<(usize, FilePriority)>, FileSelectionError> {
+        let priorities = self.priorities.read()?;
+        let mut pieces = Vec::with_capacity(pieces_quantity);
+        for i in 0..pieces_quantity {
+            let priority = self.priority_of(i, &priorities);
+            if priority != FilePriority::Skip {
+                pieces.push((i, priority));
+            }
+        }
+        Ok(pieces)
+    }
+
+    /// Returns the pieces that must be downloaded, from the highest priority to the lowest and in index order within the same priority.
+    pub fn pieces_by_priority(
+        &self,
+        pieces_quantity: usize,
+    ) -> Result<Vec<usize>, FileSelectionError> {
+        let mut pieces = self.wanted_pieces(pieces_quantity)?;
+        pieces.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        Ok(pieces.into_iter().map(|(i, _)| i).collect())
+    }
+
+    /// Returns the first and the last piece of every file that is not skipped, where media containers keep their headers.
+    pub fn file_edge_pieces(&self) -> Result<Vec<usize>, FileSelectionError> {
+        let priorities = self.priorities.read()?;
+        let mut edges = Vec::new();
+        let mut file_start = 0;
+        for (file, priority) in self.files.iter().zip(priorities.iter()) {
+            if *priority != FilePriority::Skip && file.length > 0 {
+                edges.push((file_start / self.piece_length) as usize);
+                edges.push(((file_start + file.length - 1) / self.piece_length) as usize);
+            }
+            file_start += file.length;
+        }
+        edges.dedup();
+        Ok(edges)
+    }
+
+    /// Returns the offset of the file inside the torrent.
+    pub fn file_offset(&self, file_index: usize) -> u64 {
+        self.files[..file_index]
+            .iter()
+            .map(|file| file.length)
+            .sum()
+    }
+
+    /// Returns the highest priority of the files that have bytes inside the given piece.
+    fn priority_of(&self, piece_index: usize, priorities: &[FilePriority]) -> FilePriority {
+        let files = match self.piece_files.get(piece_index) {
+            Some(files) => files.clone(),
+            None => return FilePriority::Skip,
+        };
+        files
+            .filter(|file_index| self.files[*file_index].length > 0)
+            .map(|file_index| priorities[file_index])
+            .max()
+            .unwrap_or(FilePriority::Skip)
+    }
+}
+
+/// Returns the range of the files that have bytes inside each piece. Empty files inside a range have no bytes
+/// in the piece.
+fn piece_files(files: &[TorrentFile], piece_length: u64) -> Vec<Range<usize>> {
+    let total_length: u64 = files.iter().map(|file| file.length).sum();
+    let mut ranges = vec![0..0; total_length.div_ceil(piece_length) as usize];
+    let mut file_start = 0;
+    for (i, file) in files.iter().enumerate() {
+        if file.length > 0 {
+            let first = (file_start / piece_length) as usize;
+            let last = ((file_start + file.length - 1) / piece_length) as usize;
+            for range in &mut ranges[first..=last] {
+                match range.end {
+                    0 => *range = i..i + 1,
+                    _ => range.end = i + 1,
+                }
+            }
+        }
+        file_start += file.length;
+    }
+    ranges
+}
+
+/// The resume data of a torrent saved next to its download.
+#[derive(Debug, Serialize, Deserialize)]
+struct ResumeData {
+    #[serde(rename = "file priorities", default)]
+    file_priorities: Vec<i64>,
+}
+
+/// Reads the priorities saved in the resume file.
+fn load_priorities(resume_path: &str) -> Result<Vec<FilePriority>, FileSelectionError> {
+    let resume: ResumeData = bencode::from_bytes(&fs::read(resume_path)?)?;
+    Ok(resume
+        .file_priorities
+        .into_iter()
+        .map(FilePriority::from_i64)
+        .collect())
+}
+
+/// Writes the priorities to the resume file.
+fn save_priorities(
+    resume_path: &str,
+    priorities: &[FilePriority],
+) -> Result<(), FileSelectionError> {
+    let resume = ResumeData {
+        file_priorities: priorities
+            .iter()
+            .map(|priority| priority.to_i64())
+            .collect(),
+    };
+    fs::write(resume_path, bencode::to_bytes(&resume)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_files() -> Vec<TorrentFile> {
+        vec![
+            TorrentFile {
+                path: "a.txt".to_string(),
+                length: 10,
+            },
+            TorrentFile {
+                path: "b.txt".to_string(),
+                length: 4,
+            },
+            TorrentFile {
+                path: "c.txt".to_string(),
+                length: 10,
+            },
+        ]
+    }
+
+    fn resume_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("crabrave_file_selection_{}.resume", name));
+        let _r = fs::remove_file(&path);
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_boundary_pieces_take_the_highest_priority() {
+        // pieces of 8 bytes: [a a a a a a a a] [a a b b b b c c] [c c c c c c c c]
+        let selection = FileSelection::new(test_files(), 8, true, resume_path("boundary")).unwrap();
+        selection.set_priority(0, FilePriority::Skip).unwrap();
+        selection.set_priority(1, FilePriority::Low).unwrap();
+
+        assert_eq!(selection.piece_priority(0).unwrap(), FilePriority::Skip);
+        assert_eq!(selection.piece_priority(1).unwrap(), FilePriority::Normal);
+        assert_eq!(selection.piece_priority(2).unwrap(), FilePriority::Normal);
+    }
+
+    #[test]
+    fn test_pieces_by_priority_skips_and_sorts() {
+        let selection = FileSelection::new(test_files(), 8, true, resume_path("sort")).unwrap();
+        selection.set_priority(0, FilePriority::Skip).unwrap();
+        selection.set_priority(2, FilePriority::High).unwrap();
+
+        assert_eq!(selection.pieces_by_priority(3).unwrap(), vec![1, 2]);
+
+        selection.set_priority(0, FilePriority::High).unwrap();
+        selection.set_priority(2, FilePriority::Low).unwrap();
+        assert_eq!(selection.pieces_by_priority(3).unwrap(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_file_edge_pieces() {
+        let selection = FileSelection::new(test_files(), 8, true, resume_path("edges")).unwrap();
+        assert_eq!(selection.file_edge_pieces().unwrap(), vec![0, 1, 2]);
+
+        selection.set_priority(1, FilePriority::Skip).unwrap();
+        selection.set_priority(2, FilePriority::Skip).unwrap();
+        assert_eq!(selection.file_edge_pieces().unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_priorities_are_restored_from_resume_data() {
+        let path = resume_path("restore");
+        let selection = FileSelection::new(test_files(), 8, true, path.clone()).unwrap();
+        selection.set_priority(1, FilePriority::Skip).unwrap();
+        selection.set_priority(2, FilePriority::High).unwrap();
+
+        let restored = FileSelection::new(test_files(), 8, true, path).unwrap();
+        assert_eq!(
+            restored.priorities().unwrap(),
+            vec![FilePriority::Normal, FilePriority::Skip, FilePriority::High]
+        );
+    }
+
+    #[test]
+    fn test_a_single_listed_file_is_multi_file() {
+        let files = vec![TorrentFile {
+            path: "a.txt".to_string(),
+            length: 10,
+        }];
+        let listed = FileSelection::new(files.clone(), 8, true, resume_path("listed")).unwrap();
+        assert!(listed.is_multi_file());
+        let single = FileSelection::new(files, 8, false, resume_path("single")).unwrap();
+        assert!(!single.is_multi_file());
+    }
+
+    #[test]
+    fn test_empty_files_do_not_change_the_piece_priority() {
+        let mut files = test_files();
+        files.insert(
+            1,
+            TorrentFile {
+                path: "empty.txt".to_string(),
+                length: 0,
+            },
+        );
+        let selection = FileSelection::new(files, 8, true, resume_path("empty")).unwrap();
+        selection.set_priority(0, FilePriority::Low).unwrap();
+        selection.set_priority(1, FilePriority::High).unwrap();
+        selection.set_priority(2, FilePriority::Skip).unwrap();
+        selection.set_priority(3, FilePriority::Skip).unwrap();
+
+        assert_eq!(
+            selection.wanted_pieces(4).unwrap(),
+            vec![(0, FilePriority::Low), (1, FilePriority::Low)]
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use crabrave::{
+    config::Config,
+    events::{ClientEvent, ConsoleSink, EventBus, TorrentEvent},
+    parsing::args::{get_torrents_paths, parse_command_line, parse_create_options, Command},
+    parsing::torrent_parser::torrent_parse,
+    rpc::RpcServer,
+    session::Session,
+    utilities::constants::CONFIG_PATH,
+    utilities::data_check::{check_data, import_pieces},
+    utilities::utils::{pieces_path, to_gb, to_hex},
+};
+use std::{
+    collections::HashMap,
+    env,
+    io::{self, Write},
+    path::Path,
+    process,
+    sync::mpsc::{channel, Receiver, RecvTimeoutError},
+    sync::Arc,
+    thread::spawn,
+    time::{Duration, Instant},
+};
+
+const USAGE: &str = "Usage: headless <command> [flags]
+  download <torrent>             downloads the torrent and exits once it is complete
+  seed <torrent> <data>          uploads the file or directory of the torrent
+  info <torrent>                 shows the contents of the torrent file
+  create <file or directory> <announce url> [make_torrent options]
+  check <torrent> <data>         checks the pieces of the data against the torrent
+  <torrents directory>           downloads and seeds every torrent of the directory
+Flags, they override config.yml: --port <port> --bind <ip,ip> --download-path <dir> (or --out <dir>)
+  --log-path <dir> --max-download-rate <bytes/sec> --max-upload-rate <bytes/sec>";
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+/// The command line client: downloads and seeds without a window, and works with torrent files.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command_line = match parse_command_line(&args) {
+        Ok(command_line) => command_line,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let result = match command_line.command {
+        Command::Info { torrent } => info(&torrent),
+        Command::Create {
+            path,
+            announce,
+            options,
+        } => create(&path, &announce, &options),
+        Command::Check { torrent, data } => check(&torrent, &data),
+        command => run_session(command, command_line.config),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+/// Prints the contents of the torrent file.
+fn info(torrent: &str) -> Result<(), String> {
+    let metainfo = torrent_parse(torrent).map_err(|e| e.to_string())?;
+    println!("name:         {}", metainfo.info.name);
+    println!("info hash:    {}", to_hex(&metainfo.info_hash()));
+    if let Some(announce) = &metainfo.announce {
+        println!("announce:     {}", announce);
+    }
+    for tier in &metainfo.announce_list {
+        println!("tier:         {}", tier.join(", "));
+    }
+    for url in &metainfo.url_list {
+        println!("web seed:     {}", url);
+    }
+    if let Some(comment) = &metainfo.comment {
+        println!("comment:      {}", comment);
+    }
+    if let Some(created_by) = &metainfo.created_by {
+        println!("created by:   {}", created_by);
+    }
+    if let Some(creation_date) = metainfo.creation_date {
+        println!("created at:   {} (unix time)", creation_date);
+    }
+    println!("private:      {}", metainfo.info.private);
+    println!(
+        "pieces:       {} of {}",
+        metainfo.info.pieces.len(),
+        to_gb(metainfo.info.piece_length)
+    );
+    println!("total length: {}", to_gb(metainfo.info.total_length()));
+    for file in metainfo.info.torrent_files() {
+        println!("  {} ({})", file.path, to_gb(file.length));
+    }
+    Ok(())
+}
+
+/// Writes a torrent file for the file or directory.
+fn create(path: &str, announce: &str, options: &[String]) -> Result<(), String> {
+    let (builder, output) =
+        parse_create_options(path, announce, options).map_err(|e| e.to_string())?;
+    builder.write(&output).map_err(|e| e.to_string())?;
+    println!("Torrent written to {}", output);
+    Ok(())
+}
+
+/// Hashes the pieces of the data, it fails if any of them is wrong.
+fn check(torrent: &str, data: &str) -> Result<(), String> {
+    let metainfo = torrent_parse(torrent).map_err(|e| e.to_string())?;
+    let pieces = check_data(&metainfo, data);
+    let wrong: Vec<String> = pieces
+        .iter()
+        .enumerate()
+        .filter(|(_, right)| !**right)
+        .map(|(index, _)| index.to_string())
+        .collect();
+    println!(
+        "{} of {} pieces are right",
+        pieces.len() - wrong.len(),
+        pieces.len()
+    );
+    if !wrong.is_empty() {
+        return Err(format!("wrong pieces: {}", wrong.join(", ")));
+    }
+    Ok(())
+}
+
+/// Runs a session for the download, seed and directory commands. The flags override the configuration file,
+/// which is optional. Without it the downloads and logs are written to the current directory.
+fn run_session(command: Command, flags: HashMap<String, String>) -> Result<(), String> {
+    let mut config = match Path::new(CONFIG_PATH).exists() {
+        true => Config::load(CONFIG_PATH).map_err(|e| e.to_string())?,
+        false => {
+            let mut config = Config::from_env().map_err(|e| e.to_string())?;
+            let defaults = Config::default().storage;
+            if config.storage.download_path == defaults.download_path {
+                config.storage.download_path = ".".to_string();
+            }
+            if config.storage.log_path == defaults.log_path {
+                config.storage.log_path = ".".to_string();
+            }
+            config
+        }
+    };
+    for (key, value) in &flags {
+        config.set(key, value).map_err(|e| e.to_string())?;
+    }
+    if let Command::Seed { torrent, data } = &command {
+        // the client finds the data where it would have assembled it
+        let metainfo = torrent_parse(torrent).map_err(|e| e.to_string())?;
+        let imported =
+            import_pieces(&metainfo, data, &pieces_path(torrent)).map_err(|e| e.to_string())?;
+        println!(
+            "{} of {} pieces found in {}",
+            imported,
+            metainfo.info.pieces.len(),
+            data
+        );
+        let data_dir = Path::new(data).parent().and_then(|dir| dir.to_str());
+        config
+            .set(
+                "download_path",
+                data_dir.filter(|dir| !dir.is_empty()).unwrap_or("."),
+            )
+            .map_err(|e| e.to_string())?;
+    }
+
+    let events = EventBus::new();
+    let (events_sender, events_receiver) = channel();
+    match command {
+        Command::Run { .. } => events.subscribe(Box::new(ConsoleSink::new(io::stdout()))),
+        _ => events.subscribe(Box::new(events_sender)),
+    }
+    let session = Session::new(config.clone(), events).map_err(|e| e.to_string())?;
+    for address in session.listen_addrs() {
+        println!("Listening on {}", address);
+    }
+    let session_copy = session.clone();
+    let session_handle = spawn(move || {
+        let _r = session_copy.listen();
+    });
+    let session_copy = session.clone();
+    spawn(move || {
+        let _r = session_copy.watch_config();
+    });
+    let rpc_server = RpcServer::from_config(session.clone(), &config).map_err(|e| e.to_string())?;
+    if let Some(server) = &rpc_server {
+        if let Ok(port) = server.port() {
+            println!("RPC server on 127.0.0.1:{}", port);
+        }
+        let server_copy = server.clone();
+        spawn(move || {
+            let _r = server_copy.listen();
+        });
+    }
+
+    let result = match command {
+        Command::Download { torrent } => {
+            let info_hash = session.add_torrent(&torrent).map_err(|e| e.to_string())?;
+            show_progress(&session, &events_receiver, true);
+            session
+                .remove_torrent(&info_hash)
+                .map_err(|e| e.to_string())
+        }
+        Command::Seed { torrent, .. } => {
+            session.add_torrent(&torrent).map_err(|e| e.to_string())?;
+            show_progress(&session, &events_receiver, false);
+            Ok(())
+        }
+        Command::Run { torrents_dir } => {
+            let torrent_paths = get_torrents_paths(&torrents_dir).map_err(|e| e.to_string())?;
+            for torrent_path in torrent_paths {
+                match session.add_torrent(&torrent_path) {
+                    Ok(_) => println!("{}: started", torrent_path),
+                    Err(e) => eprintln!("{}: {}", torrent_path, e),
+                }
+            }
+            session.wait().map_err(|e| e.to_string())
+        }
+        _ => Ok(()),
+    };
+
+    if let Some(server) = rpc_server {
+        let _r = server.shutdown();
+    }
+    let _r = session.shutdown();
+    let _r = session_handle.join();
+    result
+}
+
+/// Rewrites the progress of the only torrent of the session every second. Returns once the torrent is
+/// complete if until_complete is set, otherwise it runs until the process is killed.
+fn show_progress(session: &Arc<Session>, events: &Receiver<TorrentEvent>, until_complete: bool) {
+    let mut last_shown = Instant::now();
+    loop {
+        match events.recv_timeout(PROGRESS_INTERVAL) {
+            Ok(torrent_event) => match torrent_event.event {
+                ClientEvent::Completed { path } if until_complete => {
+                    return println!("\nCompleted: {}", path);
+                }
+                ClientEvent::Tracker(_) | ClientEvent::StateChanged(_) => {
+                    println!("\n{}", torrent_event.event);
+                }
+                _ => {}
+            },
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        if last_shown.elapsed() < PROGRESS_INTERVAL {
+            continue;
+        }
+        last_shown = Instant::now();
+        if let Some(torrent) = session.torrents().ok().and_then(|t| t.into_iter().next()) {
+            print!("\r{}", torrent.stats.progress_line(PROGRESS_BAR_WIDTH));
+            let _r = io::stdout().flush();
+        }
+    }
+}
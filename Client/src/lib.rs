@@ -1,12 +1,25 @@
+pub mod choker;
 pub mod client;
+pub mod config;
+pub mod connection_manager;
+pub mod disk_cache;
 pub mod download_manager;
 pub mod errors;
+pub mod events;
+pub mod file_selection;
 pub mod listener;
+pub mod local_discovery;
 pub mod logger;
 pub mod parsing;
 pub mod peer_entities;
+pub mod piece_picker;
+pub mod port_mapping;
+pub mod rate_estimator;
+pub mod rate_limiter;
+pub mod rpc;
+pub mod session;
+pub mod stats;
 pub mod test_files;
 pub mod tracker;
-pub mod ui;
 pub mod upload_manager;
 pub mod utilities;
@@ -1,37 +1,60 @@
 use crate::{
+    choker::Choker,
     download_manager::PieceStatus,
     errors::listener_error::ListenerError,
+    events::{ClientEvent, DisconnectReason, EventBus},
     logger::LogMsg,
-    peer_entities::communication_method::{CommunicationMethod, TCP},
+    peer_entities::communication_method::CommunicationMethod,
+    peer_entities::event_loop::{ConnectionHandler, EventLoop},
     peer_entities::peer::{add_piece_to_bitfield, IncomingPeer},
     peer_entities::peer_connection::PeerConnection,
-    ui::ui_codes::*,
+    rate_estimator::TransferMeter,
+    rate_limiter::RateLimiter,
+    session::{ConnectionSlot, IncomingConnection, TorrentState},
     upload_manager::PieceRequest,
-    utilities::{constants::CHOKE_ID, utils::UiParams},
+    utilities::constants::CHOKE_ID,
 };
-use glib::Sender as UISender;
 use std::{
-    io::{self},
-    net::TcpListener,
-    sync::mpsc::{Receiver, Sender},
-    sync::{Arc, Mutex},
-    thread::{self, spawn},
-    time::Duration,
+    sync::mpsc::{Receiver, RecvTimeoutError, Sender},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
 };
 
-/// This struct listens for incoming connections and creates a new thread for each one.
+/// A peer that sends nothing for this long is disconnected, it should send a keep alive every two minutes.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(3 * 60);
+
+/// This struct handles the incoming connections the session routes to the torrent. Each one is served on
+/// the event loop of the session by an IncomingPeerHandler, no thread waits for its messages.
 #[allow(clippy::type_complexity)]
 pub struct Listener {
-    listener: TcpListener,
+    incoming_receiver: Arc<Mutex<Receiver<IncomingConnection>>>,
+    state: Arc<RwLock<TorrentState>>,
     bitfield: Arc<Vec<Mutex<PieceStatus>>>,
     listener_control_receiver: Arc<Mutex<Receiver<String>>>,
     logger_sender: Arc<Mutex<Sender<LogMsg>>>,
     upload_sender: Arc<Mutex<Sender<Option<PieceRequest>>>>,
     client_id: String,
     info_hash: Vec<u8>,
-    sender_client: Arc<Mutex<UISender<Vec<(usize, UiParams, String)>>>>,
+    events: Arc<EventBus>,
     torrent_name: String,
-    threads_handles: Arc<Mutex<Vec<thread::JoinHandle<()>>>>,
+    rate_limiter: Arc<RateLimiter>,
+    rate_meter: Arc<TransferMeter>,
+    choker: Arc<Choker>,
+    event_loop: Arc<EventLoop>,
+}
+
+/// Serves an incoming peer on the event loop: answers its handshake and its messages, and chokes or
+/// unchokes it when the Choker changes its mind. The slot counts the connection in the limit of the
+/// session until it is closed.
+struct IncomingPeerHandler {
+    listener: Arc<Listener>,
+    peer_connection: Arc<PeerConnection<IncomingPeer>>,
+    /// Known once the handshake is read.
+    peer_id: Option<String>,
+    choked: bool,
+    last_frame: Instant,
+    reason: Option<DisconnectReason>,
+    _slot: ConnectionSlot,
 }
 
 #[allow(clippy::type_complexity)]
@@ -39,93 +62,105 @@ pub struct Listener {
 impl Listener {
     /// Creates a new listener instance.
     pub fn new(
-        addr: &str,
+        incoming_receiver: Arc<Mutex<Receiver<IncomingConnection>>>,
+        state: Arc<RwLock<TorrentState>>,
         bitfield: Arc<Vec<Mutex<PieceStatus>>>,
         listener_control_receiver: Arc<Mutex<Receiver<String>>>,
         logger_sender: Arc<Mutex<Sender<LogMsg>>>,
         upload_sender: Arc<Mutex<Sender<Option<PieceRequest>>>>,
         client_id: String,
         info_hash: Vec<u8>,
-        sender_client: Arc<Mutex<UISender<Vec<(usize, UiParams, String)>>>>,
+        events: Arc<EventBus>,
         torrent_name: String,
+        rate_limiter: Arc<RateLimiter>,
+        rate_meter: Arc<TransferMeter>,
+        choker: Arc<Choker>,
+        event_loop: Arc<EventLoop>,
     ) -> Result<Arc<Self>, ListenerError> {
-        let listener = TcpListener::bind(addr)?;
-        listener.set_nonblocking(true)?;
         Ok(Arc::new(Self {
-            listener,
+            incoming_receiver,
+            state,
             bitfield,
             listener_control_receiver,
             logger_sender,
             upload_sender,
             client_id,
             info_hash,
-            sender_client,
+            events,
             torrent_name,
-            threads_handles: Arc::new(Mutex::new(Vec::new())),
+            rate_limiter,
+            rate_meter,
+            choker,
+            event_loop,
         }))
     }
 
-    /// Starts to listen to incoming connections.
+    /// Starts to listen to incoming connections. The connections served so far are closed by their
+    /// handlers once the torrent is not active.
     pub fn listen(self: Arc<Self>) -> Result<(), ListenerError> {
         self.logger_sender.lock()?.send(LogMsg::Info(
             "Started Listening for connections...".to_string(),
         ))?;
 
-        for stream in self.listener.incoming() {
-            match stream {
-                Ok(s) => {
-                    let stream_connection = TCP { stream: Some(s) };
-                    if let Ok(peer_connection) =
-                        self.clone().init_incoming(Box::new(stream_connection))
-                    {
-                        let self_copy = self.clone();
-                        self.logger_sender.lock()?.send(LogMsg::Info(format!(
-                            "Incoming Peer Connection: {}",
-                            peer_connection.peer.read()?.id
-                        )))?;
-                        self.threads_handles.lock().unwrap().push(spawn(move || {
-                            let _r = self_copy.handle_connection(peer_connection);
-                        }));
-                    }
-                }
-                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                    if self.listener_control_receiver.lock()?.try_recv().is_ok() {
-                        self.logger_sender.lock()?.send(LogMsg::Info(
-                            "Listener received Stop message, terminating listener...".to_string(),
-                        ))?;
-                        self.join_finished_threads()?;
-                        return Ok(());
-                    } else {
-                        thread::sleep(Duration::from_secs(5));
+        loop {
+            if self.listener_control_receiver.lock()?.try_recv().is_ok() {
+                self.logger_sender.lock()?.send(LogMsg::Info(
+                    "Listener received Stop message, terminating listener...".to_string(),
+                ))?;
+                return Ok(());
+            }
+            let incoming = self
+                .incoming_receiver
+                .lock()?
+                .recv_timeout(Duration::from_secs(1));
+            match incoming {
+                Ok(connection) => {
+                    if !self.state.read()?.is_active() {
                         continue;
                     }
+                    if let Err(e) = self.clone().serve(connection) {
+                        self.logger_sender
+                            .lock()?
+                            .send(LogMsg::Info(format!("Error serving incoming peer: {}", e)))?;
+                    }
                 }
-
-                Err(e) => {
-                    self.join_finished_threads()?;
-                    return Err(ListenerError::from(e));
-                }
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
             }
         }
-        Ok(())
     }
 
-    /// Joins all threads when the listener is stopped.
-    fn join_finished_threads(self: Arc<Self>) -> Result<(), ListenerError> {
-        let mut handles = self.threads_handles.lock().unwrap();
-        while handles.len() > 0 {
-            match handles.pop() {
-                Some(handle) => {
-                    handle.join().unwrap();
-                    self.sender_client.lock()?.send(vec![(
-                        UPDATE_ACTIVE_CONNS,
-                        UiParams::Usize(1),
-                        self.torrent_name.clone(),
-                    )])?
-                }
-                None => break,
-            };
-        }
+    /// Hands the connection to the event loop, with a handler that serves the peer.
+    fn serve(self: Arc<Self>, connection: IncomingConnection) -> Result<(), ListenerError> {
+        let mut stream = match connection.negotiated {
+            Some(negotiated) => self
+                .event_loop
+                .adopt_negotiated(connection.stream, negotiated)?,
+            None => self.event_loop.adopt(connection.stream)?,
+        };
+        stream.set_rate_limiter(self.rate_limiter.child());
+        stream.set_rate_meter(self.rate_meter.child());
+        let peer_connection = match self.clone().init_incoming(Box::new(stream.clone())) {
+            Ok(peer_connection) => peer_connection,
+            Err(e) => {
+                stream.disconnect();
+                return Err(e);
+            }
+        };
+        self.logger_sender.lock()?.send(LogMsg::Info(format!(
+            "Incoming Peer Connection: {}",
+            peer_connection.peer.read()?.ip
+        )))?;
+        stream.handle(Box::new(IncomingPeerHandler {
+            listener: self,
+            peer_connection,
+            peer_id: None,
+            // peers start choked
+            choked: true,
+            last_frame: Instant::now(),
+            reason: None,
+            _slot: connection.slot,
+        }))?;
         Ok(())
     }
 
@@ -147,101 +182,157 @@ impl Listener {
             self.logger_sender.clone(),
             self.upload_sender.clone(),
         );
-
-        peer_connection
-            .stream
-            .lock()?
-            .set_read_timeout(Some(std::time::Duration::from_secs(3)))?;
         Ok(Arc::new(peer_connection))
     }
 
-    /// Starts the initial message exchanges with the connected peer.
-    fn handle_connection(
-        self: Arc<Self>,
-        peer_connection: Arc<PeerConnection<IncomingPeer>>,
-    ) -> Result<(), ListenerError> {
-        peer_connection.clone().handshake(self.client_id.clone())?;
-        self.sender_client.lock()?.send(vec![(
-            UPDATE_PEER_ID_IP_PORT,
-            UiParams::Vector(vec![
-                peer_connection.peer.read()?.id.clone(),
-                peer_connection.peer.read()?.ip.clone(),
-                format!("{}", peer_connection.peer.read()?.port.clone()),
-            ]),
-            self.torrent_name.clone(),
-        )])?;
-        self.sender_client.lock()?.send(vec![(
-            UPDATE_ACTIVE_CONNS,
-            UiParams::Usize(1),
-            self.torrent_name.clone(),
-        )])?;
-
-        peer_connection.clone().unchoke()?;
-        self.sender_client.lock()?.send(vec![(
-            UPDATE_UNCHOKE,
-            UiParams::Vector(vec![
-                peer_connection.peer.read()?.id.clone(),
-                "Unchoked".to_string(),
-            ]),
-            self.torrent_name.clone(),
-        )])?;
-        self.sender_client.lock()?.send(vec![(
-            UPDATE_INTERESTED,
-            UiParams::Vector(vec![
-                peer_connection.peer.read()?.id.clone(),
-                "Interested".to_string(),
-            ]),
-            self.torrent_name.clone(),
-        )])?;
-
-        peer_connection
-            .clone()
-            .bitfield(self.clone().build_bitfield()?)?;
-        loop {
-            match peer_connection.clone().read_detect_message() {
-                Ok(msg) => {
-                    if msg == CHOKE_ID {
-                        self.sender_client.lock()?.send(vec![(
-                            DELETE_ONE_ACTIVE_CONNECTION,
-                            UiParams::Vector(vec![
-                                peer_connection.peer.read()?.id.clone(),
-                                "Disconnected".to_string(),
-                            ]),
-                            self.torrent_name.clone(),
-                        )])?;
-                        return Ok(());
-                    }
-                }
-                Err(e) => {
-                    self.sender_client.lock()?.send(vec![(
-                        DELETE_ONE_ACTIVE_CONNECTION,
-                        UiParams::Vector(vec![
-                            peer_connection.peer.read()?.id.clone(),
-                            "Disconnected".to_string(),
-                        ]),
-                        self.torrent_name.clone(),
-                    )])?;
-                    return Err(ListenerError::new(format!(
+    /// Returns a Vec of bytes representing the common Bitfield.
+    fn build_bitfield(&self) -> Result<Vec<u8>, ListenerError> {
+        let mut bitfield = vec![0; self.bitfield.len().div_ceil(8)];
+        for (i, piece) in self.bitfield.iter().enumerate() {
+            if let PieceStatus::Downloaded = piece.lock().unwrap().to_owned() {
+                add_piece_to_bitfield(&mut bitfield, i as u32);
+            }
+        }
+        Ok(bitfield)
+    }
+}
+
+impl ConnectionHandler for IncomingPeerHandler {
+    fn frame(&mut self) -> bool {
+        self.last_frame = Instant::now();
+        let result = match self.peer_id {
+            None => self.handshake(),
+            Some(_) => self.message(),
+        };
+        self.keep(result)
+    }
+
+    fn tick(&mut self) -> bool {
+        let result = self.check();
+        self.keep(result)
+    }
+
+    /// Publishes that the connection with the peer was closed.
+    fn closed(&mut self, error: Option<String>) {
+        let peer_id = match self.peer_id.take() {
+            Some(peer_id) => peer_id,
+            None => return,
+        };
+        self.listener.choker.remove_peer(&peer_id);
+        let reason = match (self.reason.take(), error) {
+            (Some(reason), _) => reason,
+            (None, Some(error)) => DisconnectReason::Error(error),
+            (None, None) => DisconnectReason::TorrentNotRunning,
+        };
+        self.listener.events.publish(
+            &self.listener.torrent_name,
+            ClientEvent::PeerDisconnected { peer_id, reason },
+        );
+    }
+}
+
+impl IncomingPeerHandler {
+    /// Returns whether the connection stays open, an error closes it.
+    fn keep(&mut self, result: Result<bool, ListenerError>) -> bool {
+        match result {
+            Ok(keep) => keep,
+            Err(e) => {
+                if let Ok(logger_sender) = self.listener.logger_sender.lock() {
+                    let _r = logger_sender.send(LogMsg::Info(format!(
                         "Error reading incoming peer message: {}",
                         e
                     )));
                 }
-            };
+                self.reason = Some(DisconnectReason::Error(e.to_string()));
+                false
+            }
         }
     }
 
-    /// Returns a Vec of bytes representing the common Bitfield.
-    fn build_bitfield(self: Arc<Self>) -> Result<Vec<u8>, ListenerError> {
-        let mut bitfield_len = (self.bitfield.len() as usize) / 8;
-        if bitfield_len % 8 != 0 {
-            bitfield_len += 1;
+    /// Answers the handshake of the peer and sends it the bitfield.
+    fn handshake(&mut self) -> Result<bool, ListenerError> {
+        self.peer_connection
+            .clone()
+            .handshake(self.listener.client_id.clone())?;
+        let peer_id = self.peer_connection.peer.read()?.id.clone();
+        self.listener.events.publish(
+            &self.listener.torrent_name,
+            ClientEvent::PeerConnected {
+                peer_id: peer_id.clone(),
+                ip: self.peer_connection.peer.read()?.ip.clone(),
+                port: self.peer_connection.peer.read()?.port,
+            },
+        );
+        let meter = self.peer_connection.stream.lock()?.rate_meter();
+        self.listener
+            .choker
+            .add_peer(&peer_id, meter.unwrap_or_else(TransferMeter::new));
+        self.peer_id = Some(peer_id.clone());
+
+        self.update_choke()?;
+        self.listener.events.publish(
+            &self.listener.torrent_name,
+            ClientEvent::PeerInterested { peer_id },
+        );
+        self.peer_connection
+            .clone()
+            .bitfield(self.listener.build_bitfield()?)?;
+        Ok(true)
+    }
+
+    /// Answers a message of the peer, requests go to the UploadManager.
+    fn message(&mut self) -> Result<bool, ListenerError> {
+        let msg = self.peer_connection.clone().read_detect_message()?;
+        if msg == CHOKE_ID {
+            self.reason = Some(DisconnectReason::Choked);
+            return Ok(false);
         }
-        let mut bitfield = vec![0; bitfield_len as usize];
-        for (i, piece) in self.bitfield.iter().enumerate() {
-            if let PieceStatus::Downloaded = piece.lock().unwrap().to_owned() {
-                add_piece_to_bitfield(&mut bitfield, i as u32);
-            }
+        self.check()
+    }
+
+    /// Closes the connection once the torrent is not active or the peer is silent for too long,
+    /// otherwise chokes or unchokes the peer as the Choker says.
+    fn check(&mut self) -> Result<bool, ListenerError> {
+        if !self.listener.state.read()?.is_active() {
+            self.reason = Some(DisconnectReason::TorrentNotRunning);
+            return Ok(false);
         }
-        Ok(bitfield)
+        if self.last_frame.elapsed() > IDLE_TIMEOUT {
+            self.reason = Some(DisconnectReason::Error(
+                "The peer sent nothing for too long".to_string(),
+            ));
+            return Ok(false);
+        }
+        if self.peer_id.is_some() {
+            self.update_choke()?;
+        }
+        Ok(true)
+    }
+
+    /// Sends a choke or an unchoke message if the Choker changed its choice for the peer.
+    fn update_choke(&mut self) -> Result<(), ListenerError> {
+        let peer_id = match &self.peer_id {
+            Some(peer_id) => peer_id.clone(),
+            None => return Ok(()),
+        };
+        let choke = self.listener.choker.is_choked(&peer_id);
+        if choke == self.choked {
+            return Ok(());
+        }
+        self.choked = choke;
+        let event = match choke {
+            true => {
+                self.peer_connection.clone().choke()?;
+                ClientEvent::PeerChoked { peer_id }
+            }
+            false => {
+                self.peer_connection.clone().unchoke()?;
+                ClientEvent::PeerUnchoked { peer_id }
+            }
+        };
+        self.listener
+            .events
+            .publish(&self.listener.torrent_name, event);
+        Ok(())
     }
 }
@@ -0,0 +1,324 @@
+use crate::{
+    errors::local_discovery_error::LocalDiscoveryError,
+    utilities::constants::INFO_HASH_LEN,
+    utilities::utils::{from_hex, to_hex},
+};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+/// The multicast groups of the local peer discovery (BEP 14), the IPv4 one and the IPv6 one.
+pub const LSD_GROUPS: [SocketAddr; 2] = [
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(239, 192, 152, 143)), 6771),
+    SocketAddr::new(
+        IpAddr::V6(Ipv6Addr::new(0xff15, 0, 0, 0, 0, 0, 0xefc0, 0x988f)),
+        6771,
+    ),
+];
+/// An address announcing the same torrent again sooner than this is ignored.
+const MIN_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+/// Announces are split so each one fits in a datagram of any network.
+const MAX_MESSAGE_LEN: usize = 1400;
+const MAX_DATAGRAM_LEN: usize = 2048;
+const SEARCH_LINE: &str = "BT-SEARCH * HTTP/1.1";
+
+/// A peer that announced a torrent on the local network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalPeer {
+    pub ip: IpAddr,
+    pub port: u16,
+    pub info_hash: Vec<u8>,
+}
+
+/// An announce read from a group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Announce {
+    port: u16,
+    info_hashes: Vec<Vec<u8>>,
+    cookie: Option<String>,
+}
+
+/// Announces the torrents of the session to the multicast groups of the local network, and reads the
+/// announces of the other peers there. The cookie of the announces tells apart the ones of the session itself,
+/// which the groups send back.
+pub struct LocalDiscovery {
+    /// A socket joined to each group, with the group it sends to.
+    sockets: Vec<(UdpSocket, SocketAddr)>,
+    port: u16,
+    cookie: String,
+    /// When each address last announced each torrent.
+    last_announces: HashMap<(IpAddr, Vec<u8>), Instant>,
+}
+
+impl LocalDiscovery {
+    /// Joins the groups, announcing port as the one peers connect to. A group of a family the host has no
+    /// network of is left out, it is an error if none can be joined.
+    pub fn new(port: u16, groups: &[SocketAddr]) -> Result<LocalDiscovery, LocalDiscoveryError> {
+        let mut sockets = Vec::new();
+        let mut error = None;
+        for group in groups {
+            match join(*group) {
+                Ok(socket) => sockets.push((socket, *group)),
+                Err(e) => error = Some(e),
+            }
+        }
+        match (sockets.is_empty(), error) {
+            (true, Some(e)) => Err(LocalDiscoveryError::from(e)),
+            (true, None) => Err(LocalDiscoveryError::new(
+                "LocalDiscoveryError: no group to join".to_string(),
+            )),
+            _ => Ok(LocalDiscovery {
+                sockets,
+                port,
+                cookie: format!("{:016x}", rand::random::<u64>()),
+                last_announces: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Announces the torrents to every group. Fails only if no group could be written to.
+    pub fn announce(&self, info_hashes: &[Vec<u8>]) -> Result<(), LocalDiscoveryError> {
+        let mut result = Ok(());
+        let mut sent = false;
+        for (socket, group) in &self.sockets {
+            for message in format_announces(*group, self.port, info_hashes, &self.cookie) {
+                match socket.send_to(message.as_bytes(), group) {
+                    Ok(_) => sent = true,
+                    Err(e) => result = Err(LocalDiscoveryError::from(e)),
+                }
+            }
+        }
+        match sent {
+            true => Ok(()),
+            false => result,
+        }
+    }
+
+    /// Returns the peers announced since the last call. The announces of the session itself, malformed ones
+    /// and the ones an address repeats within a minute are left out.
+    pub fn receive(&mut self) -> Vec<LocalPeer> {
+        let now = Instant::now();
+        self.last_announces
+            .retain(|_, last| now.duration_since(*last) < MIN_ANNOUNCE_INTERVAL);
+        let mut peers = Vec::new();
+        let mut buffer = [0; MAX_DATAGRAM_LEN];
+        for (socket, _) in &self.sockets {
+            while let Ok((read, source)) = socket.recv_from(&mut buffer) {
+                let announce = match parse_announce(&buffer[..read]) {
+                    Some(announce) => announce,
+                    None => continue,
+                };
+                if announce.cookie.as_ref() == Some(&self.cookie) {
+                    continue;
+                }
+                for info_hash in announce.info_hashes {
+                    let key = (source.ip(), info_hash.clone());
+                    if self.last_announces.contains_key(&key) {
+                        continue;
+                    }
+                    self.last_announces.insert(key, now);
+                    peers.push(LocalPeer {
+                        ip: source.ip(),
+                        port: announce.port,
+                        info_hash,
+                    });
+                }
+            }
+        }
+        peers
+    }
+}
+
+/// Returns a nonblocking socket joined to the group, bound to its port along with the other clients of the
+/// host.
+fn join(group: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(group), Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    match group.ip() {
+        IpAddr::V4(ip) => {
+            socket
+                .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), group.port()).into())?;
+            socket.join_multicast_v4(&ip, &Ipv4Addr::UNSPECIFIED)?;
+        }
+        IpAddr::V6(ip) => {
+            socket.set_only_v6(true)?;
+            socket
+                .bind(&SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), group.port()).into())?;
+            // the interface of the default route
+            socket.join_multicast_v6(&ip, 0)?;
+        }
+    }
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+/// Returns the announces of the torrents, as many in each message as fit.
+fn format_announces(
+    group: SocketAddr,
+    port: u16,
+    info_hashes: &[Vec<u8>],
+    cookie: &str,
+) -> Vec<String> {
+    let header = format!("{}\r\nHost: {}\r\nPort: {}\r\n", SEARCH_LINE, group, port);
+    let trailer = format!("cookie: {}\r\n\r\n\r\n", cookie);
+    let mut messages = Vec::new();
+    let mut message = header.clone();
+    for info_hash in info_hashes {
+        let line = format!("Infohash: {}\r\n", to_hex(info_hash));
+        if message.len() > header.len()
+            && message.len() + line.len() + trailer.len() > MAX_MESSAGE_LEN
+        {
+            messages.push(message + &trailer);
+            message = header.clone();
+        }
+        message.push_str(&line);
+    }
+    if message.len() > header.len() {
+        messages.push(message + &trailer);
+    }
+    messages
+}
+
+/// Reads an announce, the names of its headers in any case. None if it is not one or it has no valid
+/// info hash.
+fn parse_announce(datagram: &[u8]) -> Option<Announce> {
+    let message = std::str::from_utf8(datagram).ok()?;
+    let mut lines = message.split("\r\n");
+    if lines.next()?.trim() != SEARCH_LINE {
+        return None;
+    }
+    let mut port = None;
+    let mut info_hashes = Vec::new();
+    let mut cookie = None;
+    for line in lines.take_while(|line| !line.is_empty()) {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim().to_ascii_lowercase(), value.trim()),
+            None => continue,
+        };
+        match name.as_str() {
+            "port" => port = value.parse::<u16>().ok(),
+            "infohash" => {
+                if let Some(info_hash) =
+                    from_hex(value).filter(|info_hash| info_hash.len() == INFO_HASH_LEN)
+                {
+                    info_hashes.push(info_hash);
+                }
+            }
+            "cookie" => cookie = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    let port = port.filter(|port| *port != 0)?;
+    if info_hashes.is_empty() {
+        return None;
+    }
+    Some(Announce {
+        port,
+        info_hashes,
+        cookie,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn info_hash(byte: u8) -> Vec<u8> {
+        vec![byte; INFO_HASH_LEN]
+    }
+
+    #[test]
+    fn test_announces_are_formatted_and_parsed() {
+        let info_hashes: Vec<Vec<u8>> = (0..60).map(info_hash).collect();
+        let messages = format_announces(LSD_GROUPS[0], 6881, &info_hashes, "c00c1e");
+        assert!(messages.len() > 1);
+        assert!(messages[0].starts_with(
+            "BT-SEARCH * HTTP/1.1\r\nHost: 239.192.152.143:6771\r\nPort: 6881\r\nInfohash: 0000"
+        ));
+        assert!(messages[0].ends_with("cookie: c00c1e\r\n\r\n\r\n"));
+        let mut parsed = Vec::new();
+        for message in &messages {
+            assert!(message.len() <= MAX_MESSAGE_LEN);
+            let announce = parse_announce(message.as_bytes()).unwrap();
+            assert_eq!(announce.port, 6881);
+            assert_eq!(announce.cookie, Some("c00c1e".to_string()));
+            parsed.extend(announce.info_hashes);
+        }
+        assert_eq!(parsed, info_hashes);
+
+        let messages = format_announces(LSD_GROUPS[1], 6881, &[info_hash(1)], "c00c1e");
+        assert!(messages[0].contains("Host: [ff15::efc0:988f]:6771\r\n"));
+    }
+
+    #[test]
+    fn test_announces_of_other_clients_are_parsed() {
+        let announce = parse_announce(
+            b"BT-SEARCH * HTTP/1.1\r\nhost: 239.192.152.143:6771\r\nPORT: 51413\r\n\
+              INFOHASH: 0A0A0A0A0A0A0A0A0A0A0A0A0A0A0A0A0A0A0A0A\r\n\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(announce.port, 51413);
+        assert_eq!(announce.info_hashes, vec![info_hash(10)]);
+        assert_eq!(announce.cookie, None);
+
+        assert!(parse_announce(b"M-SEARCH * HTTP/1.1\r\nPort: 1\r\n\r\n").is_none());
+        assert!(
+            parse_announce(b"BT-SEARCH * HTTP/1.1\r\nInfohash: 0a\r\nPort: 1\r\n\r\n").is_none()
+        );
+        assert!(parse_announce(
+            b"BT-SEARCH * HTTP/1.1\r\nInfohash: 0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a\r\n\r\n"
+        )
+        .is_none());
+        assert!(parse_announce(&[0xff, 0xfe]).is_none());
+    }
+
+    #[test]
+    fn test_peers_discover_each_other() {
+        let port = UdpSocket::bind("0.0.0.0:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let group = SocketAddr::new(LSD_GROUPS[0].ip(), port);
+        let (mut first, mut second) = match (
+            LocalDiscovery::new(6881, &[group]),
+            LocalDiscovery::new(6882, &[group]),
+        ) {
+            (Ok(first), Ok(second)) => (first, second),
+            // the host has no multicast
+            _ => return,
+        };
+        if first.announce(&[info_hash(1), info_hash(2)]).is_err() {
+            return;
+        }
+
+        let mut found = Vec::new();
+        for _ in 0..40 {
+            found.extend(second.receive());
+            if found.len() == 2 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        if found.is_empty() {
+            // the host does not send multicast back to itself
+            return;
+        }
+        assert_eq!(found.len(), 2);
+        assert!(found.iter().all(|peer| peer.port == 6881));
+        assert_eq!(found[0].info_hash, info_hash(1));
+        assert_eq!(found[1].info_hash, info_hash(2));
+        // its own announces are left out
+        assert!(first.receive().is_empty());
+
+        // an address announcing again within a minute is ignored
+        first.announce(&[info_hash(1)]).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        assert!(second.receive().is_empty());
+    }
+}
@@ -5,6 +5,8 @@ use std::{fs::File, io::Write, sync::mpsc::Receiver};
 pub struct Logger {
     pub file: File,
     pub receiver: Receiver<LogMsg>,
+    /// 0 discards every message.
+    pub level: u8,
 }
 
 /// This struct represents the message that is sent to the logger. It contains the message in string format.
@@ -19,7 +21,17 @@ impl Logger {
     pub fn new(path: String, receiver: Receiver<LogMsg>) -> Result<Logger, LoggerError> {
         let file = File::create(path)?;
 
-        Ok(Logger { file, receiver })
+        Ok(Logger {
+            file,
+            receiver,
+            level: 1,
+        })
+    }
+
+    /// Sets the level of the logger, with 0 nothing is written.
+    pub fn with_level(mut self, level: u8) -> Logger {
+        self.level = level;
+        self
     }
 
     /// This function starts the logger listener.
@@ -41,6 +53,9 @@ impl Logger {
 
     /// Writes the message into the log file.
     fn log(&mut self, message: &str) -> Result<(), LoggerError> {
+        if self.level == 0 {
+            return Ok(());
+        }
         self.file.write_all(format!("{message}\n").as_bytes())?;
         self.file.flush()?;
         Ok(())
@@ -95,6 +110,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_level_zero_writes_nothing() {
+        let path = std::env::temp_dir()
+            .join("crabrave_logs_level_zero.txt")
+            .to_string_lossy()
+            .to_string();
+        let (sender, receiver): (Sender<LogMsg>, Receiver<LogMsg>) = channel();
+        let mut logger = Logger::new(path.clone(), receiver).unwrap().with_level(0);
+        let logger_handler = spawn(move || logger.start());
+        sender.send(LogMsg::Info("not logged".to_string())).unwrap();
+        sender.send(LogMsg::End).unwrap();
+        let _r = logger_handler.join();
+        let mut buffer = String::new();
+        File::open(path)
+            .unwrap()
+            .read_to_string(&mut buffer)
+            .unwrap();
+        assert!(buffer.is_empty());
+    }
+
     #[test]
     fn test_exiting_logger() {
         // init logger
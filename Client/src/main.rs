@@ -1,18 +1,19 @@
 use crabrave::{
-    client::{Client, ClientInterface},
+    config::Config,
+    events::{ClientEvent, Direction, EventBus, LogSink, TorrentEvent, TrackerStatus},
+    file_selection::FilePriority,
+    logger::Logger,
     parsing::args::get_torrents_paths,
-    parsing::config_parser::config_parse,
-    parsing::torrent_parser::torrent_parse,
-    ui::ui_codes::*,
+    rpc::RpcServer,
+    session::Session,
     utilities::constants::*,
-    utilities::utils::{to_gb, UiParams},
+    utilities::utils::to_gb,
 };
 use gtk::{prelude::*, Builder, Grid, Label, Window, *};
 use std::{
     collections::HashMap,
     env,
     sync::mpsc::{channel, Receiver, Sender},
-    sync::{Arc, Mutex},
     thread::{spawn, JoinHandle},
 };
 
@@ -20,71 +21,75 @@ use std::{
 #[allow(clippy::type_complexity)]
 pub fn main() {
     let args: Vec<String> = env::args().collect();
-    if (args.len() < 2) || (args.len() > 2) {
-        return println!("Incorrect number of arguments");
-    }
-
-    let torrent_dir = match args.get(1) {
-        Some(dir) => dir,
-        None => return println!("Incorrect number of arguments"),
+    // the other commands are in the command line client, the headless binary
+    let torrent_dir = match args.as_slice() {
+        [_, dir] => dir,
+        _ => return println!("Usage: main <torrents directory>"),
     };
 
     let torrent_paths: Vec<String> = get_torrents_paths(torrent_dir).unwrap();
     let torrent_paths_aux = torrent_paths.clone();
     let (aux_tx, aux_rx): (
-        Sender<glib::Sender<Vec<(usize, UiParams, String)>>>,
-        Receiver<glib::Sender<Vec<(usize, UiParams, String)>>>,
+        Sender<glib::Sender<TorrentEvent>>,
+        Receiver<glib::Sender<TorrentEvent>>,
     ) = channel();
 
     let ui_handle = spawn(move || run_ui(aux_tx, torrent_paths));
 
     let client_sender = aux_rx.recv().unwrap();
 
-    let mut handles: Vec<JoinHandle<()>> = Vec::new();
+    // every torrent is served by the same session, which listens on a single port
+    let config = Config::load(CONFIG_PATH).unwrap();
+    let events = EventBus::new();
+    events.subscribe(Box::new(client_sender));
+    // the events of every torrent are also written to the log of the session
+    let (logger_sender, logger_receiver) = channel();
+    let mut logger = Logger::new(
+        format!("{}/session_log.txt", config.storage.log_path),
+        logger_receiver,
+    )
+    .unwrap()
+    .with_level(config.storage.log_level);
+    spawn(move || {
+        let _r = logger.start();
+    });
+    events.subscribe(Box::new(LogSink::new(logger_sender)));
+    let session = Session::new(config.clone(), events).unwrap();
+    let session_copy = session.clone();
+    let session_handle: JoinHandle<()> = spawn(move || {
+        let _r = session_copy.listen();
+    });
+    // the rate limits follow config.yml and its schedule while the client runs
+    let session_copy = session.clone();
+    spawn(move || {
+        let _r = session_copy.watch_config();
+    });
+    // the session can also be controlled through JSON-RPC if a rpc_port is configured
+    let rpc_server = RpcServer::from_config(session.clone(), &config).unwrap();
+    if let Some(server) = rpc_server.clone() {
+        spawn(move || {
+            let _r = server.listen();
+        });
+    }
 
-    let mut port_counter = 0;
     for torrent_path in torrent_paths_aux {
-        let torrent_path_aux1 = torrent_path.clone();
-        let mut config = config_parse(CONFIG_PATH.to_string()).unwrap();
-
-        config.insert("torrent_path".to_string(), torrent_path.clone());
-        let torrent_data = match torrent_parse(&torrent_path) {
-            Ok(data) => data,
-            Err(_) => {
-                continue;
-            }
-        };
-
-        let ui_sender = Arc::new(Mutex::new(client_sender.clone()));
-        let port = LISTENING_PORT + port_counter;
-        let (client, logger_handler) =
-            Client::create(config, ui_sender, torrent_path_aux1, port, torrent_data).unwrap();
-
-        let (download_handler, listener_handler, upload_handler) = client.start().unwrap();
-        handles.push(logger_handler);
-        handles.push(download_handler);
-        handles.push(listener_handler);
-        handles.push(upload_handler);
-
-        port_counter += 1;
+        // torrents that can not be read or announced are skipped
+        let _r = session.add_torrent(&torrent_path);
     }
 
-    // Waits for the threads to finish
-    while !handles.is_empty() {
-        match handles.pop() {
-            Some(handle) => handle.join().unwrap(),
-            None => break,
-        };
+    // Waits for the torrents to finish
+    session.wait().unwrap();
+    if let Some(server) = rpc_server {
+        server.shutdown().unwrap();
     }
+    session.shutdown().unwrap();
+    session_handle.join().unwrap();
     // Waits for the UI thread to finish
     ui_handle.join().unwrap();
 }
 
 /// Runs the UI.
-fn run_ui(
-    sender_aux: Sender<glib::Sender<Vec<(usize, UiParams, String)>>>,
-    torrent_paths: Vec<String>,
-) {
+fn run_ui(sender_aux: Sender<glib::Sender<TorrentEvent>>, torrent_paths: Vec<String>) {
     let application = gtk::Application::new(Some("com.crabrave"), Default::default());
     application.connect_activate(move |application| {
         build_ui(application, sender_aux.clone(), torrent_paths.clone())
@@ -96,12 +101,12 @@ fn run_ui(
 #[allow(clippy::type_complexity)]
 fn build_ui(
     application: &gtk::Application,
-    sender_aux: Sender<glib::Sender<Vec<(usize, UiParams, String)>>>,
+    sender_aux: Sender<glib::Sender<TorrentEvent>>,
     torrent_paths: Vec<String>,
 ) {
     let (sender_client, receiver_client): (
-        glib::Sender<Vec<(usize, UiParams, String)>>,
-        glib::Receiver<Vec<(usize, UiParams, String)>>,
+        glib::Sender<TorrentEvent>,
+        glib::Receiver<TorrentEvent>,
     ) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
 
     sender_aux.send(sender_client).unwrap();
@@ -138,6 +143,7 @@ fn build_ui(
         (String::from("pieces_quantity"), vec![String::from("0")]),
         (String::from("download_speed"), vec![String::from("0")]),
         (String::from("upload_speed"), vec![String::from("0")]),
+        (String::from("cache_stats"), vec![String::from("0 / 0")]),
         (String::from("percentage"), vec![String::from("0")]),
         (String::from("filename"), vec![String::from("")]),
         (String::from("torrents"), torrent_paths.clone()),
@@ -234,68 +240,297 @@ fn build_ui(
     }
     buttonbox.show_all();
 
-    receiver_client.attach(None, move |msg| {
-        let code = &msg[0].0;
-        let param = &msg[0].1;
-        let current_torrent = &msg[0].2;
-
-        match *code {
-            UPDATE_DOWNSPEED => {
-                if let UiParams::Vector(vector) = param {
-                    let mut dic_aux = HashMap::<String, Vec<String>>::new();
-                    let current_torrent_hash = &dic_torrents[current_torrent];
-
-                    for key in current_torrent_hash.keys() {
-                        if key == "download_speed" {
-                            dic_aux.insert(key.to_string(), vec![vector[1].to_string()]);
-                        } else if key == "torrents" {
-                            dic_aux.insert(key.to_string(), current_torrent_hash[key].clone());
-                        } else {
-                            dic_aux.insert(
-                                key.to_string(),
-                                vec![current_torrent_hash[key][0].to_string()],
-                            );
-                        }
+    receiver_client.attach(None, move |torrent_event: TorrentEvent| {
+        let current_torrent = &torrent_event.torrent;
+
+        match &torrent_event.event {
+            ClientEvent::RateSample {
+                peer_id,
+                direction: Direction::Download,
+                bytes_per_second,
+            } => {
+                let speed = format!("{} bytes / sec", bytes_per_second);
+                let mut dic_aux = HashMap::<String, Vec<String>>::new();
+                let current_torrent_hash = &dic_torrents[current_torrent];
+
+                for key in current_torrent_hash.keys() {
+                    if key == "download_speed" {
+                        dic_aux.insert(key.to_string(), vec![speed.clone()]);
+                    } else if key == "torrents" {
+                        dic_aux.insert(key.to_string(), current_torrent_hash[key].clone());
+                    } else {
+                        dic_aux.insert(
+                            key.to_string(),
+                            vec![current_torrent_hash[key][0].to_string()],
+                        );
                     }
-                    *dic_torrents.get_mut(current_torrent).unwrap() = dic_aux;
-                    let label = &labels.get_mut(&vector[0]).unwrap()[0];
-                    label.set_label(vector[1].as_str());
                 }
+                *dic_torrents.get_mut(current_torrent).unwrap() = dic_aux;
+                let label = &labels.get_mut(peer_id).unwrap()[0];
+                label.set_label(speed.as_str());
                 glib::Continue(true)
             }
-            UPDATE_UPSPEED => {
-                if let UiParams::Vector(vector) = param {
-                    let mut dic_aux = HashMap::<String, Vec<String>>::new();
-                    let current_torrent_hash = &dic_torrents[current_torrent];
-
-                    for key in current_torrent_hash.keys() {
-                        if key == "upload_speed" {
-                            dic_aux.insert(key.to_string(), vec![vector[1].to_string()]);
-                        } else if key == "torrents" {
-                            dic_aux.insert(key.to_string(), current_torrent_hash[key].clone());
-                        } else {
-                            dic_aux.insert(
-                                key.to_string(),
-                                vec![current_torrent_hash[key][0].to_string()],
-                            );
-                        }
+            ClientEvent::RateSample {
+                peer_id,
+                direction: Direction::Upload,
+                bytes_per_second,
+            } => {
+                let speed = format!("{} bytes / sec", bytes_per_second);
+                let mut dic_aux = HashMap::<String, Vec<String>>::new();
+                let current_torrent_hash = &dic_torrents[current_torrent];
+
+                for key in current_torrent_hash.keys() {
+                    if key == "upload_speed" {
+                        dic_aux.insert(key.to_string(), vec![speed.clone()]);
+                    } else if key == "torrents" {
+                        dic_aux.insert(key.to_string(), current_torrent_hash[key].clone());
+                    } else {
+                        dic_aux.insert(
+                            key.to_string(),
+                            vec![current_torrent_hash[key][0].to_string()],
+                        );
                     }
-                    *dic_torrents.get_mut(current_torrent).unwrap() = dic_aux;
+                }
+                *dic_torrents.get_mut(current_torrent).unwrap() = dic_aux;
+
+                let label = &labels.get_mut(peer_id).unwrap()[1];
+                label.set_label(speed.as_str());
+                glib::Continue(true)
+            }
+            ClientEvent::CacheStats { hits, misses } => {
+                let cache_stats_text = format!("{} / {}", hits, misses);
+                let mut dic_aux = HashMap::<String, Vec<String>>::new();
+                let current_torrent_hash = &dic_torrents[current_torrent];
 
-                    let label = &labels.get_mut(&vector[0]).unwrap()[1];
-                    label.set_label(vector[1].as_str());
+                for key in current_torrent_hash.keys() {
+                    if key == "cache_stats" {
+                        dic_aux.insert(key.to_string(), vec![cache_stats_text.clone()]);
+                    } else if key == "torrents" {
+                        dic_aux.insert(key.to_string(), current_torrent_hash[key].clone());
+                    } else {
+                        dic_aux.insert(
+                            key.to_string(),
+                            vec![current_torrent_hash[key][0].to_string()],
+                        );
+                    }
                 }
+                *dic_torrents.get_mut(current_torrent).unwrap() = dic_aux;
+
+                let cache_stats_label: Label = builder
+                    .object("summary_cache_stats")
+                    .expect("Couldn't get sum cache stats");
+                cache_stats_label.set_label(cache_stats_text.as_str());
                 glib::Continue(true)
             }
-            DELETE_ONE_ACTIVE_CONNECTION => {
-                // Changes the peer status to disconnected
-                if let UiParams::Vector(vector) = param {
-                    let label = &labels.get_mut(&vector[0]).unwrap();
-                    label[0].set_label("0 bytes / sec");
-                    label[1].set_label("0 bytes / sec");
-                    label[2].set_label("Disconnected");
-                    label[3].set_label("Disconnected");
+            ClientEvent::Metadata {
+                name,
+                total_length,
+                files,
+            } => {
+                let current_torrent_hash = &dic_torrents[current_torrent];
+                let mut dic_aux = HashMap::<String, Vec<String>>::new();
+
+                for key in current_torrent_hash.keys() {
+                    if key == "total_size" {
+                        dic_aux.insert(key.to_string(), vec![to_gb(*total_length)]);
+                    } else if key == "torrents" {
+                        dic_aux.insert(key.to_string(), current_torrent_hash[key].clone());
+                    } else {
+                        dic_aux.insert(
+                            key.to_string(),
+                            vec![current_torrent_hash[key][0].to_string()],
+                        );
+                    }
+                }
+
+                let dic_aux_aux = dic_aux.clone();
+                *dic_torrents.get_mut(current_torrent).unwrap() = dic_aux;
+
+                for file in dic_aux_aux.get("torrents").unwrap() {
+                    let dic_aux = dic_torrents.clone();
+                    let file_aux = file.clone();
+                    let builder_aux = builder.clone();
+                    let button = buttons[file].clone();
+
+                    button.connect_clicked(move |_| {
+                        // Downloaded pieces
+                        let torrent_hash = dic_aux.get(&file_aux).unwrap();
+                        let downloaded_pieces_text =
+                            &torrent_hash.get("downloaded_pieces").unwrap()[0];
+                        let download_label: Label = builder_aux
+                            .object("summary_downloaded_pieces")
+                            .expect("Couldn't get sum down pieces");
+
+                        download_label.set_label(downloaded_pieces_text.as_str());
+
+                        // Verified pieces
+                        let verified_pieces_text = &torrent_hash.get("verified_pieces").unwrap()[0];
+                        let verified_label: Label = builder_aux
+                            .object("summary_verified_pieces")
+                            .expect("Couldn't get sum verified pieces");
+                        verified_label.set_label(verified_pieces_text.as_str());
+
+                        // Active connections
+                        let active_conns_text = &torrent_hash.get("active_connections").unwrap()[0];
+                        let active_conns_label: Label = builder_aux
+                            .object("summary_active_conns")
+                            .expect("Couldn't get sum active conns");
+                        active_conns_label.set_label(active_conns_text.as_str());
+
+                        // Total size
+                        let total_size_text = &torrent_hash.get("total_size").unwrap()[0];
+                        let total_size_label: Label = builder_aux
+                            .object("summary_total_siz")
+                            .expect("Couldn't get sum total size");
+                        total_size_label.set_label(total_size_text.as_str());
+
+                        // Peers quantity
+                        let peers_quantity_text = &torrent_hash.get("peers_quantity").unwrap()[0];
+                        let peers_quantity_label: Label = builder_aux
+                            .object("summary_peers_quant")
+                            .expect("Couldn't get sum peers quantity");
+                        peers_quantity_label.set_label(peers_quantity_text.as_str());
+
+                        // Filename
+                        let peers_quantity_text = &torrent_hash.get("filename").unwrap()[0];
+                        let peers_quantity_label: Label = builder_aux
+                            .object("summary_nam")
+                            .expect("Couldn't get sum peers quantity");
+                        peers_quantity_label.set_label(peers_quantity_text.as_str());
+                        // Progress bar
+                        let percentage = &torrent_hash.get("percentage").unwrap()[0];
+                        let progress_bar: ProgressBar = builder_aux
+                            .object("progressbar")
+                            .expect("Couldn't get progress bar");
+                        progress_bar.set_fraction(percentage.parse::<f64>().unwrap());
+                    });
+                }
+                buttonbox.show_all();
+
+                let current_torrent_hash = &dic_torrents[current_torrent];
+                let mut dic_aux = HashMap::<String, Vec<String>>::new();
+
+                for key in current_torrent_hash.keys() {
+                    if key == "filename" {
+                        dic_aux.insert(key.to_string(), vec![name.to_string()]);
+                    } else if key == "torrents" {
+                        dic_aux.insert(key.to_string(), current_torrent_hash[key].clone());
+                    } else {
+                        dic_aux.insert(
+                            key.to_string(),
+                            vec![current_torrent_hash[key][0].to_string()],
+                        );
+                    }
+                }
+                let dic_aux_aux = dic_aux.clone();
+                *dic_torrents.get_mut(current_torrent).unwrap() = dic_aux;
+
+                for file in dic_aux_aux.get("torrents").unwrap() {
+                    let dic_aux = dic_torrents.clone();
+                    let file_aux = file.clone();
+                    let builder_aux = builder.clone();
+                    let button = buttons[file].clone();
+
+                    button.connect_clicked(move |_| {
+                        // Downloaded pieces
+                        let torrent_hash = dic_aux.get(&file_aux).unwrap();
+                        let downloaded_pieces_text =
+                            &torrent_hash.get("downloaded_pieces").unwrap()[0];
+                        let download_label: Label = builder_aux
+                            .object("summary_downloaded_pieces")
+                            .expect("Couldn't get sum down pieces");
+
+                        download_label.set_label(downloaded_pieces_text.as_str());
+
+                        // Verified pieces
+                        let verified_pieces_text = &torrent_hash.get("verified_pieces").unwrap()[0];
+                        let verified_label: Label = builder_aux
+                            .object("summary_verified_pieces")
+                            .expect("Couldn't get sum verified pieces");
+                        verified_label.set_label(verified_pieces_text.as_str());
+
+                        // Active connections
+                        let active_conns_text = &torrent_hash.get("active_connections").unwrap()[0];
+                        let active_conns_label: Label = builder_aux
+                            .object("summary_active_conns")
+                            .expect("Couldn't get sum active conns");
+                        active_conns_label.set_label(active_conns_text.as_str());
+
+                        // Total size
+                        let total_size_text = &torrent_hash.get("total_size").unwrap()[0];
+                        let total_size_label: Label = builder_aux
+                            .object("summary_total_siz")
+                            .expect("Couldn't get sum total size");
+                        total_size_label.set_label(total_size_text.as_str());
+
+                        // Peers quantity
+                        let peers_quantity_text = &torrent_hash.get("peers_quantity").unwrap()[0];
+                        let peers_quantity_label: Label = builder_aux
+                            .object("summary_peers_quant")
+                            .expect("Couldn't get sum peers quantity");
+                        peers_quantity_label.set_label(peers_quantity_text.as_str());
+
+                        // Filename
+                        let peers_quantity_text = &torrent_hash.get("filename").unwrap()[0];
+                        let peers_quantity_label: Label = builder_aux
+                            .object("summary_nam")
+                            .expect("Couldn't get sum peers quantity");
+                        peers_quantity_label.set_label(peers_quantity_text.as_str());
+                        // Progress bar
+                        let percentage = &torrent_hash.get("percentage").unwrap()[0];
+                        let progress_bar: ProgressBar = builder_aux
+                            .object("progressbar")
+                            .expect("Couldn't get progress bar");
+                        progress_bar.set_fraction(percentage.parse::<f64>().unwrap());
+                    });
                 }
+                buttonbox.show_all();
+
+                // Lists the files of the torrent with their priorities when its button is clicked
+                let file_selection = files.clone();
+                let builder_aux = builder.clone();
+                buttons[current_torrent].connect_clicked(move |_| {
+                    let files_box: gtk::Box = builder_aux
+                        .object("files_box")
+                        .expect("Couldn't get files box");
+                    for child in files_box.children() {
+                        files_box.remove(&child);
+                    }
+
+                    let priorities = file_selection.priorities().unwrap();
+                    for (i, file) in file_selection.files().iter().enumerate() {
+                        let row = gtk::Box::new(Orientation::Horizontal, 10);
+                        let priority_combo = gtk::ComboBoxText::new();
+                        for priority in ["Skip", "Low", "Normal", "High"] {
+                            priority_combo.append_text(priority);
+                        }
+                        priority_combo.set_active(Some(priorities[i].to_i64() as u32));
+
+                        let file_selection_aux = file_selection.clone();
+                        priority_combo.connect_changed(move |combo| {
+                            if let Some(active) = combo.active() {
+                                let _r = file_selection_aux
+                                    .set_priority(i, FilePriority::from_i64(active as i64));
+                            }
+                        });
+                        let file_label = gtk::Label::new(Some(
+                            format!("{} ({})", file.path, to_gb(file.length)).as_str(),
+                        ));
+                        row.pack_start(&priority_combo, false, false, 0);
+                        row.pack_start(&file_label, false, false, 0);
+                        files_box.pack_start(&row, false, false, 0);
+                    }
+                    files_box.show_all();
+                });
+                glib::Continue(true)
+            }
+            ClientEvent::PeerDisconnected { peer_id, .. } => {
+                // Changes the peer status to disconnected
+                let label = &labels.get_mut(peer_id).unwrap();
+                label[0].set_label("0 bytes / sec");
+                label[1].set_label("0 bytes / sec");
+                label[2].set_label("Disconnected");
+                label[3].set_label("Disconnected");
 
                 // Reduces the number of active connections
                 let current_torrent_hash = &dic_torrents[current_torrent];
@@ -383,190 +618,175 @@ fn build_ui(
                 buttonbox.show_all();
                 glib::Continue(true)
             }
-            GET_PIECES_QUANTITY => {
-                if let UiParams::Integer(quantity) = param {
-                    let current_torrent_hash = &dic_torrents[current_torrent];
-
-                    let mut dic_aux = HashMap::<String, Vec<String>>::new();
-
-                    for key in current_torrent_hash.keys() {
-                        if key == "pieces_quantity" {
-                            dic_aux.insert(key.to_string(), vec![quantity.to_string()]);
-                        } else if key == "torrents" {
-                            dic_aux.insert(key.to_string(), current_torrent_hash[key].clone());
-                        } else {
-                            dic_aux.insert(
-                                key.to_string(),
-                                vec![current_torrent_hash[key][0].to_string()],
-                            );
-                        }
-                    }
-                    let dic_aux_aux = dic_aux.clone();
-                    *dic_torrents.get_mut(current_torrent).unwrap() = dic_aux;
-
-                    for file in dic_aux_aux.get("torrents").unwrap() {
-                        let dic_aux = dic_torrents.clone();
-                        let file_aux = file.clone();
-                        let builder_aux = builder.clone();
-                        let button = buttons[file].clone();
-
-                        button.connect_clicked(move |_| {
-                            // Downloaded pieces
-                            let torrent_hash = dic_aux.get(&file_aux).unwrap();
-                            let downloaded_pieces_text =
-                                &torrent_hash.get("downloaded_pieces").unwrap()[0];
-                            let download_label: Label = builder_aux
-                                .object("summary_downloaded_pieces")
-                                .expect("Couldn't get sum down pieces");
-
-                            download_label.set_label(downloaded_pieces_text.as_str());
-
-                            // Verified pieces
-                            let verified_pieces_text =
-                                &torrent_hash.get("verified_pieces").unwrap()[0];
-                            let verified_label: Label = builder_aux
-                                .object("summary_verified_pieces")
-                                .expect("Couldn't get sum verified pieces");
-                            verified_label.set_label(verified_pieces_text.as_str());
-
-                            // Active connections
-                            let active_conns_text =
-                                &torrent_hash.get("active_connections").unwrap()[0];
-                            let active_conns_label: Label = builder_aux
-                                .object("summary_active_conns")
-                                .expect("Couldn't get sum active conns");
-                            active_conns_label.set_label(active_conns_text.as_str());
-
-                            // Total size
-                            let total_size_text = &torrent_hash.get("total_size").unwrap()[0];
-                            let total_size_label: Label = builder_aux
-                                .object("summary_total_siz")
-                                .expect("Couldn't get sum total size");
-                            total_size_label.set_label(total_size_text.as_str());
-
-                            // Peers quantity
-                            let peers_quantity_text =
-                                &torrent_hash.get("peers_quantity").unwrap()[0];
-                            let peers_quantity_label: Label = builder_aux
-                                .object("summary_peers_quant")
-                                .expect("Couldn't get sum peers quantity");
-                            peers_quantity_label.set_label(peers_quantity_text.as_str());
-
-                            // Filename
-                            let peers_quantity_text = &torrent_hash.get("filename").unwrap()[0];
-                            let peers_quantity_label: Label = builder_aux
-                                .object("summary_nam")
-                                .expect("Couldn't get sum peers quantity");
-                            peers_quantity_label.set_label(peers_quantity_text.as_str());
-                            // Progress bar
-                            let percentage = &torrent_hash.get("percentage").unwrap()[0];
-                            let progress_bar: ProgressBar = builder_aux
-                                .object("progressbar")
-                                .expect("Couldn't get progress bar");
-                            progress_bar.set_fraction(percentage.parse::<f64>().unwrap());
-                        });
+            ClientEvent::PiecesChecked { pieces, verified } => {
+                let current_torrent_hash = &dic_torrents[current_torrent];
+
+                let mut dic_aux = HashMap::<String, Vec<String>>::new();
+
+                for key in current_torrent_hash.keys() {
+                    if key == "pieces_quantity" {
+                        dic_aux.insert(key.to_string(), vec![pieces.to_string()]);
+                    } else if key == "torrents" {
+                        dic_aux.insert(key.to_string(), current_torrent_hash[key].clone());
+                    } else {
+                        dic_aux.insert(
+                            key.to_string(),
+                            vec![current_torrent_hash[key][0].to_string()],
+                        );
                     }
-                    buttonbox.show_all();
                 }
-                glib::Continue(true)
-            }
-            UPDATE_INITIAL_DOWNLOADED_PIECES => {
-                if let UiParams::Integer(cont_num_downloaded_pieces) = param {
-                    let current_torrent_hash = &dic_torrents[current_torrent];
-
-                    let mut dic_aux = HashMap::<String, Vec<String>>::new();
-
-                    for key in current_torrent_hash.keys() {
-                        if key == "downloaded_pieces" || key == "verified_pieces" {
-                            dic_aux.insert(
-                                key.to_string(),
-                                vec![cont_num_downloaded_pieces.to_string()],
-                            );
-                        } else if key == "percentage" {
-                            let percentage: f64 = *cont_num_downloaded_pieces as f64
-                                / current_torrent_hash.get("pieces_quantity").unwrap()[0]
-                                    .parse::<i32>()
-                                    .unwrap() as f64;
-                            dic_aux.insert(key.to_string(), vec![percentage.to_string()]);
-                        } else if key == "torrents" {
-                            dic_aux.insert(key.to_string(), current_torrent_hash[key].clone());
-                        } else {
-                            dic_aux.insert(
-                                key.to_string(),
-                                vec![current_torrent_hash[key][0].to_string()],
-                            );
-                        }
-                    }
-                    let dic_aux_aux = dic_aux.clone();
-                    *dic_torrents.get_mut(current_torrent).unwrap() = dic_aux;
-
-                    for file in dic_aux_aux.get("torrents").unwrap() {
-                        let dic_aux = dic_torrents.clone();
-                        let file_aux = file.clone();
-                        let builder_aux = builder.clone();
-                        let button = buttons[file].clone();
-
-                        button.connect_clicked(move |_| {
-                            // Downloaded pieces
-                            let torrent_hash = dic_aux.get(&file_aux).unwrap();
-                            let downloaded_pieces_text =
-                                &torrent_hash.get("downloaded_pieces").unwrap()[0];
-                            let download_label: Label = builder_aux
-                                .object("summary_downloaded_pieces")
-                                .expect("Couldn't get sum down pieces");
-
-                            download_label.set_label(downloaded_pieces_text.as_str());
-
-                            // Verified pieces
-                            let verified_pieces_text =
-                                &torrent_hash.get("verified_pieces").unwrap()[0];
-                            let verified_label: Label = builder_aux
-                                .object("summary_verified_pieces")
-                                .expect("Couldn't get sum verified pieces");
-                            verified_label.set_label(verified_pieces_text.as_str());
-
-                            // Active connections
-                            let active_conns_text =
-                                &torrent_hash.get("active_connections").unwrap()[0];
-                            let active_conns_label: Label = builder_aux
-                                .object("summary_active_conns")
-                                .expect("Couldn't get sum active conns");
-                            active_conns_label.set_label(active_conns_text.as_str());
-
-                            // Total size
-                            let total_size_text = &torrent_hash.get("total_size").unwrap()[0];
-                            let total_size_label: Label = builder_aux
-                                .object("summary_total_siz")
-                                .expect("Couldn't get sum total size");
-                            total_size_label.set_label(total_size_text.as_str());
-
-                            // Peers quantity
-                            let peers_quantity_text =
-                                &torrent_hash.get("peers_quantity").unwrap()[0];
-                            let peers_quantity_label: Label = builder_aux
-                                .object("summary_peers_quant")
-                                .expect("Couldn't get sum peers quantity");
-                            peers_quantity_label.set_label(peers_quantity_text.as_str());
-
-                            // Filename
-                            let peers_quantity_text = &torrent_hash.get("filename").unwrap()[0];
-                            let peers_quantity_label: Label = builder_aux
-                                .object("summary_nam")
-                                .expect("Couldn't get sum peers quantity");
-                            peers_quantity_label.set_label(peers_quantity_text.as_str());
-                            // Progress bar
-                            let percentage = &torrent_hash.get("percentage").unwrap()[0];
-                            let progress_bar: ProgressBar = builder_aux
-                                .object("progressbar")
-                                .expect("Couldn't get progress bar");
-                            progress_bar.set_fraction(percentage.parse::<f64>().unwrap());
-                        });
+                let dic_aux_aux = dic_aux.clone();
+                *dic_torrents.get_mut(current_torrent).unwrap() = dic_aux;
+
+                for file in dic_aux_aux.get("torrents").unwrap() {
+                    let dic_aux = dic_torrents.clone();
+                    let file_aux = file.clone();
+                    let builder_aux = builder.clone();
+                    let button = buttons[file].clone();
+
+                    button.connect_clicked(move |_| {
+                        // Downloaded pieces
+                        let torrent_hash = dic_aux.get(&file_aux).unwrap();
+                        let downloaded_pieces_text =
+                            &torrent_hash.get("downloaded_pieces").unwrap()[0];
+                        let download_label: Label = builder_aux
+                            .object("summary_downloaded_pieces")
+                            .expect("Couldn't get sum down pieces");
+
+                        download_label.set_label(downloaded_pieces_text.as_str());
+
+                        // Verified pieces
+                        let verified_pieces_text = &torrent_hash.get("verified_pieces").unwrap()[0];
+                        let verified_label: Label = builder_aux
+                            .object("summary_verified_pieces")
+                            .expect("Couldn't get sum verified pieces");
+                        verified_label.set_label(verified_pieces_text.as_str());
+
+                        // Active connections
+                        let active_conns_text = &torrent_hash.get("active_connections").unwrap()[0];
+                        let active_conns_label: Label = builder_aux
+                            .object("summary_active_conns")
+                            .expect("Couldn't get sum active conns");
+                        active_conns_label.set_label(active_conns_text.as_str());
+
+                        // Total size
+                        let total_size_text = &torrent_hash.get("total_size").unwrap()[0];
+                        let total_size_label: Label = builder_aux
+                            .object("summary_total_siz")
+                            .expect("Couldn't get sum total size");
+                        total_size_label.set_label(total_size_text.as_str());
+
+                        // Peers quantity
+                        let peers_quantity_text = &torrent_hash.get("peers_quantity").unwrap()[0];
+                        let peers_quantity_label: Label = builder_aux
+                            .object("summary_peers_quant")
+                            .expect("Couldn't get sum peers quantity");
+                        peers_quantity_label.set_label(peers_quantity_text.as_str());
+
+                        // Filename
+                        let peers_quantity_text = &torrent_hash.get("filename").unwrap()[0];
+                        let peers_quantity_label: Label = builder_aux
+                            .object("summary_nam")
+                            .expect("Couldn't get sum peers quantity");
+                        peers_quantity_label.set_label(peers_quantity_text.as_str());
+                        // Progress bar
+                        let percentage = &torrent_hash.get("percentage").unwrap()[0];
+                        let progress_bar: ProgressBar = builder_aux
+                            .object("progressbar")
+                            .expect("Couldn't get progress bar");
+                        progress_bar.set_fraction(percentage.parse::<f64>().unwrap());
+                    });
+                }
+                buttonbox.show_all();
+
+                let current_torrent_hash = &dic_torrents[current_torrent];
+
+                let mut dic_aux = HashMap::<String, Vec<String>>::new();
+
+                for key in current_torrent_hash.keys() {
+                    if key == "downloaded_pieces" || key == "verified_pieces" {
+                        dic_aux.insert(key.to_string(), vec![verified.to_string()]);
+                    } else if key == "percentage" {
+                        let percentage: f64 = *verified as f64
+                            / current_torrent_hash.get("pieces_quantity").unwrap()[0]
+                                .parse::<i32>()
+                                .unwrap() as f64;
+                        dic_aux.insert(key.to_string(), vec![percentage.to_string()]);
+                    } else if key == "torrents" {
+                        dic_aux.insert(key.to_string(), current_torrent_hash[key].clone());
+                    } else {
+                        dic_aux.insert(
+                            key.to_string(),
+                            vec![current_torrent_hash[key][0].to_string()],
+                        );
                     }
-                    buttonbox.show_all();
                 }
+                let dic_aux_aux = dic_aux.clone();
+                *dic_torrents.get_mut(current_torrent).unwrap() = dic_aux;
+
+                for file in dic_aux_aux.get("torrents").unwrap() {
+                    let dic_aux = dic_torrents.clone();
+                    let file_aux = file.clone();
+                    let builder_aux = builder.clone();
+                    let button = buttons[file].clone();
+
+                    button.connect_clicked(move |_| {
+                        // Downloaded pieces
+                        let torrent_hash = dic_aux.get(&file_aux).unwrap();
+                        let downloaded_pieces_text =
+                            &torrent_hash.get("downloaded_pieces").unwrap()[0];
+                        let download_label: Label = builder_aux
+                            .object("summary_downloaded_pieces")
+                            .expect("Couldn't get sum down pieces");
+
+                        download_label.set_label(downloaded_pieces_text.as_str());
+
+                        // Verified pieces
+                        let verified_pieces_text = &torrent_hash.get("verified_pieces").unwrap()[0];
+                        let verified_label: Label = builder_aux
+                            .object("summary_verified_pieces")
+                            .expect("Couldn't get sum verified pieces");
+                        verified_label.set_label(verified_pieces_text.as_str());
+
+                        // Active connections
+                        let active_conns_text = &torrent_hash.get("active_connections").unwrap()[0];
+                        let active_conns_label: Label = builder_aux
+                            .object("summary_active_conns")
+                            .expect("Couldn't get sum active conns");
+                        active_conns_label.set_label(active_conns_text.as_str());
+
+                        // Total size
+                        let total_size_text = &torrent_hash.get("total_size").unwrap()[0];
+                        let total_size_label: Label = builder_aux
+                            .object("summary_total_siz")
+                            .expect("Couldn't get sum total size");
+                        total_size_label.set_label(total_size_text.as_str());
+
+                        // Peers quantity
+                        let peers_quantity_text = &torrent_hash.get("peers_quantity").unwrap()[0];
+                        let peers_quantity_label: Label = builder_aux
+                            .object("summary_peers_quant")
+                            .expect("Couldn't get sum peers quantity");
+                        peers_quantity_label.set_label(peers_quantity_text.as_str());
+
+                        // Filename
+                        let peers_quantity_text = &torrent_hash.get("filename").unwrap()[0];
+                        let peers_quantity_label: Label = builder_aux
+                            .object("summary_nam")
+                            .expect("Couldn't get sum peers quantity");
+                        peers_quantity_label.set_label(peers_quantity_text.as_str());
+                        // Progress bar
+                        let percentage = &torrent_hash.get("percentage").unwrap()[0];
+                        let progress_bar: ProgressBar = builder_aux
+                            .object("progressbar")
+                            .expect("Couldn't get progress bar");
+                        progress_bar.set_fraction(percentage.parse::<f64>().unwrap());
+                    });
+                }
+                buttonbox.show_all();
                 glib::Continue(true)
             }
-            UPDATE_DOWNLOADED_PIECES => {
+            ClientEvent::PieceDownloaded { .. } => {
                 let current_torrent_hash = &dic_torrents[current_torrent];
                 let cont_num_downloaded_pieces = vec![
                     current_torrent_hash["downloaded_pieces"][0]
@@ -664,7 +884,7 @@ fn build_ui(
 
                 glib::Continue(true)
             }
-            UPDATE_ACTIVE_CONNS => {
+            ClientEvent::PeerConnected { peer_id, ip, port } => {
                 let current_torrent_hash = &dic_torrents[current_torrent];
                 let mut dic_aux = HashMap::<String, Vec<String>>::new();
 
@@ -748,199 +968,13 @@ fn build_ui(
                     });
                 }
                 buttonbox.show_all();
-                glib::Continue(true)
-            }
-            UPDATE_TOTAL_SIZE => {
-                if let UiParams::U64(size) = param {
-                    let current_torrent_hash = &dic_torrents[current_torrent];
-                    let mut dic_aux = HashMap::<String, Vec<String>>::new();
-
-                    for key in current_torrent_hash.keys() {
-                        if key == "total_size" {
-                            dic_aux.insert(key.to_string(), vec![to_gb(*size)]);
-                        } else if key == "torrents" {
-                            dic_aux.insert(key.to_string(), current_torrent_hash[key].clone());
-                        } else {
-                            dic_aux.insert(
-                                key.to_string(),
-                                vec![current_torrent_hash[key][0].to_string()],
-                            );
-                        }
-                    }
 
-                    let dic_aux_aux = dic_aux.clone();
-                    *dic_torrents.get_mut(current_torrent).unwrap() = dic_aux;
-
-                    for file in dic_aux_aux.get("torrents").unwrap() {
-                        let dic_aux = dic_torrents.clone();
-                        let file_aux = file.clone();
-                        let builder_aux = builder.clone();
-                        let button = buttons[file].clone();
-
-                        button.connect_clicked(move |_| {
-                            // Downloaded pieces
-                            let torrent_hash = dic_aux.get(&file_aux).unwrap();
-                            let downloaded_pieces_text =
-                                &torrent_hash.get("downloaded_pieces").unwrap()[0];
-                            let download_label: Label = builder_aux
-                                .object("summary_downloaded_pieces")
-                                .expect("Couldn't get sum down pieces");
-
-                            download_label.set_label(downloaded_pieces_text.as_str());
-
-                            // Verified pieces
-                            let verified_pieces_text =
-                                &torrent_hash.get("verified_pieces").unwrap()[0];
-                            let verified_label: Label = builder_aux
-                                .object("summary_verified_pieces")
-                                .expect("Couldn't get sum verified pieces");
-                            verified_label.set_label(verified_pieces_text.as_str());
-
-                            // Active connections
-                            let active_conns_text =
-                                &torrent_hash.get("active_connections").unwrap()[0];
-                            let active_conns_label: Label = builder_aux
-                                .object("summary_active_conns")
-                                .expect("Couldn't get sum active conns");
-                            active_conns_label.set_label(active_conns_text.as_str());
-
-                            // Total size
-                            let total_size_text = &torrent_hash.get("total_size").unwrap()[0];
-                            let total_size_label: Label = builder_aux
-                                .object("summary_total_siz")
-                                .expect("Couldn't get sum total size");
-                            total_size_label.set_label(total_size_text.as_str());
-
-                            // Peers quantity
-                            let peers_quantity_text =
-                                &torrent_hash.get("peers_quantity").unwrap()[0];
-                            let peers_quantity_label: Label = builder_aux
-                                .object("summary_peers_quant")
-                                .expect("Couldn't get sum peers quantity");
-                            peers_quantity_label.set_label(peers_quantity_text.as_str());
-
-                            // Filename
-                            let peers_quantity_text = &torrent_hash.get("filename").unwrap()[0];
-                            let peers_quantity_label: Label = builder_aux
-                                .object("summary_nam")
-                                .expect("Couldn't get sum peers quantity");
-                            peers_quantity_label.set_label(peers_quantity_text.as_str());
-                            // Progress bar
-                            let percentage = &torrent_hash.get("percentage").unwrap()[0];
-                            let progress_bar: ProgressBar = builder_aux
-                                .object("progressbar")
-                                .expect("Couldn't get progress bar");
-                            progress_bar.set_fraction(percentage.parse::<f64>().unwrap());
-                        });
-                    }
-                    buttonbox.show_all();
-                }
-                glib::Continue(true)
-            }
-            UPDATE_FILENAME => {
-                if let UiParams::String(filename) = param {
-                    let current_torrent_hash = &dic_torrents[current_torrent];
-                    let mut dic_aux = HashMap::<String, Vec<String>>::new();
-
-                    for key in current_torrent_hash.keys() {
-                        if key == "filename" {
-                            dic_aux.insert(key.to_string(), vec![filename.to_string()]);
-                        } else if key == "torrents" {
-                            dic_aux.insert(key.to_string(), current_torrent_hash[key].clone());
-                        } else {
-                            dic_aux.insert(
-                                key.to_string(),
-                                vec![current_torrent_hash[key][0].to_string()],
-                            );
-                        }
-                    }
-                    let dic_aux_aux = dic_aux.clone();
-                    *dic_torrents.get_mut(current_torrent).unwrap() = dic_aux;
-
-                    for file in dic_aux_aux.get("torrents").unwrap() {
-                        let dic_aux = dic_torrents.clone();
-                        let file_aux = file.clone();
-                        let builder_aux = builder.clone();
-                        let button = buttons[file].clone();
-
-                        button.connect_clicked(move |_| {
-                            // Downloaded pieces
-                            let torrent_hash = dic_aux.get(&file_aux).unwrap();
-                            let downloaded_pieces_text =
-                                &torrent_hash.get("downloaded_pieces").unwrap()[0];
-                            let download_label: Label = builder_aux
-                                .object("summary_downloaded_pieces")
-                                .expect("Couldn't get sum down pieces");
-
-                            download_label.set_label(downloaded_pieces_text.as_str());
-
-                            // Verified pieces
-                            let verified_pieces_text =
-                                &torrent_hash.get("verified_pieces").unwrap()[0];
-                            let verified_label: Label = builder_aux
-                                .object("summary_verified_pieces")
-                                .expect("Couldn't get sum verified pieces");
-                            verified_label.set_label(verified_pieces_text.as_str());
-
-                            // Active connections
-                            let active_conns_text =
-                                &torrent_hash.get("active_connections").unwrap()[0];
-                            let active_conns_label: Label = builder_aux
-                                .object("summary_active_conns")
-                                .expect("Couldn't get sum active conns");
-                            active_conns_label.set_label(active_conns_text.as_str());
-
-                            // Total size
-                            let total_size_text = &torrent_hash.get("total_size").unwrap()[0];
-                            let total_size_label: Label = builder_aux
-                                .object("summary_total_siz")
-                                .expect("Couldn't get sum total size");
-                            total_size_label.set_label(total_size_text.as_str());
-
-                            // Peers quantity
-                            let peers_quantity_text =
-                                &torrent_hash.get("peers_quantity").unwrap()[0];
-                            let peers_quantity_label: Label = builder_aux
-                                .object("summary_peers_quant")
-                                .expect("Couldn't get sum peers quantity");
-                            peers_quantity_label.set_label(peers_quantity_text.as_str());
-
-                            // Filename
-                            let peers_quantity_text = &torrent_hash.get("filename").unwrap()[0];
-                            let peers_quantity_label: Label = builder_aux
-                                .object("summary_nam")
-                                .expect("Couldn't get sum peers quantity");
-                            peers_quantity_label.set_label(peers_quantity_text.as_str());
-                            // Progress bar
-                            let percentage = &torrent_hash.get("percentage").unwrap()[0];
-                            let progress_bar: ProgressBar = builder_aux
-                                .object("progressbar")
-                                .expect("Couldn't get progress bar");
-                            progress_bar.set_fraction(percentage.parse::<f64>().unwrap());
-                        });
-                    }
-                    buttonbox.show_all();
-                }
-
-                glib::Continue(true)
-            }
-            UPDATE_PEER_ID_IP_PORT => {
-                if let UiParams::Vector(vector) = param {
-                    for i in 0..vector.len() {
-                        if i == 0 {
-                            let label_id: Label = gtk::Label::new(Some(&vector[0]));
-                            grid.attach(&label_id, -8, row_jump, 9, 13);
-                        }
-                        if i == 1 {
-                            let label_ip: Label = gtk::Label::new(Some(&vector[1]));
-                            grid.attach(&label_ip, -7, row_jump, 10, 13);
-                        }
-                        if i == 2 {
-                            let label_port: Label = gtk::Label::new(Some(&vector[2]));
-                            grid.attach(&label_port, -5, row_jump, 11, 13);
-                        }
-                    }
-                }
+                let label_id: Label = gtk::Label::new(Some(peer_id));
+                grid.attach(&label_id, -8, row_jump, 9, 13);
+                let label_ip: Label = gtk::Label::new(Some(ip));
+                grid.attach(&label_ip, -7, row_jump, 10, 13);
+                let label_port: Label = gtk::Label::new(Some(&port.to_string()));
+                grid.attach(&label_port, -5, row_jump, 11, 13);
 
                 let label_down_speed: Label =
                     gtk::Label::new(Some("0 bytes / sec".to_string().as_str()));
@@ -966,35 +1000,34 @@ fn build_ui(
 
                 grid.show_all();
                 row_jump += 100;
-                if let UiParams::Vector(vector) = param {
-                    let id_aux = vector[0].clone();
-                    labels.insert(
-                        id_aux,
-                        vec![
-                            label_down_speed,
-                            label_up_speed,
-                            label_peer_status,
-                            label_client_status,
-                        ],
-                    );
-                }
+                let id_aux = peer_id.clone();
+                labels.insert(
+                    id_aux,
+                    vec![
+                        label_down_speed,
+                        label_up_speed,
+                        label_peer_status,
+                        label_client_status,
+                    ],
+                );
                 glib::Continue(true)
             }
-            UPDATE_UNCHOKE => {
-                if let UiParams::Vector(vector) = param {
-                    let label = &labels.get_mut(&vector[0]).unwrap()[2];
-                    label.set_label(vector[1].as_str());
-                }
+            ClientEvent::PeerUnchoked { peer_id } => {
+                let label = &labels.get_mut(peer_id).unwrap()[2];
+                label.set_label("Unchoked");
                 glib::Continue(true)
             }
-            UPDATE_INTERESTED => {
-                if let UiParams::Vector(vector) = param {
-                    let label = &labels.get_mut(&vector[0]).unwrap()[3];
-                    label.set_label(vector[1].as_str());
-                }
+            ClientEvent::PeerChoked { peer_id } => {
+                let label = &labels.get_mut(peer_id).unwrap()[2];
+                label.set_label("Choked");
                 glib::Continue(true)
             }
-            UPDATE_VERIFIED_PIECES => {
+            ClientEvent::PeerInterested { peer_id } => {
+                let label = &labels.get_mut(peer_id).unwrap()[3];
+                label.set_label("Interested");
+                glib::Continue(true)
+            }
+            ClientEvent::PieceVerified { .. } => {
                 let current_torrent_hash = &dic_torrents[current_torrent];
                 let mut dic_aux = HashMap::<String, Vec<String>>::new();
 
@@ -1082,89 +1115,84 @@ fn build_ui(
                 buttonbox.show_all();
                 glib::Continue(true)
             }
-            UPDATE_PEERS_NUMBER => {
-                if let UiParams::Usize(peers_number) = param {
-                    let current_torrent_hash = &dic_torrents[current_torrent];
-                    let mut dic_aux = HashMap::<String, Vec<String>>::new();
-
-                    for key in current_torrent_hash.keys() {
-                        if key == "peers_quantity" {
-                            dic_aux.insert(key.to_string(), vec![peers_number.to_string()]);
-                        } else if key == "torrents" {
-                            dic_aux.insert(key.to_string(), current_torrent_hash[key].clone());
-                        } else {
-                            dic_aux.insert(
-                                key.to_string(),
-                                vec![current_torrent_hash[key][0].to_string()],
-                            );
-                        }
-                    }
-                    let dic_aux_aux = dic_aux.clone();
-                    *dic_torrents.get_mut(current_torrent).unwrap() = dic_aux;
-
-                    for file in dic_aux_aux.get("torrents").unwrap() {
-                        let dic_aux = dic_torrents.clone();
-                        let file_aux = file.clone();
-                        let builder_aux = builder.clone();
-                        let button = buttons[file].clone();
-
-                        button.connect_clicked(move |_| {
-                            // Downloaded pieces
-                            let torrent_hash = dic_aux.get(&file_aux).unwrap();
-                            let downloaded_pieces_text =
-                                &torrent_hash.get("downloaded_pieces").unwrap()[0];
-                            let download_label: Label = builder_aux
-                                .object("summary_downloaded_pieces")
-                                .expect("Couldn't get sum down pieces");
-
-                            download_label.set_label(downloaded_pieces_text.as_str());
-
-                            // Verified pieces
-                            let verified_pieces_text =
-                                &torrent_hash.get("verified_pieces").unwrap()[0];
-                            let verified_label: Label = builder_aux
-                                .object("summary_verified_pieces")
-                                .expect("Couldn't get sum verified pieces");
-                            verified_label.set_label(verified_pieces_text.as_str());
-
-                            // Active connections
-                            let active_conns_text =
-                                &torrent_hash.get("active_connections").unwrap()[0];
-                            let active_conns_label: Label = builder_aux
-                                .object("summary_active_conns")
-                                .expect("Couldn't get sum active conns");
-                            active_conns_label.set_label(active_conns_text.as_str());
-
-                            // Total size
-                            let total_size_text = &torrent_hash.get("total_size").unwrap()[0];
-                            let total_size_label: Label = builder_aux
-                                .object("summary_total_siz")
-                                .expect("Couldn't get sum total size");
-                            total_size_label.set_label(total_size_text.as_str());
-
-                            // Peers quantity
-                            let peers_quantity_text =
-                                &torrent_hash.get("peers_quantity").unwrap()[0];
-                            let peers_quantity_label: Label = builder_aux
-                                .object("summary_peers_quant")
-                                .expect("Couldn't get sum peers quantity");
-                            peers_quantity_label.set_label(peers_quantity_text.as_str());
-                            // Filename
-                            let peers_quantity_text = &torrent_hash.get("filename").unwrap()[0];
-                            let peers_quantity_label: Label = builder_aux
-                                .object("summary_nam")
-                                .expect("Couldn't get sum peers quantity");
-                            peers_quantity_label.set_label(peers_quantity_text.as_str());
-                            // Progress bar
-                            let percentage = &torrent_hash.get("percentage").unwrap()[0];
-                            let progress_bar: ProgressBar = builder_aux
-                                .object("progressbar")
-                                .expect("Couldn't get progress bar");
-                            progress_bar.set_fraction(percentage.parse::<f64>().unwrap());
-                        });
+            ClientEvent::Tracker(TrackerStatus::Announced { peers }) => {
+                let current_torrent_hash = &dic_torrents[current_torrent];
+                let mut dic_aux = HashMap::<String, Vec<String>>::new();
+
+                for key in current_torrent_hash.keys() {
+                    if key == "peers_quantity" {
+                        dic_aux.insert(key.to_string(), vec![peers.to_string()]);
+                    } else if key == "torrents" {
+                        dic_aux.insert(key.to_string(), current_torrent_hash[key].clone());
+                    } else {
+                        dic_aux.insert(
+                            key.to_string(),
+                            vec![current_torrent_hash[key][0].to_string()],
+                        );
                     }
-                    buttonbox.show_all();
                 }
+                let dic_aux_aux = dic_aux.clone();
+                *dic_torrents.get_mut(current_torrent).unwrap() = dic_aux;
+
+                for file in dic_aux_aux.get("torrents").unwrap() {
+                    let dic_aux = dic_torrents.clone();
+                    let file_aux = file.clone();
+                    let builder_aux = builder.clone();
+                    let button = buttons[file].clone();
+
+                    button.connect_clicked(move |_| {
+                        // Downloaded pieces
+                        let torrent_hash = dic_aux.get(&file_aux).unwrap();
+                        let downloaded_pieces_text =
+                            &torrent_hash.get("downloaded_pieces").unwrap()[0];
+                        let download_label: Label = builder_aux
+                            .object("summary_downloaded_pieces")
+                            .expect("Couldn't get sum down pieces");
+
+                        download_label.set_label(downloaded_pieces_text.as_str());
+
+                        // Verified pieces
+                        let verified_pieces_text = &torrent_hash.get("verified_pieces").unwrap()[0];
+                        let verified_label: Label = builder_aux
+                            .object("summary_verified_pieces")
+                            .expect("Couldn't get sum verified pieces");
+                        verified_label.set_label(verified_pieces_text.as_str());
+
+                        // Active connections
+                        let active_conns_text = &torrent_hash.get("active_connections").unwrap()[0];
+                        let active_conns_label: Label = builder_aux
+                            .object("summary_active_conns")
+                            .expect("Couldn't get sum active conns");
+                        active_conns_label.set_label(active_conns_text.as_str());
+
+                        // Total size
+                        let total_size_text = &torrent_hash.get("total_size").unwrap()[0];
+                        let total_size_label: Label = builder_aux
+                            .object("summary_total_siz")
+                            .expect("Couldn't get sum total size");
+                        total_size_label.set_label(total_size_text.as_str());
+
+                        // Peers quantity
+                        let peers_quantity_text = &torrent_hash.get("peers_quantity").unwrap()[0];
+                        let peers_quantity_label: Label = builder_aux
+                            .object("summary_peers_quant")
+                            .expect("Couldn't get sum peers quantity");
+                        peers_quantity_label.set_label(peers_quantity_text.as_str());
+                        // Filename
+                        let peers_quantity_text = &torrent_hash.get("filename").unwrap()[0];
+                        let peers_quantity_label: Label = builder_aux
+                            .object("summary_nam")
+                            .expect("Couldn't get sum peers quantity");
+                        peers_quantity_label.set_label(peers_quantity_text.as_str());
+                        // Progress bar
+                        let percentage = &torrent_hash.get("percentage").unwrap()[0];
+                        let progress_bar: ProgressBar = builder_aux
+                            .object("progressbar")
+                            .expect("Couldn't get progress bar");
+                        progress_bar.set_fraction(percentage.parse::<f64>().unwrap());
+                    });
+                }
+                buttonbox.show_all();
                 glib::Continue(true)
             }
             _ => glib::Continue(true),
@@ -0,0 +1,23 @@
+use crabrave::parsing::args::parse_create_options;
+use std::env;
+
+const USAGE: &str = "Usage: make_torrent <file or directory> <announce url> [-o output.torrent] \
+[--announce-list url1,url2;url3] [--comment text] [--created-by name] [--private] \
+[--url-list url] [--piece-length bytes]";
+
+/// Creates a .torrent file from a file or a directory.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        return println!("{}", USAGE);
+    }
+
+    let (builder, output) = match parse_create_options(&args[1], &args[2], &args[3..]) {
+        Ok(options) => options,
+        Err(e) => return println!("{}\n{}", e, USAGE),
+    };
+    match builder.write(&output) {
+        Ok(_) => println!("Torrent written to {}", output),
+        Err(e) => println!("Error creating the torrent: {}", e),
+    }
+}
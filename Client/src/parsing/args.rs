@@ -1,5 +1,44 @@
-use crate::errors::args_error::ArgsError;
-use std::fs::read_dir;
+use crate::{errors::args_error::ArgsError, parsing::torrent_builder::TorrentBuilder};
+use std::{collections::HashMap, fs::read_dir, path::Path};
+
+/// The flags that override a value of the configuration file, and the key they override.
+const CONFIG_FLAGS: [(&str, &str); 7] = [
+    ("--port", "port"),
+    ("--bind", "bind_addresses"),
+    ("--download-path", "download_path"),
+    ("--out", "download_path"),
+    ("--log-path", "log_path"),
+    ("--max-download-rate", "max_download_rate"),
+    ("--max-upload-rate", "max_upload_rate"),
+];
+
+/// What the command line client was asked to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Downloads and seeds every torrent of a directory.
+    Run { torrents_dir: String },
+    /// Downloads a torrent file and exits once it is complete.
+    Download { torrent: String },
+    /// Seeds the torrent from data that is already on disk.
+    Seed { torrent: String, data: String },
+    /// Shows the contents of a torrent file.
+    Info { torrent: String },
+    /// Creates a torrent file, the options are the ones of make_torrent.
+    Create {
+        path: String,
+        announce: String,
+        options: Vec<String>,
+    },
+    /// Checks the pieces of the data on disk against the torrent.
+    Check { torrent: String, data: String },
+}
+
+/// A command and the configuration values given as flags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandLine {
+    pub command: Command,
+    pub config: HashMap<String, String>,
+}
 
 pub fn get_torrents_paths(torrent_dir: &str) -> Result<Vec<String>, ArgsError> {
     //! Returns a String Vector with the filenames into given directory.
@@ -17,6 +56,146 @@ pub fn get_torrents_paths(torrent_dir: &str) -> Result<Vec<String>, ArgsError> {
     Ok(torrent_paths)
 }
 
+/// Parses the arguments of the command line client, without the name of the program.
+/// A single argument that is not a command is read as a directory of torrents to run.
+pub fn parse_command_line(args: &[String]) -> Result<CommandLine, ArgsError> {
+    let (name, rest) = args
+        .split_first()
+        .ok_or_else(|| ArgsError::with_msg("ArgsError: missing command".to_string()))?;
+    if name == "create" {
+        return match rest {
+            [path, announce, options @ ..] => Ok(CommandLine {
+                command: Command::Create {
+                    path: path.clone(),
+                    announce: announce.clone(),
+                    options: options.to_vec(),
+                },
+                config: HashMap::new(),
+            }),
+            _ => Err(wrong_arguments(name)),
+        };
+    }
+
+    let (positional, config) = split_flags(rest)?;
+    let command = match (name.as_str(), positional.as_slice()) {
+        ("download", [torrent]) => Command::Download {
+            torrent: torrent.clone(),
+        },
+        ("seed", [torrent, data]) => Command::Seed {
+            torrent: torrent.clone(),
+            data: data.clone(),
+        },
+        ("info", [torrent]) => Command::Info {
+            torrent: torrent.clone(),
+        },
+        ("check", [torrent, data]) => Command::Check {
+            torrent: torrent.clone(),
+            data: data.clone(),
+        },
+        ("download" | "seed" | "info" | "check", _) => return Err(wrong_arguments(name)),
+        (_, []) => Command::Run {
+            torrents_dir: name.clone(),
+        },
+        _ => {
+            return Err(ArgsError::with_msg(format!(
+                "ArgsError: unknown command {}",
+                name
+            )))
+        }
+    };
+    Ok(CommandLine { command, config })
+}
+
+/// Returns a TorrentBuilder for the file or directory at path with the options of make_torrent,
+/// and the path of the torrent file to write.
+pub fn parse_create_options(
+    path: &str,
+    announce: &str,
+    options: &[String],
+) -> Result<(TorrentBuilder, String), ArgsError> {
+    let mut builder = TorrentBuilder::new(path, announce);
+    let mut output = match Path::new(path).file_name().and_then(|name| name.to_str()) {
+        Some(name) => format!("{}.torrent", name),
+        None => return Err(ArgsError::with_msg(format!("Invalid path {}", path))),
+    };
+    let mut url_list = Vec::new();
+
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        if option == "--private" {
+            builder = builder.private(true);
+            continue;
+        }
+        let value = options
+            .next()
+            .ok_or_else(|| ArgsError::with_msg(format!("Missing value for {}", option)))?;
+        builder = match option.as_str() {
+            "-o" => {
+                output = value.to_string();
+                builder
+            }
+            "--announce-list" => builder.announce_list(
+                // tiers are separated by ';' and the trackers of a tier by ','
+                value
+                    .split(';')
+                    .map(|tier| tier.split(',').map(|url| url.to_string()).collect())
+                    .collect(),
+            ),
+            "--comment" => builder.comment(value),
+            "--created-by" => builder.created_by(value),
+            "--url-list" => {
+                url_list.push(value.to_string());
+                builder
+            }
+            "--piece-length" => match value.parse::<u64>() {
+                Ok(piece_length) => builder.piece_length(piece_length),
+                Err(_) => {
+                    return Err(ArgsError::with_msg(format!(
+                        "Invalid piece length {}",
+                        value
+                    )))
+                }
+            },
+            _ => return Err(ArgsError::with_msg(format!("Unknown option {}", option))),
+        };
+    }
+    Ok((builder.url_list(url_list), output))
+}
+
+/// Splits the arguments in the positional ones and the configuration values of the flags.
+fn split_flags(args: &[String]) -> Result<(Vec<String>, HashMap<String, String>), ArgsError> {
+    let mut positional = Vec::new();
+    let mut config = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            positional.push(arg.clone());
+            continue;
+        }
+        let key = CONFIG_FLAGS
+            .iter()
+            .find(|(flag, _)| flag == arg)
+            .map(|(_, key)| *key)
+            .ok_or_else(|| ArgsError::with_msg(format!("ArgsError: unknown flag {}", arg)))?;
+        let value = args
+            .next()
+            .ok_or_else(|| ArgsError::with_msg(format!("ArgsError: missing value for {}", arg)))?;
+        // numbers are checked here, so a typo is not found once the torrent is running
+        if !key.ends_with("_path") && value.parse::<u64>().is_err() {
+            return Err(ArgsError::with_msg(format!(
+                "ArgsError: {} is not a number",
+                value
+            )));
+        }
+        config.insert(key.to_string(), value.clone());
+    }
+    Ok((positional, config))
+}
+
+fn wrong_arguments(command: &str) -> ArgsError {
+    ArgsError::with_msg(format!("ArgsError: wrong arguments for {}", command))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -30,4 +209,55 @@ mod tests {
         let paths = get_torrents_paths("src/test_files/torrent_test_files").unwrap();
         assert!(paths.len() >= 1);
     }
+
+    fn args(line: &str) -> Vec<String> {
+        line.split(' ').map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_flags_override_the_config() {
+        let command_line =
+            parse_command_line(&args("download a.torrent --out downloads --port 6881")).unwrap();
+        assert_eq!(
+            command_line.command,
+            Command::Download {
+                torrent: "a.torrent".to_string()
+            }
+        );
+        assert_eq!(command_line.config["download_path"], "downloads");
+        assert_eq!(command_line.config["port"], "6881");
+    }
+
+    #[test]
+    fn test_commands_and_their_arguments() {
+        assert_eq!(
+            parse_command_line(&args("seed a.torrent data"))
+                .unwrap()
+                .command,
+            Command::Seed {
+                torrent: "a.torrent".to_string(),
+                data: "data".to_string()
+            }
+        );
+        assert_eq!(
+            parse_command_line(&args("torrents")).unwrap().command,
+            Command::Run {
+                torrents_dir: "torrents".to_string()
+            }
+        );
+        assert_eq!(
+            parse_command_line(&args("create dir http://tracker --private"))
+                .unwrap()
+                .command,
+            Command::Create {
+                path: "dir".to_string(),
+                announce: "http://tracker".to_string(),
+                options: vec!["--private".to_string()]
+            }
+        );
+        assert!(parse_command_line(&args("check a.torrent")).is_err());
+        assert!(parse_command_line(&args("info a.torrent --port many")).is_err());
+        assert!(parse_command_line(&args("info a.torrent --verbose")).is_err());
+        assert!(parse_command_line(&args("upload a.torrent")).is_err());
+    }
 }
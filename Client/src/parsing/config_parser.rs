@@ -5,14 +5,16 @@ use std::{
     io::{prelude::*, BufReader, Error},
 };
 
-/// Returns a Hashmap of Strings with the configuration fields given a configuration file.
+/// Returns the values of a configuration file given its path. Keys inside a section are joined to it
+/// with a dot, so `port` indented under `network:` is returned as `network.port`.
 pub fn config_parse(filename: String) -> Result<HashMap<String, String>, ConfigParserError> {
     let cfgfile = File::open(&filename);
     let cfgfile = cfgfile?;
     let cfgfile = BufReader::new(cfgfile);
     let mut cfg = HashMap::new();
-    for line in cfgfile.lines() {
-        process_line(line, &mut cfg)?;
+    let mut section = None;
+    for (index, line) in cfgfile.lines().enumerate() {
+        process_line(line, index + 1, &mut section, &mut cfg)?;
     }
 
     if cfg.keys().len() == 0 {
@@ -23,30 +25,67 @@ pub fn config_parse(filename: String) -> Result<HashMap<String, String>, ConfigP
 }
 
 /// Reads a line and if there is a key and value, it adds them to the HashMap.
+/// A key without a value opens the section of the indented lines that follow it.
 fn process_line(
     line: Result<String, Error>,
+    line_number: usize,
+    section: &mut Option<String>,
     cfg: &mut HashMap<String, String>,
 ) -> Result<(), ConfigParserError> {
-    let line = line?.replace(' ', "").replace('"', "");
-    let index = line.find(':').unwrap_or(0);
-
-    if index == 0 {
+    let line = line?;
+    let line = match line.find(" #") {
+        Some(comment) => &line[..comment],
+        None => line.as_str(),
+    };
+    if line.trim().is_empty() || line.trim_start().starts_with('#') {
         return Ok(());
     }
 
-    let key = line.get(0..index);
-    let key = key.unwrap_or("");
-
-    let val = line.get(index + 1..);
-    let val = match val {
-        Some(val) => val,
-        None => return Err(ConfigParserError::new()),
-    };
+    let (key, val) = line.split_once(':').ok_or_else(|| {
+        ConfigParserError::with_msg(format!(
+            "ConfigParserError: line {} is not a key and a value",
+            line_number
+        ))
+    })?;
+    let indented = line.starts_with(' ') || line.starts_with('\t');
+    let key = key.trim();
+    let val = unquote(val.trim());
+    if key.is_empty() {
+        return Err(ConfigParserError::with_msg(format!(
+            "ConfigParserError: line {} has no key",
+            line_number
+        )));
+    }
 
-    cfg.insert(key.to_string(), val.to_string());
+    match (indented, section.as_ref()) {
+        (false, _) if val.is_empty() => *section = Some(key.to_string()),
+        (false, _) => {
+            *section = None;
+            cfg.insert(key.to_string(), val.to_string());
+        }
+        (true, Some(section)) => {
+            cfg.insert(format!("{}.{}", section, key), val.to_string());
+        }
+        (true, None) => {
+            return Err(ConfigParserError::with_msg(format!(
+                "ConfigParserError: {} at line {} is indented outside of a section",
+                key, line_number
+            )))
+        }
+    }
     Ok(())
 }
 
+/// Removes the quotes around a value, the ones inside of it are kept.
+fn unquote(val: &str) -> &str {
+    for quote in ['"', '\''] {
+        if val.len() >= 2 && val.starts_with(quote) && val.ends_with(quote) {
+            return &val[1..val.len() - 1];
+        }
+    }
+    val
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -84,4 +123,30 @@ mod tests {
         ]);
         assert_eq!(cfg.unwrap(), right);
     }
+
+    #[test]
+    fn test_sections_and_comments() {
+        let filename = std::env::temp_dir().join("crabrave_config_sections.yml");
+        std::fs::write(
+            &filename,
+            "# the client\nlog_path: \"my logs\"\nnetwork:\n  port: 6881 # default\n  rpc_token: 'a b'\n\nstorage:\n  download_path: downloads\n",
+        )
+        .unwrap();
+        let cfg = config_parse(filename.to_str().unwrap().to_string()).unwrap();
+        let right = HashMap::from([
+            ("log_path".to_string(), "my logs".to_string()),
+            ("network.port".to_string(), "6881".to_string()),
+            ("network.rpc_token".to_string(), "a b".to_string()),
+            ("storage.download_path".to_string(), "downloads".to_string()),
+        ]);
+        assert_eq!(cfg, right);
+    }
+
+    #[test]
+    fn test_indented_key_without_section() {
+        let filename = std::env::temp_dir().join("crabrave_config_indented.yml");
+        std::fs::write(&filename, "  port: 6881\n").unwrap();
+        let error = config_parse(filename.to_str().unwrap().to_string()).unwrap_err();
+        assert!(error.to_string().contains("port at line 1"));
+    }
 }
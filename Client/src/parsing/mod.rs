@@ -1,5 +1,4 @@
 pub mod args;
-pub mod bdecoder;
-pub mod bencoder;
 pub mod config_parser;
+pub mod torrent_builder;
 pub mod torrent_parser;
@@ -0,0 +1,397 @@
+use crate::{
+    errors::torrent_builder_error::TorrentBuilderError,
+    parsing::torrent_parser::{FileEntry, InfoDict, Metainfo},
+    utilities::constants::PIECE_HASH_LEN,
+};
+use sha1::{Digest, Sha1};
+use std::{
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    thread,
+};
+
+const MIN_PIECE_LENGTH: u64 = 16 * 1024;
+const MAX_PIECE_LENGTH: u64 = 16 * 1024 * 1024;
+const TARGET_PIECES_QUANTITY: u64 = 1500;
+const DEFAULT_CREATED_BY: &str = "CrabRave";
+
+/// The TorrentBuilder creates a .torrent file from a file or a directory.
+pub struct TorrentBuilder {
+    path: String,
+    announce: String,
+    announce_list: Vec<Vec<String>>,
+    comment: Option<String>,
+    created_by: String,
+    private: bool,
+    url_list: Vec<String>,
+    piece_length: Option<u64>,
+    threads: usize,
+}
+
+/// A file that goes into the torrent: where it is on disk and the path components stored in the torrent.
+struct SourceFile {
+    disk_path: PathBuf,
+    torrent_path: Vec<String>,
+    length: u64,
+}
+
+impl TorrentBuilder {
+    /// Creates a builder for the file or directory at path, announcing to the given tracker url.
+    pub fn new(path: &str, announce: &str) -> TorrentBuilder {
+        TorrentBuilder {
+            path: path.to_string(),
+            announce: announce.to_string(),
+            announce_list: Vec::new(),
+            comment: None,
+            created_by: DEFAULT_CREATED_BY.to_string(),
+            private: false,
+            url_list: Vec::new(),
+            piece_length: None,
+            threads: thread::available_parallelism()
+                .map(|threads| threads.get())
+                .unwrap_or(1),
+        }
+    }
+
+    /// Sets the tiers of trackers of the announce-list.
+    pub fn announce_list(mut self, tiers: Vec<Vec<String>>) -> TorrentBuilder {
+        self.announce_list = tiers;
+        self
+    }
+
+    /// Sets the comment of the torrent.
+    pub fn comment(mut self, comment: &str) -> TorrentBuilder {
+        self.comment = Some(comment.to_string());
+        self
+    }
+
+    /// Sets the program that created the torrent.
+    pub fn created_by(mut self, created_by: &str) -> TorrentBuilder {
+        self.created_by = created_by.to_string();
+        self
+    }
+
+    /// Marks the torrent as private, so clients only get peers from its trackers.
+    pub fn private(mut self, private: bool) -> TorrentBuilder {
+        self.private = private;
+        self
+    }
+
+    /// Sets the web seeds of the torrent.
+    pub fn url_list(mut self, urls: Vec<String>) -> TorrentBuilder {
+        self.url_list = urls;
+        self
+    }
+
+    /// Sets the piece length instead of picking one from the size of the content. It must be a power of two of
+    /// at least 16 KiB.
+    pub fn piece_length(mut self, piece_length: u64) -> TorrentBuilder {
+        self.piece_length = Some(piece_length);
+        self
+    }
+
+    /// Sets how many threads hash the pieces.
+    pub fn threads(mut self, threads: usize) -> TorrentBuilder {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Hashes the content and returns the bencoded torrent.
+    pub fn build(&self) -> Result<Vec<u8>, TorrentBuilderError> {
+        if let Some(piece_length) = self.piece_length {
+            if piece_length < MIN_PIECE_LENGTH || !piece_length.is_power_of_two() {
+                return Err(TorrentBuilderError::new(format!(
+                    "TorrentBuilderError: piece length {} is not a power of two of at least {}",
+                    piece_length, MIN_PIECE_LENGTH
+                )));
+            }
+        }
+        let root = Path::new(&self.path);
+        let name = root
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| {
+                TorrentBuilderError::new(format!("TorrentBuilderError: invalid path {}", self.path))
+            })?
+            .to_string();
+        let files = collect_files(root)?;
+        let total_length: u64 = files.iter().map(|file| file.length).sum();
+        if total_length == 0 {
+            return Err(TorrentBuilderError::new(format!(
+                "TorrentBuilderError: {} has no content",
+                self.path
+            )));
+        }
+        let piece_length = match self.piece_length {
+            Some(piece_length) => piece_length,
+            None => pick_piece_length(total_length),
+        };
+        let pieces = hash_pieces(&files, total_length, piece_length, self.threads)?;
+
+        let mut info = if root.is_dir() {
+            let files = files
+                .iter()
+                .map(|file| FileEntry::new(file.torrent_path.clone(), file.length))
+                .collect();
+            InfoDict::new(name, piece_length, pieces, None, files)
+        } else {
+            InfoDict::new(name, piece_length, pieces, Some(total_length), vec![])
+        };
+        info.private = self.private;
+
+        let mut metainfo = Metainfo::new(self.announce.clone(), info);
+        metainfo.announce_list = self.announce_list.clone();
+        metainfo.comment = self.comment.clone();
+        metainfo.created_by = Some(self.created_by.clone());
+        metainfo.creation_date = Some(chrono::Utc::now().timestamp());
+        metainfo.url_list = self.url_list.clone();
+        Ok(metainfo.to_bytes())
+    }
+
+    /// Builds the torrent and writes it at dst_path.
+    pub fn write(&self, dst_path: &str) -> Result<(), TorrentBuilderError> {
+        let torrent = self.build()?;
+        fs::write(dst_path, torrent)?;
+        Ok(())
+    }
+}
+
+/// Returns a power of two piece length that splits the content in about TARGET_PIECES_QUANTITY pieces.
+pub fn pick_piece_length(total_length: u64) -> u64 {
+    let mut piece_length = MIN_PIECE_LENGTH;
+    while total_length / piece_length > TARGET_PIECES_QUANTITY && piece_length < MAX_PIECE_LENGTH {
+        piece_length *= 2;
+    }
+    piece_length
+}
+
+/// Returns the files inside root sorted by path, or root itself if it is a file.
+fn collect_files(root: &Path) -> Result<Vec<SourceFile>, TorrentBuilderError> {
+    if root.is_file() {
+        return Ok(vec![SourceFile {
+            disk_path: root.to_path_buf(),
+            torrent_path: vec![],
+            length: fs::metadata(root)?.len(),
+        }]);
+    }
+    let mut files = Vec::new();
+    walk_dir(root, &mut Vec::new(), &mut files)?;
+    Ok(files)
+}
+
+/// Adds the files of dir to files, going into its subdirectories in name order. Symbolic links to
+/// directories are skipped, they could point back to the directory being walked.
+fn walk_dir(
+    dir: &Path,
+    prefix: &mut Vec<String>,
+    files: &mut Vec<SourceFile>,
+) -> Result<(), TorrentBuilderError> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()?;
+    entries.sort();
+    for entry in entries {
+        let name = match entry.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let file_type = fs::symlink_metadata(&entry)?.file_type();
+        if file_type.is_symlink() && entry.is_dir() {
+            continue;
+        }
+        prefix.push(name);
+        if file_type.is_dir() {
+            walk_dir(&entry, prefix, files)?;
+        } else {
+            files.push(SourceFile {
+                length: fs::metadata(&entry)?.len(),
+                disk_path: entry,
+                torrent_path: prefix.clone(),
+            });
+        }
+        prefix.pop();
+    }
+    Ok(())
+}
+
+/// Returns the sha1 of every piece. The pieces are split in contiguous ranges, one per thread.
+fn hash_pieces(
+    files: &[SourceFile],
+    total_length: u64,
+    piece_length: u64,
+    threads: usize,
+) -> Result<Vec<[u8; PIECE_HASH_LEN]>, TorrentBuilderError> {
+    let pieces_quantity = total_length.div_ceil(piece_length) as usize;
+    let pieces_per_thread = pieces_quantity.div_ceil(threads);
+
+    let hashes = thread::scope(|scope| {
+        let handles: Vec<_> = (0..pieces_quantity)
+            .step_by(pieces_per_thread)
+            .map(|first| {
+                let last = (first + pieces_per_thread).min(pieces_quantity);
+                scope.spawn(move || {
+                    let mut hashes = Vec::with_capacity(last - first);
+                    for i in first..last {
+                        let start = i as u64 * piece_length;
+                        let length = piece_length.min(total_length - start);
+                        let piece = read_range(files, start, length)?;
+                        hashes.push(Sha1::digest(&piece).into());
+                    }
+                    Ok::<Vec<[u8; PIECE_HASH_LEN]>, TorrentBuilderError>(hashes)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join())
+            .collect::<Vec<_>>()
+    });
+
+    let mut pieces = Vec::with_capacity(pieces_quantity);
+    for hash in hashes {
+        pieces.extend(hash??);
+    }
+    Ok(pieces)
+}
+
+/// Reads length bytes starting at offset of the content, which is the concatenation of all the files.
+fn read_range(
+    files: &[SourceFile],
+    offset: u64,
+    length: u64,
+) -> Result<Vec<u8>, TorrentBuilderError> {
+    let mut data = Vec::with_capacity(length as usize);
+    let end = offset + length;
+    let mut file_start = 0;
+    for file in files {
+        let file_end = file_start + file.length;
+        if file_start < end && file_end > offset {
+            let begin = offset.max(file_start) - file_start;
+            let finish = end.min(file_end) - file_start;
+            let mut source = File::open(&file.disk_path)?;
+            source.seek(SeekFrom::Start(begin))?;
+            let mut buffer = vec![0; (finish - begin) as usize];
+            source.read_exact(&mut buffer)?;
+            data.extend(buffer);
+        }
+        if file_end >= end {
+            break;
+        }
+        file_start = file_end;
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parsing::torrent_parser::torrent_parse, test_files::test_helper::test_dir};
+
+    #[test]
+    fn test_pick_piece_length() {
+        assert_eq!(pick_piece_length(1), MIN_PIECE_LENGTH);
+        assert_eq!(pick_piece_length(1500 * 1024 * 1024), 1024 * 1024);
+        assert_eq!(pick_piece_length(u64::MAX), MAX_PIECE_LENGTH);
+    }
+
+    #[test]
+    fn test_build_directory_torrent() {
+        let dir = test_dir("torrent_builder", "directory");
+        let content = dir.join("content");
+        fs::create_dir_all(content.join("sub")).unwrap();
+        fs::write(content.join("b.txt"), vec![2u8; 20000]).unwrap();
+        fs::write(content.join("sub/a.txt"), vec![1u8; 30000]).unwrap();
+
+        let torrent_path = dir.join("content.torrent");
+        TorrentBuilder::new(content.to_str().unwrap(), "http://tracker/announce")
+            .announce_list(vec![vec!["http://tracker/announce".to_string()]])
+            .comment("test")
+            .private(true)
+            .piece_length(MIN_PIECE_LENGTH)
+            .threads(2)
+            .write(torrent_path.to_str().unwrap())
+            .unwrap();
+
+        let metainfo = torrent_parse(torrent_path.to_str().unwrap()).unwrap();
+        let files = metainfo.info.torrent_files();
+        assert_eq!(metainfo.info.total_length(), 50000);
+        assert_eq!(files[0].path, "b.txt");
+        assert_eq!(files[1].path, "sub/a.txt");
+        assert_eq!(metainfo.comment, Some("test".to_string()));
+        assert_eq!(metainfo.announce_list.len(), 1);
+        assert!(metainfo.creation_date.is_some());
+        assert!(metainfo.url_list.is_empty());
+        assert!(metainfo.info.private);
+
+        // the second piece holds the end of b.txt and the start of sub/a.txt
+        let mut piece = vec![2u8; 20000 - MIN_PIECE_LENGTH as usize];
+        piece.extend(vec![1u8; 2 * MIN_PIECE_LENGTH as usize - 20000]);
+        assert_eq!(metainfo.info.pieces.len(), 4);
+        assert_eq!(metainfo.info.pieces[1][..], Sha1::digest(&piece)[..]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_directories_are_skipped() {
+        let dir = test_dir("torrent_builder", "symlinks");
+        let content = dir.join("content");
+        fs::create_dir_all(&content).unwrap();
+        fs::write(content.join("a.txt"), vec![1u8; 100]).unwrap();
+        std::os::unix::fs::symlink(&content, content.join("loop")).unwrap();
+        std::os::unix::fs::symlink(content.join("a.txt"), content.join("b.txt")).unwrap();
+
+        let files = collect_files(&content).unwrap();
+        let paths: Vec<Vec<String>> = files.into_iter().map(|file| file.torrent_path).collect();
+        assert_eq!(
+            paths,
+            vec![vec!["a.txt".to_string()], vec!["b.txt".to_string()]]
+        );
+    }
+
+    #[test]
+    fn test_build_single_file_torrent() {
+        let dir = test_dir("torrent_builder", "single");
+        let file = dir.join("file.iso");
+        fs::write(&file, vec![7u8; 1000]).unwrap();
+
+        let torrent = TorrentBuilder::new(file.to_str().unwrap(), "http://tracker/announce")
+            .url_list(vec!["http://mirror/file.iso".to_string()])
+            .build()
+            .unwrap();
+        let metainfo = Metainfo::from_bytes(&torrent).unwrap();
+
+        assert_eq!(
+            metainfo.url_list,
+            vec!["http://mirror/file.iso".to_string()]
+        );
+        assert_eq!(metainfo.info.name, "file.iso");
+        assert_eq!(metainfo.info.length, Some(1000));
+        assert_eq!(
+            metainfo.info.pieces_hash(),
+            Sha1::digest(vec![7u8; 1000]).to_vec()
+        );
+    }
+
+    #[test]
+    fn test_invalid_piece_lengths_are_rejected() {
+        let dir = test_dir("torrent_builder", "piece_length");
+        let file = dir.join("file.iso");
+        fs::write(&file, vec![7u8; 1000]).unwrap();
+
+        for piece_length in [0, 1024, 3 * MIN_PIECE_LENGTH] {
+            assert!(
+                TorrentBuilder::new(file.to_str().unwrap(), "http://tracker/announce")
+                    .piece_length(piece_length)
+                    .build()
+                    .is_err()
+            );
+        }
+        assert!(
+            TorrentBuilder::new(file.to_str().unwrap(), "http://tracker/announce")
+                .piece_length(2 * MIN_PIECE_LENGTH)
+                .build()
+                .is_ok()
+        );
+    }
+}
@@ -1,74 +1,515 @@
 use crate::{
     errors::torrent_parser_error::TorrentParserError,
-    parsing::bdecoder::{bdecode, from_string_to_vec, Decodification},
-    parsing::bencoder::{bencode, BencoderTypes},
-    utilities::utils::i64_to_vecu8,
+    utilities::constants::{MAX_TORRENT_SIZE, PIECE_HASH_LEN},
+};
+use bencode::{
+    bdecode_ref_with_limits, bencode, find_raw_with_limits, Dictionary, DictionaryRef, Limits,
+    Value, ValueRef,
 };
 use sha1::{Digest, Sha1};
 use std::{
-    collections::HashMap,
     fs::File,
     io::{prelude::*, BufReader},
 };
 
+/// A file described by the torrent. Multi-file torrents list their files in the order they are laid out across the pieces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TorrentFile {
+    pub path: String,
+    pub length: u64,
+}
+
+/// The contents of a .torrent file. Keys that are not known are kept, so re-encoding gives back the same torrent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metainfo {
+    /// Torrents with announce-list tiers may leave it out.
+    pub announce: Option<String>,
+    pub announce_list: Vec<Vec<String>>,
+    pub comment: Option<String>,
+    pub created_by: Option<String>,
+    pub creation_date: Option<i64>,
+    pub url_list: Vec<String>,
+    pub info: InfoDict,
+    extra: Dictionary,
+    /// The info dictionary exactly as it was read, its hash is the info hash even if it was not canonical.
+    raw_info: Option<Vec<u8>>,
+}
+
+/// The info dictionary of a torrent, the one identified by the info hash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InfoDict {
+    pub name: String,
+    pub piece_length: u64,
+    pub pieces: Vec<[u8; PIECE_HASH_LEN]>,
+    /// Set for single-file torrents.
+    pub length: Option<u64>,
+    /// Set for multi-file torrents.
+    pub files: Vec<FileEntry>,
+    /// Only a private value of 1 is read as true, any other value is kept as an unknown key.
+    pub private: bool,
+    extra: Dictionary,
+}
+
+/// An entry of the files list of a multi-file torrent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileEntry {
+    pub length: u64,
+    pub path: Vec<String>,
+    extra: Dictionary,
+}
+
 /// Reads the torrent file and proccess its data.
-pub fn torrent_parse(filename: &str) -> Result<HashMap<String, Vec<u8>>, TorrentParserError> {
+pub fn torrent_parse(filename: &str) -> Result<Metainfo, TorrentParserError> {
+    torrent_parse_with_max_length(filename, MAX_TORRENT_SIZE)
+}
+
+/// Same as torrent_parse, failing if the file is bigger than max_length bytes.
+pub fn torrent_parse_with_max_length(
+    filename: &str,
+    max_length: usize,
+) -> Result<Metainfo, TorrentParserError> {
     let torrentfile = File::open(&filename);
     let torrentfile = torrentfile?;
 
-    let mut torrentfile = BufReader::new(torrentfile);
+    // one byte more than the limit is enough to tell the file is too big
+    let mut torrentfile = BufReader::new(torrentfile).take(max_length as u64 + 1);
     let mut torrent_vec = Vec::new();
 
     torrentfile.read_to_end(&mut torrent_vec)?;
-    let torrent = &torrent_vec;
-    let decoded = bdecode(torrent)?;
-
-    get_torrent_info(&decoded)
-}
-
-/// Returns a Hashmap of vectors using Bencoder and Bdecoder.
-fn get_torrent_info(
-    decoded: &Decodification,
-) -> Result<HashMap<String, Vec<u8>>, TorrentParserError> {
-    let mut data: HashMap<String, Vec<u8>> = HashMap::new();
-    if let Decodification::Dic(hashmap_aux) = decoded {
-        if let Decodification::String(str_aux) = &hashmap_aux[&from_string_to_vec("announce")] {
-            data.insert("url".to_string(), str_aux.clone());
-        }
-
-        if let Decodification::Dic(info_hashmap) = &hashmap_aux[&from_string_to_vec("info")] {
-            let encoded = bencode(&BencoderTypes::Decodification(
-                hashmap_aux[&from_string_to_vec("info")].clone(),
-            ));
-            let mut hasher = Sha1::new();
-            hasher.update(encoded);
-            let hash = hasher.finalize()[..].to_vec();
-            data.insert("info_hash".to_string(), hash);
-
-            if let Decodification::Int(piece_length) =
-                &info_hashmap[&from_string_to_vec("piece length")]
-            {
-                data.insert(
-                    "piece length".to_string(),
-                    i64_to_vecu8(piece_length).to_vec(),
-                );
+    Metainfo::from_bytes_with_max_length(&torrent_vec, max_length)
+}
+
+impl Metainfo {
+    /// Creates the metainfo of a torrent announced to the given tracker.
+    pub fn new(announce: String, info: InfoDict) -> Metainfo {
+        Metainfo {
+            announce: Some(announce),
+            announce_list: Vec::new(),
+            comment: None,
+            created_by: None,
+            creation_date: None,
+            url_list: Vec::new(),
+            info,
+            extra: Dictionary::new(),
+            raw_info: None,
+        }
+    }
+
+    /// Decodes and validates a bencoded torrent. The byte strings are read in place, so the pieces are only copied once.
+    /// Dictionary keys may come in any order, as some encoders write them, the info hash is still the one of
+    /// the info dictionary as it was read.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Metainfo, TorrentParserError> {
+        Metainfo::from_bytes_with_max_length(bytes, MAX_TORRENT_SIZE)
+    }
+
+    /// Same as from_bytes, failing if the torrent is longer than max_length bytes.
+    pub fn from_bytes_with_max_length(
+        bytes: &[u8],
+        max_length: usize,
+    ) -> Result<Metainfo, TorrentParserError> {
+        let limits = Limits {
+            max_length,
+            canonical: false,
+            ..Limits::default()
+        };
+        let mut metainfo = Metainfo::from_value_ref(bdecode_ref_with_limits(bytes, limits)?)?;
+        metainfo.raw_info =
+            find_raw_with_limits(bytes, "info", limits)?.map(|raw_info| raw_info.to_vec());
+        Ok(metainfo)
+    }
+
+    /// Validates a decoded torrent.
+    pub fn from_value(decoded: &Value) -> Result<Metainfo, TorrentParserError> {
+        Metainfo::from_value_ref(ValueRef::from(decoded))
+    }
+
+    fn from_value_ref(decoded: ValueRef) -> Result<Metainfo, TorrentParserError> {
+        let mut dic = match decoded {
+            ValueRef::Dict(dic) => dic,
+            _ => return Err(invalid("torrent is not a dictionary")),
+        };
+        let info = match dic.remove("info".as_bytes()) {
+            Some(info) => InfoDict::from_value_ref(info)?,
+            None => return Err(invalid("missing info dictionary")),
+        };
+        let announce = take_string(&mut dic, "announce")?;
+
+        let mut announce_list = Vec::new();
+        if let Some(tiers) = take_list(&mut dic, "announce-list")? {
+            for tier in tiers {
+                match tier {
+                    ValueRef::List(urls) => announce_list.push(
+                        urls.iter()
+                            .map(|url| as_string(url, "announce-list"))
+                            .collect::<Result<Vec<String>, TorrentParserError>>()?,
+                    ),
+                    _ => return Err(invalid("announce-list tier is not a list")),
+                }
             }
-            if let Decodification::String(str_aux2) = &info_hashmap[&from_string_to_vec("pieces")] {
-                data.insert("pieces".to_string(), str_aux2.clone());
+        }
+        if announce.is_none() && announce_list.iter().all(|tier| tier.is_empty()) {
+            return Err(invalid("missing announce"));
+        }
+        // url-list can be a single url or a list of them
+        let url_list = match dic.remove("url-list".as_bytes()) {
+            Some(ValueRef::List(urls)) => urls
+                .iter()
+                .map(|url| as_string(url, "url-list"))
+                .collect::<Result<Vec<String>, TorrentParserError>>()?,
+            Some(url) => vec![as_string(&url, "url-list")?],
+            None => Vec::new(),
+        };
+
+        Ok(Metainfo {
+            announce,
+            announce_list,
+            comment: take_string(&mut dic, "comment")?,
+            created_by: take_string(&mut dic, "created by")?,
+            creation_date: take_int(&mut dic, "creation date")?,
+            url_list,
+            info,
+            extra: to_dictionary(dic),
+            raw_info: None,
+        })
+    }
+
+    /// Returns the torrent as a bencoded Value, unknown keys included.
+    pub fn to_value(&self) -> Value {
+        let mut dic = self.extra.clone();
+        if let Some(announce) = &self.announce {
+            dic.insert(b"announce".to_vec(), Value::from(announce.as_str()));
+        }
+        if !self.announce_list.is_empty() {
+            let tiers = self
+                .announce_list
+                .iter()
+                .map(|tier| Value::List(tier.iter().map(|url| Value::from(url.as_str())).collect()))
+                .collect();
+            dic.insert(b"announce-list".to_vec(), Value::List(tiers));
+        }
+        if let Some(comment) = &self.comment {
+            dic.insert(b"comment".to_vec(), Value::from(comment.as_str()));
+        }
+        if let Some(created_by) = &self.created_by {
+            dic.insert(b"created by".to_vec(), Value::from(created_by.as_str()));
+        }
+        if let Some(creation_date) = self.creation_date {
+            dic.insert(b"creation date".to_vec(), Value::Int(creation_date));
+        }
+        if !self.url_list.is_empty() {
+            let urls = self
+                .url_list
+                .iter()
+                .map(|url| Value::from(url.as_str()))
+                .collect();
+            dic.insert(b"url-list".to_vec(), Value::List(urls));
+        }
+        dic.insert(b"info".to_vec(), self.info.to_value());
+        Value::Dict(dic)
+    }
+
+    /// Returns the bencoded torrent.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bencode(&self.to_value())
+    }
+
+    /// Returns the tracker to announce to, the announce key or else the first tracker of the announce-list tiers.
+    pub fn announce_url(&self) -> Option<&str> {
+        match &self.announce {
+            Some(announce) => Some(announce),
+            None => self
+                .announce_list
+                .iter()
+                .flatten()
+                .next()
+                .map(|url| url.as_str()),
+        }
+    }
+
+    /// Returns the sha1 of the info dictionary. Torrents read from bytes hash the info dictionary as it was read,
+    /// the others hash its encoding.
+    pub fn info_hash(&self) -> Vec<u8> {
+        let mut hasher = Sha1::new();
+        match &self.raw_info {
+            Some(raw_info) => hasher.update(raw_info),
+            None => hasher.update(self.info.to_bytes()),
+        }
+        hasher.finalize()[..].to_vec()
+    }
+}
+
+impl InfoDict {
+    /// Creates a single-file info dictionary when length is set, or a multi-file one with the given files.
+    pub fn new(
+        name: String,
+        piece_length: u64,
+        pieces: Vec<[u8; PIECE_HASH_LEN]>,
+        length: Option<u64>,
+        files: Vec<FileEntry>,
+    ) -> InfoDict {
+        InfoDict {
+            name,
+            piece_length,
+            pieces,
+            length,
+            files,
+            private: false,
+            extra: Dictionary::new(),
+        }
+    }
+
+    /// Validates a decoded info dictionary.
+    pub fn from_value(decoded: &Value) -> Result<InfoDict, TorrentParserError> {
+        InfoDict::from_value_ref(ValueRef::from(decoded))
+    }
+
+    fn from_value_ref(decoded: ValueRef) -> Result<InfoDict, TorrentParserError> {
+        let mut dic = match decoded {
+            ValueRef::Dict(dic) => dic,
+            _ => return Err(invalid("info is not a dictionary")),
+        };
+        let name = take_string(&mut dic, "name")?.ok_or_else(|| invalid("missing name"))?;
+        check_path_component(&name, "name")?;
+        let piece_length = match take_int(&mut dic, "piece length")? {
+            Some(piece_length) if piece_length > 0 => piece_length as u64,
+            Some(_) => return Err(invalid("piece length must be positive")),
+            None => return Err(invalid("missing piece length")),
+        };
+        let pieces: Vec<[u8; PIECE_HASH_LEN]> = match dic.remove("pieces".as_bytes()) {
+            Some(ValueRef::Bytes(pieces)) if pieces.len() % PIECE_HASH_LEN == 0 => pieces
+                .chunks(PIECE_HASH_LEN)
+                .map(|hash| {
+                    let mut piece = [0; PIECE_HASH_LEN];
+                    piece.copy_from_slice(hash);
+                    piece
+                })
+                .collect(),
+            Some(ValueRef::Bytes(_)) => {
+                return Err(invalid("pieces length is not a multiple of 20"))
             }
-            if let Decodification::String(str_aux3) = &info_hashmap[&from_string_to_vec("name")] {
-                data.insert("name".to_string(), str_aux3.clone());
+            Some(_) => return Err(invalid("pieces is not a string")),
+            None => return Err(invalid("missing pieces")),
+        };
+        let length = match take_int(&mut dic, "length")? {
+            Some(length) if length >= 0 => Some(length as u64),
+            Some(_) => return Err(invalid("length must not be negative")),
+            None => None,
+        };
+        let mut files = Vec::new();
+        if let Some(files_list) = take_list(&mut dic, "files")? {
+            for file in files_list {
+                files.push(FileEntry::from_value_ref(file)?);
             }
-            if let Decodification::Int(lenght) = &info_hashmap[&from_string_to_vec("length")] {
-                data.insert("length".to_string(), i64_to_vecu8(lenght).to_vec());
+        }
+        let total_length = match (length, files.is_empty()) {
+            (Some(_), false) => return Err(invalid("torrent has both length and files")),
+            (None, true) => return Err(invalid("torrent without length or files")),
+            (Some(length), true) => length,
+            (None, false) => files.iter().map(|file| file.length).sum(),
+        };
+        if pieces.len() as u64 != total_length.div_ceil(piece_length) {
+            return Err(invalid("pieces do not match the length of the torrent"));
+        }
+        let private = match dic.get("private".as_bytes()) {
+            Some(ValueRef::Int(1)) => {
+                dic.remove("private".as_bytes());
+                true
             }
-        } else {
-            return Err(TorrentParserError::new(
-                "Info hash_map is not of type Decodification".to_string(),
-            ));
+            _ => false,
+        };
+
+        Ok(InfoDict {
+            name,
+            piece_length,
+            pieces,
+            length,
+            files,
+            private,
+            extra: to_dictionary(dic),
+        })
+    }
+
+    /// Returns the info dictionary as a bencoded Value, unknown keys included.
+    pub fn to_value(&self) -> Value {
+        let mut dic = self.extra.clone();
+        dic.insert(b"name".to_vec(), Value::from(self.name.as_str()));
+        dic.insert(
+            b"piece length".to_vec(),
+            Value::Int(self.piece_length as i64),
+        );
+        dic.insert(b"pieces".to_vec(), Value::Bytes(self.pieces_hash()));
+        if let Some(length) = self.length {
+            dic.insert(b"length".to_vec(), Value::Int(length as i64));
+        }
+        if !self.files.is_empty() {
+            let files = self.files.iter().map(|file| file.to_value()).collect();
+            dic.insert(b"files".to_vec(), Value::List(files));
+        }
+        if self.private {
+            dic.insert(b"private".to_vec(), Value::Int(1));
+        }
+        Value::Dict(dic)
+    }
+
+    /// Returns the bencoded info dictionary.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bencode(&self.to_value())
+    }
+
+    /// Returns the hashes of all the pieces one after the other.
+    pub fn pieces_hash(&self) -> Vec<u8> {
+        self.pieces.concat()
+    }
+
+    /// Returns the size of the whole torrent.
+    pub fn total_length(&self) -> u64 {
+        match self.length {
+            Some(length) => length,
+            None => self.files.iter().map(|file| file.length).sum(),
+        }
+    }
+
+    /// Returns true if the info dictionary lists its files, even if it has only one.
+    pub fn is_multi_file(&self) -> bool {
+        self.length.is_none()
+    }
+
+    /// Returns the files of the torrent. Single-file torrents return one file named after the torrent.
+    pub fn torrent_files(&self) -> Vec<TorrentFile> {
+        match self.length {
+            Some(length) => vec![TorrentFile {
+                path: self.name.clone(),
+                length,
+            }],
+            None => self
+                .files
+                .iter()
+                .map(|file| TorrentFile {
+                    path: file.path.join("/"),
+                    length: file.length,
+                })
+                .collect(),
         }
     }
-    Ok(data)
+}
+
+impl FileEntry {
+    /// Creates an entry of the files list.
+    pub fn new(path: Vec<String>, length: u64) -> FileEntry {
+        FileEntry {
+            length,
+            path,
+            extra: Dictionary::new(),
+        }
+    }
+
+    fn from_value_ref(decoded: ValueRef) -> Result<FileEntry, TorrentParserError> {
+        let mut dic = match decoded {
+            ValueRef::Dict(dic) => dic,
+            _ => return Err(invalid("file is not a dictionary")),
+        };
+        let length = match take_int(&mut dic, "length")? {
+            Some(length) if length >= 0 => length as u64,
+            Some(_) => return Err(invalid("file length must not be negative")),
+            None => return Err(invalid("file without length")),
+        };
+        let path = match take_list(&mut dic, "path")? {
+            Some(path) if !path.is_empty() => path
+                .iter()
+                .map(|component| {
+                    let component = as_string(component, "path")?;
+                    check_path_component(&component, "path")?;
+                    Ok(component)
+                })
+                .collect::<Result<Vec<String>, TorrentParserError>>()?,
+            _ => return Err(invalid("file without path")),
+        };
+        Ok(FileEntry {
+            length,
+            path,
+            extra: to_dictionary(dic),
+        })
+    }
+
+    fn to_value(&self) -> Value {
+        let mut dic = self.extra.clone();
+        dic.insert(b"length".to_vec(), Value::Int(self.length as i64));
+        dic.insert(
+            b"path".to_vec(),
+            Value::List(
+                self.path
+                    .iter()
+                    .map(|part| Value::from(part.as_str()))
+                    .collect(),
+            ),
+        );
+        Value::Dict(dic)
+    }
+}
+
+fn invalid(reason: &str) -> TorrentParserError {
+    TorrentParserError::new(format!("TorrentParserError: {}", reason))
+}
+
+/// Fails if the name or the part of a path could leave the download directory once joined to it: empty parts,
+/// the current and the parent directory and parts with separators, which also covers absolute paths.
+fn check_path_component(component: &str, key: &str) -> Result<(), TorrentParserError> {
+    if component.is_empty()
+        || component == "."
+        || component == ".."
+        || component.contains(['/', '\\', '\0'])
+    {
+        return Err(invalid(&format!(
+            "{} has an unsafe part {:?}",
+            key, component
+        )));
+    }
+    Ok(())
+}
+
+/// Copies the keys that were not read into an owned dictionary.
+fn to_dictionary(dic: DictionaryRef) -> Dictionary {
+    dic.into_iter()
+        .map(|(key, value)| (key.to_vec(), value.to_value()))
+        .collect()
+}
+
+/// Returns the value as an UTF-8 string, key is only used for the error.
+fn as_string(value: &ValueRef, key: &str) -> Result<String, TorrentParserError> {
+    match value {
+        ValueRef::Bytes(bytes) => String::from_utf8(bytes.to_vec())
+            .map_err(|_| invalid(&format!("{} is not valid UTF-8", key))),
+        _ => Err(invalid(&format!("{} is not a string", key))),
+    }
+}
+
+/// Removes the key from the dictionary and returns it as a string.
+fn take_string(dic: &mut DictionaryRef, key: &str) -> Result<Option<String>, TorrentParserError> {
+    match dic.remove(key.as_bytes()) {
+        Some(value) => Ok(Some(as_string(&value, key)?)),
+        None => Ok(None),
+    }
+}
+
+/// Removes the key from the dictionary and returns it as an integer.
+fn take_int(dic: &mut DictionaryRef, key: &str) -> Result<Option<i64>, TorrentParserError> {
+    match dic.remove(key.as_bytes()) {
+        Some(ValueRef::Int(value)) => Ok(Some(value)),
+        Some(_) => Err(invalid(&format!("{} is not an integer", key))),
+        None => Ok(None),
+    }
+}
+
+/// Removes the key from the dictionary and returns it as a list.
+fn take_list<'a>(
+    dic: &mut DictionaryRef<'a>,
+    key: &str,
+) -> Result<Option<Vec<ValueRef<'a>>>, TorrentParserError> {
+    match dic.remove(key.as_bytes()) {
+        Some(ValueRef::List(value)) => Ok(Some(value)),
+        Some(_) => Err(invalid(&format!("{} is not a list", key))),
+        None => Ok(None),
+    }
 }
 
 #[cfg(test)]
@@ -82,8 +523,8 @@ mod tests {
         let decoded = torrent_parse(&filename);
 
         assert_eq!(
-            String::from_utf8(decoded.unwrap()["url"].clone()).unwrap(),
-            "http://torrent.ubuntu.com:6969/announce"
+            decoded.unwrap().announce,
+            Some("http://torrent.ubuntu.com:6969/announce".to_string())
         );
     }
 
@@ -95,8 +536,165 @@ mod tests {
         let decoded = torrent_parse(&filename);
 
         assert_eq!(
-            String::from_utf8(decoded.unwrap()["url"].clone()).unwrap(),
-            "https://torrent.ubuntu.com/announce"
+            decoded.unwrap().announce,
+            Some("https://torrent.ubuntu.com/announce".to_string())
+        );
+    }
+
+    #[test]
+    fn test_single_file_torrent_files() {
+        let filename = String::from(
+            "src/test_files/torrent_test_files/ubuntu-21.10-desktop-amd64.iso.torrent",
+        );
+        let metainfo = torrent_parse(&filename).unwrap();
+        let files = metainfo.info.torrent_files();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "ubuntu-21.10-desktop-amd64.iso");
+        assert_eq!(Some(files[0].length), metainfo.info.length);
+        assert_eq!(
+            metainfo.info.pieces.len() as u64,
+            metainfo
+                .info
+                .total_length()
+                .div_ceil(metainfo.info.piece_length)
         );
     }
+
+    #[test]
+    fn test_multi_file_torrent_files() {
+        let metainfo = Metainfo::from_bytes(b"d8:announce3:url4:infod5:filesld6:lengthi10e4:pathl3:dir5:a.txteed6:lengthi6e4:pathl5:b.txteee4:name4:test12:piece lengthi8e6:pieces40:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaee").unwrap();
+
+        assert_eq!(
+            metainfo.info.torrent_files(),
+            vec![
+                TorrentFile {
+                    path: "dir/a.txt".to_string(),
+                    length: 10
+                },
+                TorrentFile {
+                    path: "b.txt".to_string(),
+                    length: 6
+                },
+            ]
+        );
+        assert_eq!(metainfo.info.total_length(), 16);
+    }
+
+    #[test]
+    fn test_round_trip_keeps_the_info_hash() {
+        let bytes = std::fs::read(
+            "src/test_files/torrent_test_files/ubuntu-14.04.6-server-ppc64el.iso.torrent",
+        )
+        .unwrap();
+        let metainfo = Metainfo::from_bytes(&bytes).unwrap();
+
+        // the info dictionary is the last value of the torrent
+        let info_start = bytes
+            .windows(6)
+            .position(|window| window == b"4:info")
+            .unwrap()
+            + 6;
+        let mut hasher = Sha1::new();
+        hasher.update(&bytes[info_start..bytes.len() - 1]);
+        assert_eq!(metainfo.info_hash(), hasher.finalize()[..].to_vec());
+        assert_eq!(metainfo.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_unsorted_info_keeps_its_info_hash() {
+        let info = format!(
+            "d6:lengthi8e4:name1:a6:pieces20:{}12:piece lengthi8ee",
+            "a".repeat(20)
+        );
+        let bytes = format!("d8:announce3:url4:info{}e", info);
+        let metainfo = Metainfo::from_bytes(bytes.as_bytes()).unwrap();
+
+        assert_eq!(metainfo.info.piece_length, 8);
+        assert_eq!(metainfo.info_hash(), Sha1::digest(info.as_bytes()).to_vec());
+        assert_ne!(metainfo.info.to_bytes(), info.as_bytes().to_vec());
+        // duplicate keys are still rejected
+        assert!(Metainfo::from_bytes(b"d8:announce3:url8:announce3:url4:infod6:lengthi8e4:name1:a12:piece lengthi8e6:pieces20:aaaaaaaaaaaaaaaaaaaaee").is_err());
+    }
+
+    #[test]
+    fn test_torrents_longer_than_the_limit_are_rejected() {
+        let bytes = b"d8:announce3:url4:infod6:lengthi8e4:name1:a12:piece lengthi8e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        assert!(Metainfo::from_bytes_with_max_length(bytes, bytes.len()).is_ok());
+        assert!(Metainfo::from_bytes_with_max_length(bytes, bytes.len() - 1).is_err());
+    }
+
+    #[test]
+    fn test_unknown_keys_are_kept() {
+        let bytes = b"d8:announce3:url4:infod6:lengthi5e4:name1:a12:piece lengthi8e6:pieces20:aaaaaaaaaaaaaaaaaaaa7:privatei0e6:sourcei3ee7:unknown1:xe";
+        let metainfo = Metainfo::from_bytes(bytes).unwrap();
+
+        assert!(!metainfo.info.private);
+        assert_eq!(metainfo.to_bytes(), bytes.to_vec());
+    }
+
+    #[test]
+    fn test_invalid_torrents() {
+        // pieces is not a multiple of 20
+        assert!(Metainfo::from_bytes(
+            b"d8:announce3:url4:infod6:lengthi5e4:name1:a12:piece lengthi8e6:pieces3:abcee"
+        )
+        .is_err());
+        // missing piece length
+        assert!(
+            Metainfo::from_bytes(b"d8:announce3:url4:infod6:lengthi5e4:name1:a6:pieces0:ee")
+                .is_err()
+        );
+        // both length and files
+        assert!(Metainfo::from_bytes(b"d8:announce3:url4:infod5:filesld6:lengthi1e4:pathl1:beee6:lengthi5e4:name1:a12:piece lengthi8e6:pieces0:ee").is_err());
+        // missing info
+        assert!(Metainfo::from_bytes(b"d8:announce3:urle").is_err());
+        // too few and too many pieces
+        assert!(Metainfo::from_bytes(
+            b"d8:announce3:url4:infod6:lengthi9e4:name1:a12:piece lengthi8e6:pieces20:aaaaaaaaaaaaaaaaaaaaee"
+        )
+        .is_err());
+        assert!(Metainfo::from_bytes(
+            b"d8:announce3:url4:infod6:lengthi8e4:name1:a12:piece lengthi8e6:pieces40:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaee"
+        )
+        .is_err());
+        // no tracker at all
+        assert!(Metainfo::from_bytes(
+            b"d4:infod6:lengthi8e4:name1:a12:piece lengthi8e6:pieces20:aaaaaaaaaaaaaaaaaaaaee"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_announce_list_without_announce() {
+        let bytes = b"d13:announce-listll5:url-ael5:url-bee4:infod6:lengthi8e4:name1:a12:piece lengthi8e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        let metainfo = Metainfo::from_bytes(bytes).unwrap();
+
+        assert_eq!(metainfo.announce, None);
+        assert_eq!(metainfo.announce_url(), Some("url-a"));
+        assert_eq!(metainfo.to_bytes(), bytes.to_vec());
+    }
+
+    #[test]
+    fn test_unsafe_paths_are_rejected() {
+        for path in ["2:..", "0:", "1:.", "5:/etc/", "4:a/..", "4:..\\a", "2:a\0"] {
+            let torrent = format!("d8:announce3:url4:infod5:filesld6:lengthi1e4:pathl1:a{}eee4:name4:test12:piece lengthi8e6:pieces20:aaaaaaaaaaaaaaaaaaaaee", path);
+            assert!(
+                Metainfo::from_bytes(torrent.as_bytes()).is_err(),
+                "{}",
+                path
+            );
+        }
+        for name in ["2:..", "0:", "9:/root/.ss"] {
+            let torrent = format!(
+                "d8:announce3:url4:infod6:lengthi1e4:name{}12:piece lengthi8e6:pieces20:aaaaaaaaaaaaaaaaaaaaee",
+                name
+            );
+            assert!(
+                Metainfo::from_bytes(torrent.as_bytes()).is_err(),
+                "{}",
+                name
+            );
+        }
+    }
 }
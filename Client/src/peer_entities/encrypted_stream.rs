@@ -0,0 +1,812 @@
+use crate::{
+    config::EncryptionPolicy,
+    errors::{
+        communication_method_error::CommunicationMethodError, encryption_error::EncryptionError,
+    },
+    peer_entities::communication_method::CommunicationMethod,
+    peer_entities::event_loop::LoopStream,
+    rate_estimator::TransferMeter,
+    rate_limiter::RateLimiter,
+};
+use sha1::{Digest, Sha1};
+use std::{
+    fmt::{self, Debug},
+    io,
+    net::{SocketAddr, TcpStream},
+    sync::Arc,
+    time::Duration,
+};
+
+/// The prime of the Diffie-Hellman key exchange of MSE, its generator is 2.
+const PRIME: [u8; KEY_LEN] = [
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xC9, 0x0F, 0xDA, 0xA2, 0x21, 0x68, 0xC2, 0x34,
+    0xC4, 0xC6, 0x62, 0x8B, 0x80, 0xDC, 0x1C, 0xD1, 0x29, 0x02, 0x4E, 0x08, 0x8A, 0x67, 0xCC, 0x74,
+    0x02, 0x0B, 0xBE, 0xA6, 0x3B, 0x13, 0x9B, 0x22, 0x51, 0x4A, 0x08, 0x79, 0x8E, 0x34, 0x04, 0xDD,
+    0xEF, 0x95, 0x19, 0xB3, 0xCD, 0x3A, 0x43, 0x1B, 0x30, 0x2B, 0x0A, 0x6D, 0xF2, 0x5F, 0x14, 0x37,
+    0x4F, 0xE1, 0x35, 0x6D, 0x6D, 0x51, 0xC2, 0x45, 0xE4, 0x85, 0xB5, 0x76, 0x62, 0x5E, 0x7E, 0xC6,
+    0xF4, 0x4C, 0x42, 0xE9, 0xA6, 0x3A, 0x36, 0x21, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09, 0x05, 0x63,
+];
+const GENERATOR: u8 = 2;
+/// The public keys and the shared secret, big endian.
+const KEY_LEN: usize = 96;
+const PRIVATE_KEY_LEN: usize = 20;
+const HASH_LEN: usize = 20;
+/// The longest padding a peer can send, the handshake is searched for past it.
+const MAX_PAD_LEN: usize = 512;
+/// The verification constant, eight zeros that tell the encrypted part of the handshake was decrypted right.
+const VC: [u8; 8] = [0; 8];
+pub const CRYPTO_PLAINTEXT: u32 = 1;
+pub const CRYPTO_RC4: u32 = 2;
+/// The bytes of the RC4 stream thrown away before it is used.
+const RC4_DISCARD: usize = 1024;
+/// How long each read of the encryption handshake of an outgoing connection waits.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The RC4 stream cipher.
+#[derive(Clone)]
+pub struct Rc4 {
+    state: [u8; 256],
+    i: u8,
+    j: u8,
+}
+
+/// The ciphers of the payload of a connection, one for each direction.
+#[derive(Debug, Clone)]
+pub struct Cipher {
+    pub decrypt: Rc4,
+    pub encrypt: Rc4,
+}
+
+/// What the encryption handshake of an incoming connection agreed on.
+#[derive(Debug)]
+pub struct Negotiated {
+    /// The torrent the peer asked for.
+    pub info_hash: Vec<u8>,
+    /// None if the payload goes in plaintext.
+    pub cipher: Option<Cipher>,
+    /// The payload the peer sent within the handshake, decrypted, usually its BitTorrent handshake.
+    pub received: Vec<u8>,
+}
+
+/// Encrypts a connection with MSE (Message Stream Encryption): once the stream connects, a Diffie-Hellman key
+/// exchange gives a secret both peers share, the info hash tells the peer the torrent without being sent,
+/// and what is read and written afterwards goes through RC4, or in plaintext if that is what the peers
+/// agreed on. The PeerConnection reading and writing through it sees the plaintext.
+pub struct EncryptedStream {
+    stream: Box<dyn CommunicationMethod + Send>,
+    info_hash: Vec<u8>,
+    policy: EncryptionPolicy,
+    /// None while the connection is plaintext.
+    cipher: Option<Cipher>,
+    read_timeout: Option<Duration>,
+}
+
+/// Where the handshake is read from and written to.
+trait Channel {
+    fn receive(&mut self, buf: &mut [u8]) -> Result<(), EncryptionError>;
+    fn send(&mut self, buf: &[u8]) -> Result<(), EncryptionError>;
+}
+
+impl Rc4 {
+    pub fn new(key: &[u8]) -> Rc4 {
+        let mut state = [0; 256];
+        for (i, byte) in state.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let mut j: u8 = 0;
+        for i in 0..state.len() {
+            j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+            state.swap(i, j as usize);
+        }
+        Rc4 { state, i: 0, j: 0 }
+    }
+
+    /// Encrypts or decrypts the data in place.
+    pub fn apply(&mut self, data: &mut [u8]) {
+        for byte in data {
+            self.i = self.i.wrapping_add(1);
+            self.j = self.j.wrapping_add(self.state[self.i as usize]);
+            self.state.swap(self.i as usize, self.j as usize);
+            let index = self.state[self.i as usize].wrapping_add(self.state[self.j as usize]);
+            *byte ^= self.state[index as usize];
+        }
+    }
+}
+
+impl Debug for Rc4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rc4").finish_non_exhaustive()
+    }
+}
+
+impl EncryptedStream {
+    /// Wraps the stream, which is encrypted as the policy says once it connects. The info hash is the one
+    /// of the torrent the connection is for.
+    pub fn new(
+        stream: Box<dyn CommunicationMethod + Send>,
+        info_hash: Vec<u8>,
+        policy: EncryptionPolicy,
+    ) -> EncryptedStream {
+        EncryptedStream {
+            stream,
+            info_hash,
+            policy,
+            cipher: None,
+            read_timeout: None,
+        }
+    }
+
+    /// Returns whether the payload is encrypted with RC4.
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Runs the handshake of the initiator on the connected stream.
+    fn negotiate(&mut self) -> Result<(), EncryptionError> {
+        self.stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let negotiated = initiate(
+            self.stream.as_mut(),
+            &self.info_hash,
+            crypto_methods(self.policy),
+        );
+        self.stream.set_read_timeout(self.read_timeout)?;
+        self.cipher = negotiated?;
+        Ok(())
+    }
+}
+
+impl CommunicationMethod for EncryptedStream {
+    /// Creates a stream on the event loop of LoopStream::create, there is no info hash to encrypt it with.
+    fn create() -> Box<dyn CommunicationMethod + Send> {
+        Box::new(EncryptedStream::new(
+            LoopStream::create(),
+            Vec::new(),
+            EncryptionPolicy::Disabled,
+        ))
+    }
+
+    /// Connects and negotiates the encryption. If the peer does not answer the handshake, a policy that
+    /// is not forced connects again in plaintext.
+    fn connect(&mut self, ip: &str, port: u16) -> Result<(), CommunicationMethodError> {
+        self.cipher = None;
+        self.stream.connect(ip, port)?;
+        if self.policy == EncryptionPolicy::Disabled {
+            return Ok(());
+        }
+        match self.negotiate() {
+            Ok(()) => Ok(()),
+            Err(_) if self.policy == EncryptionPolicy::Enabled => self.stream.connect(ip, port),
+            Err(e) => {
+                self.stream.disconnect();
+                Err(CommunicationMethodError::from(e))
+            }
+        }
+    }
+
+    fn peer_addr(&self) -> Result<SocketAddr, CommunicationMethodError> {
+        self.stream.peer_addr()
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), CommunicationMethodError> {
+        self.stream.read_exact(buf)?;
+        if let Some(cipher) = &mut self.cipher {
+            cipher.decrypt.apply(buf);
+        }
+        Ok(())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), CommunicationMethodError> {
+        match &mut self.cipher {
+            Some(cipher) => {
+                let mut encrypted = buf.to_vec();
+                cipher.encrypt.apply(&mut encrypted);
+                self.stream.write_all(&encrypted)
+            }
+            None => self.stream.write_all(buf),
+        }
+    }
+
+    fn set_read_timeout(&mut self, dur: Option<Duration>) -> Result<(), CommunicationMethodError> {
+        self.read_timeout = dur;
+        self.stream.set_read_timeout(dur)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.stream.is_connected()
+    }
+
+    fn disconnect(&mut self) -> bool {
+        self.cipher = None;
+        self.stream.disconnect()
+    }
+
+    fn set_rate_limiter(&mut self, limiter: Arc<RateLimiter>) {
+        self.stream.set_rate_limiter(limiter)
+    }
+
+    fn rate_limiter(&self) -> Option<Arc<RateLimiter>> {
+        self.stream.rate_limiter()
+    }
+
+    fn set_rate_meter(&mut self, meter: Arc<TransferMeter>) {
+        self.stream.set_rate_meter(meter)
+    }
+
+    fn rate_meter(&self) -> Option<Arc<TransferMeter>> {
+        self.stream.rate_meter()
+    }
+}
+
+impl Channel for dyn CommunicationMethod + Send {
+    fn receive(&mut self, buf: &mut [u8]) -> Result<(), EncryptionError> {
+        Ok(CommunicationMethod::read_exact(self, buf)?)
+    }
+
+    fn send(&mut self, buf: &[u8]) -> Result<(), EncryptionError> {
+        Ok(CommunicationMethod::write_all(self, buf)?)
+    }
+}
+
+impl Channel for TcpStream {
+    fn receive(&mut self, buf: &mut [u8]) -> Result<(), EncryptionError> {
+        Ok(io::Read::read_exact(self, buf)?)
+    }
+
+    fn send(&mut self, buf: &[u8]) -> Result<(), EncryptionError> {
+        Ok(io::Write::write_all(self, buf)?)
+    }
+}
+
+/// Answers the encryption handshake of an incoming connection, the stream must have a read timeout. The
+/// info hash the peer asks for must be one of info_hashes.
+pub fn accept(
+    stream: &mut TcpStream,
+    info_hashes: &[Vec<u8>],
+    policy: EncryptionPolicy,
+) -> Result<Negotiated, EncryptionError> {
+    respond(stream, info_hashes, crypto_methods(policy))
+}
+
+/// Returns the methods the policy allows, RC4 is preferred when the peer offers both.
+fn crypto_methods(policy: EncryptionPolicy) -> u32 {
+    match policy {
+        EncryptionPolicy::Disabled => CRYPTO_PLAINTEXT,
+        EncryptionPolicy::Enabled => CRYPTO_PLAINTEXT | CRYPTO_RC4,
+        EncryptionPolicy::Forced => CRYPTO_RC4,
+    }
+}
+
+/// The handshake of the peer that connects, offering the methods of provide. Returns the cipher of the
+/// payload, None if the peer chose plaintext.
+fn initiate<C: Channel + ?Sized>(
+    channel: &mut C,
+    info_hash: &[u8],
+    provide: u32,
+) -> Result<Option<Cipher>, EncryptionError> {
+    let private_key: [u8; PRIVATE_KEY_LEN] = rand::random();
+    let mut message = mod_pow(&[GENERATOR], &private_key).to_vec();
+    message.extend(padding());
+    channel.send(&message)?;
+
+    let mut peer_key = [0; KEY_LEN];
+    channel.receive(&mut peer_key)?;
+    let secret = mod_pow(&peer_key, &private_key);
+    let mut cipher = payload_cipher(&secret, info_hash, true);
+    let mut message = hash(&[b"req1", &secret]).to_vec();
+    message.extend(xor(
+        &hash(&[b"req2", info_hash]),
+        &hash(&[b"req3", &secret]),
+    ));
+    let mut encrypted = VC.to_vec();
+    encrypted.extend(provide.to_be_bytes());
+    // no padding, and no payload: the BitTorrent handshake is sent once the stream connects
+    encrypted.extend(0u16.to_be_bytes());
+    encrypted.extend(0u16.to_be_bytes());
+    cipher.encrypt.apply(&mut encrypted);
+    message.extend(encrypted);
+    channel.send(&message)?;
+
+    // the answer starts with the verification constant past the padding of the peer
+    let mut expected = VC;
+    cipher.decrypt.clone().apply(&mut expected);
+    synchronize(channel, &expected)?;
+    cipher.decrypt.apply(&mut expected);
+    let select = read_u32(channel, &mut cipher.decrypt)?;
+    if select.count_ones() != 1 || select & provide == 0 {
+        return Err(EncryptionError::new(format!(
+            "EncryptionError: the peer selected the method {}",
+            select
+        )));
+    }
+    read_padding(channel, &mut cipher.decrypt)?;
+    Ok((select == CRYPTO_RC4).then_some(cipher))
+}
+
+/// The handshake of the peer that is connected to, accepting the methods of accept.
+fn respond<C: Channel + ?Sized>(
+    channel: &mut C,
+    info_hashes: &[Vec<u8>],
+    accept: u32,
+) -> Result<Negotiated, EncryptionError> {
+    let mut peer_key = [0; KEY_LEN];
+    channel.receive(&mut peer_key)?;
+    let private_key: [u8; PRIVATE_KEY_LEN] = rand::random();
+    let mut message = mod_pow(&[GENERATOR], &private_key).to_vec();
+    message.extend(padding());
+    channel.send(&message)?;
+
+    let secret = mod_pow(&peer_key, &private_key);
+    synchronize(channel, &hash(&[b"req1", &secret]))?;
+    let mut obfuscated = [0; HASH_LEN];
+    channel.receive(&mut obfuscated)?;
+    let req3 = hash(&[b"req3", &secret]);
+    let info_hash = info_hashes
+        .iter()
+        .find(|info_hash| xor(&hash(&[b"req2", info_hash]), &req3) == obfuscated)
+        .ok_or_else(|| EncryptionError::new("EncryptionError: unknown torrent".to_string()))?
+        .clone();
+    let mut cipher = payload_cipher(&secret, &info_hash, false);
+
+    let mut vc = [0; VC.len()];
+    channel.receive(&mut vc)?;
+    cipher.decrypt.apply(&mut vc);
+    if vc != VC {
+        return Err(EncryptionError::new(
+            "EncryptionError: invalid verification constant".to_string(),
+        ));
+    }
+    let provide = read_u32(channel, &mut cipher.decrypt)?;
+    read_padding(channel, &mut cipher.decrypt)?;
+    let mut len = [0; 2];
+    channel.receive(&mut len)?;
+    cipher.decrypt.apply(&mut len);
+    let mut received = vec![0; u16::from_be_bytes(len) as usize];
+    channel.receive(&mut received)?;
+    cipher.decrypt.apply(&mut received);
+
+    let select = match provide & accept {
+        methods if methods & CRYPTO_RC4 != 0 => CRYPTO_RC4,
+        methods if methods & CRYPTO_PLAINTEXT != 0 => CRYPTO_PLAINTEXT,
+        _ => {
+            return Err(EncryptionError::new(format!(
+                "EncryptionError: the peer provided the methods {}",
+                provide
+            )))
+        }
+    };
+    let mut answer = VC.to_vec();
+    answer.extend(select.to_be_bytes());
+    answer.extend(0u16.to_be_bytes());
+    cipher.encrypt.apply(&mut answer);
+    channel.send(&answer)?;
+    Ok(Negotiated {
+        info_hash,
+        cipher: (select == CRYPTO_RC4).then_some(cipher),
+        received,
+    })
+}
+
+/// Reads until the last bytes read are the expected ones, at most MAX_PAD_LEN bytes past them.
+fn synchronize<C: Channel + ?Sized>(
+    channel: &mut C,
+    expected: &[u8],
+) -> Result<(), EncryptionError> {
+    let mut window = vec![0; expected.len()];
+    channel.receive(&mut window)?;
+    for _ in 0..MAX_PAD_LEN {
+        if window == expected {
+            return Ok(());
+        }
+        window.remove(0);
+        window.push(0);
+        channel.receive(&mut window[expected.len() - 1..])?;
+    }
+    match window == expected {
+        true => Ok(()),
+        false => Err(EncryptionError::new(
+            "EncryptionError: the peer did not answer the handshake".to_string(),
+        )),
+    }
+}
+
+fn read_u32<C: Channel + ?Sized>(
+    channel: &mut C,
+    decrypt: &mut Rc4,
+) -> Result<u32, EncryptionError> {
+    let mut bytes = [0; 4];
+    channel.receive(&mut bytes)?;
+    decrypt.apply(&mut bytes);
+    Ok(u32::from_be_bytes(bytes))
+}
+
+/// Reads the length of a padding and the padding.
+fn read_padding<C: Channel + ?Sized>(
+    channel: &mut C,
+    decrypt: &mut Rc4,
+) -> Result<(), EncryptionError> {
+    let mut len = [0; 2];
+    channel.receive(&mut len)?;
+    decrypt.apply(&mut len);
+    let len = u16::from_be_bytes(len) as usize;
+    if len > MAX_PAD_LEN {
+        return Err(EncryptionError::new(format!(
+            "EncryptionError: padding of {} bytes",
+            len
+        )));
+    }
+    let mut padding = vec![0; len];
+    channel.receive(&mut padding)?;
+    decrypt.apply(&mut padding);
+    Ok(())
+}
+
+/// Random bytes of a random length, so the handshake has no fixed size.
+fn padding() -> Vec<u8> {
+    (0..rand::random::<usize>() % (MAX_PAD_LEN + 1))
+        .map(|_| rand::random())
+        .collect()
+}
+
+/// The ciphers of the payload: the initiator encrypts with keyA and the other peer with keyB.
+fn payload_cipher(secret: &[u8], info_hash: &[u8], initiator: bool) -> Cipher {
+    let key_a = rc4(&hash(&[b"keyA", secret, info_hash]));
+    let key_b = rc4(&hash(&[b"keyB", secret, info_hash]));
+    match initiator {
+        true => Cipher {
+            decrypt: key_b,
+            encrypt: key_a,
+        },
+        false => Cipher {
+            decrypt: key_a,
+            encrypt: key_b,
+        },
+    }
+}
+
+fn rc4(key: &[u8]) -> Rc4 {
+    let mut rc4 = Rc4::new(key);
+    rc4.apply(&mut [0; RC4_DISCARD]);
+    rc4
+}
+
+fn hash(parts: &[&[u8]]) -> [u8; HASH_LEN] {
+    let mut hasher = Sha1::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+fn xor(a: &[u8; HASH_LEN], b: &[u8; HASH_LEN]) -> [u8; HASH_LEN] {
+    let mut result = [0; HASH_LEN];
+    for (byte, (a, b)) in result.iter_mut().zip(a.iter().zip(b)) {
+        *byte = a ^ b;
+    }
+    result
+}
+
+/// Returns base to the power of exponent modulo the prime, both big endian. The products are reduced with
+/// Montgomery multiplication on 32 bit limbs, least significant first.
+fn mod_pow(base: &[u8], exponent: &[u8]) -> [u8; KEY_LEN] {
+    let modulus = limbs(&PRIME);
+    let inverse = montgomery_inverse(modulus[0]);
+    let base = to_montgomery(&limbs(base), &modulus);
+    let mut result = to_montgomery(&[1], &modulus);
+    for byte in exponent {
+        for bit in (0..8).rev() {
+            result = montgomery_mul(&result, &result, &modulus, inverse);
+            if (byte >> bit) & 1 == 1 {
+                result = montgomery_mul(&result, &base, &modulus, inverse);
+            }
+        }
+    }
+    let mut one = vec![0; modulus.len()];
+    one[0] = 1;
+    let result = montgomery_mul(&result, &one, &modulus, inverse);
+    let mut bytes = [0; KEY_LEN];
+    for (chunk, limb) in bytes.rchunks_mut(4).zip(result) {
+        chunk.copy_from_slice(&limb.to_be_bytes());
+    }
+    bytes
+}
+
+fn limbs(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .rchunks(4)
+        .map(|chunk| chunk.iter().fold(0, |limb, byte| limb << 8 | *byte as u32))
+        .collect()
+}
+
+/// Returns -1 / limb modulo 2^32, limb is odd.
+fn montgomery_inverse(limb: u32) -> u32 {
+    let mut inverse: u32 = 1;
+    // each step doubles the bits that are right
+    for _ in 0..5 {
+        inverse = inverse.wrapping_mul(2u32.wrapping_sub(limb.wrapping_mul(inverse)));
+    }
+    inverse.wrapping_neg()
+}
+
+/// Returns number * 2^(32 * limbs of modulus) modulo modulus.
+fn to_montgomery(number: &[u32], modulus: &[u32]) -> Vec<u32> {
+    let mut shifted = vec![0; modulus.len()];
+    shifted.extend(number);
+    remainder(&shifted, modulus)
+}
+
+/// Returns a * b / 2^(32 * limbs of modulus) modulo modulus.
+fn montgomery_mul(a: &[u32], b: &[u32], modulus: &[u32], inverse: u32) -> Vec<u32> {
+    let len = modulus.len();
+    let mut t = vec![0u32; len + 2];
+    for b_limb in b {
+        let mut carry = 0u64;
+        for j in 0..len {
+            let sum = t[j] as u64 + a[j] as u64 * *b_limb as u64 + carry;
+            t[j] = sum as u32;
+            carry = sum >> 32;
+        }
+        let sum = t[len] as u64 + carry;
+        t[len] = sum as u32;
+        t[len + 1] = (sum >> 32) as u32;
+
+        let m = t[0].wrapping_mul(inverse);
+        let mut carry = (t[0] as u64 + m as u64 * modulus[0] as u64) >> 32;
+        for j in 1..len {
+            let sum = t[j] as u64 + m as u64 * modulus[j] as u64 + carry;
+            t[j - 1] = sum as u32;
+            carry = sum >> 32;
+        }
+        let sum = t[len] as u64 + carry;
+        t[len - 1] = sum as u32;
+        t[len] = t[len + 1] + (sum >> 32) as u32;
+    }
+    t.truncate(len + 1);
+    if !is_less(&t, modulus) {
+        subtract(&mut t, modulus);
+    }
+    t.truncate(len);
+    t
+}
+
+/// Returns number modulo modulus, one bit at a time.
+fn remainder(number: &[u32], modulus: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u32; modulus.len() + 1];
+    for bit in (0..number.len() * 32).rev() {
+        let mut carry = (number[bit / 32] >> (bit % 32)) & 1;
+        for limb in result.iter_mut() {
+            let next = *limb >> 31;
+            *limb = *limb << 1 | carry;
+            carry = next;
+        }
+        if !is_less(&result, modulus) {
+            subtract(&mut result, modulus);
+        }
+    }
+    result.truncate(modulus.len());
+    result
+}
+
+/// Returns a < b, b has no more limbs than a.
+fn is_less(a: &[u32], b: &[u32]) -> bool {
+    for i in (0..a.len()).rev() {
+        let b_limb = b.get(i).copied().unwrap_or(0);
+        if a[i] != b_limb {
+            return a[i] < b_limb;
+        }
+    }
+    false
+}
+
+/// Subtracts b from a, which is not less than b.
+fn subtract(a: &mut [u32], b: &[u32]) {
+    let mut borrow = 0u64;
+    for (i, limb) in a.iter_mut().enumerate() {
+        let subtrahend = b.get(i).copied().unwrap_or(0) as u64 + borrow;
+        borrow = (subtrahend > *limb as u64) as u64;
+        *limb = (*limb as u64 + (borrow << 32) - subtrahend) as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        peer_entities::event_loop::{ConnectionHandler, EventLoop},
+        utilities::constants::{INFO_HASH_LEN, PSTR, RESERVED_SPACE_LEN},
+        utilities::utils::from_hex,
+    };
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::mpsc::{channel, Receiver},
+        thread::spawn,
+    };
+
+    const INFO_HASH: [u8; INFO_HASH_LEN] = [7; INFO_HASH_LEN];
+
+    fn handshake() -> Vec<u8> {
+        let mut handshake = vec![PSTR.len() as u8];
+        handshake.extend(PSTR.as_bytes());
+        handshake.extend([0; RESERVED_SPACE_LEN as usize]);
+        handshake.extend(INFO_HASH);
+        handshake.extend(b"-CR0001-123456789012");
+        handshake
+    }
+
+    /// Sends every frame back.
+    struct Echo {
+        stream: LoopStream,
+    }
+
+    impl ConnectionHandler for Echo {
+        fn frame(&mut self) -> bool {
+            let frame = self.stream.take_frame();
+            self.stream.write_all(&frame).is_ok()
+        }
+    }
+
+    /// Answers the encryption handshake of one connection accepting the methods of accept, then echoes
+    /// its frames on an event loop like the listener of a torrent. Sends what the handshake agreed on.
+    fn encrypted_echo(accept: u32) -> (u16, Receiver<Result<bool, String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = channel();
+        spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            socket.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).unwrap();
+            let negotiated = match respond(&mut socket, &[vec![1; 20], INFO_HASH.to_vec()], accept)
+            {
+                Ok(negotiated) => negotiated,
+                Err(e) => return sender.send(Err(e.to_string())).unwrap(),
+            };
+            assert_eq!(negotiated.info_hash, INFO_HASH);
+            sender.send(Ok(negotiated.cipher.is_some())).unwrap();
+            let event_loop = EventLoop::new().unwrap();
+            let stream = event_loop.adopt_negotiated(socket, negotiated).unwrap();
+            stream
+                .handle(Box::new(Echo {
+                    stream: stream.clone(),
+                }))
+                .unwrap();
+            std::thread::sleep(Duration::from_secs(2));
+        });
+        (port, receiver)
+    }
+
+    fn echoed(stream: &mut EncryptedStream) {
+        let mut message = handshake();
+        message.extend([0, 0, 0, 5, 4, 0, 0, 0, 9]);
+        stream.write_all(&message).unwrap();
+        let mut received = vec![0; message.len()];
+        stream.read_exact(&mut received).unwrap();
+        assert_eq!(received, message);
+    }
+
+    #[test]
+    fn test_rc4_keystream() {
+        let mut data = b"Plaintext".to_vec();
+        Rc4::new(b"Key").apply(&mut data);
+        assert_eq!(data, from_hex("bbf316e8d940af0ad3").unwrap());
+        Rc4::new(b"Key").apply(&mut data);
+        assert_eq!(data, b"Plaintext");
+    }
+
+    #[test]
+    fn test_key_exchange() {
+        let private_key: Vec<u8> = (1..=20).collect();
+        let public_key = from_hex(
+            "96e112dab29e8c5272accb9b17b26887ce54a144a4e3b697c7d159b7a817e556b0918db2b4c658e02a87f7e5fb14b18a\
+             553e084cbf3dad2d30f16596ccb982d406258c61b30c5c1dae2ddc60bdbd48d79896312aad63238c39e1a633821eb693",
+        )
+        .unwrap();
+        assert_eq!(mod_pow(&[GENERATOR], &private_key).to_vec(), public_key);
+
+        let (a, b): ([u8; 20], [u8; 20]) = (rand::random(), rand::random());
+        let (public_a, public_b) = (mod_pow(&[GENERATOR], &a), mod_pow(&[GENERATOR], &b));
+        assert_eq!(mod_pow(&public_b, &a), mod_pow(&public_a, &b));
+        // keys past the prime are reduced
+        assert_eq!(mod_pow(&[0xff; KEY_LEN], &[1]), {
+            let mut reduced = [0xff; KEY_LEN];
+            subtract_bytes(&mut reduced, &PRIME);
+            reduced
+        });
+    }
+
+    fn subtract_bytes(a: &mut [u8; KEY_LEN], b: &[u8; KEY_LEN]) {
+        let mut limbs_a = limbs(a);
+        subtract(&mut limbs_a, &limbs(b));
+        for (chunk, limb) in a.rchunks_mut(4).zip(limbs_a) {
+            chunk.copy_from_slice(&limb.to_be_bytes());
+        }
+    }
+
+    #[test]
+    fn test_encrypted_streams_interoperate() {
+        let (port, negotiated) = encrypted_echo(CRYPTO_PLAINTEXT | CRYPTO_RC4);
+        let mut stream = EncryptedStream::new(
+            LoopStream::create(),
+            INFO_HASH.to_vec(),
+            EncryptionPolicy::Forced,
+        );
+        stream.connect("127.0.0.1", port).unwrap();
+        assert!(negotiated.recv().unwrap().unwrap());
+        assert!(stream.is_encrypted());
+        echoed(&mut stream);
+    }
+
+    #[test]
+    fn test_plaintext_can_be_selected() {
+        let (port, negotiated) = encrypted_echo(CRYPTO_PLAINTEXT);
+        let mut stream = EncryptedStream::new(
+            LoopStream::create(),
+            INFO_HASH.to_vec(),
+            EncryptionPolicy::Enabled,
+        );
+        stream.connect("127.0.0.1", port).unwrap();
+        assert!(!negotiated.recv().unwrap().unwrap());
+        assert!(!stream.is_encrypted());
+        echoed(&mut stream);
+    }
+
+    #[test]
+    fn test_unknown_torrents_and_methods_are_refused() {
+        let (port, negotiated) = encrypted_echo(CRYPTO_RC4);
+        let mut stream =
+            EncryptedStream::new(LoopStream::create(), vec![2; 20], EncryptionPolicy::Forced);
+        assert!(stream.connect("127.0.0.1", port).is_err());
+        assert!(negotiated.recv().unwrap().is_err());
+
+        let (port, negotiated) = encrypted_echo(CRYPTO_RC4);
+        let mut socket = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        socket.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).unwrap();
+        assert!(initiate(&mut socket, &INFO_HASH, CRYPTO_PLAINTEXT).is_err());
+        assert!(negotiated.recv().unwrap().is_err());
+    }
+
+    /// A peer without encryption, it closes connections that do not start with the handshake.
+    fn plaintext_peer(connections: usize) -> (u16, Receiver<bool>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = channel();
+        spawn(move || {
+            for _ in 0..connections {
+                let (mut socket, _) = listener.accept().unwrap();
+                let mut received = vec![0; handshake().len()];
+                socket.read_exact(&mut received).unwrap();
+                let plaintext = received == handshake();
+                sender.send(plaintext).unwrap();
+                if plaintext {
+                    socket.write_all(&handshake()).unwrap();
+                }
+            }
+        });
+        (port, receiver)
+    }
+
+    #[test]
+    fn test_only_forced_encryption_refuses_plaintext_peers() {
+        let (port, handshakes) = plaintext_peer(2);
+        let mut stream = EncryptedStream::new(
+            LoopStream::create(),
+            INFO_HASH.to_vec(),
+            EncryptionPolicy::Enabled,
+        );
+        stream.connect("127.0.0.1", port).unwrap();
+        assert!(!stream.is_encrypted());
+        stream.write_all(&handshake()).unwrap();
+        let mut received = vec![0; handshake().len()];
+        stream.read_exact(&mut received).unwrap();
+        assert_eq!(received, handshake());
+        assert!(!handshakes.recv().unwrap());
+        assert!(handshakes.recv().unwrap());
+
+        let (port, handshakes) = plaintext_peer(1);
+        let mut stream = EncryptedStream::new(
+            LoopStream::create(),
+            INFO_HASH.to_vec(),
+            EncryptionPolicy::Forced,
+        );
+        assert!(stream.connect("127.0.0.1", port).is_err());
+        assert!(!handshakes.recv().unwrap());
+    }
+}
@@ -0,0 +1,1076 @@
+use crate::{
+    errors::communication_method_error::CommunicationMethodError,
+    events::Direction,
+    peer_entities::communication_method::CommunicationMethod,
+    peer_entities::encrypted_stream::{Cipher, Negotiated, Rc4},
+    rate_estimator::{Traffic, TransferMeter},
+    rate_limiter::RateLimiter,
+    utilities::constants::{
+        CHUNK_LEN_LEN, INFO_HASH_LEN, PEER_ID_LEN, PSTR_LEN_LEN, RESERVED_SPACE_LEN,
+    },
+    utilities::utils::vecu8_to_u32,
+};
+use mio::{net::TcpStream, Events, Interest, Poll, Token, Waker};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::{self, Debug},
+    io::{ErrorKind, Read, Write},
+    net::{IpAddr, Shutdown, SocketAddr},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{channel, Receiver, Sender, TryRecvError},
+        Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError,
+    },
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+
+const WAKER: Token = Token(usize::MAX);
+/// How often the handlers are ticked.
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+/// The loop stops reading the socket of a stream while this many bytes wait in its inbox, or wait for a
+/// handler to be set.
+const MAX_INBOX: usize = 1024 * 1024;
+/// A peer that leaves this many bytes unread is closed, the writes would pile up otherwise.
+const MAX_OUTBOX: usize = 4 * 1024 * 1024;
+/// The longest message a peer can send, enough for the bitfield of a huge torrent.
+const MAX_FRAME_LEN: usize = 1024 * 1024;
+const READ_BUFFER_LEN: usize = 64 * 1024;
+
+/// Reacts to the frames of a connection on the thread of the event loop. It must never block: the frame it
+/// is called for is already in its stream, and what it writes is sent by the loop afterwards.
+pub trait ConnectionHandler: Send {
+    /// Handles the handshake or the message waiting in the stream, the connection is closed if it returns false.
+    fn frame(&mut self) -> bool;
+    /// Called about once a second, the connection is closed if it returns false.
+    fn tick(&mut self) -> bool {
+        true
+    }
+    /// Called once the connection is closed, with the error that closed it. There is no error when the
+    /// handler or a stream closed it.
+    fn closed(&mut self, _error: Option<String>) {}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Status {
+    Connecting,
+    Open,
+    Closed(String),
+}
+
+#[derive(Debug)]
+struct Buffers {
+    status: Status,
+    /// Read from the socket, waiting to be read by the stream.
+    inbox: VecDeque<u8>,
+    /// Written by the stream, waiting to be sent.
+    outbox: VecDeque<u8>,
+    /// The loop stopped reading the socket until the stream reads the inbox.
+    paused: bool,
+    flush_pending: bool,
+    /// Encrypts what the stream writes, for the connections encrypted with MSE.
+    encrypt: Option<Rc4>,
+    /// Takes the bytes that go through the socket. The loop never waits for it, it holds the connection
+    /// until the limiter is paid for what was read or written.
+    limiter: Option<Arc<RateLimiter>>,
+    /// The outbox is not sent before then, the limiter is paying for it.
+    send_at: Option<Instant>,
+}
+
+/// What the streams and the loop share of a connection.
+#[derive(Debug)]
+struct Shared {
+    token: Token,
+    peer_addr: SocketAddr,
+    buffers: Mutex<Buffers>,
+    changed: Condvar,
+}
+
+/// How the bytes read from a connection reach the one that reads them.
+enum Delivery {
+    /// They wait until a handler is set, like the handshake of an accepted connection.
+    Waiting,
+    /// They go to the inbox as they arrive, a thread reads them with read_exact.
+    Stream,
+    /// They are split in frames, each one is put alone in the inbox and handled on the loop.
+    Frames(Box<dyn ConnectionHandler>),
+}
+
+enum Command {
+    Open {
+        socket: TcpStream,
+        shared: Arc<Shared>,
+        delivery: Delivery,
+        decrypt: Option<Box<Rc4>>,
+    },
+    Handle(Token, Box<dyn ConnectionHandler>),
+    Flush(Token),
+    Resume(Token),
+    Close(Token),
+}
+
+/// Why the loop closes a connection: an error, or None when the handler asked for it.
+type CloseReason = Option<String>;
+
+/// Runs the sockets of the peer connections on one thread. Each connection reads and writes its socket only
+/// when it is ready, buffering what comes in until it is read and what goes out until the socket takes it,
+/// so no thread waits on a socket. Connections are used through a LoopStream, either from another thread
+/// like a blocking socket or on the loop itself by a ConnectionHandler.
+pub struct EventLoop {
+    commands: Mutex<Sender<Command>>,
+    waker: Waker,
+    next_token: AtomicUsize,
+    thread: ThreadId,
+}
+
+/// A connection run by an EventLoop. Its clones share the connection.
+#[derive(Debug, Clone)]
+pub struct LoopStream {
+    event_loop: Arc<EventLoop>,
+    shared: Option<Arc<Shared>>,
+    read_timeout: Option<Duration>,
+    limiter: Option<Arc<RateLimiter>>,
+    meter: Option<Arc<TransferMeter>>,
+}
+
+impl EventLoop {
+    /// Starts the thread of the loop.
+    pub fn new() -> Result<Arc<EventLoop>, CommunicationMethodError> {
+        let poll = Poll::new()?;
+        let waker = Waker::new(poll.registry(), WAKER)?;
+        let (sender, receiver) = channel();
+        let reactor = Reactor {
+            poll,
+            commands: receiver,
+            connections: HashMap::new(),
+        };
+        let handle = thread::Builder::new()
+            .name("event loop".to_string())
+            .spawn(move || reactor.run())?;
+        Ok(Arc::new(EventLoop {
+            commands: Mutex::new(sender),
+            waker,
+            next_token: AtomicUsize::new(0),
+            thread: handle.thread().id(),
+        }))
+    }
+
+    /// Returns a stream that is not connected yet.
+    pub fn stream(self: &Arc<Self>) -> LoopStream {
+        LoopStream {
+            event_loop: self.clone(),
+            shared: None,
+            read_timeout: None,
+            limiter: None,
+            meter: None,
+        }
+    }
+
+    /// Runs a connection accepted elsewhere. What the peer sends waits until a handler is set with
+    /// LoopStream::handle.
+    pub fn adopt(
+        self: &Arc<Self>,
+        socket: std::net::TcpStream,
+    ) -> Result<LoopStream, CommunicationMethodError> {
+        self.adopt_with(socket, None, Vec::new())
+    }
+
+    /// Runs a connection accepted elsewhere whose encryption handshake was answered already. The loop
+    /// decrypts what the peer sends, starting with what it sent within the handshake, and encrypts what the
+    /// stream writes, so the handlers see the plaintext.
+    pub fn adopt_negotiated(
+        self: &Arc<Self>,
+        socket: std::net::TcpStream,
+        negotiated: Negotiated,
+    ) -> Result<LoopStream, CommunicationMethodError> {
+        self.adopt_with(socket, negotiated.cipher, negotiated.received)
+    }
+
+    fn adopt_with(
+        self: &Arc<Self>,
+        socket: std::net::TcpStream,
+        cipher: Option<Cipher>,
+        received: Vec<u8>,
+    ) -> Result<LoopStream, CommunicationMethodError> {
+        socket.set_nonblocking(true)?;
+        let peer_addr = socket.peer_addr()?;
+        let shared = self.open(
+            TcpStream::from_std(socket),
+            peer_addr,
+            Status::Open,
+            Delivery::Waiting,
+            cipher,
+            received,
+        );
+        let mut stream = self.stream();
+        stream.shared = Some(shared);
+        Ok(stream)
+    }
+
+    /// Hands the socket to the loop. The received bytes are the first ones of the connection, and the
+    /// cipher encrypts and decrypts what goes through it.
+    fn open(
+        &self,
+        socket: TcpStream,
+        peer_addr: SocketAddr,
+        status: Status,
+        delivery: Delivery,
+        cipher: Option<Cipher>,
+        received: Vec<u8>,
+    ) -> Arc<Shared> {
+        let (decrypt, encrypt) = match cipher {
+            Some(cipher) => (Some(Box::new(cipher.decrypt)), Some(cipher.encrypt)),
+            None => (None, None),
+        };
+        let shared = Arc::new(Shared {
+            token: Token(self.next_token.fetch_add(1, Ordering::Relaxed)),
+            peer_addr,
+            buffers: Mutex::new(Buffers {
+                status,
+                inbox: received.into(),
+                outbox: VecDeque::new(),
+                paused: false,
+                flush_pending: false,
+                encrypt,
+                limiter: None,
+                send_at: None,
+            }),
+            changed: Condvar::new(),
+        });
+        self.send(Command::Open {
+            socket,
+            shared: shared.clone(),
+            delivery,
+            decrypt,
+        });
+        shared
+    }
+
+    fn send(&self, command: Command) {
+        let sent = self
+            .commands
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .send(command);
+        if sent.is_ok() {
+            let _r = self.waker.wake();
+        }
+    }
+
+    /// Returns true on the thread of the loop, where nothing can wait for the loop.
+    fn on_loop(&self) -> bool {
+        thread::current().id() == self.thread
+    }
+}
+
+impl Debug for EventLoop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventLoop")
+            .field("thread", &self.thread)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LoopStream {
+    /// Lets the handler react to the frames of the connection from now on. The bytes the connection
+    /// already got are its first frames.
+    pub fn handle(
+        &self,
+        handler: Box<dyn ConnectionHandler>,
+    ) -> Result<(), CommunicationMethodError> {
+        let shared = self.shared()?;
+        self.event_loop.send(Command::Handle(shared.token, handler));
+        Ok(())
+    }
+
+    /// Takes the whole frame being handled, for the handlers that parse it themselves.
+    pub fn take_frame(&self) -> Vec<u8> {
+        match &self.shared {
+            Some(shared) => shared.lock().inbox.drain(..).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn shared(&self) -> Result<&Arc<Shared>, CommunicationMethodError> {
+        self.shared.as_ref().ok_or_else(|| {
+            CommunicationMethodError::new("Stream is None, could be disconnected".to_string())
+        })
+    }
+}
+
+impl CommunicationMethod for LoopStream {
+    /// Creates a stream on the event loop shared by the streams made with create().
+    fn create() -> Box<dyn CommunicationMethod + Send> {
+        static DEFAULT_LOOP: OnceLock<Arc<EventLoop>> = OnceLock::new();
+        let event_loop =
+            DEFAULT_LOOP.get_or_init(|| EventLoop::new().expect("could not start the event loop"));
+        Box::new(event_loop.stream())
+    }
+
+    /// Connects to the peer, waiting until the connection is open. The reads and writes go through the loop.
+    /// The ip is an IPv4 or an IPv6 address, the latter with or without brackets.
+    fn connect(&mut self, ip: &str, port: u16) -> Result<(), CommunicationMethodError> {
+        let ip: IpAddr = ip
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+            .map_err(|_| CommunicationMethodError::new(format!("Invalid ip: {}", ip)))?;
+        self.disconnect();
+        let peer_addr = SocketAddr::new(ip, port);
+        let socket = TcpStream::connect(peer_addr)?;
+        let shared = self.event_loop.open(
+            socket,
+            peer_addr,
+            Status::Connecting,
+            Delivery::Stream,
+            None,
+            Vec::new(),
+        );
+        shared.lock().limiter = self.limiter.clone();
+        let buffers = shared
+            .changed
+            .wait_timeout_while(shared.lock(), CONNECT_TIMEOUT, |buffers| {
+                buffers.status == Status::Connecting
+            })
+            .unwrap_or_else(PoisonError::into_inner)
+            .0;
+        match buffers.status.clone() {
+            Status::Open => {
+                drop(buffers);
+                self.shared = Some(shared);
+                Ok(())
+            }
+            Status::Closed(error) => Err(CommunicationMethodError::new(error)),
+            Status::Connecting => {
+                drop(buffers);
+                self.event_loop.send(Command::Close(shared.token));
+                Err(CommunicationMethodError::new(format!(
+                    "Connection to {} timed out",
+                    peer_addr
+                )))
+            }
+        }
+    }
+
+    /// Reads what the loop already read from the socket, waiting up to the read timeout for the rest.
+    /// On the loop only the frame being handled can be read. The loop took the bytes from the limiter
+    /// when it read them.
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), CommunicationMethodError> {
+        let shared = self.shared()?.clone();
+        let on_loop = self.event_loop.on_loop();
+        let deadline = self.read_timeout.map(|timeout| Instant::now() + timeout);
+        let mut buffers = shared.lock();
+        while buffers.inbox.len() < buf.len() {
+            if on_loop {
+                return Err(CommunicationMethodError::new(
+                    "The frame is shorter than the message".to_string(),
+                ));
+            }
+            if let Status::Closed(error) = &buffers.status {
+                return Err(CommunicationMethodError::new(error.clone()));
+            }
+            buffers = match deadline {
+                None => shared
+                    .changed
+                    .wait(buffers)
+                    .unwrap_or_else(PoisonError::into_inner),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(CommunicationMethodError::new("Read timed out".to_string()));
+                    }
+                    shared
+                        .changed
+                        .wait_timeout(buffers, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+            };
+        }
+        let len = buf.len();
+        for (byte, read) in buf.iter_mut().zip(buffers.inbox.drain(..len)) {
+            *byte = read;
+        }
+        let resume = buffers.paused && buffers.inbox.len() < MAX_INBOX / 2;
+        buffers.paused &= !resume;
+        drop(buffers);
+        if resume {
+            self.event_loop.send(Command::Resume(shared.token));
+        }
+        if let Some(meter) = &self.meter {
+            meter.record(Direction::Download, Traffic::Wire, buf.len());
+        }
+        Ok(())
+    }
+
+    /// Queues the bytes to be sent by the loop, it waits neither for the socket nor for the limiter. The
+    /// bytes are taken from the limiter now, and the loop holds the outbox until they are paid for.
+    /// The connection is closed if the outbox would go over MAX_OUTBOX, the peer is not reading.
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), CommunicationMethodError> {
+        let shared = self.shared()?.clone();
+        let mut guard = shared.lock();
+        let buffers = &mut *guard;
+        if let Status::Closed(error) = &buffers.status {
+            return Err(CommunicationMethodError::new(error.clone()));
+        }
+        if buffers.outbox.len() + buf.len() > MAX_OUTBOX {
+            let error = format!("The peer left {} bytes unread", buffers.outbox.len());
+            buffers.status = Status::Closed(error.clone());
+            drop(guard);
+            shared.changed.notify_all();
+            self.event_loop.send(Command::Close(shared.token));
+            return Err(CommunicationMethodError::new(error));
+        }
+        if let Some(limiter) = &buffers.limiter {
+            let now = Instant::now();
+            let wait = limiter.reserve(Direction::Upload, buf.len(), now);
+            if !wait.is_zero() {
+                buffers.send_at = buffers.send_at.max(Some(now + wait));
+            }
+        }
+        match &mut buffers.encrypt {
+            Some(encrypt) => {
+                let mut encrypted = buf.to_vec();
+                encrypt.apply(&mut encrypted);
+                buffers.outbox.extend(encrypted);
+            }
+            None => buffers.outbox.extend(buf),
+        }
+        let flush = !buffers.flush_pending;
+        buffers.flush_pending = true;
+        drop(guard);
+        if flush {
+            self.event_loop.send(Command::Flush(shared.token));
+        }
+        if let Some(meter) = &self.meter {
+            meter.record(Direction::Upload, Traffic::Wire, buf.len());
+        }
+        Ok(())
+    }
+
+    fn set_read_timeout(&mut self, dur: Option<Duration>) -> Result<(), CommunicationMethodError> {
+        self.read_timeout = dur;
+        Ok(())
+    }
+
+    fn peer_addr(&self) -> Result<SocketAddr, CommunicationMethodError> {
+        Ok(self.shared()?.peer_addr)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.shared
+            .as_ref()
+            .is_some_and(|shared| shared.lock().status == Status::Open)
+    }
+
+    /// Closes the connection once what was written is sent. The stream can connect again afterwards.
+    fn disconnect(&mut self) -> bool {
+        match self.shared.take() {
+            Some(shared) => {
+                self.event_loop.send(Command::Close(shared.token));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_rate_limiter(&mut self, limiter: Arc<RateLimiter>) {
+        if let Some(shared) = &self.shared {
+            shared.lock().limiter = Some(limiter.clone());
+        }
+        self.limiter = Some(limiter);
+    }
+
+    fn rate_limiter(&self) -> Option<Arc<RateLimiter>> {
+        self.limiter.clone()
+    }
+
+    fn set_rate_meter(&mut self, meter: Arc<TransferMeter>) {
+        self.meter = Some(meter);
+    }
+
+    fn rate_meter(&self) -> Option<Arc<TransferMeter>> {
+        self.meter.clone()
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Buffers> {
+        self.buffers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn set_status(&self, status: Status) {
+        self.lock().status = status;
+        self.changed.notify_all();
+    }
+}
+
+/// Splits the bytes of a connection in frames: the handshake, then the messages with their length.
+#[derive(Debug, Default)]
+struct FrameReader {
+    buffer: Vec<u8>,
+    handshaken: bool,
+}
+
+impl FrameReader {
+    fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Returns the next whole frame, an error if the peer sent a message too long to be one.
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>, String> {
+        let len = match (self.handshaken, self.buffer.first()) {
+            (false, None) => return Ok(None),
+            (false, Some(pstr_len)) => {
+                PSTR_LEN_LEN
+                    + *pstr_len as usize
+                    + RESERVED_SPACE_LEN as usize
+                    + INFO_HASH_LEN
+                    + PEER_ID_LEN
+            }
+            (true, _) if self.buffer.len() < CHUNK_LEN_LEN => return Ok(None),
+            (true, _) => {
+                let len = vecu8_to_u32(&self.buffer) as usize;
+                if len > MAX_FRAME_LEN {
+                    return Err(format!("The peer sent a message of {} bytes", len));
+                }
+                CHUNK_LEN_LEN + len
+            }
+        };
+        if self.buffer.len() < len {
+            return Ok(None);
+        }
+        self.handshaken = true;
+        Ok(Some(self.buffer.drain(..len).collect()))
+    }
+}
+
+/// A connection as the loop sees it.
+struct Connection {
+    socket: TcpStream,
+    shared: Arc<Shared>,
+    reader: FrameReader,
+    delivery: Delivery,
+    /// Decrypts what the peer sends, for the connections encrypted with MSE.
+    decrypt: Option<Rc4>,
+    /// The socket is not read before then, the limiter is paying for what was read.
+    read_at: Option<Instant>,
+}
+
+impl Connection {
+    /// Does what the readiness of the socket allows.
+    fn ready(&mut self, readable: bool, writable: bool) -> Result<(), CloseReason> {
+        if self.shared.lock().status == Status::Connecting {
+            if let Some(error) = self.socket.take_error().unwrap_or_else(Some) {
+                return Err(Some(error.to_string()));
+            }
+            match self.socket.peer_addr() {
+                Ok(_) => self.shared.set_status(Status::Open),
+                // not connected yet, it was a spurious event
+                Err(e) if e.kind() == ErrorKind::NotConnected => return Ok(()),
+                Err(e) => return Err(Some(e.to_string())),
+            }
+        }
+        if writable {
+            self.flush()?;
+        }
+        if readable {
+            self.read()?;
+        }
+        Ok(())
+    }
+
+    /// Reads the socket until it has nothing more, until the inbox of the stream is full or until the
+    /// limiter has to be paid for what was read.
+    fn read(&mut self) -> Result<(), CloseReason> {
+        let mut buffer = [0; READ_BUFFER_LEN];
+        loop {
+            if self.shared.lock().paused || self.read_at.is_some() {
+                return Ok(());
+            }
+            match self.socket.read(&mut buffer) {
+                Ok(0) => return Err(Some("The peer closed the connection".to_string())),
+                Ok(read) => {
+                    self.received(&mut buffer[..read])?;
+                    let limiter = self.shared.lock().limiter.clone();
+                    if let Some(limiter) = limiter {
+                        let now = Instant::now();
+                        let wait = limiter.reserve(Direction::Download, read, now);
+                        if !wait.is_zero() {
+                            self.read_at = Some(now + wait);
+                        }
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Some(e.to_string())),
+            }
+        }
+    }
+
+    fn received(&mut self, bytes: &mut [u8]) -> Result<(), CloseReason> {
+        if let Some(decrypt) = &mut self.decrypt {
+            decrypt.apply(bytes);
+        }
+        match self.delivery {
+            Delivery::Stream => {
+                let mut buffers = self.shared.lock();
+                buffers.inbox.extend(bytes.iter());
+                buffers.paused = buffers.inbox.len() >= MAX_INBOX;
+                drop(buffers);
+                self.shared.changed.notify_all();
+                Ok(())
+            }
+            Delivery::Waiting => {
+                self.reader.push(bytes);
+                // reading goes on once the handler is set
+                self.shared.lock().paused = self.reader.buffer.len() >= MAX_INBOX;
+                Ok(())
+            }
+            Delivery::Frames(_) => {
+                self.reader.push(bytes);
+                self.handle_frames()
+            }
+        }
+    }
+
+    /// Hands the whole frames read so far to the handler, one at a time.
+    fn handle_frames(&mut self) -> Result<(), CloseReason> {
+        while let Some(frame) = self.reader.next_frame().map_err(Some)? {
+            let handler = match &mut self.delivery {
+                Delivery::Frames(handler) => handler,
+                _ => return Ok(()),
+            };
+            self.shared.lock().inbox = frame.into();
+            let keep = guarded(|| handler.frame()).map_err(Some)?;
+            // what the handler did not read of the frame is dropped with it
+            self.shared.lock().inbox.clear();
+            if !keep {
+                return Err(None);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes what was written to the stream until the socket takes no more, unless the limiter holds it.
+    fn flush(&mut self) -> Result<(), CloseReason> {
+        let mut buffers = self.shared.lock();
+        buffers.flush_pending = false;
+        if buffers.send_at.is_some() {
+            return Ok(());
+        }
+        while !buffers.outbox.is_empty() {
+            let written = match self.socket.write(buffers.outbox.as_slices().0) {
+                Ok(0) => return Err(Some("The peer closed the connection".to_string())),
+                Ok(written) => written,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Some(e.to_string())),
+            };
+            buffers.outbox.drain(..written);
+        }
+        Ok(())
+    }
+
+    /// Reads and sends again once the limiter was paid for what was held. Returns when the connection is
+    /// held until, if it still is.
+    fn release(&mut self, now: Instant) -> Result<Option<Instant>, CloseReason> {
+        if self.read_at.is_some_and(|at| at <= now) {
+            self.read_at = None;
+            self.read()?;
+        }
+        let send_at = self.shared.lock().send_at;
+        if send_at.is_some_and(|at| at <= now) {
+            self.shared.lock().send_at = None;
+            self.flush()?;
+        }
+        let send_at = self.shared.lock().send_at;
+        Ok(self.read_at.into_iter().chain(send_at).min())
+    }
+}
+
+/// The thread of an EventLoop.
+struct Reactor {
+    poll: Poll,
+    commands: Receiver<Command>,
+    connections: HashMap<Token, Connection>,
+}
+
+impl Reactor {
+    /// Runs the connections until the EventLoop is dropped.
+    fn run(mut self) {
+        let mut events = Events::with_capacity(1024);
+        let mut next_tick = Instant::now() + TICK_INTERVAL;
+        let mut next_release: Option<Instant> = None;
+        loop {
+            let wake_at = next_release.map_or(next_tick, |at| at.min(next_tick));
+            let timeout = wake_at.saturating_duration_since(Instant::now());
+            if let Err(e) = self.poll.poll(&mut events, Some(timeout)) {
+                if e.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                return self.close_all(e.to_string());
+            }
+            for event in events.iter() {
+                let token = event.token();
+                let result = match self.connections.get_mut(&token) {
+                    Some(connection) => connection.ready(
+                        event.is_readable() || event.is_read_closed() || event.is_error(),
+                        event.is_writable(),
+                    ),
+                    None => Ok(()),
+                };
+                if let Err(reason) = result {
+                    self.close(token, reason);
+                }
+            }
+            loop {
+                match self.commands.try_recv() {
+                    Ok(command) => self.command(command),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        return self.close_all("The event loop stopped".to_string())
+                    }
+                }
+            }
+            next_release = self.release();
+            if Instant::now() >= next_tick {
+                self.tick();
+                next_tick = Instant::now() + TICK_INTERVAL;
+            }
+        }
+    }
+
+    fn command(&mut self, command: Command) {
+        let (token, result) = match command {
+            Command::Open {
+                mut socket,
+                shared,
+                delivery,
+                decrypt,
+            } => {
+                let token = shared.token;
+                let registered = self.poll.registry().register(
+                    &mut socket,
+                    token,
+                    Interest::READABLE | Interest::WRITABLE,
+                );
+                if let Err(e) = registered {
+                    shared.set_status(Status::Closed(e.to_string()));
+                    return;
+                }
+                self.connections.insert(
+                    token,
+                    Connection {
+                        socket,
+                        shared,
+                        reader: FrameReader::default(),
+                        delivery,
+                        decrypt: decrypt.map(|decrypt| *decrypt),
+                        read_at: None,
+                    },
+                );
+                (token, Ok(()))
+            }
+            Command::Handle(token, mut handler) => match self.connections.get_mut(&token) {
+                Some(connection) => {
+                    // the bytes a thread did not read are the first ones of the frames
+                    let unread: Vec<u8> = connection.shared.lock().inbox.drain(..).collect();
+                    connection.reader.buffer.splice(..0, unread);
+                    connection.shared.lock().paused = false;
+                    connection.delivery = Delivery::Frames(handler);
+                    let result = connection.handle_frames();
+                    (token, result.and_then(|_| connection.read()))
+                }
+                None => {
+                    handler.closed(Some("The connection is closed".to_string()));
+                    return;
+                }
+            },
+            Command::Flush(token) => (
+                token,
+                self.connections
+                    .get_mut(&token)
+                    .map_or(Ok(()), |connection| connection.flush()),
+            ),
+            Command::Resume(token) => (
+                token,
+                self.connections
+                    .get_mut(&token)
+                    .map_or(Ok(()), |connection| connection.read()),
+            ),
+            Command::Close(token) => (token, Err(None)),
+        };
+        if let Err(reason) = result {
+            self.close(token, reason);
+        }
+    }
+
+    /// Releases the connections the limiter held until now, returns when the next one is due.
+    fn release(&mut self) -> Option<Instant> {
+        let now = Instant::now();
+        let mut next = None;
+        let mut closing = Vec::new();
+        for (token, connection) in self.connections.iter_mut() {
+            match connection.release(now) {
+                Ok(at) => next = next.into_iter().chain(at).min(),
+                Err(reason) => closing.push((*token, reason)),
+            }
+        }
+        for (token, reason) in closing {
+            self.close(token, reason);
+        }
+        next
+    }
+
+    fn tick(&mut self) {
+        let mut closing = Vec::new();
+        for (token, connection) in self.connections.iter_mut() {
+            if let Delivery::Frames(handler) = &mut connection.delivery {
+                match guarded(|| handler.tick()) {
+                    Ok(true) => {}
+                    Ok(false) => closing.push((*token, None)),
+                    Err(e) => closing.push((*token, Some(e))),
+                }
+            }
+        }
+        for (token, reason) in closing {
+            self.close(token, reason);
+        }
+    }
+
+    /// Sends what it can of what was written, and closes the connection.
+    fn close(&mut self, token: Token, reason: CloseReason) {
+        let mut connection = match self.connections.remove(&token) {
+            Some(connection) => connection,
+            None => return,
+        };
+        // what was written is sent even if the limiter held it
+        connection.shared.lock().send_at = None;
+        let _r = connection.flush();
+        let _r = connection.socket.shutdown(Shutdown::Both);
+        let _r = self.poll.registry().deregister(&mut connection.socket);
+        connection.shared.set_status(Status::Closed(
+            reason
+                .clone()
+                .unwrap_or_else(|| "The connection was closed".to_string()),
+        ));
+        if let Delivery::Frames(handler) = &mut connection.delivery {
+            let _r = guarded(|| handler.closed(reason));
+        }
+    }
+
+    fn close_all(&mut self, error: String) {
+        let tokens: Vec<Token> = self.connections.keys().copied().collect();
+        for token in tokens {
+            self.close(token, Some(error.clone()));
+        }
+    }
+}
+
+/// Runs a call of a handler, a handler that panics loses its connection instead of stopping the loop.
+fn guarded<T>(call: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(call))
+        .map_err(|_| "The handler of the connection panicked".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::constants::PSTR;
+    use std::net::TcpListener;
+
+    fn handshake() -> Vec<u8> {
+        let mut handshake = vec![PSTR.len() as u8];
+        handshake.extend(PSTR.as_bytes());
+        handshake.extend([0; RESERVED_SPACE_LEN as usize]);
+        handshake.extend([5; INFO_HASH_LEN]);
+        handshake.extend(b"-CR0001-123456789012");
+        handshake
+    }
+
+    /// Sends every frame back.
+    struct Echo {
+        stream: LoopStream,
+    }
+
+    impl ConnectionHandler for Echo {
+        fn frame(&mut self) -> bool {
+            let frame = self.stream.take_frame();
+            self.stream.write_all(&frame).is_ok()
+        }
+    }
+
+    #[test]
+    fn test_frames_are_split_across_reads() {
+        let mut bytes = handshake();
+        bytes.extend([0, 0, 0, 1, 2]);
+        bytes.extend([0, 0, 0, 5, 4, 0, 0, 0, 7]);
+        bytes.extend([0, 0, 0]);
+        let mut reader = FrameReader::default();
+        let mut frames = Vec::new();
+        for part in bytes.chunks(7) {
+            reader.push(part);
+            while let Some(frame) = reader.next_frame().unwrap() {
+                frames.push(frame);
+            }
+        }
+        assert_eq!(
+            frames,
+            vec![
+                handshake(),
+                vec![0, 0, 0, 1, 2],
+                vec![0, 0, 0, 5, 4, 0, 0, 0, 7]
+            ]
+        );
+
+        // the last message is not whole yet
+        assert!(reader.next_frame().unwrap().is_none());
+        let mut reader = FrameReader {
+            buffer: Vec::new(),
+            handshaken: true,
+        };
+        reader.push(&[255, 255, 255, 255]);
+        assert!(reader.next_frame().is_err());
+    }
+
+    #[test]
+    fn test_streams_and_handlers_share_the_loop() {
+        let event_loop = EventLoop::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let mut client = event_loop.stream();
+        client.connect("127.0.0.1", port).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        // what arrives before the handler is set is not lost
+        client.write_all(&handshake()).unwrap();
+        let (socket, _) = listener.accept().unwrap();
+        let server = event_loop.adopt(socket).unwrap();
+        server
+            .handle(Box::new(Echo {
+                stream: server.clone(),
+            }))
+            .unwrap();
+        client.write_all(&[0, 0, 0, 1, 2]).unwrap();
+
+        let mut echoed = vec![0; handshake().len() + 5];
+        client.read_exact(&mut echoed).unwrap();
+        assert_eq!(echoed[..handshake().len()], handshake());
+        assert_eq!(echoed[handshake().len()..], [0, 0, 0, 1, 2]);
+        assert!(client.is_connected());
+        assert!(client.disconnect());
+        assert!(!client.is_connected());
+        assert!(client.write_all(&[0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_the_limiter_holds_the_connections_of_the_loop() {
+        let event_loop = EventLoop::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let (socket, _) = listener.accept().unwrap();
+        let mut server = event_loop.adopt(socket).unwrap();
+        // the buckets start with a second of bytes, the other second is waited for
+        server.set_rate_limiter(RateLimiter::new(100_000, 100_000));
+        server
+            .handle(Box::new(Echo {
+                stream: server.clone(),
+            }))
+            .unwrap();
+
+        let mut frame = vec![0, 0, 0x27, 0x10];
+        frame.extend([7; 10_000]);
+        let mut sent = handshake();
+        for _ in 0..20 {
+            sent.extend(&frame);
+        }
+        let start = Instant::now();
+        let mut writer = client.try_clone().unwrap();
+        let to_send = sent.clone();
+        let writing = thread::spawn(move || writer.write_all(&to_send).unwrap());
+        let mut echoed = vec![0; sent.len()];
+        client.read_exact(&mut echoed).unwrap();
+        let elapsed = start.elapsed();
+        writing.join().unwrap();
+
+        assert_eq!(echoed, sent);
+        assert!(elapsed >= Duration::from_millis(900), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(5), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn test_a_peer_that_does_not_read_is_closed() {
+        let event_loop = EventLoop::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let mut client = event_loop.stream();
+        client.connect("127.0.0.1", port).unwrap();
+        // the peer never reads
+        let (_socket, _) = listener.accept().unwrap();
+        let block = vec![0; 64 * 1024];
+        let mut written = 0;
+        while client.write_all(&block).is_ok() {
+            written += block.len();
+            // more than the socket buffers of any host and the outbox
+            assert!(written < 64 * MAX_OUTBOX);
+        }
+        assert!(written >= MAX_OUTBOX - block.len());
+        assert!(!client.is_connected());
+        assert!(client.write_all(&[0]).is_err());
+    }
+
+    #[test]
+    fn test_reads_time_out_and_fail_once_closed() {
+        let event_loop = EventLoop::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let mut client = event_loop.stream();
+        client.connect("127.0.0.1", port).unwrap();
+        let (socket, _) = listener.accept().unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let mut buffer = [0; 4];
+        assert!(client.read_exact(&mut buffer).is_err());
+
+        drop(socket);
+        client.set_read_timeout(None).unwrap();
+        assert!(client.read_exact(&mut buffer).is_err());
+        assert!(!client.is_connected());
+
+        drop(listener);
+        assert!(client.connect("127.0.0.1", port).is_err());
+    }
+
+    #[test]
+    fn test_ipv6_peers_are_dialed() {
+        let listener = match TcpListener::bind("[::1]:0") {
+            Ok(listener) => listener,
+            // the host has no IPv6
+            Err(_) => return,
+        };
+        let port = listener.local_addr().unwrap().port();
+        let event_loop = EventLoop::new().unwrap();
+
+        let mut client = event_loop.stream();
+        assert!(client.connect("::1:", port).is_err());
+        assert!(client.connect("localhost", port).is_err());
+        client.connect("::1", port).unwrap();
+        let (socket, peer_addr) = listener.accept().unwrap();
+        assert_eq!(client.peer_addr().unwrap(), socket.local_addr().unwrap());
+        assert!(peer_addr.is_ipv6());
+
+        client.connect("[::1]", port).unwrap();
+        assert!(listener.accept().is_ok());
+    }
+}
@@ -1,3 +1,5 @@
 pub mod communication_method;
+pub mod encrypted_stream;
+pub mod event_loop;
 pub mod peer;
 pub mod peer_connection;
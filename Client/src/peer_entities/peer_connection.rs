@@ -1,8 +1,10 @@
 use crate::{
     errors::peer_connection_error::PeerConnectionError,
+    events::Direction,
     logger::LogMsg,
     peer_entities::communication_method::CommunicationMethod,
     peer_entities::peer::PeerInterface,
+    rate_estimator::Traffic,
     upload_manager::PieceRequest,
     utilities::constants::*,
     utilities::utils::{u32_to_vecu8, vecu8_to_u32},
@@ -359,7 +361,10 @@ impl<P: PeerInterface> PeerConnection<P> {
             )));
         }
 
-        let chunk = self.read_n_bytes(CHUNK_SIZE as usize)?;
+        let chunk = self.clone().read_n_bytes(CHUNK_SIZE as usize)?;
+        if let Some(meter) = self.stream.lock()?.rate_meter() {
+            meter.record(Direction::Download, Traffic::Payload, chunk.len());
+        }
 
         Ok(chunk)
     }
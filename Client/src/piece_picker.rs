@@ -0,0 +1,142 @@
+use crate::file_selection::FilePriority;
+use std::cmp::Reverse;
+
+/// The order in which the pieces of a torrent are requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadMode {
+    /// Pieces owned by fewer peers are requested first.
+    RarestFirst,
+    /// The lookahead pieces after the playback position are requested in order, the rest rarest first.
+    Sequential { lookahead: usize },
+    /// The first and last pieces of every file are requested before the rest, which is rarest first.
+    FirstAndLast,
+}
+
+/// The PiecePicker decides which missing pieces are requested first, according to the download mode of the torrent.
+#[derive(Debug)]
+pub struct PiecePicker {
+    mode: DownloadMode,
+    playback_position: usize,
+}
+
+impl PiecePicker {
+    /// Creates a new PiecePicker with the playback position at the start of the torrent.
+    pub fn new(mode: DownloadMode) -> PiecePicker {
+        PiecePicker {
+            mode,
+            playback_position: 0,
+        }
+    }
+
+    /// Returns the current download mode.
+    pub fn mode(&self) -> DownloadMode {
+        self.mode
+    }
+
+    /// Changes the download mode.
+    pub fn set_mode(&mut self, mode: DownloadMode) {
+        self.mode = mode;
+    }
+
+    /// Moves the playback position to the given piece, the sequential window starts there from now on.
+    pub fn set_playback_position(&mut self, piece_index: usize) {
+        self.playback_position = piece_index;
+    }
+
+    /// Returns the missing pieces in the order they should be requested.
+    /// missing holds (index, priority) in index order, availability the number of peers that have each piece,
+    /// and edges the first and last pieces of the wanted files.
+    pub fn order(
+        &self,
+        missing: &[(usize, FilePriority)],
+        availability: &[usize],
+        edges: &[usize],
+    ) -> Vec<usize> {
+        // the deadline is the first missing piece at the playback position, it moves as pieces arrive
+        let deadline = missing
+            .iter()
+            .map(|(i, _)| *i)
+            .find(|i| *i >= self.playback_position);
+
+        let mut pieces: Vec<(Option<usize>, FilePriority, usize, usize)> = missing
+            .iter()
+            .map(|(i, priority)| {
+                let urgency = match (self.mode, deadline) {
+                    (DownloadMode::Sequential { lookahead }, Some(deadline))
+                        if *i >= deadline && *i < deadline + lookahead =>
+                    {
+                        Some(*i)
+                    }
+                    (DownloadMode::FirstAndLast, _) if edges.contains(i) => Some(*i),
+                    _ => None,
+                };
+                let peers = availability.get(*i).copied().unwrap_or(0);
+                (urgency, *priority, peers, *i)
+            })
+            .collect();
+
+        pieces.sort_by_key(|(urgency, priority, peers, i)| {
+            (urgency.is_none(), *urgency, Reverse(*priority), *peers, *i)
+        });
+        pieces.into_iter().map(|(_, _, _, i)| i).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn missing(quantity: usize) -> Vec<(usize, FilePriority)> {
+        (0..quantity).map(|i| (i, FilePriority::Normal)).collect()
+    }
+
+    #[test]
+    fn test_rarest_first() {
+        let picker = PiecePicker::new(DownloadMode::RarestFirst);
+        let availability = vec![3, 1, 2, 1];
+        assert_eq!(
+            picker.order(&missing(4), &availability, &[]),
+            vec![1, 3, 2, 0]
+        );
+    }
+
+    #[test]
+    fn test_file_priority_comes_before_rarity() {
+        let picker = PiecePicker::new(DownloadMode::RarestFirst);
+        let pieces = vec![
+            (0, FilePriority::High),
+            (1, FilePriority::Normal),
+            (2, FilePriority::Low),
+        ];
+        assert_eq!(picker.order(&pieces, &[5, 1, 0], &[]), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_sequential_window_follows_the_deadline() {
+        let mut picker = PiecePicker::new(DownloadMode::Sequential { lookahead: 2 });
+        let availability = vec![1, 5, 5, 1, 2, 0];
+        assert_eq!(
+            picker.order(&missing(6), &availability, &[]),
+            vec![0, 1, 5, 3, 4, 2]
+        );
+
+        // piece 2 is already downloaded, so the window moves past it
+        picker.set_playback_position(2);
+        let pieces: Vec<(usize, FilePriority)> =
+            missing(6).into_iter().filter(|(i, _)| *i != 2).collect();
+        assert_eq!(
+            picker.order(&pieces, &availability, &[]),
+            vec![3, 4, 5, 0, 1]
+        );
+    }
+
+    #[test]
+    fn test_first_and_last_pieces_first() {
+        let picker = PiecePicker::new(DownloadMode::FirstAndLast);
+        let availability = vec![4, 0, 1, 4];
+        assert_eq!(
+            picker.order(&missing(4), &availability, &[0, 3]),
+            vec![0, 3, 1, 2]
+        );
+    }
+}
@@ -0,0 +1,887 @@
+use crate::{errors::port_mapping_error::PortMappingError, utilities::utils::vecu8_to_u32};
+use std::{
+    fs, io,
+    io::{Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant},
+};
+
+/// The port of NAT-PMP and PCP servers.
+const PCP_PORT: u16 = 5351;
+/// Where UPnP devices listen for searches.
+const SSDP_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(239, 255, 255, 250)), 1900);
+const NAT_PMP_VERSION: u8 = 0;
+const PCP_VERSION: u8 = 2;
+const PCP_ANNOUNCE: u8 = 0;
+const PCP_MAP: u8 = 1;
+/// Set in the opcode of the responses.
+const RESPONSE_BIT: u8 = 128;
+const PCP_HEADER_LEN: usize = 24;
+const PCP_MAP_LEN: usize = 60;
+/// The flag of /proc/net/route for routes through a gateway.
+const RTF_GATEWAY: u16 = 2;
+/// The first wait for an answer of a NAT-PMP or PCP server, it doubles on each try (RFC 6886).
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(250);
+const REQUEST_TRIES: u32 = 3;
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(2);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_RESPONSE_LEN: u64 = 64 * 1024;
+/// The services of an Internet Gateway Device that map ports, the preferred first.
+const UPNP_SERVICES: [&str; 3] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:2",
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+/// The fault of gateways that only make mappings without a lease.
+const ONLY_PERMANENT_LEASES: &str = "<errorCode>725</errorCode>";
+const MAPPING_DESCRIPTION: &str = "crabrave";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Tcp,
+    Udp,
+}
+
+impl Transport {
+    fn name(self) -> &'static str {
+        match self {
+            Transport::Tcp => "TCP",
+            Transport::Udp => "UDP",
+        }
+    }
+
+    /// The IANA protocol number, the one PCP uses.
+    fn protocol_number(self) -> u8 {
+        match self {
+            Transport::Tcp => 6,
+            Transport::Udp => 17,
+        }
+    }
+
+    fn nat_pmp_opcode(self) -> u8 {
+        match self {
+            Transport::Udp => 1,
+            Transport::Tcp => 2,
+        }
+    }
+}
+
+/// Where the gateways are looked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gateways {
+    /// The NAT-PMP or PCP server, None if the host has no default gateway.
+    pub pcp: Option<SocketAddr>,
+    /// Where the UPnP search is sent.
+    pub ssdp: SocketAddr,
+}
+
+impl Gateways {
+    /// The default gateway of the host and the UPnP multicast group.
+    pub fn of_host() -> Gateways {
+        let routes = fs::read_to_string("/proc/net/route").unwrap_or_default();
+        Gateways {
+            pcp: default_gateway(&routes).map(|ip| SocketAddr::new(ip, PCP_PORT)),
+            ssdp: SSDP_ADDR,
+        }
+    }
+}
+
+/// The address peers outside the network reach the client at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternalAddress {
+    pub ip: IpAddr,
+    pub port: u16,
+    /// The mapping has to be renewed before it ends, zero is a mapping without an end.
+    pub lifetime: Duration,
+}
+
+/// An http url of a UPnP gateway, the host has the port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HttpUrl {
+    host: String,
+    path: String,
+}
+
+/// A gateway found, and the protocol it is talked to with.
+#[derive(Debug, Clone)]
+enum Gateway {
+    /// The nonce tells the server the mappings are ours, the same one renews or removes them.
+    Pcp {
+        server: SocketAddr,
+        nonce: [u8; 12],
+    },
+    NatPmp {
+        server: SocketAddr,
+    },
+    Upnp {
+        control: HttpUrl,
+        service: &'static str,
+    },
+}
+
+/// Maps a port on the gateway of the network, so peers outside of it can connect to the client. The gateway
+/// is looked for the first time the port is mapped: a PCP or a NAT-PMP server at the default gateway, or
+/// a UPnP Internet Gateway Device found over SSDP. The port is mapped for TCP and UDP with the same
+/// external port, and mapping it again renews the leases.
+#[derive(Debug)]
+pub struct PortMapper {
+    gateways: Gateways,
+    port: u16,
+    gateway: Option<Gateway>,
+    /// The transports mapped on the gateway so far.
+    mapped: Vec<Transport>,
+}
+
+impl PortMapper {
+    pub fn new(port: u16, gateways: Gateways) -> PortMapper {
+        PortMapper {
+            gateways,
+            port,
+            gateway: None,
+            mapped: Vec::new(),
+        }
+    }
+
+    /// Maps the port, or renews the mappings, for the lifetime asked. Returns the external address of the
+    /// TCP mapping, its lifetime is the one the gateway gave.
+    pub fn map(&mut self, lifetime: Duration) -> Result<ExternalAddress, PortMappingError> {
+        let gateway = match &self.gateway {
+            Some(gateway) => gateway.clone(),
+            None => {
+                let gateway = self.discover()?;
+                self.gateway = Some(gateway.clone());
+                gateway
+            }
+        };
+        let external = gateway.map(Transport::Tcp, self.port, lifetime)?;
+        self.mapped.retain(|transport| *transport != Transport::Tcp);
+        self.mapped.push(Transport::Tcp);
+        gateway.map(Transport::Udp, self.port, lifetime)?;
+        self.mapped.retain(|transport| *transport != Transport::Udp);
+        self.mapped.push(Transport::Udp);
+        Ok(external)
+    }
+
+    /// Removes the mappings made, the last error is returned after trying every one.
+    pub fn unmap(&mut self) -> Result<(), PortMappingError> {
+        let gateway = match &self.gateway {
+            Some(gateway) => gateway,
+            None => return Ok(()),
+        };
+        let mut result = Ok(());
+        for transport in self.mapped.drain(..) {
+            if let Err(e) = gateway.unmap(transport, self.port) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Asks the default gateway whether it speaks PCP or NAT-PMP, and otherwise searches for a UPnP one.
+    fn discover(&self) -> Result<Gateway, PortMappingError> {
+        if let Some(server) = self.gateways.pcp {
+            let socket = connected_socket(server)?;
+            let announce = pcp_header(PCP_ANNOUNCE, 0, socket.local_addr()?.ip());
+            // a NAT-PMP server answers the PCP request in its own version
+            match request(&socket, &announce) {
+                Ok(response) if response.first() == Some(&PCP_VERSION) => {
+                    return Ok(Gateway::Pcp {
+                        server,
+                        nonce: rand::random(),
+                    })
+                }
+                Ok(response) if response.first() == Some(&NAT_PMP_VERSION) => {
+                    return Ok(Gateway::NatPmp { server })
+                }
+                _ => {}
+            }
+        }
+        let location = ssdp_search(self.gateways.ssdp)?;
+        let (status, description) = http_request(&location, "GET", "", "")?;
+        let (service, control) = match status {
+            200 => control_url(&description),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            PortMappingError::new(
+                "PortMappingError: the UPnP gateway can not map ports".to_string(),
+            )
+        })?;
+        let control = match control.strip_prefix("http://") {
+            Some(_) => parse_url(&control)?,
+            None => HttpUrl {
+                host: location.host,
+                path: format!("/{}", control.trim_start_matches('/')),
+            },
+        };
+        Ok(Gateway::Upnp { control, service })
+    }
+}
+
+impl Gateway {
+    fn map(
+        &self,
+        transport: Transport,
+        port: u16,
+        lifetime: Duration,
+    ) -> Result<ExternalAddress, PortMappingError> {
+        match self {
+            Gateway::Pcp { server, nonce } => pcp_map(*server, nonce, transport, port, lifetime),
+            Gateway::NatPmp { server } => nat_pmp_map(*server, transport, port, lifetime),
+            Gateway::Upnp { control, service } => {
+                upnp_map(control, service, transport, port, lifetime)
+            }
+        }
+    }
+
+    fn unmap(&self, transport: Transport, port: u16) -> Result<(), PortMappingError> {
+        match self {
+            Gateway::Pcp { server, nonce } => {
+                pcp_map(*server, nonce, transport, port, Duration::ZERO)?;
+            }
+            Gateway::NatPmp { server } => {
+                nat_pmp_map(*server, transport, port, Duration::ZERO)?;
+            }
+            Gateway::Upnp { control, service } => {
+                let arguments = [
+                    ("NewRemoteHost", String::new()),
+                    ("NewExternalPort", port.to_string()),
+                    ("NewProtocol", transport.name().to_string()),
+                ];
+                let (status, response) = soap(control, service, "DeletePortMapping", &arguments)?;
+                if status != 200 {
+                    return Err(upnp_error("DeletePortMapping", &response));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Maps the port with PCP, a lifetime of zero removes the mapping.
+fn pcp_map(
+    server: SocketAddr,
+    nonce: &[u8; 12],
+    transport: Transport,
+    port: u16,
+    lifetime: Duration,
+) -> Result<ExternalAddress, PortMappingError> {
+    let socket = connected_socket(server)?;
+    let mut map = pcp_header(PCP_MAP, seconds(lifetime), socket.local_addr()?.ip());
+    map.extend(nonce);
+    map.push(transport.protocol_number());
+    map.extend([0; 3]);
+    map.extend(port.to_be_bytes());
+    map.extend(port.to_be_bytes());
+    // any IPv4 address
+    map.extend(Ipv4Addr::UNSPECIFIED.to_ipv6_mapped().octets());
+
+    let response = request(&socket, &map)?;
+    if response.len() < PCP_MAP_LEN
+        || response[0] != PCP_VERSION
+        || response[1] != RESPONSE_BIT | PCP_MAP
+        || response[PCP_HEADER_LEN..PCP_HEADER_LEN + 12] != nonce[..]
+    {
+        return Err(invalid_response("PCP"));
+    }
+    if response[3] != 0 {
+        return Err(PortMappingError::new(format!(
+            "PortMappingError: the PCP server refused the mapping (result {})",
+            response[3]
+        )));
+    }
+    let mut octets = [0; 16];
+    octets.copy_from_slice(&response[44..PCP_MAP_LEN]);
+    let ip = Ipv6Addr::from(octets);
+    Ok(ExternalAddress {
+        ip: match ip.to_ipv4_mapped() {
+            Some(ip) => IpAddr::V4(ip),
+            None => IpAddr::V6(ip),
+        },
+        port: u16::from_be_bytes([response[42], response[43]]),
+        lifetime: Duration::from_secs(vecu8_to_u32(&response[4..8]) as u64),
+    })
+}
+
+/// The common header of PCP requests, the addresses are IPv6 or IPv4 mapped to IPv6.
+fn pcp_header(opcode: u8, lifetime: u32, client: IpAddr) -> Vec<u8> {
+    let client = match client {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    };
+    let mut header = vec![PCP_VERSION, opcode, 0, 0];
+    header.extend(lifetime.to_be_bytes());
+    header.extend(client.octets());
+    header
+}
+
+/// Maps the port with NAT-PMP, a lifetime of zero removes the mapping.
+fn nat_pmp_map(
+    server: SocketAddr,
+    transport: Transport,
+    port: u16,
+    lifetime: Duration,
+) -> Result<ExternalAddress, PortMappingError> {
+    let socket = connected_socket(server)?;
+    let opcode = transport.nat_pmp_opcode();
+    let mut map = vec![NAT_PMP_VERSION, opcode, 0, 0];
+    map.extend(port.to_be_bytes());
+    // the suggested external port, none when removing
+    match lifetime.is_zero() {
+        true => map.extend([0, 0]),
+        false => map.extend(port.to_be_bytes()),
+    }
+    map.extend(seconds(lifetime).to_be_bytes());
+    let response = nat_pmp_request(&socket, &map, 16)?;
+    let external_port = u16::from_be_bytes([response[10], response[11]]);
+    let lifetime = Duration::from_secs(vecu8_to_u32(&response[12..16]) as u64);
+
+    let response = nat_pmp_request(&socket, &[NAT_PMP_VERSION, 0], 12)?;
+    Ok(ExternalAddress {
+        ip: IpAddr::V4(Ipv4Addr::new(
+            response[8],
+            response[9],
+            response[10],
+            response[11],
+        )),
+        port: external_port,
+        lifetime,
+    })
+}
+
+/// Sends a NAT-PMP request and returns the response, checked to be the successful answer to it.
+fn nat_pmp_request(
+    socket: &UdpSocket,
+    message: &[u8],
+    response_len: usize,
+) -> Result<Vec<u8>, PortMappingError> {
+    let response = request(socket, message)?;
+    if response.len() < response_len
+        || response[0] != NAT_PMP_VERSION
+        || response[1] != RESPONSE_BIT | message[1]
+    {
+        return Err(invalid_response("NAT-PMP"));
+    }
+    match u16::from_be_bytes([response[2], response[3]]) {
+        0 => Ok(response),
+        result => Err(PortMappingError::new(format!(
+            "PortMappingError: the NAT-PMP server refused the request (result {})",
+            result
+        ))),
+    }
+}
+
+/// Maps the port with UPnP. Gateways that only make mappings without a lease get one of those.
+fn upnp_map(
+    control: &HttpUrl,
+    service: &str,
+    transport: Transport,
+    port: u16,
+    lifetime: Duration,
+) -> Result<ExternalAddress, PortMappingError> {
+    // the address of the client in the network of the gateway
+    let client = connected_socket(control.address()?)?.local_addr()?.ip();
+    let mut lease = seconds(lifetime);
+    loop {
+        let arguments = [
+            ("NewRemoteHost", String::new()),
+            ("NewExternalPort", port.to_string()),
+            ("NewProtocol", transport.name().to_string()),
+            ("NewInternalPort", port.to_string()),
+            ("NewInternalClient", client.to_string()),
+            ("NewEnabled", "1".to_string()),
+            ("NewPortMappingDescription", MAPPING_DESCRIPTION.to_string()),
+            ("NewLeaseDuration", lease.to_string()),
+        ];
+        let (status, response) = soap(control, service, "AddPortMapping", &arguments)?;
+        match status {
+            200 => break,
+            _ if lease > 0 && response.contains(ONLY_PERMANENT_LEASES) => lease = 0,
+            _ => return Err(upnp_error("AddPortMapping", &response)),
+        }
+    }
+
+    let (status, response) = soap(control, service, "GetExternalIPAddress", &[])?;
+    let ip = match status {
+        200 => xml_value(&response, "NewExternalIPAddress").and_then(|ip| ip.parse().ok()),
+        _ => None,
+    }
+    .ok_or_else(|| upnp_error("GetExternalIPAddress", &response))?;
+    Ok(ExternalAddress {
+        ip,
+        port,
+        lifetime: Duration::from_secs(lease as u64),
+    })
+}
+
+/// Calls an action of the UPnP service, returns the http status and the body of the response.
+fn soap(
+    control: &HttpUrl,
+    service: &str,
+    action: &str,
+    arguments: &[(&str, String)],
+) -> Result<(u16, String), PortMappingError> {
+    let arguments: String = arguments
+        .iter()
+        .map(|(name, value)| format!("<{0}>{1}</{0}>", name, value))
+        .collect();
+    let body = format!(
+        "<?xml version=\"1.0\"?>\r\n<s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\"><s:Body><u:{0} xmlns:u=\"{1}\">{2}</u:{0}></s:Body></s:Envelope>\r\n",
+        action, service, arguments
+    );
+    let headers = format!(
+        "Content-Type: text/xml; charset=\"utf-8\"\r\nSOAPAction: \"{}#{}\"\r\n",
+        service, action
+    );
+    http_request(control, "POST", &headers, &body)
+}
+
+fn upnp_error(action: &str, response: &str) -> PortMappingError {
+    PortMappingError::new(format!(
+        "PortMappingError: the UPnP gateway refused {} (error {})",
+        action,
+        xml_value(response, "errorCode").unwrap_or_default()
+    ))
+}
+
+/// Sends an SSDP search for Internet Gateway Devices and returns the location of the description of the
+/// first one that answers.
+fn ssdp_search(ssdp: SocketAddr) -> Result<HttpUrl, PortMappingError> {
+    let socket = UdpSocket::bind(unspecified(ssdp))?;
+    let search = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\n\r\n",
+        ssdp
+    );
+    socket.send_to(search.as_bytes(), ssdp)?;
+    let deadline = Instant::now() + SEARCH_TIMEOUT;
+    let mut buffer = [0; 2048];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(PortMappingError::new(
+                "PortMappingError: no gateway was found".to_string(),
+            ));
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let read = match socket.recv_from(&mut buffer) {
+            Ok((read, _)) => read,
+            Err(e) if is_timeout(&e) => continue,
+            Err(e) => return Err(PortMappingError::from(e)),
+        };
+        let answer = String::from_utf8_lossy(&buffer[..read]);
+        let location = answer.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("location")
+                .then(|| value.trim().to_string())
+        });
+        if let Some(location) = location {
+            return parse_url(&location);
+        }
+    }
+}
+
+/// Returns the service found in the description of the gateway and its control url.
+fn control_url(description: &str) -> Option<(&'static str, String)> {
+    UPNP_SERVICES.iter().find_map(|service| {
+        let start = description.find(&format!("<serviceType>{}</serviceType>", service))?;
+        let control = xml_value(&description[start..], "controlURL")?;
+        Some((*service, control))
+    })
+}
+
+/// Returns the text of the first element with the tag.
+fn xml_value(xml: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(xml[start..end].trim().to_string())
+}
+
+fn parse_url(url: &str) -> Result<HttpUrl, PortMappingError> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| PortMappingError::new(format!("PortMappingError: invalid url {}", url)))?;
+    let (host, path) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, "/"),
+    };
+    Ok(HttpUrl {
+        host: host.to_string(),
+        path: path.to_string(),
+    })
+}
+
+impl HttpUrl {
+    /// Resolves the host, on port 80 if it has none.
+    fn address(&self) -> Result<SocketAddr, PortMappingError> {
+        let addresses = match self.host.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_ok() => self.host.to_socket_addrs(),
+            _ => (self.host.as_str(), 80).to_socket_addrs(),
+        };
+        addresses?.next().ok_or_else(|| {
+            PortMappingError::new(format!("PortMappingError: unknown host {}", self.host))
+        })
+    }
+}
+
+/// Sends an HTTP/1.0 request, so the answer is not chunked, and returns the status and the body of the response.
+fn http_request(
+    url: &HttpUrl,
+    method: &str,
+    headers: &str,
+    body: &str,
+) -> Result<(u16, String), PortMappingError> {
+    let mut stream = TcpStream::connect_timeout(&url.address()?, HTTP_TIMEOUT)?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+    write!(
+        stream,
+        "{} {} HTTP/1.0\r\nHost: {}\r\n{}Content-Length: {}\r\n\r\n{}",
+        method,
+        url.path,
+        url.host,
+        headers,
+        body.len(),
+        body
+    )?;
+    let mut response = Vec::new();
+    stream.take(MAX_RESPONSE_LEN).read_to_end(&mut response)?;
+    let response = String::from_utf8_lossy(&response);
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| invalid_response("HTTP"))?;
+    let status = head
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid_response("HTTP"))?;
+    Ok((status, body.to_string()))
+}
+
+/// Returns a UDP socket connected to the server, its local address is the one of the client in the network
+/// of the server.
+fn connected_socket(server: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = UdpSocket::bind(unspecified(server))?;
+    socket.connect(server)?;
+    Ok(socket)
+}
+
+/// Sends the request until the server answers, waiting twice as long each time.
+fn request(socket: &UdpSocket, message: &[u8]) -> Result<Vec<u8>, PortMappingError> {
+    let mut buffer = [0; 1100];
+    for attempt in 0..REQUEST_TRIES {
+        socket.send(message)?;
+        socket.set_read_timeout(Some(REQUEST_TIMEOUT * 2u32.pow(attempt)))?;
+        match socket.recv(&mut buffer) {
+            Ok(read) => return Ok(buffer[..read].to_vec()),
+            Err(e) if is_timeout(&e) => continue,
+            Err(e) => return Err(PortMappingError::from(e)),
+        }
+    }
+    Err(PortMappingError::new(format!(
+        "PortMappingError: {} did not answer",
+        socket.peer_addr()?
+    )))
+}
+
+/// Returns the gateway of the default route in the contents of /proc/net/route.
+fn default_gateway(routes: &str) -> Option<IpAddr> {
+    routes.lines().skip(1).find_map(|route| {
+        let fields: Vec<&str> = route.split_whitespace().collect();
+        let flags = u16::from_str_radix(fields.get(3)?, 16).ok()?;
+        if *fields.get(1)? != "00000000" || flags & RTF_GATEWAY == 0 {
+            return None;
+        }
+        // the bytes of the address written as an integer of the host
+        let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+        Some(IpAddr::V4(Ipv4Addr::from(gateway.to_ne_bytes())))
+    })
+}
+
+/// The address that binds any port of the family of the address.
+fn unspecified(address: SocketAddr) -> SocketAddr {
+    match address {
+        SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+        SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+    }
+}
+
+fn seconds(duration: Duration) -> u32 {
+    duration.as_secs().min(u32::MAX as u64) as u32
+}
+
+fn is_timeout(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+fn invalid_response(protocol: &str) -> PortMappingError {
+    PortMappingError::new(format!(
+        "PortMappingError: invalid {} response from the gateway",
+        protocol
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        net::TcpListener,
+        sync::mpsc::{channel, Receiver},
+        thread::spawn,
+    };
+
+    const EXTERNAL_IP: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 5);
+
+    /// Answers like a PCP server, or like a NAT-PMP one that does not know PCP, and sends the requests
+    /// it gets.
+    fn fake_pcp_gateway(pcp: bool) -> (SocketAddr, Receiver<Vec<u8>>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        let (sender, receiver) = channel();
+        spawn(move || {
+            let mut buffer = [0; 1100];
+            while let Ok((read, from)) = socket.recv_from(&mut buffer) {
+                let request = buffer[..read].to_vec();
+                let opcode = request[1];
+                let mut response = vec![request[0], RESPONSE_BIT | opcode, 0, 0];
+                match (pcp, request[0], opcode) {
+                    (true, PCP_VERSION, _) => {
+                        response.extend(&request[4..8]);
+                        response.extend([0; 16]);
+                        if opcode == PCP_MAP {
+                            response.extend(&request[24..44]);
+                            response.extend(EXTERNAL_IP.to_ipv6_mapped().octets());
+                        }
+                    }
+                    // unsupported version
+                    (false, PCP_VERSION, _) => response = vec![NAT_PMP_VERSION, 129, 0, 1],
+                    (false, NAT_PMP_VERSION, 0) => {
+                        response.extend([0; 4]);
+                        response.extend(EXTERNAL_IP.octets());
+                    }
+                    (false, NAT_PMP_VERSION, _) => {
+                        response.extend([0; 4]);
+                        response.extend(&request[4..12]);
+                    }
+                    _ => continue,
+                }
+                if sender.send(request).is_err() {
+                    return;
+                }
+                socket.send_to(&response, from).unwrap();
+            }
+        });
+        (address, receiver)
+    }
+
+    /// Reads an http request whole, the body is as long as the Content-Length.
+    fn read_http_request(stream: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            let read = stream.read(&mut buffer).unwrap();
+            request.extend(&buffer[..read]);
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .map_or(0, |length| length.parse().unwrap());
+                if body.len() >= length || read == 0 {
+                    return text;
+                }
+            }
+        }
+    }
+
+    /// Answers the SSDP search and the UPnP actions like a gateway that only makes permanent mappings,
+    /// and sends the actions it gets.
+    fn fake_upnp_gateway() -> (SocketAddr, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let http = listener.local_addr().unwrap();
+        let ssdp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let ssdp_address = ssdp.local_addr().unwrap();
+        spawn(move || {
+            let mut buffer = [0; 2048];
+            while let Ok((_, from)) = ssdp.recv_from(&mut buffer) {
+                let answer = format!(
+                    "HTTP/1.1 200 OK\r\nST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\nLocation: http://{}/rootDesc.xml\r\n\r\n",
+                    http
+                );
+                ssdp.send_to(answer.as_bytes(), from).unwrap();
+            }
+        });
+
+        let (sender, receiver) = channel();
+        spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let request = read_http_request(&mut stream);
+                let (status, body) = if request.starts_with("GET /rootDesc.xml") {
+                    (200, "<root><device><serviceList><service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType><controlURL>/ctl/L3F</controlURL></service><service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType><controlURL>/ctl/IPConn</controlURL></service></serviceList></device></root>".to_string())
+                } else if !request.starts_with("POST /ctl/IPConn") {
+                    (404, String::new())
+                } else if request.contains("#AddPortMapping")
+                    && !request.contains("<NewLeaseDuration>0<")
+                {
+                    (500, "<s:Fault><detail><UPnPError><errorCode>725</errorCode></UPnPError></detail></s:Fault>".to_string())
+                } else if request.contains("#GetExternalIPAddress") {
+                    (200, "<u:GetExternalIPAddressResponse><NewExternalIPAddress>203.0.113.5</NewExternalIPAddress></u:GetExternalIPAddressResponse>".to_string())
+                } else {
+                    (200, String::new())
+                };
+                if sender.send(request).is_err() {
+                    return;
+                }
+                let response = format!(
+                    "HTTP/1.1 {} OK\r\nContent-Length: {}\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (ssdp_address, receiver)
+    }
+
+    #[test]
+    fn test_pcp_and_nat_pmp_mappings() {
+        for pcp in [true, false] {
+            let (server, requests) = fake_pcp_gateway(pcp);
+            let mut mapper = PortMapper::new(
+                6881,
+                Gateways {
+                    pcp: Some(server),
+                    ssdp: SSDP_ADDR,
+                },
+            );
+            let external = mapper.map(Duration::from_secs(7200)).unwrap();
+            assert_eq!(
+                external,
+                ExternalAddress {
+                    ip: IpAddr::V4(EXTERNAL_IP),
+                    port: 6881,
+                    lifetime: Duration::from_secs(7200)
+                }
+            );
+            mapper.unmap().unwrap();
+
+            let requests: Vec<Vec<u8>> = requests.try_iter().collect();
+            // the announce tells which protocol the gateway speaks
+            assert_eq!(requests[0][..2], [PCP_VERSION, PCP_ANNOUNCE]);
+            let maps: Vec<&Vec<u8>> = requests[1..]
+                .iter()
+                .filter(|request| request[1] != 0)
+                .collect();
+            assert_eq!(maps.len(), 4);
+            for (map, lifetime) in maps.iter().zip([7200u32, 7200, 0, 0]) {
+                match pcp {
+                    true => {
+                        assert_eq!(map[..2], [PCP_VERSION, PCP_MAP]);
+                        assert_eq!(vecu8_to_u32(&map[4..8]), lifetime);
+                        // the same nonce renews and removes the mappings
+                        assert_eq!(map[24..36], maps[0][24..36]);
+                        assert_eq!(map[40..42], 6881u16.to_be_bytes());
+                    }
+                    false => {
+                        assert_eq!(map[0], NAT_PMP_VERSION);
+                        assert_eq!(vecu8_to_u32(&map[8..12]), lifetime);
+                        assert_eq!(map[4..6], 6881u16.to_be_bytes());
+                    }
+                }
+            }
+            let protocols: Vec<u8> = maps.iter().map(|map| map[1]).collect();
+            match pcp {
+                true => assert_eq!(
+                    maps.iter().map(|map| map[36]).collect::<Vec<u8>>(),
+                    [6, 17, 6, 17]
+                ),
+                false => assert_eq!(protocols, [2, 1, 2, 1]),
+            }
+        }
+    }
+
+    #[test]
+    fn test_upnp_mappings() {
+        let (ssdp, requests) = fake_upnp_gateway();
+        let mut mapper = PortMapper::new(6881, Gateways { pcp: None, ssdp });
+        let external = mapper.map(Duration::from_secs(3600)).unwrap();
+        // the gateway only makes mappings without a lease
+        assert_eq!(
+            external,
+            ExternalAddress {
+                ip: IpAddr::V4(EXTERNAL_IP),
+                port: 6881,
+                lifetime: Duration::ZERO
+            }
+        );
+        mapper.unmap().unwrap();
+        // nothing is left to remove
+        mapper.unmap().unwrap();
+
+        let requests: Vec<String> = requests.try_iter().collect();
+        assert!(requests[0].starts_with("GET /rootDesc.xml HTTP/1.0"));
+        let actions: Vec<&str> = requests[1..]
+            .iter()
+            .map(|request| {
+                let action = request.split_once('#').unwrap().1;
+                &action[..action.find('"').unwrap()]
+            })
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                "AddPortMapping",
+                "AddPortMapping",
+                "GetExternalIPAddress",
+                "AddPortMapping",
+                "AddPortMapping",
+                "GetExternalIPAddress",
+                "DeletePortMapping",
+                "DeletePortMapping"
+            ]
+        );
+        assert!(requests[2].contains("<NewProtocol>TCP</NewProtocol>"));
+        assert!(requests[2].contains("<NewInternalClient>127.0.0.1</NewInternalClient>"));
+        assert!(requests[5].contains("<NewProtocol>UDP</NewProtocol>"));
+    }
+
+    #[test]
+    fn test_the_default_gateway_is_read_from_the_routes() {
+        let gateway = u32::from_ne_bytes([192, 168, 1, 1]);
+        let routes = format!(
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n\
+             eth0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\n\
+             eth0\t00000000\t{:08X}\t0003\t0\t0\t0\t00000000\n",
+            gateway
+        );
+        assert_eq!(
+            default_gateway(&routes),
+            Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)))
+        );
+        assert_eq!(default_gateway("Iface\tDestination\n"), None);
+    }
+
+    #[test]
+    fn test_control_url_of_the_description() {
+        let description = "<serviceType>urn:schemas-upnp-org:service:WANPPPConnection:1</serviceType><controlURL> /ppp </controlURL>";
+        assert_eq!(
+            control_url(description),
+            Some((UPNP_SERVICES[2], "/ppp".to_string()))
+        );
+        assert_eq!(control_url("<root></root>"), None);
+        assert_eq!(
+            parse_url("http://192.168.1.1:5000").unwrap(),
+            HttpUrl {
+                host: "192.168.1.1:5000".to_string(),
+                path: "/".to_string()
+            }
+        );
+        assert!(parse_url("https://gateway/").is_err());
+    }
+}
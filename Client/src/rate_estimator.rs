@@ -0,0 +1,246 @@
+use crate::events::Direction;
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// The rates are the bytes of the last WINDOW_SLOTS slots, 5 seconds.
+const WINDOW_SLOTS: usize = 20;
+const SLOT_DURATION: Duration = Duration::from_millis(250);
+
+/// Measures the bytes per second of a sliding window. The window is split in slots, the oldest one is
+/// dropped as time goes by, so the rate follows the transfers of the last seconds and goes to 0 when they stop.
+#[derive(Debug, Clone)]
+pub struct RateEstimator {
+    slots: [u64; WINDOW_SLOTS],
+    start: Instant,
+    /// The slot the bytes are added to, counted since start.
+    current: u64,
+}
+
+impl RateEstimator {
+    pub fn new(now: Instant) -> RateEstimator {
+        RateEstimator {
+            slots: [0; WINDOW_SLOTS],
+            start: now,
+            current: 0,
+        }
+    }
+
+    pub fn add(&mut self, bytes: usize, now: Instant) {
+        self.advance(now);
+        self.slots[self.current as usize % WINDOW_SLOTS] += bytes as u64;
+    }
+
+    /// Returns the bytes per second of the window. A window younger than its length is averaged
+    /// over its age, at least a slot. A now older than the current slot, read before another thread
+    /// added to a later one, is taken as the start of the current slot.
+    pub fn rate(&mut self, now: Instant) -> u64 {
+        self.advance(now);
+        let in_current = now
+            .saturating_duration_since(self.start)
+            .saturating_sub(SLOT_DURATION * self.current as u32);
+        let window = SLOT_DURATION * self.current.min(WINDOW_SLOTS as u64 - 1) as u32 + in_current;
+        let bytes: u64 = self.slots.iter().sum();
+        (bytes as f64 / window.max(SLOT_DURATION).as_secs_f64()) as u64
+    }
+
+    /// Moves to the slot of now, emptying the ones left behind.
+    fn advance(&mut self, now: Instant) {
+        let slot = (now.saturating_duration_since(self.start).as_nanos() / SLOT_DURATION.as_nanos())
+            as u64;
+        if slot <= self.current {
+            return;
+        }
+        let passed = (slot - self.current).min(WINDOW_SLOTS as u64);
+        for next in 1..=passed {
+            self.slots[(self.current + next) as usize % WINDOW_SLOTS] = 0;
+        }
+        self.current = slot;
+    }
+}
+
+/// Bytes per second of a connection, a torrent or the session. The payload is the data of the pieces,
+/// the protocol bytes are the rest of what goes through the sockets: handshakes, requests and headers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferRates {
+    pub download_payload: u64,
+    pub download_protocol: u64,
+    pub upload_payload: u64,
+    pub upload_protocol: u64,
+}
+
+/// What the bytes recorded in a TransferMeter are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Traffic {
+    /// Every byte read from or written to the socket.
+    Wire,
+    /// The bytes of pieces, a part of the Wire bytes.
+    Payload,
+}
+
+/// Measures the transfers of a connection. Meters are nested like the RateLimiters: the bytes of a peer
+/// are also recorded in the meter of its torrent and in the one of the session.
+#[derive(Debug)]
+pub struct TransferMeter {
+    /// Wire and Payload of Download, then of Upload.
+    estimators: Mutex<[RateEstimator; 4]>,
+    /// Payload bytes since the meter was created, downloaded and uploaded.
+    payload_totals: Mutex<[u64; 2]>,
+    parent: Option<Arc<TransferMeter>>,
+}
+
+impl TransferMeter {
+    /// Creates a meter without parent, the one of the session.
+    pub fn new() -> Arc<TransferMeter> {
+        TransferMeter::with_parent(None)
+    }
+
+    /// Creates a meter that records its bytes in this one too.
+    pub fn child(self: &Arc<Self>) -> Arc<TransferMeter> {
+        TransferMeter::with_parent(Some(self.clone()))
+    }
+
+    pub fn record(&self, direction: Direction, traffic: Traffic, bytes: usize) {
+        let now = Instant::now();
+        self.record_at(direction, traffic, bytes, now);
+    }
+
+    /// Returns the rates of the last seconds.
+    pub fn rates(&self) -> TransferRates {
+        self.rates_at(Instant::now())
+    }
+
+    /// Returns the payload bytes transferred in the direction since the meter was created.
+    pub fn payload_total(&self, direction: Direction) -> u64 {
+        let totals = self
+            .payload_totals
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        totals[index(direction, Traffic::Wire) / 2]
+    }
+
+    fn with_parent(parent: Option<Arc<TransferMeter>>) -> Arc<TransferMeter> {
+        let now = Instant::now();
+        Arc::new(TransferMeter {
+            estimators: Mutex::new([
+                RateEstimator::new(now),
+                RateEstimator::new(now),
+                RateEstimator::new(now),
+                RateEstimator::new(now),
+            ]),
+            payload_totals: Mutex::new([0; 2]),
+            parent,
+        })
+    }
+
+    fn record_at(&self, direction: Direction, traffic: Traffic, bytes: usize, now: Instant) {
+        self.estimators
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)[index(direction, traffic)]
+        .add(bytes, now);
+        if traffic == Traffic::Payload {
+            self.payload_totals
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)[index(direction, traffic) / 2] +=
+                bytes as u64;
+        }
+        if let Some(parent) = &self.parent {
+            parent.record_at(direction, traffic, bytes, now);
+        }
+    }
+
+    fn rates_at(&self, now: Instant) -> TransferRates {
+        let mut estimators = self
+            .estimators
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut rate = |direction, traffic| estimators[index(direction, traffic)].rate(now);
+        let download_wire = rate(Direction::Download, Traffic::Wire);
+        let download_payload = rate(Direction::Download, Traffic::Payload);
+        let upload_wire = rate(Direction::Upload, Traffic::Wire);
+        let upload_payload = rate(Direction::Upload, Traffic::Payload);
+        TransferRates {
+            download_payload,
+            download_protocol: download_wire.saturating_sub(download_payload),
+            upload_payload,
+            upload_protocol: upload_wire.saturating_sub(upload_payload),
+        }
+    }
+}
+
+fn index(direction: Direction, traffic: Traffic) -> usize {
+    let direction = match direction {
+        Direction::Download => 0,
+        Direction::Upload => 2,
+    };
+    match traffic {
+        Traffic::Wire => direction,
+        Traffic::Payload => direction + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_follows_the_last_seconds() {
+        let start = Instant::now();
+        let mut estimator = RateEstimator::new(start);
+        // 950 bytes every slot for two seconds
+        for slot in 0..8 {
+            estimator.add(950, start + SLOT_DURATION * slot);
+        }
+        assert_eq!(estimator.rate(start + Duration::from_secs(2)), 3800);
+        // once the window is full the rate is averaged over all of it
+        assert_eq!(estimator.rate(start + SLOT_DURATION * 19), 1600);
+        // once the transfers stop for longer than the window it goes to 0
+        assert_eq!(estimator.rate(start + Duration::from_secs(8)), 0);
+    }
+
+    #[test]
+    fn test_young_window_is_averaged_over_a_slot_at_least() {
+        let start = Instant::now();
+        let mut estimator = RateEstimator::new(start);
+        estimator.add(500, start);
+        assert_eq!(estimator.rate(start), 2000);
+    }
+
+    #[test]
+    fn test_rate_before_the_last_add_does_not_panic() {
+        let start = Instant::now();
+        let mut estimator = RateEstimator::new(start);
+        estimator.add(500, start + SLOT_DURATION * 2);
+        // a now read before the add moved to the third slot
+        assert_eq!(estimator.rate(start + SLOT_DURATION), 1000);
+    }
+
+    #[test]
+    fn test_meters_record_in_their_parents() {
+        let start = Instant::now();
+        let session = TransferMeter::new();
+        let torrent = session.child();
+        let first = torrent.child();
+        let second = torrent.child();
+        first.record_at(Direction::Download, Traffic::Wire, 1100, start);
+        first.record_at(Direction::Download, Traffic::Payload, 1000, start);
+        second.record_at(Direction::Upload, Traffic::Wire, 300, start);
+
+        let now = start + Duration::from_secs(1);
+        assert_eq!(
+            first.rates_at(now),
+            TransferRates {
+                download_payload: 1000,
+                download_protocol: 100,
+                ..TransferRates::default()
+            }
+        );
+        let rates = session.rates_at(now);
+        assert_eq!(rates.download_payload, 1000);
+        assert_eq!(rates.upload_protocol, 300);
+        assert_eq!(second.rates_at(now).download_payload, 0);
+        assert_eq!(session.payload_total(Direction::Download), 1000);
+        assert_eq!(torrent.payload_total(Direction::Upload), 0);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::events::Direction;
+use chrono::{Local, Timelike};
+use std::{
+    sync::{Arc, Mutex, PoisonError, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Lets rate bytes per second through, 0 is unlimited. It holds at most a second of them, so a connection
+/// that was idle can not send a long burst afterwards.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: u64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> TokenBucket {
+        TokenBucket {
+            rate,
+            tokens: rate as f64,
+            last: Instant::now(),
+        }
+    }
+
+    fn set_rate(&mut self, rate: u64) {
+        self.rate = rate;
+        self.tokens = self.tokens.min(rate as f64);
+    }
+
+    /// Takes the tokens of bytes and returns how long to wait until the bucket is out of debt.
+    /// The tokens go below zero, so a buffer longer than a second of the rate still goes through.
+    fn reserve(&mut self, bytes: usize, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = now;
+        if self.rate == 0 {
+            return Duration::ZERO;
+        }
+        let rate = self.rate as f64;
+        self.tokens = (self.tokens + elapsed * rate).min(rate) - bytes as f64;
+        match self.tokens < 0.0 {
+            true => Duration::from_secs_f64(-self.tokens / rate),
+            false => Duration::ZERO,
+        }
+    }
+}
+
+/// Limits the bytes read and written by connections. Limiters are nested: the one of the session is the parent
+/// of the one of each torrent, which is the parent of the one of each peer, and a transfer waits for the
+/// slowest of them.
+#[derive(Debug)]
+pub struct RateLimiter {
+    download: Mutex<TokenBucket>,
+    upload: Mutex<TokenBucket>,
+    parent: Option<Arc<RateLimiter>>,
+    children: Mutex<Vec<Weak<RateLimiter>>>,
+    child_rates: Mutex<(u64, u64)>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter without parent, the one of the session. Rates are bytes per second, 0 is unlimited.
+    pub fn new(download_rate: u64, upload_rate: u64) -> Arc<RateLimiter> {
+        RateLimiter::with_parent(download_rate, upload_rate, None)
+    }
+
+    /// Creates a limiter nested in this one, with the rates set with set_child_rates.
+    pub fn child(self: &Arc<Self>) -> Arc<RateLimiter> {
+        let (download_rate, upload_rate) = *lock(&self.child_rates);
+        let child = RateLimiter::with_parent(download_rate, upload_rate, Some(self.clone()));
+        let mut children = lock(&self.children);
+        children.retain(|child| child.strong_count() > 0);
+        children.push(Arc::downgrade(&child));
+        child
+    }
+
+    /// Returns the download and upload rates of this limiter, not the ones of its parents.
+    pub fn rates(&self) -> (u64, u64) {
+        (lock(&self.download).rate, lock(&self.upload).rate)
+    }
+
+    pub fn set_rates(&self, download_rate: u64, upload_rate: u64) {
+        lock(&self.download).set_rate(download_rate);
+        lock(&self.upload).set_rate(upload_rate);
+    }
+
+    /// Sets the rates of the children of this limiter, the ones created so far and the ones created later.
+    pub fn set_child_rates(&self, download_rate: u64, upload_rate: u64) {
+        *lock(&self.child_rates) = (download_rate, upload_rate);
+        for child in lock(&self.children).iter().filter_map(Weak::upgrade) {
+            child.set_rates(download_rate, upload_rate);
+        }
+    }
+
+    /// Waits until bytes can be transferred in the direction without going over any of the limits.
+    pub fn throttle(&self, direction: Direction, bytes: usize) {
+        let wait = self.reserve(direction, bytes, Instant::now());
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+
+    /// Waits until the bytes already transferred in the direction are within the limits, without taking any.
+    /// For the ones that pass their bytes to a connection that is limited on its own.
+    pub fn wait_ready(&self, direction: Direction) {
+        self.throttle(direction, 0);
+    }
+
+    fn with_parent(
+        download_rate: u64,
+        upload_rate: u64,
+        parent: Option<Arc<RateLimiter>>,
+    ) -> Arc<RateLimiter> {
+        Arc::new(RateLimiter {
+            download: Mutex::new(TokenBucket::new(download_rate)),
+            upload: Mutex::new(TokenBucket::new(upload_rate)),
+            parent,
+            children: Mutex::new(Vec::new()),
+            child_rates: Mutex::new((0, 0)),
+        })
+    }
+
+    /// Takes the bytes from this limiter and every parent, returns the longest wait of them. The bytes are
+    /// taken even if there is a wait, for the ones that can not wait like the event loop.
+    pub fn reserve(&self, direction: Direction, bytes: usize, now: Instant) -> Duration {
+        let bucket = match direction {
+            Direction::Download => &self.download,
+            Direction::Upload => &self.upload,
+        };
+        let wait = lock(bucket).reserve(bytes, now);
+        match &self.parent {
+            Some(parent) => wait.max(parent.reserve(direction, bytes, now)),
+            None => wait,
+        }
+    }
+}
+
+/// Returns the minutes since midnight in the local time, what the scheduled limits are compared with.
+pub fn minute_of_day() -> u32 {
+    let now = Local::now();
+    now.hour() * 60 + now.minute()
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_waits_for_the_debt() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(1000);
+        bucket.last = start;
+        assert_eq!(bucket.reserve(500, start), Duration::ZERO);
+        assert_eq!(bucket.reserve(1000, start), Duration::from_millis(500));
+        // after a second the debt is paid and half a second of tokens is left
+        let later = start + Duration::from_secs(1);
+        assert_eq!(bucket.reserve(500, later), Duration::ZERO);
+        // a long idle time does not fill more than a second
+        let idle = later + Duration::from_secs(60);
+        assert_eq!(bucket.reserve(2000, idle), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_unlimited_bucket_never_waits() {
+        let mut bucket = TokenBucket::new(0);
+        assert_eq!(bucket.reserve(usize::MAX, Instant::now()), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_the_slowest_limiter_is_waited_for() {
+        let session = RateLimiter::new(1000, 0);
+        let torrent = session.child();
+        torrent.set_child_rates(100, 100);
+        let peer = torrent.child();
+        assert_eq!(peer.rates(), (100, 100));
+
+        let now = Instant::now();
+        assert_eq!(
+            peer.reserve(Direction::Download, 200, now),
+            Duration::from_secs(1)
+        );
+        assert_eq!(peer.reserve(Direction::Upload, 100, now), Duration::ZERO);
+        // the session had 1000 and the peer took 200 of them
+        assert_eq!(
+            session.reserve(Direction::Download, 1000, now),
+            Duration::from_millis(200)
+        );
+    }
+
+    #[test]
+    fn test_child_rates_reach_the_children_already_created() {
+        let torrent = RateLimiter::new(0, 0);
+        let peer = torrent.child();
+        assert_eq!(peer.rates(), (0, 0));
+        torrent.set_child_rates(10, 20);
+        assert_eq!(peer.rates(), (10, 20));
+        drop(peer);
+        assert_eq!(torrent.child().rates(), (10, 20));
+    }
+}
@@ -0,0 +1,647 @@
+use crate::{
+    config::Config,
+    errors::rpc_error::RpcError,
+    file_selection::FilePriority,
+    piece_picker::DownloadMode,
+    session::{Session, TorrentStatus},
+    stats::PeerStats,
+    utilities::constants::INFO_HASH_LEN,
+    utilities::utils::{from_hex, to_hex},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread::{self, spawn},
+    time::Duration,
+};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// The session refused the call, because the torrent is not in the session for example.
+pub const SERVER_ERROR: i64 = -32000;
+
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(200);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_HEADERS_LEN: usize = 8 * 1024;
+const MAX_BODY_LEN: usize = 16 * 1024 * 1024;
+
+/// A request read from the socket, header names are lowercase.
+struct HttpRequest {
+    request_line: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// Controls a Session through JSON-RPC 2.0 calls sent with `POST /rpc`. It only listens on localhost
+/// and every request must carry the configured token as `Authorization: Bearer <token>`.
+///
+/// Methods, torrents are named by the hex of their info hash:
+/// - add_torrent {name, torrent}: the torrent is the base64 of the contents of the torrent file.
+///   Magnet links are not supported, the info dictionary is only read from torrent files.
+/// - remove_torrent, pause, resume, list_peers {info_hash}
+/// - set_file_priority {info_hash, file, priority}: priority is skip, low, normal or high.
+/// - set_download_mode {info_hash, mode, lookahead}: mode is rarest_first, sequential or first_last, the
+///   lookahead is the number of pieces after the playback position that sequential requests in order.
+/// - set_playback_position {info_hash, piece}: the piece the sequential window starts at.
+/// - set_limits {download_rate, upload_rate, peer_download_rate, peer_upload_rate}: bytes per second,
+///   0 is unlimited, the ones left out are kept. With an info_hash only the download_rate and
+///   upload_rate of that torrent are set. The scheduled limits of the configuration still apply.
+/// - list_torrents, session_stats: rates are the bytes per second of the last 5 seconds, split in the payload
+///   and the protocol bytes. Torrents also have their eta in seconds, null while nothing is downloaded,
+///   the payload bytes transferred, the share ratio and the seconds spent seeding.
+pub struct RpcServer {
+    listener: TcpListener,
+    session: Arc<Session>,
+    token: String,
+    listening: Mutex<bool>,
+}
+
+impl RpcServer {
+    /// Creates the server listening on the local port.
+    pub fn new(
+        session: Arc<Session>,
+        port: u16,
+        token: String,
+    ) -> Result<Arc<RpcServer>, RpcError> {
+        if token.is_empty() {
+            return Err(RpcError::new(
+                INTERNAL_ERROR,
+                "RpcError: the token can not be empty".to_string(),
+            ));
+        }
+        let listener = TcpListener::bind(format!("127.0.0.1:{}", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Arc::new(RpcServer {
+            listener,
+            session,
+            token,
+            listening: Mutex::new(true),
+        }))
+    }
+
+    /// Creates the server if the configuration has a rpc_port. A rpc_token is required with it,
+    /// the session is never controlled without one.
+    pub fn from_config(
+        session: Arc<Session>,
+        config: &Config,
+    ) -> Result<Option<Arc<RpcServer>>, RpcError> {
+        let port = match config.network.rpc_port {
+            Some(port) => port,
+            None => return Ok(None),
+        };
+        let token = config.network.rpc_token.as_ref().ok_or_else(|| {
+            RpcError::new(
+                INTERNAL_ERROR,
+                "RpcError: network.rpc_port is configured without a network.rpc_token".to_string(),
+            )
+        })?;
+        Ok(Some(RpcServer::new(session, port, token.clone())?))
+    }
+
+    /// Returns the port the server listens on.
+    pub fn port(&self) -> Result<u16, RpcError> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    /// Answers requests until the server is shut down, each one in its own thread.
+    pub fn listen(self: Arc<Self>) -> Result<(), RpcError> {
+        while *self.listening.lock()? {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    let self_copy = self.clone();
+                    spawn(move || {
+                        let _r = self_copy.handle(stream);
+                    });
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(ACCEPT_INTERVAL);
+                }
+                Err(e) => return Err(RpcError::from(e)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes listen return.
+    pub fn shutdown(&self) -> Result<(), RpcError> {
+        *self.listening.lock()? = false;
+        Ok(())
+    }
+
+    fn handle(&self, mut stream: TcpStream) -> Result<(), RpcError> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        let response = match read_request(&stream) {
+            Ok(request) => self.respond(&request),
+            Err(_) => http_response("400 Bad Request", ""),
+        };
+        stream.write_all(response.as_bytes())?;
+        Ok(())
+    }
+
+    /// Returns the HTTP response to the request.
+    fn respond(&self, request: &HttpRequest) -> String {
+        if !request.request_line.starts_with("POST /rpc ") {
+            return http_response("404 Not Found", "");
+        }
+        let token = request
+            .headers
+            .get("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if !token.is_some_and(|token| same_token(token, &self.token)) {
+            return http_response("401 Unauthorized", "");
+        }
+
+        let response = match serde_json::from_slice::<Value>(&request.body) {
+            Ok(Value::Array(calls)) if !calls.is_empty() => {
+                Value::Array(calls.iter().map(|call| self.call(call)).collect())
+            }
+            Ok(call) => self.call(&call),
+            Err(e) => error_response(Value::Null, RpcError::from(e)),
+        };
+        http_response("200 OK", &response.to_string())
+    }
+
+    /// Runs a JSON-RPC call and returns its response. Every call is answered, even the ones without id.
+    fn call(&self, call: &Value) -> Value {
+        let id = call.get("id").cloned().unwrap_or(Value::Null);
+        let method = call.get("method").and_then(Value::as_str);
+        let result = match (call.get("jsonrpc").and_then(Value::as_str), method) {
+            (Some("2.0"), Some(method)) => {
+                self.dispatch(method, call.get("params").unwrap_or(&Value::Null))
+            }
+            _ => Err(RpcError::new(
+                INVALID_REQUEST,
+                "RpcError: not a JSON-RPC 2.0 call".to_string(),
+            )),
+        };
+        match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "result": result, "id": id}),
+            Err(e) => error_response(id, e),
+        }
+    }
+
+    fn dispatch(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "add_torrent" => self.add_torrent(params),
+            "remove_torrent" => {
+                self.session.remove_torrent(&info_hash_param(params)?)?;
+                Ok(Value::Null)
+            }
+            "pause" => {
+                self.session.pause(&info_hash_param(params)?)?;
+                Ok(Value::Null)
+            }
+            "resume" => {
+                self.session.resume(&info_hash_param(params)?)?;
+                Ok(Value::Null)
+            }
+            "set_file_priority" => {
+                let file = params
+                    .get("file")
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| missing_param("file"))?;
+                let priority = priority_from_name(str_param(params, "priority")?)?;
+                self.session.set_file_priority(
+                    &info_hash_param(params)?,
+                    file as usize,
+                    priority,
+                )?;
+                Ok(Value::Null)
+            }
+            "set_download_mode" => {
+                let mode = mode_from_name(str_param(params, "mode")?, params)?;
+                self.session
+                    .set_download_mode(&info_hash_param(params)?, mode)?;
+                Ok(Value::Null)
+            }
+            "set_playback_position" => {
+                let piece = params
+                    .get("piece")
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| missing_param("piece"))?;
+                self.session
+                    .set_playback_position(&info_hash_param(params)?, piece as usize)?;
+                Ok(Value::Null)
+            }
+            "list_torrents" => Ok(self.session.torrents()?.iter().map(torrent_json).collect()),
+            "list_peers" => Ok(self
+                .session
+                .peers(&info_hash_param(params)?)?
+                .iter()
+                .map(peer_json)
+                .collect()),
+            "session_stats" => {
+                let stats = self.session.stats()?;
+                Ok(json!({
+                    "port": stats.port,
+                    "torrents": stats.torrents,
+                    "active_connections": stats.active_connections,
+                    "download_rate": stats.download_rate,
+                    "upload_rate": stats.upload_rate,
+                    "download_protocol_rate": stats.download_protocol_rate,
+                    "upload_protocol_rate": stats.upload_protocol_rate,
+                    "download_limit": stats.download_limit,
+                    "upload_limit": stats.upload_limit,
+                }))
+            }
+            "set_limits" => self.set_limits(params),
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("RpcError: unknown method {}", method),
+            )),
+        }
+    }
+
+    fn set_limits(&self, params: &Value) -> Result<Value, RpcError> {
+        if params.get("info_hash").is_some() {
+            self.session.set_torrent_limits(
+                &info_hash_param(params)?,
+                rate_param(params, "download_rate")?.unwrap_or(0),
+                rate_param(params, "upload_rate")?.unwrap_or(0),
+            )?;
+            return Ok(Value::Null);
+        }
+        let mut limits = self.session.limits();
+        for (name, limit) in [
+            ("download_rate", &mut limits.max_download_rate),
+            ("upload_rate", &mut limits.max_upload_rate),
+            ("peer_download_rate", &mut limits.max_peer_download_rate),
+            ("peer_upload_rate", &mut limits.max_peer_upload_rate),
+        ] {
+            if let Some(rate) = rate_param(params, name)? {
+                *limit = rate;
+            }
+        }
+        self.session.set_limits(limits)?;
+        Ok(Value::Null)
+    }
+
+    fn add_torrent(&self, params: &Value) -> Result<Value, RpcError> {
+        let contents = STANDARD.decode(str_param(params, "torrent")?)?;
+        let info_hash = self
+            .session
+            .add_torrent_file(str_param(params, "name")?, &contents)?;
+        Ok(json!({ "info_hash": to_hex(&info_hash) }))
+    }
+}
+
+/// Reads the request line, the headers and the body given by the Content-Length.
+fn read_request(stream: &TcpStream) -> Result<HttpRequest, RpcError> {
+    let mut reader = BufReader::new(stream.take((MAX_HEADERS_LEN + MAX_BODY_LEN) as u64));
+    let mut request_line = String::new();
+    let mut headers_len = reader.read_line(&mut request_line)?;
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        let read = reader.read_line(&mut line)?;
+        headers_len += read;
+        if read == 0 || headers_len > MAX_HEADERS_LEN {
+            return Err(RpcError::new(
+                INVALID_REQUEST,
+                "RpcError: incomplete headers".to_string(),
+            ));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let body_len = headers
+        .get("content-length")
+        .and_then(|len| len.parse::<usize>().ok())
+        .unwrap_or(0);
+    if body_len > MAX_BODY_LEN {
+        return Err(RpcError::new(
+            INVALID_REQUEST,
+            "RpcError: the body is too long".to_string(),
+        ));
+    }
+    let mut body = vec![0; body_len];
+    reader.read_exact(&mut body)?;
+    Ok(HttpRequest {
+        request_line,
+        headers,
+        body,
+    })
+}
+
+fn http_response(status: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "error": {"code": error.code(), "message": error.to_string()},
+        "id": id,
+    })
+}
+
+/// Compares every byte, so the time taken does not tell how much of the token was right.
+fn same_token(received: &str, token: &str) -> bool {
+    received.len() == token.len()
+        && received
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn missing_param(name: &str) -> RpcError {
+    RpcError::new(
+        INVALID_PARAMS,
+        format!("RpcError: missing or invalid param {}", name),
+    )
+}
+
+fn str_param<'a>(params: &'a Value, name: &str) -> Result<&'a str, RpcError> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| missing_param(name))
+}
+
+/// Returns None if the param is left out, and fails if it is not a number.
+fn rate_param(params: &Value, name: &str) -> Result<Option<u64>, RpcError> {
+    match params.get(name) {
+        Some(rate) => rate.as_u64().map(Some).ok_or_else(|| missing_param(name)),
+        None => Ok(None),
+    }
+}
+
+fn info_hash_param(params: &Value) -> Result<Vec<u8>, RpcError> {
+    from_hex(str_param(params, "info_hash")?)
+        .filter(|info_hash| info_hash.len() == INFO_HASH_LEN)
+        .ok_or_else(|| missing_param("info_hash"))
+}
+
+fn priority_name(priority: FilePriority) -> &'static str {
+    match priority {
+        FilePriority::Skip => "skip",
+        FilePriority::Low => "low",
+        FilePriority::Normal => "normal",
+        FilePriority::High => "high",
+    }
+}
+
+fn priority_from_name(name: &str) -> Result<FilePriority, RpcError> {
+    match name {
+        "skip" => Ok(FilePriority::Skip),
+        "low" => Ok(FilePriority::Low),
+        "normal" => Ok(FilePriority::Normal),
+        "high" => Ok(FilePriority::High),
+        _ => Err(missing_param("priority")),
+    }
+}
+
+/// The lookahead is only read for the sequential mode.
+fn mode_from_name(name: &str, params: &Value) -> Result<DownloadMode, RpcError> {
+    match name {
+        "rarest_first" => Ok(DownloadMode::RarestFirst),
+        "sequential" => match params.get("lookahead").and_then(Value::as_u64) {
+            Some(lookahead) if lookahead > 0 => Ok(DownloadMode::Sequential {
+                lookahead: lookahead as usize,
+            }),
+            _ => Err(missing_param("lookahead")),
+        },
+        "first_last" => Ok(DownloadMode::FirstAndLast),
+        _ => Err(missing_param("mode")),
+    }
+}
+
+fn torrent_json(torrent: &TorrentStatus) -> Value {
+    let stats = &torrent.stats;
+    let files: Vec<Value> = match &stats.files {
+        Some(files) => files
+            .files()
+            .iter()
+            .zip(files.priorities().unwrap_or_default())
+            .map(|(file, priority)| {
+                json!({
+                    "path": file.path,
+                    "length": file.length,
+                    "priority": priority_name(priority),
+                })
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    json!({
+        "info_hash": to_hex(&torrent.info_hash),
+        "torrent_path": torrent.torrent_path,
+        "name": stats.name,
+        "state": format!("{:?}", torrent.state).to_lowercase(),
+        "total_length": stats.total_length,
+        "pieces": stats.pieces,
+        "verified": stats.verified,
+        "progress": stats.progress(),
+        "download_rate": torrent.rates.download_payload,
+        "upload_rate": torrent.rates.upload_payload,
+        "download_protocol_rate": torrent.rates.download_protocol,
+        "upload_protocol_rate": torrent.rates.upload_protocol,
+        "eta": stats.eta(torrent.rates.download_payload),
+        "downloaded": torrent.downloaded,
+        "uploaded": torrent.uploaded,
+        "ratio": torrent.ratio,
+        "seed_time": torrent.seed_time.as_secs(),
+        "peers": stats.peers.len(),
+        "files": files,
+    })
+}
+
+fn peer_json(peer: &PeerStats) -> Value {
+    json!({
+        "peer_id": peer.peer_id,
+        "ip": peer.ip,
+        "port": peer.port,
+        "download_rate": peer.download_rate,
+        "upload_rate": peer.upload_rate,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventBus;
+
+    const TOKEN: &str = "secret";
+
+    fn start_server() -> (Arc<RpcServer>, u16) {
+        let mut config = Config::default();
+        config.set("port", "0").unwrap();
+        let session = Session::new(config, EventBus::new()).unwrap();
+        let server = RpcServer::new(session, 0, TOKEN.to_string()).unwrap();
+        let port = server.port().unwrap();
+        let server_copy = server.clone();
+        spawn(move || server_copy.listen());
+        (server, port)
+    }
+
+    fn post(port: u16, token: &str, body: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(
+            stream,
+            "POST /rpc HTTP/1.1\r\nAuthorization: Bearer {}\r\nContent-Length: {}\r\n\r\n{}",
+            token,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn call(port: u16, body: &str) -> Value {
+        let response = post(port, TOKEN, body);
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[test]
+    fn test_requests_without_the_token_are_refused() {
+        let (server, port) = start_server();
+        let response = post(
+            port,
+            "wrong!",
+            r#"{"jsonrpc":"2.0","method":"session_stats","id":1}"#,
+        );
+        assert!(response.starts_with("HTTP/1.1 401"));
+        server.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_session_stats_and_list_torrents() {
+        let (server, port) = start_server();
+        let response = call(port, r#"{"jsonrpc":"2.0","method":"session_stats","id":1}"#);
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["torrents"], 0);
+        assert_eq!(response["result"]["active_connections"], 0);
+
+        let response = call(
+            port,
+            r#"[{"jsonrpc":"2.0","method":"list_torrents","id":2},{"jsonrpc":"2.0","method":"session_stats","id":3}]"#,
+        );
+        assert_eq!(response[0]["result"], json!([]));
+        assert_eq!(response[1]["id"], 3);
+        server.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_set_limits() {
+        let (server, port) = start_server();
+        let set = json!({"jsonrpc": "2.0", "method": "set_limits", "params": {"download_rate": 1024, "peer_upload_rate": 10}, "id": 1});
+        assert_eq!(call(port, &set.to_string())["result"], Value::Null);
+        let limits = server.session.limits();
+        assert_eq!(limits.max_download_rate, 1024);
+        assert_eq!(limits.max_upload_rate, 0);
+        assert_eq!(limits.max_peer_upload_rate, 10);
+        let stats = call(port, r#"{"jsonrpc":"2.0","method":"session_stats","id":2}"#);
+        assert_eq!(stats["result"]["download_limit"], 1024);
+
+        let wrong = json!({"jsonrpc": "2.0", "method": "set_limits", "params": {"upload_rate": "fast"}, "id": 3});
+        assert_eq!(
+            call(port, &wrong.to_string())["error"]["code"],
+            INVALID_PARAMS
+        );
+        server.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_download_mode_params() {
+        let (server, port) = start_server();
+        let info_hash = to_hex(&[7; INFO_HASH_LEN]);
+        for (params, code) in [
+            (
+                json!({"info_hash": info_hash, "mode": "random"}),
+                INVALID_PARAMS,
+            ),
+            (
+                json!({"info_hash": info_hash, "mode": "sequential"}),
+                INVALID_PARAMS,
+            ),
+            (
+                json!({"info_hash": info_hash, "mode": "sequential", "lookahead": 8}),
+                SERVER_ERROR,
+            ),
+            (
+                json!({"info_hash": info_hash, "mode": "first_last"}),
+                SERVER_ERROR,
+            ),
+        ] {
+            let set =
+                json!({"jsonrpc": "2.0", "method": "set_download_mode", "params": params, "id": 1});
+            assert_eq!(call(port, &set.to_string())["error"]["code"], code);
+        }
+        let position = json!({"jsonrpc": "2.0", "method": "set_playback_position", "params": {"info_hash": info_hash}, "id": 2});
+        assert_eq!(
+            call(port, &position.to_string())["error"]["code"],
+            INVALID_PARAMS
+        );
+        let position = json!({"jsonrpc": "2.0", "method": "set_playback_position", "params": {"info_hash": info_hash, "piece": 4}, "id": 3});
+        assert_eq!(
+            call(port, &position.to_string())["error"]["code"],
+            SERVER_ERROR
+        );
+        server.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_errors_have_the_json_rpc_codes() {
+        let (server, port) = start_server();
+        assert_eq!(call(port, "{")["error"]["code"], PARSE_ERROR);
+        assert_eq!(
+            call(port, r#"{"method":"pause","id":1}"#)["error"]["code"],
+            INVALID_REQUEST
+        );
+        assert_eq!(
+            call(port, r#"{"jsonrpc":"2.0","method":"reboot","id":1}"#)["error"]["code"],
+            METHOD_NOT_FOUND
+        );
+        assert_eq!(
+            call(
+                port,
+                r#"{"jsonrpc":"2.0","method":"pause","params":{"info_hash":"12"},"id":1}"#
+            )["error"]["code"],
+            INVALID_PARAMS
+        );
+        let info_hash = to_hex(&[7; INFO_HASH_LEN]);
+        let pause = json!({"jsonrpc": "2.0", "method": "pause", "params": {"info_hash": info_hash}, "id": 1});
+        assert_eq!(
+            call(port, &pause.to_string())["error"]["code"],
+            SERVER_ERROR
+        );
+        let magnet = json!({"jsonrpc": "2.0", "method": "add_torrent", "params": {"magnet": "magnet:?xt=urn:btih:00"}, "id": 1});
+        assert_eq!(
+            call(port, &magnet.to_string())["error"]["code"],
+            INVALID_PARAMS
+        );
+        server.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        let info_hash = vec![0, 15, 16, 255];
+        assert_eq!(to_hex(&info_hash), "000f10ff");
+        assert_eq!(from_hex("000f10ff").unwrap(), info_hash);
+        assert!(from_hex("0g").is_none());
+        assert!(from_hex("abc").is_none());
+    }
+}
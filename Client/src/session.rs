@@ -0,0 +1,1284 @@
+use crate::{
+    client::{Client, ClientInterface, PeerList},
+    config::{Config, EncryptionPolicy, GoalAction, LimitsConfig, NetworkConfig, SeedingConfig},
+    errors::session_error::SessionError,
+    events::{ClientEvent, Direction, EventBus},
+    file_selection::{FilePriority, FileSelection},
+    local_discovery::{LocalDiscovery, LocalPeer, LSD_GROUPS},
+    parsing::torrent_parser::torrent_parse_with_max_length,
+    peer_entities::encrypted_stream::{self, Negotiated},
+    peer_entities::event_loop::EventLoop,
+    piece_picker::{DownloadMode, PiecePicker},
+    port_mapping::{ExternalAddress, Gateways, PortMapper},
+    rate_estimator::{TransferMeter, TransferRates},
+    rate_limiter::{minute_of_day, RateLimiter},
+    stats::{PeerStats, StatsRecorder, TorrentStats},
+    tracker::Announcer,
+    utilities::constants::{HANDSHAKE_LEN, INFO_HASH_LEN, PSTR, PSTR_LEN_LEN, RESERVED_SPACE_LEN},
+    utilities::utils::create_id,
+};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    collections::HashMap,
+    fs, io,
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    sync::mpsc::Sender,
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    thread::{self, spawn, JoinHandle},
+    time::{Duration, Instant},
+};
+
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(200);
+/// The longest wait after accepting failed, when the process is out of file descriptors for instance.
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(5);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const CONFIG_RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+/// The lease asked for the port mappings, they are renewed halfway through.
+const MAPPING_LIFETIME: Duration = Duration::from_secs(2 * 60 * 60);
+/// How long to wait before looking for a gateway again after none answered.
+const MAPPING_RETRY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// How often each active torrent is announced to the local network.
+const LOCAL_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// The state of a torrent in the session. Paused torrents keep their pieces but do not talk to peers,
+/// stopped torrents were removed from the session. Seeding torrents are complete and only upload,
+/// queued ones wait like paused ones until the session has a free slot for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TorrentState {
+    Running,
+    Paused,
+    Stopped,
+    Seeding,
+    Queued,
+}
+
+impl TorrentState {
+    /// Returns whether the torrent talks to peers.
+    pub fn is_active(self) -> bool {
+        matches!(self, TorrentState::Running | TorrentState::Seeding)
+    }
+}
+
+/// Bounds the number of peer connections, incoming and outgoing, of every torrent of the session.
+#[derive(Debug)]
+pub struct ConnectionLimit {
+    max: usize,
+    active: Mutex<usize>,
+}
+
+/// A connection counted by the ConnectionLimit, it is given back when dropped.
+#[derive(Debug)]
+pub struct ConnectionSlot {
+    limit: Arc<ConnectionLimit>,
+}
+
+impl ConnectionLimit {
+    /// Creates a limit of max connections.
+    pub fn new(max: usize) -> Arc<ConnectionLimit> {
+        Arc::new(ConnectionLimit {
+            max,
+            active: Mutex::new(0),
+        })
+    }
+
+    /// Returns a slot for a new connection, or None if the limit was reached.
+    pub fn try_acquire(self: &Arc<Self>) -> Option<ConnectionSlot> {
+        let mut active = self.active.lock().ok()?;
+        if *active >= self.max {
+            return None;
+        }
+        *active += 1;
+        Some(ConnectionSlot {
+            limit: self.clone(),
+        })
+    }
+
+    /// Returns the number of connections open right now.
+    pub fn active(&self) -> usize {
+        self.active.lock().map(|active| *active).unwrap_or(self.max)
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        if let Ok(mut active) = self.limit.active.lock() {
+            *active -= 1;
+        }
+    }
+}
+
+/// A peer connection accepted by the session, handed to the torrent its handshake asked for.
+#[derive(Debug)]
+pub struct IncomingConnection {
+    pub stream: TcpStream,
+    pub slot: ConnectionSlot,
+    /// What the encryption handshake agreed on, None for plaintext connections.
+    pub negotiated: Option<Negotiated>,
+}
+
+/// The ip and the port told to the trackers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnnouncedAddress {
+    /// None lets the trackers take the address the announce comes from.
+    pub ip: Option<IpAddr>,
+    pub port: u16,
+}
+
+/// What every torrent of the session shares: the peer id, and the address announced to the trackers which
+/// the port mapping changes once the gateway answers, the announcer every tracker is reached through, the limits of the connections and of the half-open
+/// ones, the rate limiter and the meter the ones of the torrents are nested in, the event loop that runs the
+/// sockets of every peer and how they are encrypted.
+#[derive(Debug, Clone)]
+pub struct SessionContext {
+    pub peer_id: String,
+    pub announced: Arc<RwLock<AnnouncedAddress>>,
+    pub announcer: Arc<Announcer>,
+    pub connections: Arc<ConnectionLimit>,
+    pub half_open: Arc<ConnectionLimit>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub rate_meter: Arc<TransferMeter>,
+    pub event_loop: Arc<EventLoop>,
+    pub encryption: EncryptionPolicy,
+}
+
+impl SessionContext {
+    /// Returns the address to announce now.
+    pub fn announced(&self) -> AnnouncedAddress {
+        *self
+            .announced
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// How the session reaches a running torrent.
+pub struct TorrentHandle {
+    pub torrent_path: String,
+    state: Arc<RwLock<TorrentState>>,
+    files: Arc<FileSelection>,
+    rate_limiter: Arc<RateLimiter>,
+    rate_meter: Arc<TransferMeter>,
+    incoming_sender: Mutex<Sender<IncomingConnection>>,
+    /// Where the peers found on the local network are added.
+    peers: PeerList,
+    /// Orders the pieces the torrent requests, its mode and playback position change while downloading.
+    piece_picker: Arc<RwLock<PiecePicker>>,
+    /// Private torrents only get peers from their trackers, they are left out of the local discovery.
+    private: bool,
+    /// Queued torrents are resumed in the order they were added.
+    added: Instant,
+    /// The time spent seeding, None until the torrent is complete.
+    seeded: Mutex<Option<Duration>>,
+}
+
+/// A torrent of the session and what its events told so far.
+#[derive(Debug, Clone)]
+pub struct TorrentStatus {
+    pub info_hash: Vec<u8>,
+    pub torrent_path: String,
+    pub state: TorrentState,
+    pub stats: TorrentStats,
+    /// Measured by the meter of the torrent.
+    pub rates: TransferRates,
+    /// Payload bytes since the torrent was added.
+    pub downloaded: u64,
+    pub uploaded: u64,
+    /// The uploaded bytes over the length of the torrent.
+    pub ratio: f64,
+    pub seed_time: Duration,
+}
+
+/// The totals of every torrent of the session.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionStats {
+    pub port: u16,
+    pub torrents: usize,
+    pub active_connections: usize,
+    /// The payload bytes per second, the data of the pieces.
+    pub download_rate: u64,
+    pub upload_rate: u64,
+    /// The bytes per second of everything else: handshakes, requests and message headers.
+    pub download_protocol_rate: u64,
+    pub upload_protocol_rate: u64,
+    /// The limits of the session right now, 0 is unlimited.
+    pub download_limit: u64,
+    pub upload_limit: u64,
+}
+
+/// The Session runs many torrents in one process. It owns the listening sockets, one for each address of
+/// the configuration on the same port, and routes every incoming connection to the torrent whose info hash
+/// is in the handshake.
+pub struct Session {
+    listeners: Vec<TcpListener>,
+    context: SessionContext,
+    config: Config,
+    limits: RwLock<LimitsConfig>,
+    seeding: RwLock<SeedingConfig>,
+    events: Arc<EventBus>,
+    stats: Arc<StatsRecorder>,
+    torrents: RwLock<HashMap<Vec<u8>, TorrentHandle>>,
+    threads_handles: Mutex<Vec<JoinHandle<()>>>,
+    listening: Mutex<bool>,
+    /// None if the configuration turns the port mapping off, or once the session is shut down.
+    port_mapper: Mutex<Option<PortMapper>>,
+}
+
+impl Session {
+    /// Creates the session listening on the addresses and the port of the configuration, or on a free port
+    /// if that one is taken. The external ip and port of the configuration are announced if they are set,
+    /// otherwise the port listened on until the port mapping gets the external address.
+    pub fn new(config: Config, events: Arc<EventBus>) -> Result<Arc<Session>, SessionError> {
+        let listeners = bind_listeners(&config.network)?;
+        let port = listeners[0].local_addr()?.port();
+        let stats = StatsRecorder::new();
+        events.subscribe(Box::new(stats.clone()));
+        let (download_limit, upload_limit) = config.limits.session_rates(minute_of_day());
+        let peer_id = create_id();
+        let announced = Arc::new(RwLock::new(AnnouncedAddress {
+            ip: config.network.external_ip,
+            port: config.network.external_port.unwrap_or(port),
+        }));
+        Ok(Arc::new(Session {
+            context: SessionContext {
+                announcer: Arc::new(Announcer::new(
+                    peer_id.clone(),
+                    announced.clone(),
+                    config.tracker.numwant,
+                )),
+                peer_id,
+                announced,
+                connections: ConnectionLimit::new(config.network.max_connections),
+                half_open: ConnectionLimit::new(config.network.max_half_open),
+                rate_limiter: RateLimiter::new(download_limit, upload_limit),
+                rate_meter: TransferMeter::new(),
+                event_loop: EventLoop::new()?,
+                encryption: config.network.encryption,
+            },
+            listeners,
+            port_mapper: Mutex::new(
+                config
+                    .network
+                    .port_mapping
+                    .then(|| PortMapper::new(port, Gateways::of_host())),
+            ),
+            limits: RwLock::new(config.limits),
+            seeding: RwLock::new(config.seeding),
+            config,
+            events,
+            stats,
+            torrents: RwLock::new(HashMap::new()),
+            threads_handles: Mutex::new(Vec::new()),
+            listening: Mutex::new(true),
+        }))
+    }
+
+    /// Returns the port every torrent is reached at, the one announced to the trackers.
+    pub fn port(&self) -> u16 {
+        self.context.announced().port
+    }
+
+    /// Returns the addresses listened on.
+    pub fn listen_addrs(&self) -> Vec<SocketAddr> {
+        self.listeners
+            .iter()
+            .filter_map(|listener| listener.local_addr().ok())
+            .collect()
+    }
+
+    /// Returns the number of peer connections of the whole session.
+    pub fn active_connections(&self) -> usize {
+        self.context.connections.active()
+    }
+
+    /// Reads the torrent file, announces it and starts downloading it, or queues it if the session already
+    /// downloads as many torrents as it can. Returns its info hash.
+    pub fn add_torrent(&self, torrent_path: &str) -> Result<Vec<u8>, SessionError> {
+        let metainfo =
+            torrent_parse_with_max_length(torrent_path, self.config.storage.max_torrent_size)?;
+        let info_hash = metainfo.info_hash();
+        if self.torrents.read()?.contains_key(&info_hash) {
+            return Err(already_added(torrent_path));
+        }
+        let private = metainfo.info.private;
+
+        let (client, logger_handle) = Client::create(
+            self.config.clone(),
+            self.events.clone(),
+            torrent_path.to_string(),
+            self.context.clone(),
+            metainfo,
+        )?;
+        let limits = self.limits();
+        let rate_limiter = client.get_rate_limiter();
+        rate_limiter.set_child_rates(limits.max_peer_download_rate, limits.max_peer_upload_rate);
+        let torrent = TorrentHandle {
+            torrent_path: torrent_path.to_string(),
+            state: client.get_state(),
+            files: client.get_file_selection(),
+            rate_limiter,
+            rate_meter: client.get_rate_meter(),
+            incoming_sender: Mutex::new(client.get_incoming_sender()),
+            peers: client.get_peer_list(),
+            piece_picker: client.get_piece_picker(),
+            private,
+            added: Instant::now(),
+            seeded: Mutex::new(None),
+        };
+        {
+            // checked again under the same lock as the insert, another call may have added it meanwhile
+            let mut torrents = self.torrents.write()?;
+            if torrents.contains_key(&info_hash) {
+                return Err(already_added(torrent_path));
+            }
+            let mut downloads = 0;
+            for torrent in torrents.values() {
+                if *torrent.state.read()? == TorrentState::Running
+                    && torrent.seeded.lock()?.is_none()
+                {
+                    downloads += 1;
+                }
+            }
+            if is_full(downloads, self.seeding().max_active_downloads) {
+                self.change_state(&torrent, TorrentState::Queued)?;
+            }
+            torrents.insert(info_hash.clone(), torrent);
+        }
+        let (download_handle, listener_handle, upload_handle) = match client.start() {
+            Ok(handles) => handles,
+            Err(e) => {
+                self.torrents.write()?.remove(&info_hash);
+                return Err(SessionError::from(e));
+            }
+        };
+        self.threads_handles.lock()?.extend([
+            logger_handle,
+            download_handle,
+            listener_handle,
+            upload_handle,
+        ]);
+        Ok(info_hash)
+    }
+
+    /// Saves the contents of a torrent file received from elsewhere in the torrents directory
+    /// and adds it like add_torrent. Only the file name is kept from file_name.
+    pub fn add_torrent_file(
+        &self,
+        file_name: &str,
+        contents: &[u8],
+    ) -> Result<Vec<u8>, SessionError> {
+        let file_name = Path::new(file_name)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .filter(|name| name.ends_with(".torrent"))
+            .ok_or_else(|| {
+                SessionError::new(format!(
+                    "SessionError: {} is not the name of a torrent file",
+                    file_name
+                ))
+            })?;
+        let torrents_path = &self.config.storage.torrents_path;
+        fs::create_dir_all(torrents_path)?;
+        let torrent_path = format!("{}/{}", torrents_path, file_name);
+        if Path::new(&torrent_path).exists() && fs::read(&torrent_path)? != contents {
+            return Err(SessionError::new(format!(
+                "SessionError: another torrent is saved as {}",
+                torrent_path
+            )));
+        }
+        fs::write(&torrent_path, contents)?;
+        self.add_torrent(&torrent_path)
+    }
+
+    /// Stops the torrent and forgets it. Its downloaded pieces are kept on disk.
+    pub fn remove_torrent(&self, info_hash: &[u8]) -> Result<(), SessionError> {
+        let torrent = self
+            .torrents
+            .write()?
+            .remove(info_hash)
+            .ok_or_else(not_found)?;
+        *torrent.state.write()? = TorrentState::Stopped;
+        self.events.publish(
+            &torrent.torrent_path,
+            ClientEvent::StateChanged(TorrentState::Stopped),
+        );
+        self.stats.remove(&torrent.torrent_path);
+        Ok(())
+    }
+
+    /// Disconnects the torrent from its peers until it is resumed.
+    pub fn pause(&self, info_hash: &[u8]) -> Result<(), SessionError> {
+        self.set_state(info_hash, TorrentState::Paused)
+    }
+
+    /// Lets a paused torrent connect to peers again.
+    pub fn resume(&self, info_hash: &[u8]) -> Result<(), SessionError> {
+        self.set_state(info_hash, TorrentState::Running)
+    }
+
+    /// Changes the priority of a file of the torrent, skipped files stop being downloaded.
+    pub fn set_file_priority(
+        &self,
+        info_hash: &[u8],
+        file_index: usize,
+        priority: FilePriority,
+    ) -> Result<(), SessionError> {
+        let torrents = self.torrents.read()?;
+        let torrent = torrents.get(info_hash).ok_or_else(not_found)?;
+        torrent.files.set_priority(file_index, priority)?;
+        Ok(())
+    }
+
+    /// Limits the torrent on top of the limits of the session, 0 is unlimited.
+    pub fn set_torrent_limits(
+        &self,
+        info_hash: &[u8],
+        download_rate: u64,
+        upload_rate: u64,
+    ) -> Result<(), SessionError> {
+        let torrents = self.torrents.read()?;
+        let torrent = torrents.get(info_hash).ok_or_else(not_found)?;
+        torrent.rate_limiter.set_rates(download_rate, upload_rate);
+        Ok(())
+    }
+
+    /// Changes the order in which the missing pieces of the torrent are requested.
+    pub fn set_download_mode(
+        &self,
+        info_hash: &[u8],
+        mode: DownloadMode,
+    ) -> Result<(), SessionError> {
+        let torrents = self.torrents.read()?;
+        let torrent = torrents.get(info_hash).ok_or_else(not_found)?;
+        torrent
+            .piece_picker
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_mode(mode);
+        Ok(())
+    }
+
+    /// Moves the playback position of the torrent, the sequential mode requests the pieces after it first.
+    pub fn set_playback_position(
+        &self,
+        info_hash: &[u8],
+        piece_index: usize,
+    ) -> Result<(), SessionError> {
+        let torrents = self.torrents.read()?;
+        let torrent = torrents.get(info_hash).ok_or_else(not_found)?;
+        torrent
+            .piece_picker
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_playback_position(piece_index);
+        Ok(())
+    }
+
+    /// Returns the state, progress and rates of every torrent of the session.
+    pub fn torrents(&self) -> Result<Vec<TorrentStatus>, SessionError> {
+        let mut torrents = Vec::new();
+        for (info_hash, torrent) in self.torrents.read()?.iter() {
+            let stats = self.stats.torrent(&torrent.torrent_path);
+            let uploaded = torrent.rate_meter.payload_total(Direction::Upload);
+            torrents.push(TorrentStatus {
+                info_hash: info_hash.clone(),
+                torrent_path: torrent.torrent_path.clone(),
+                state: *torrent.state.read()?,
+                rates: torrent.rate_meter.rates(),
+                downloaded: torrent.rate_meter.payload_total(Direction::Download),
+                uploaded,
+                ratio: ratio(uploaded, stats.total_length),
+                seed_time: torrent.seeded.lock()?.unwrap_or_default(),
+                stats,
+            });
+        }
+        Ok(torrents)
+    }
+
+    /// Returns the peers the torrent is connected to.
+    pub fn peers(&self, info_hash: &[u8]) -> Result<Vec<PeerStats>, SessionError> {
+        let torrents = self.torrents.read()?;
+        let torrent = torrents.get(info_hash).ok_or_else(not_found)?;
+        Ok(self
+            .stats
+            .torrent(&torrent.torrent_path)
+            .peers
+            .into_values()
+            .collect())
+    }
+
+    /// Returns the totals of the session.
+    pub fn stats(&self) -> Result<SessionStats, SessionError> {
+        let (download_limit, upload_limit) = self.context.rate_limiter.rates();
+        let rates = self.context.rate_meter.rates();
+        Ok(SessionStats {
+            port: self.port(),
+            torrents: self.torrents.read()?.len(),
+            active_connections: self.active_connections(),
+            download_rate: rates.download_payload,
+            upload_rate: rates.upload_payload,
+            download_protocol_rate: rates.download_protocol,
+            upload_protocol_rate: rates.upload_protocol,
+            download_limit,
+            upload_limit,
+        })
+    }
+
+    fn set_state(&self, info_hash: &[u8], state: TorrentState) -> Result<(), SessionError> {
+        let torrents = self.torrents.read()?;
+        let torrent = torrents.get(info_hash).ok_or_else(not_found)?;
+        self.change_state(torrent, state)
+    }
+
+    fn change_state(
+        &self,
+        torrent: &TorrentHandle,
+        state: TorrentState,
+    ) -> Result<(), SessionError> {
+        *torrent.state.write()? = state;
+        self.events
+            .publish(&torrent.torrent_path, ClientEvent::StateChanged(state));
+        Ok(())
+    }
+
+    /// Accepts incoming connections until the session is shut down. Each one is routed in its own thread,
+    /// so a peer that is slow to send its handshake does not hold the others. The port is mapped on the
+    /// gateway meanwhile, if the configuration asks for it. A failed accept is logged and retried after a
+    /// wait that grows while it keeps failing.
+    pub fn listen(self: Arc<Self>) -> Result<(), SessionError> {
+        let self_copy = self.clone();
+        spawn(move || {
+            let _r = self_copy.map_port();
+        });
+        if self.config.network.local_discovery {
+            let self_copy = self.clone();
+            spawn(move || {
+                let _r = self_copy.discover_local_peers();
+            });
+        }
+        let mut backoff = ACCEPT_INTERVAL;
+        while *self.listening.lock()? {
+            let mut accepted = false;
+            let mut failed = false;
+            for listener in &self.listeners {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        accepted = true;
+                        let self_copy = self.clone();
+                        spawn(move || {
+                            let _r = self_copy.route(stream);
+                        });
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    // a connection aborted before it was accepted or no descriptors left, the listener still works
+                    Err(e) => {
+                        self.events
+                            .publish("", ClientEvent::AcceptFailed(e.to_string()));
+                        failed = true;
+                    }
+                }
+            }
+            if failed {
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+            } else {
+                backoff = ACCEPT_INTERVAL;
+                if !accepted {
+                    thread::sleep(ACCEPT_INTERVAL);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps the listening port on the gateway, and renews the mappings halfway through their lease, until
+    /// the session is shut down. The external address the gateway gives is announced, except for the ip or
+    /// the port the configuration sets. A gateway that does not answer is looked for again later.
+    fn map_port(&self) -> Result<(), SessionError> {
+        let mut next_mapping = Instant::now();
+        while *self.listening.lock()? {
+            if Instant::now() >= next_mapping {
+                let mapped = match self
+                    .port_mapper
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .as_mut()
+                {
+                    Some(mapper) => mapper.map(MAPPING_LIFETIME),
+                    None => return Ok(()),
+                };
+                next_mapping = Instant::now()
+                    + match mapped {
+                        Ok(external) => {
+                            self.announce(external);
+                            match external.lifetime.is_zero() {
+                                true => MAPPING_LIFETIME / 2,
+                                false => external.lifetime / 2,
+                            }
+                        }
+                        Err(_) => MAPPING_RETRY_INTERVAL,
+                    };
+            }
+            thread::sleep(ACCEPT_INTERVAL);
+        }
+        Ok(())
+    }
+
+    /// Announces the active torrents to the local network, each one as soon as it is active and then every
+    /// few minutes, and adds the peers announced there to the torrents they announce, until the session is
+    /// shut down. The port announced is the one listened on, the peers are in the same network. Private
+    /// torrents are neither announced nor given local peers.
+    fn discover_local_peers(&self) -> Result<(), SessionError> {
+        let port = match self.listen_addrs().first() {
+            Some(address) => address.port(),
+            None => return Ok(()),
+        };
+        let mut discovery = LocalDiscovery::new(port, &LSD_GROUPS)?;
+        let mut last_announces: HashMap<Vec<u8>, Instant> = HashMap::new();
+        while *self.listening.lock()? {
+            let due = self.local_announces_due(&mut last_announces)?;
+            if !due.is_empty() {
+                // announced again in the next interval if no group could be written to
+                let _r = discovery.announce(&due);
+                for info_hash in due {
+                    last_announces.insert(info_hash, Instant::now());
+                }
+            }
+            for peer in discovery.receive() {
+                self.add_local_peer(&peer)?;
+            }
+            thread::sleep(ACCEPT_INTERVAL);
+        }
+        Ok(())
+    }
+
+    /// Returns the info hashes of the active public torrents not announced to the local network in the last
+    /// interval, and forgets the announces of the torrents that are no longer active.
+    fn local_announces_due(
+        &self,
+        last_announces: &mut HashMap<Vec<u8>, Instant>,
+    ) -> Result<Vec<Vec<u8>>, SessionError> {
+        let mut due = Vec::new();
+        for (info_hash, torrent) in self.torrents.read()?.iter() {
+            if torrent.private || !torrent.state.read()?.is_active() {
+                last_announces.remove(info_hash);
+                continue;
+            }
+            let announced = last_announces
+                .get(info_hash)
+                .is_some_and(|last| last.elapsed() < LOCAL_ANNOUNCE_INTERVAL);
+            if !announced {
+                due.push(info_hash.clone());
+            }
+        }
+        Ok(due)
+    }
+
+    /// Adds a peer found on the local network to the torrent it announced, if that torrent is active and
+    /// public. Returns whether it was added.
+    fn add_local_peer(&self, peer: &LocalPeer) -> Result<bool, SessionError> {
+        if let Some(torrent) = self.torrents.read()?.get(&peer.info_hash) {
+            if !torrent.private && torrent.state.read()?.is_active() {
+                return Ok(torrent.peers.add(peer.ip.to_string(), peer.port));
+            }
+        }
+        Ok(false)
+    }
+
+    /// Announces the external address from now on, the values of the configuration go first.
+    fn announce(&self, external: ExternalAddress) {
+        let network = &self.config.network;
+        *self
+            .context
+            .announced
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = AnnouncedAddress {
+            ip: network.external_ip.or(Some(external.ip)),
+            port: network.external_port.unwrap_or(external.port),
+        };
+    }
+
+    /// Returns the rate limits in use, the ones of the configuration file until it changes.
+    pub fn limits(&self) -> LimitsConfig {
+        *self.limits.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Replaces the rate limits of the session and of every peer connection.
+    pub fn set_limits(&self, limits: LimitsConfig) -> Result<(), SessionError> {
+        *self.limits.write().unwrap_or_else(PoisonError::into_inner) = limits;
+        self.apply_limits()
+    }
+
+    /// Returns the seeding goals and the queue limits in use.
+    pub fn seeding(&self) -> SeedingConfig {
+        *self.seeding.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Replaces the seeding goals and the queue limits, they are applied in the next check of watch_config.
+    pub fn set_seeding(&self, seeding: SeedingConfig) {
+        *self.seeding.write().unwrap_or_else(PoisonError::into_inner) = seeding;
+    }
+
+    /// Reads the configuration file again whenever it changes, until the session is shut down, and applies
+    /// its rate limits and seeding goals. The rest of the values need a restart. A file that is not valid
+    /// is ignored, the values in use are kept until it is fixed. The scheduled limits start and end here,
+    /// and the goals and the queue are checked here too, so it also has to run without a configuration file.
+    pub fn watch_config(self: Arc<Self>) -> Result<(), SessionError> {
+        let modified = |path: &Path| fs::metadata(path).and_then(|file| file.modified()).ok();
+        let source = self.config.source().cloned();
+        let mut last_modified = source.as_deref().and_then(modified);
+        let mut last_check = Instant::now();
+        while *self.listening.lock()? {
+            thread::sleep(CONFIG_RELOAD_INTERVAL);
+            let current = source.as_deref().and_then(modified);
+            if current != last_modified {
+                last_modified = current;
+                if let Ok(config) = self.config.reload() {
+                    *self.limits.write().unwrap_or_else(PoisonError::into_inner) = config.limits;
+                    self.set_seeding(config.seeding);
+                }
+            }
+            self.apply_limits()?;
+            self.apply_seeding(last_check.elapsed())?;
+            last_check = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Adds elapsed to the seed time of the seeding torrents, pauses or removes the ones that reached a goal,
+    /// and queues or resumes torrents so the active ones stay within the limits. The torrents added first
+    /// keep their slots.
+    fn apply_seeding(&self, elapsed: Duration) -> Result<(), SessionError> {
+        let seeding = self.seeding();
+        let mut reached = Vec::new();
+        {
+            let torrents = self.torrents.read()?;
+            let mut ordered: Vec<(&Vec<u8>, &TorrentHandle)> = torrents.iter().collect();
+            ordered.sort_by_key(|(_, torrent)| torrent.added);
+            let (mut downloads, mut seeds) = (0, 0);
+            let mut queued = Vec::new();
+            for (info_hash, torrent) in ordered {
+                let state = *torrent.state.read()?;
+                let mut seeded = torrent.seeded.lock()?;
+                if state == TorrentState::Seeding {
+                    *seeded = Some(seeded.unwrap_or_default() + elapsed);
+                }
+                let is_seed = seeded.is_some();
+                drop(seeded);
+                if state == TorrentState::Seeding && self.goal_reached(torrent, &seeding)? {
+                    reached.push(info_hash.clone());
+                    continue;
+                }
+                // a resumed seed runs for a moment before the DownloadManager sees it is complete
+                let (active, max) = match is_seed {
+                    true => (&mut seeds, seeding.max_active_seeds),
+                    false => (&mut downloads, seeding.max_active_downloads),
+                };
+                match state {
+                    TorrentState::Running | TorrentState::Seeding if is_full(*active, max) => {
+                        self.change_state(torrent, TorrentState::Queued)?;
+                    }
+                    TorrentState::Running | TorrentState::Seeding => *active += 1,
+                    TorrentState::Queued => queued.push((torrent, is_seed)),
+                    _ => {}
+                }
+            }
+            for (torrent, is_seed) in queued {
+                let (active, max) = match is_seed {
+                    true => (&mut seeds, seeding.max_active_seeds),
+                    false => (&mut downloads, seeding.max_active_downloads),
+                };
+                if !is_full(*active, max) {
+                    *active += 1;
+                    self.change_state(torrent, TorrentState::Running)?;
+                }
+            }
+        }
+        for info_hash in reached {
+            match seeding.goal_action {
+                GoalAction::Pause => self.pause(&info_hash)?,
+                GoalAction::Remove => self.remove_torrent(&info_hash)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether the torrent reached the share ratio or the seed time of the configuration.
+    fn goal_reached(
+        &self,
+        torrent: &TorrentHandle,
+        seeding: &SeedingConfig,
+    ) -> Result<bool, SessionError> {
+        let total_length = self.stats.torrent(&torrent.torrent_path).total_length;
+        let ratio = ratio(
+            torrent.rate_meter.payload_total(Direction::Upload),
+            total_length,
+        );
+        let seed_time = torrent.seeded.lock()?.unwrap_or_default();
+        Ok((seeding.share_ratio > 0.0 && ratio >= seeding.share_ratio)
+            || (seeding.seed_time > 0 && seed_time.as_secs() >= seeding.seed_time * 60))
+    }
+
+    /// Sets the limiters to the limits of this time of the day.
+    fn apply_limits(&self) -> Result<(), SessionError> {
+        let limits = self.limits();
+        let (download_rate, upload_rate) = limits.session_rates(minute_of_day());
+        self.context
+            .rate_limiter
+            .set_rates(download_rate, upload_rate);
+        for torrent in self.torrents.read()?.values() {
+            torrent
+                .rate_limiter
+                .set_child_rates(limits.max_peer_download_rate, limits.max_peer_upload_rate);
+        }
+        Ok(())
+    }
+
+    /// Makes listen return, and removes the port mappings made on the gateway.
+    pub fn shutdown(&self) -> Result<(), SessionError> {
+        *self.listening.lock()? = false;
+        let port_mapper = self
+            .port_mapper
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(mut port_mapper) = port_mapper {
+            // the leases end by themselves if the gateway does not answer
+            let _r = port_mapper.unmap();
+        }
+        Ok(())
+    }
+
+    /// Waits for the threads of every torrent added so far to finish.
+    pub fn wait(&self) -> Result<(), SessionError> {
+        loop {
+            let handle = self.threads_handles.lock()?.pop();
+            match handle {
+                Some(handle) => {
+                    let _r = handle.join();
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Hands the connection to the active torrent it asks for, otherwise the connection is closed. A
+    /// connection that does not start with the handshake is answered as an encrypted one, which asks for
+    /// the torrent in its encryption handshake, unless the policy disables encryption; forced encryption
+    /// refuses plaintext connections.
+    fn route(&self, mut stream: TcpStream) -> Result<(), SessionError> {
+        let slot = match self.context.connections.try_acquire() {
+            Some(slot) => slot,
+            None => return Ok(()),
+        };
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let encryption = self.context.encryption;
+        let (info_hash, negotiated) = match peek_info_hash(&stream)? {
+            Some(_) if encryption == EncryptionPolicy::Forced => {
+                return Err(SessionError::new(
+                    "SessionError: plaintext connections are refused".to_string(),
+                ))
+            }
+            Some(info_hash) => (info_hash, None),
+            None if encryption == EncryptionPolicy::Disabled => {
+                return Err(SessionError::new(
+                    "SessionError: the peer does not speak the BitTorrent protocol".to_string(),
+                ))
+            }
+            None => {
+                let info_hashes: Vec<Vec<u8>> = self.torrents.read()?.keys().cloned().collect();
+                let negotiated = encrypted_stream::accept(&mut stream, &info_hashes, encryption)?;
+                (negotiated.info_hash.clone(), Some(negotiated))
+            }
+        };
+
+        let torrents = self.torrents.read()?;
+        if let Some(torrent) = torrents.get(&info_hash) {
+            if torrent.state.read()?.is_active() {
+                torrent.incoming_sender.lock()?.send(IncomingConnection {
+                    stream,
+                    slot,
+                    negotiated,
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Binds a socket on every address of the configuration, all on the port of the first one. A taken port
+/// is replaced by a free one up to port_retries times. IPv6 addresses are left out on a host without IPv6.
+fn bind_listeners(network: &NetworkConfig) -> Result<Vec<TcpListener>, SessionError> {
+    let mut port = network.port;
+    let mut retries = 0;
+    loop {
+        match bind_on_port(&network.bind_addresses, port) {
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse && retries < network.port_retries => {
+                retries += 1;
+                // the system picks a free one
+                port = 0;
+            }
+            result => return Ok(result?),
+        }
+    }
+}
+
+fn bind_on_port(addresses: &[IpAddr], port: u16) -> io::Result<Vec<TcpListener>> {
+    let mut listeners: Vec<TcpListener> = Vec::new();
+    for ip in addresses {
+        let port = match listeners.first() {
+            Some(listener) => listener.local_addr()?.port(),
+            None => port,
+        };
+        match bind(SocketAddr::new(*ip, port)) {
+            Ok(listener) => listeners.push(listener),
+            Err(e) if ip.is_ipv6() && e.kind() != io::ErrorKind::AddrInUse => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    if listeners.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            "there is no address to listen on",
+        ));
+    }
+    Ok(listeners)
+}
+
+/// Binds a non-blocking listening socket. IPv6 sockets only take IPv6 connections, so `::` and `0.0.0.0`
+/// are listened on together.
+fn bind(address: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    if address.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    // like std, so a port is listened on again right after the client exits
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(128)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+fn already_added(torrent_path: &str) -> SessionError {
+    SessionError::new(format!("SessionError: {} was already added", torrent_path))
+}
+
+/// Returns whether active reached max, 0 is no limit.
+fn is_full(active: usize, max: usize) -> bool {
+    max > 0 && active >= max
+}
+
+fn ratio(uploaded: u64, total_length: u64) -> f64 {
+    match total_length {
+        0 => 0.0,
+        total_length => uploaded as f64 / total_length as f64,
+    }
+}
+
+fn not_found() -> SessionError {
+    SessionError::new("SessionError: torrent not found".to_string())
+}
+
+/// Returns the info hash of the handshake sent by the peer without reading it from the stream,
+/// so the torrent still gets the whole handshake. None if the peer sent something else.
+fn peek_info_hash(stream: &TcpStream) -> Result<Option<Vec<u8>>, SessionError> {
+    let mut handshake = [0; HANDSHAKE_LEN];
+    let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    loop {
+        let read = stream.peek(&mut handshake)?;
+        if read == HANDSHAKE_LEN {
+            break;
+        }
+        if read == 0 || Instant::now() >= deadline {
+            return Err(SessionError::new(
+                "SessionError: incomplete handshake".to_string(),
+            ));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    if handshake[0] as usize != PSTR.len()
+        || &handshake[PSTR_LEN_LEN..=PSTR.len()] != PSTR.as_bytes()
+    {
+        return Ok(None);
+    }
+    let start = PSTR_LEN_LEN + PSTR.len() + RESERVED_SPACE_LEN as usize;
+    Ok(Some(handshake[start..start + INFO_HASH_LEN].to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peer_entities::{
+        communication_method::CommunicationMethod, encrypted_stream::EncryptedStream,
+        event_loop::LoopStream,
+    };
+    use std::io::{Read, Write};
+
+    fn handshake(info_hash: &[u8]) -> Vec<u8> {
+        let mut handshake = vec![PSTR.len() as u8];
+        handshake.extend(PSTR.as_bytes());
+        handshake.extend([0; RESERVED_SPACE_LEN as usize]);
+        handshake.extend(info_hash);
+        handshake.extend(b"-CR0001-123456789012");
+        handshake
+    }
+
+    fn connected_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (client, server)
+    }
+
+    #[test]
+    fn test_connection_limit_gives_back_dropped_slots() {
+        let limit = ConnectionLimit::new(2);
+        let first = limit.try_acquire().unwrap();
+        let _second = limit.try_acquire().unwrap();
+        assert!(limit.try_acquire().is_none());
+        assert_eq!(limit.active(), 2);
+
+        drop(first);
+        assert_eq!(limit.active(), 1);
+        assert!(limit.try_acquire().is_some());
+    }
+
+    #[test]
+    fn test_peek_info_hash_leaves_the_handshake_in_the_stream() {
+        let (mut client, mut server) = connected_pair();
+        let info_hash = [7; INFO_HASH_LEN];
+        client.write_all(&handshake(&info_hash)).unwrap();
+
+        assert_eq!(peek_info_hash(&server).unwrap(), Some(info_hash.to_vec()));
+        let mut received = vec![0; HANDSHAKE_LEN];
+        server.read_exact(&mut received).unwrap();
+        assert_eq!(received, handshake(&info_hash));
+    }
+
+    fn torrent(
+        session: &Session,
+        position: u64,
+        state: TorrentState,
+        seeded: Option<u64>,
+    ) -> Vec<u8> {
+        let files = FileSelection::new(Vec::new(), 1, false, String::new()).unwrap();
+        let handle = TorrentHandle {
+            torrent_path: format!("{}.torrent", position),
+            state: Arc::new(RwLock::new(state)),
+            files: Arc::new(files),
+            rate_limiter: RateLimiter::new(0, 0),
+            rate_meter: TransferMeter::new(),
+            incoming_sender: Mutex::new(std::sync::mpsc::channel().0),
+            peers: PeerList::new(
+                vec![position as u8; INFO_HASH_LEN],
+                session.context.peer_id.clone(),
+                &session.context,
+                RateLimiter::new(0, 0),
+                TransferMeter::new(),
+                std::sync::mpsc::channel().0,
+                std::sync::mpsc::channel().0,
+            ),
+            piece_picker: Arc::new(RwLock::new(PiecePicker::new(DownloadMode::RarestFirst))),
+            private: false,
+            added: Instant::now() + Duration::from_secs(position),
+            seeded: Mutex::new(seeded.map(Duration::from_secs)),
+        };
+        let info_hash = vec![position as u8; INFO_HASH_LEN];
+        session
+            .torrents
+            .write()
+            .unwrap()
+            .insert(info_hash.clone(), handle);
+        info_hash
+    }
+
+    fn state(session: &Session, info_hash: &[u8]) -> TorrentState {
+        *session.torrents.read().unwrap()[info_hash]
+            .state
+            .read()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_download_mode_and_playback_position_of_a_torrent() {
+        let mut config = Config::default();
+        config.set("port", "0").unwrap();
+        let session = Session::new(config, EventBus::new()).unwrap();
+        let info_hash = torrent(&session, 0, TorrentState::Running, None);
+        let mode = DownloadMode::Sequential { lookahead: 2 };
+        session.set_download_mode(&info_hash, mode).unwrap();
+        session.set_playback_position(&info_hash, 3).unwrap();
+
+        let torrents = session.torrents.read().unwrap();
+        let picker = torrents[&info_hash].piece_picker.read().unwrap();
+        assert_eq!(picker.mode(), mode);
+        let missing: Vec<(usize, FilePriority)> =
+            (0..6).map(|i| (i, FilePriority::Normal)).collect();
+        assert_eq!(picker.order(&missing, &[1; 6], &[])[..2], [3, 4]);
+        assert!(session
+            .set_playback_position(&[9; INFO_HASH_LEN], 0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_private_torrents_are_left_out_of_the_local_discovery() {
+        let mut config = Config::default();
+        config.set("port", "0").unwrap();
+        let session = Session::new(config, EventBus::new()).unwrap();
+        let public = torrent(&session, 0, TorrentState::Running, None);
+        let private = torrent(&session, 1, TorrentState::Running, None);
+        session
+            .torrents
+            .write()
+            .unwrap()
+            .get_mut(&private)
+            .unwrap()
+            .private = true;
+
+        let mut last_announces = HashMap::new();
+        let due = session.local_announces_due(&mut last_announces).unwrap();
+        assert_eq!(due, vec![public.clone()]);
+
+        let peer = |info_hash: &Vec<u8>| LocalPeer {
+            ip: "192.0.2.7".parse().unwrap(),
+            port: 6881,
+            info_hash: info_hash.clone(),
+        };
+        assert!(session.add_local_peer(&peer(&public)).unwrap());
+        assert!(!session.add_local_peer(&peer(&private)).unwrap());
+        let torrents = session.torrents.read().unwrap();
+        assert!(torrents[&private].peers.add("192.0.2.7".to_string(), 6881));
+    }
+
+    #[test]
+    fn test_a_peer_is_added_to_a_torrent_once() {
+        let mut config = Config::default();
+        config.set("port", "0").unwrap();
+        let session = Session::new(config, EventBus::new()).unwrap();
+        let info_hash = torrent(&session, 0, TorrentState::Running, None);
+        let torrents = session.torrents.read().unwrap();
+        let peers = &torrents[&info_hash].peers;
+        assert!(peers.add("192.0.2.7".to_string(), 6881));
+        assert!(!peers.add("192.0.2.7".to_string(), 6881));
+        assert!(peers.add("192.0.2.7".to_string(), 6882));
+    }
+
+    #[test]
+    fn test_goals_and_queue() {
+        let mut config = Config::default();
+        config.set("port", "0").unwrap();
+        config.set("seed_time", "1").unwrap();
+        config.set("max_active_downloads", "1").unwrap();
+        config.set("max_active_seeds", "1").unwrap();
+        let session = Session::new(config, EventBus::new()).unwrap();
+        let finished = torrent(&session, 0, TorrentState::Seeding, Some(59));
+        let seed = torrent(&session, 1, TorrentState::Seeding, Some(0));
+        let queued_seed = torrent(&session, 2, TorrentState::Queued, Some(0));
+        let queued_download = torrent(&session, 3, TorrentState::Queued, None);
+        let download = torrent(&session, 4, TorrentState::Running, None);
+        let extra_download = torrent(&session, 5, TorrentState::Running, None);
+
+        session.apply_seeding(Duration::from_secs(1)).unwrap();
+        // a minute of seeding is the goal
+        assert_eq!(state(&session, &finished), TorrentState::Paused);
+        assert_eq!(state(&session, &seed), TorrentState::Seeding);
+        assert_eq!(state(&session, &queued_seed), TorrentState::Queued);
+        // the active torrents added first keep their slots
+        assert_eq!(state(&session, &download), TorrentState::Running);
+        assert_eq!(state(&session, &extra_download), TorrentState::Queued);
+        assert_eq!(state(&session, &queued_download), TorrentState::Queued);
+
+        session.pause(&seed).unwrap();
+        session.pause(&download).unwrap();
+        session.apply_seeding(Duration::ZERO).unwrap();
+        // the queued torrents added first take the free slots
+        assert_eq!(state(&session, &queued_seed), TorrentState::Running);
+        assert_eq!(state(&session, &queued_download), TorrentState::Running);
+        assert_eq!(state(&session, &extra_download), TorrentState::Queued);
+    }
+
+    #[test]
+    fn test_session_listens_on_ipv4_and_ipv6() {
+        let mut config = Config::default();
+        config.set("port", "0").unwrap();
+        let session = Session::new(config, EventBus::new()).unwrap();
+        let port = session.port();
+        assert!(TcpStream::connect(("127.0.0.1", port)).is_ok());
+        // the host may have no IPv6
+        if TcpListener::bind("[::1]:0").is_ok() {
+            assert_eq!(session.listen_addrs().len(), 2);
+            assert_eq!(session.listen_addrs()[1].port(), port);
+            assert!(TcpStream::connect(("::1", port)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_a_taken_port_is_replaced() {
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = taken.local_addr().unwrap().port();
+        let mut config = Config::default();
+        config.set("bind_addresses", "127.0.0.1").unwrap();
+        config.set("port", &port.to_string()).unwrap();
+        config.set("port_retries", "0").unwrap();
+        assert!(Session::new(config.clone(), EventBus::new()).is_err());
+
+        config.set("port_retries", "3").unwrap();
+        config.set("external_port", "51413").unwrap();
+        let session = Session::new(config, EventBus::new()).unwrap();
+        let listen_addrs = session.listen_addrs();
+        assert_eq!(listen_addrs.len(), 1);
+        assert_ne!(listen_addrs[0].port(), port);
+        // the trackers are told the external port
+        assert_eq!(session.port(), 51413);
+    }
+
+    #[test]
+    fn test_peek_info_hash_of_other_protocols() {
+        let (mut client, server) = connected_pair();
+        client.write_all(&[b'G'; HANDSHAKE_LEN]).unwrap();
+        assert_eq!(peek_info_hash(&server).unwrap(), None);
+
+        let (client, server) = connected_pair();
+        drop(client);
+        assert!(peek_info_hash(&server).is_err());
+    }
+
+    #[test]
+    fn test_encrypted_connections_are_routed() {
+        let mut config = Config::default();
+        config.set("port", "0").unwrap();
+        let session = Session::new(config, EventBus::new()).unwrap();
+        let info_hash = torrent(&session, 0, TorrentState::Running, None);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        *session.torrents.read().unwrap()[&info_hash]
+            .incoming_sender
+            .lock()
+            .unwrap() = sender;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let routing = spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            session.route(stream)
+        });
+
+        let mut stream = EncryptedStream::new(
+            LoopStream::create(),
+            info_hash.clone(),
+            EncryptionPolicy::Forced,
+        );
+        stream.connect("127.0.0.1", port).unwrap();
+        assert!(stream.is_encrypted());
+        routing.join().unwrap().unwrap();
+        let connection = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        let negotiated = connection.negotiated.unwrap();
+        assert_eq!(negotiated.info_hash, info_hash);
+        assert!(negotiated.cipher.is_some());
+    }
+}
@@ -0,0 +1,295 @@
+use crate::{
+    events::{ClientEvent, Direction, EventSink, TorrentEvent, TrackerStatus},
+    file_selection::FileSelection,
+    utilities::utils::to_gb,
+};
+use std::{
+    collections::HashMap,
+    sync::mpsc::SendError,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// A connected peer of a torrent and the last rates measured with it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerStats {
+    pub peer_id: String,
+    pub ip: String,
+    pub port: u16,
+    pub download_rate: u64,
+    pub upload_rate: u64,
+}
+
+/// What the events of a torrent tell about it: its files, progress, tracker and peers.
+#[derive(Debug, Clone, Default)]
+pub struct TorrentStats {
+    pub name: String,
+    pub total_length: u64,
+    pub files: Option<Arc<FileSelection>>,
+    pub pieces: usize,
+    pub verified: usize,
+    pub tracker: Option<TrackerStatus>,
+    pub peers: HashMap<String, PeerStats>,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+}
+
+impl TorrentStats {
+    /// Returns the verified pieces over the pieces of the torrent, from 0 to 1.
+    pub fn progress(&self) -> f64 {
+        match self.pieces {
+            0 => 0.0,
+            pieces => self.verified as f64 / pieces as f64,
+        }
+    }
+
+    /// Returns the bytes per second downloaded from every connected peer.
+    pub fn download_rate(&self) -> u64 {
+        self.peers.values().map(|peer| peer.download_rate).sum()
+    }
+
+    /// Returns the bytes per second uploaded to every connected peer.
+    pub fn upload_rate(&self) -> u64 {
+        self.peers.values().map(|peer| peer.upload_rate).sum()
+    }
+
+    /// Returns the seconds left to download the rest of the torrent at the rate, None if the rate is 0
+    /// and there is something left.
+    pub fn eta(&self, download_rate: u64) -> Option<u64> {
+        let left = (self.total_length as f64 * (1.0 - self.progress())).ceil() as u64;
+        match (left, download_rate) {
+            (0, _) => Some(0),
+            (_, 0) => None,
+            (left, rate) => Some(left.div_ceil(rate)),
+        }
+    }
+
+    /// Returns a line with a bar of width characters, the percentage, the rates, the time left and the peers.
+    pub fn progress_line(&self, width: usize) -> String {
+        let filled = ((self.progress() * width as f64) as usize).min(width);
+        let eta = match self.eta(self.download_rate()) {
+            Some(seconds) => format!(
+                "{}:{:02}:{:02}",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60
+            ),
+            None => "-".to_string(),
+        };
+        format!(
+            "[{}{}] {:5.1}%  down {}/s  up {}/s  eta {}  {} peers",
+            "#".repeat(filled),
+            ".".repeat(width - filled),
+            self.progress() * 100.0,
+            to_gb(self.download_rate()),
+            to_gb(self.upload_rate()),
+            eta,
+            self.peers.len()
+        )
+    }
+
+    fn update(&mut self, event: &ClientEvent) {
+        match event {
+            ClientEvent::Metadata {
+                name,
+                total_length,
+                files,
+            } => {
+                self.name = name.clone();
+                self.total_length = *total_length;
+                self.files = Some(files.clone());
+            }
+            ClientEvent::PiecesChecked { pieces, verified } => {
+                self.pieces = *pieces;
+                self.verified = *verified;
+            }
+            ClientEvent::PieceVerified { .. } => self.verified += 1,
+            ClientEvent::Tracker(status) => self.tracker = Some(status.clone()),
+            ClientEvent::PeerConnected { peer_id, ip, port } => {
+                self.peers.insert(
+                    peer_id.clone(),
+                    PeerStats {
+                        peer_id: peer_id.clone(),
+                        ip: ip.clone(),
+                        port: *port,
+                        ..PeerStats::default()
+                    },
+                );
+            }
+            ClientEvent::PeerDisconnected { peer_id, .. } => {
+                self.peers.remove(peer_id);
+            }
+            ClientEvent::RateSample {
+                peer_id,
+                direction,
+                bytes_per_second,
+            } => {
+                if let Some(peer) = self.peers.get_mut(peer_id) {
+                    match direction {
+                        Direction::Download => peer.download_rate = *bytes_per_second,
+                        Direction::Upload => peer.upload_rate = *bytes_per_second,
+                    }
+                }
+            }
+            ClientEvent::CacheStats { hits, misses } => {
+                self.cache_hits = *hits;
+                self.cache_misses = *misses;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Keeps the TorrentStats of every torrent of the session up to date, it is subscribed to the EventBus.
+/// Torrents are known by the path of their torrent file, like in the events.
+#[derive(Debug, Default)]
+pub struct StatsRecorder {
+    torrents: Mutex<HashMap<String, TorrentStats>>,
+}
+
+impl StatsRecorder {
+    pub fn new() -> Arc<StatsRecorder> {
+        Arc::new(StatsRecorder::default())
+    }
+
+    /// Returns the stats of the torrent, empty if none of its events was published yet.
+    pub fn torrent(&self, torrent_path: &str) -> TorrentStats {
+        self.torrents
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(torrent_path)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Forgets a torrent removed from the session.
+    pub fn remove(&self, torrent_path: &str) {
+        self.torrents
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(torrent_path);
+    }
+
+    fn record(&self, event: &TorrentEvent) {
+        let mut torrents = self.torrents.lock().unwrap_or_else(PoisonError::into_inner);
+        torrents
+            .entry(event.torrent.clone())
+            .or_default()
+            .update(&event.event);
+    }
+}
+
+impl EventSink for Arc<StatsRecorder> {
+    fn send(&mut self, event: TorrentEvent) -> Result<(), SendError<TorrentEvent>> {
+        self.record(&event);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{DisconnectReason, EventBus};
+
+    #[test]
+    fn test_recorder_follows_the_progress_and_the_peers() {
+        let bus = EventBus::new();
+        let recorder = StatsRecorder::new();
+        bus.subscribe(Box::new(recorder.clone()));
+
+        bus.publish(
+            "test.torrent",
+            ClientEvent::PiecesChecked {
+                pieces: 4,
+                verified: 1,
+            },
+        );
+        bus.publish("test.torrent", ClientEvent::PieceVerified { index: 2 });
+        for (peer_id, port) in [("first", 6881), ("second", 6882)] {
+            bus.publish(
+                "test.torrent",
+                ClientEvent::PeerConnected {
+                    peer_id: peer_id.to_string(),
+                    ip: "127.0.0.1".to_string(),
+                    port,
+                },
+            );
+            bus.publish(
+                "test.torrent",
+                ClientEvent::RateSample {
+                    peer_id: peer_id.to_string(),
+                    direction: Direction::Download,
+                    bytes_per_second: 100,
+                },
+            );
+        }
+        bus.publish(
+            "test.torrent",
+            ClientEvent::PeerDisconnected {
+                peer_id: "first".to_string(),
+                reason: DisconnectReason::Choked,
+            },
+        );
+
+        let stats = recorder.torrent("test.torrent");
+        assert_eq!(stats.progress(), 0.5);
+        assert_eq!(stats.download_rate(), 100);
+        assert_eq!(stats.upload_rate(), 0);
+        assert_eq!(stats.peers.len(), 1);
+        assert_eq!(stats.peers["second"].port, 6882);
+    }
+
+    #[test]
+    fn test_progress_line() {
+        let mut stats = TorrentStats {
+            total_length: 4 * 1024 * 1024,
+            pieces: 4,
+            verified: 1,
+            ..TorrentStats::default()
+        };
+        stats.peers.insert(
+            "peer".to_string(),
+            PeerStats {
+                download_rate: 2048,
+                ..PeerStats::default()
+            },
+        );
+        assert_eq!(
+            stats.progress_line(8),
+            "[##......]  25.0%  down 2 KB/s  up 0 B/s  eta 0:25:36  1 peers"
+        );
+    }
+
+    #[test]
+    fn test_eta() {
+        let stats = TorrentStats {
+            total_length: 1000,
+            pieces: 4,
+            verified: 3,
+            ..TorrentStats::default()
+        };
+        assert_eq!(stats.eta(100), Some(3));
+        assert_eq!(stats.eta(0), None);
+        let done = TorrentStats {
+            verified: 4,
+            ..stats
+        };
+        assert_eq!(done.eta(0), Some(0));
+    }
+
+    #[test]
+    fn test_rates_of_unknown_peers_are_ignored() {
+        let recorder = StatsRecorder::new();
+        recorder.record(&TorrentEvent {
+            torrent: "test.torrent".to_string(),
+            event: ClientEvent::RateSample {
+                peer_id: "unknown".to_string(),
+                direction: Direction::Upload,
+                bytes_per_second: 100,
+            },
+        });
+
+        let stats = recorder.torrent("test.torrent");
+        assert!(stats.peers.is_empty());
+        assert_eq!(stats.upload_rate(), 0);
+    }
+}
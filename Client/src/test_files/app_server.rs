@@ -1,7 +1,7 @@
+use crabrave::disk_cache::read_piece;
 use crabrave::utilities::constants::*;
 use sha1::{Digest, Sha1};
 use std::{
-    fs::File,
     io::{self, Read, Write},
     net::TcpStream,
 };
@@ -84,12 +84,12 @@ fn main() {
 
     println!("Received successfully");
 
-    let mut piece_file =
-        File::open("src/downloaded_pieces/debian-11.3.0-amd64-netinst.iso.torrent/piece_0.txt")
-            .unwrap();
-
-    let mut piece_file_data = vec![];
-    piece_file.read_to_end(&mut piece_file_data).unwrap(); // Read real piece file
+    let piece_file_data = read_piece(
+        "src/downloaded_pieces/debian-11.3.0-amd64-netinst.iso.torrent",
+        piece_length as u64,
+        0,
+    )
+    .unwrap(); // Read real piece file
 
     assert_eq!(piece_file_data.len(), piece_data.len()); // Same length
     assert_eq!(apply_sha1(&piece_data), apply_sha1(&piece_file_data)); // Same sha1 hash
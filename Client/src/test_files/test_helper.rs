@@ -2,7 +2,11 @@ use crate::{
     errors::communication_method_error::CommunicationMethodError,
     peer_entities::communication_method::CommunicationMethod,
 };
-use std::io::{self, Cursor, Read, Write};
+use std::{
+    env, fs,
+    io::{self, Cursor, Read, Write},
+    path::PathBuf,
+};
 
 /// This struct is used to mock a TCP connection.
 #[derive(Debug, Clone)]
@@ -95,3 +99,11 @@ impl CommunicationMethod for MockTcpStream {
         true
     }
 }
+
+/// Returns an empty directory for the test name of a module, removing what an earlier run left in it.
+pub fn test_dir(module: &str, name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("crabrave_{}_{}", module, name));
+    let _r = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -1,26 +1,54 @@
 use crate::{
-    errors::tracker_error::TrackerError,
-    logger::LogMsg,
-    parsing::bdecoder::{bdecode, from_string_to_vec, from_vec_to_string, Decodification},
-    peer_entities::peer::Peer,
-    utilities::constants::NUMBER_OF_PEERS_TO_ORDER,
+    errors::tracker_error::TrackerError, logger::LogMsg, peer_entities::peer::Peer,
+    session::AnnouncedAddress, utilities::constants::NUMBER_OF_PEERS_TO_ORDER,
     utilities::utils::to_urlencoded,
 };
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
 use std::{
     collections::HashMap,
+    fmt,
     io::{Read, Write},
-    net::TcpStream,
+    net::{IpAddr, TcpStream},
     str,
     sync::mpsc::Sender,
-    sync::Arc,
+    sync::{Arc, PoisonError, RwLock},
 };
 
 /// This struct is used to initialize connection with the tracker and store its information.
 #[derive(Debug, PartialEq)]
 pub struct Tracker {
-    pub peers: Decodification,
+    pub peers: Vec<TrackerPeer>,
     pub info_hash: Vec<u8>,
-    pub interval: Decodification,
+    pub interval: i64,
+}
+
+/// The length of a peer in a compact list: the address and the port.
+const COMPACT_IPV4_LEN: usize = 6;
+const COMPACT_IPV6_LEN: usize = 18;
+
+/// The bencoded answer of the tracker to an announce. A failed announce only has the failure reason.
+/// The peers are a list of dictionaries or a compact string of IPv4 peers, and the IPv6 peers can
+/// come apart in `peers6` (BEP 7).
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct AnnounceResponse {
+    #[serde(rename = "failure reason")]
+    pub failure_reason: Option<String>,
+    #[serde(default)]
+    pub interval: i64,
+    #[serde(default, deserialize_with = "deserialize_peers")]
+    pub peers: Vec<TrackerPeer>,
+    #[serde(default, deserialize_with = "deserialize_peers6")]
+    pub peers6: Vec<TrackerPeer>,
+}
+
+/// A peer listed in the announce response.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct TrackerPeer {
+    pub ip: String,
+    pub port: u16,
 }
 
 pub trait TrackerInterface {
@@ -46,17 +74,17 @@ impl TrackerInterface for Tracker {
         let response = request_tracker(info, &info_hash)?;
         sender_logger.send(LogMsg::Info("RESPONSE OBTAINED SUCCESSFULLY".to_string()))?;
 
-        if let Decodification::Dic(dic_aux) = response {
-            let tracker = Tracker {
-                interval: dic_aux[&from_string_to_vec("interval")].clone(),
-
-                peers: dic_aux[&from_string_to_vec("peers")].clone(),
-                info_hash,
-            };
-            Ok(Arc::new(tracker))
-        } else {
-            Err(TrackerError::new("Expected Dic not found".to_string()))
+        if let Some(reason) = response.failure_reason {
+            return Err(TrackerError::new(format!("TrackerError: {}", reason)));
         }
+        let mut peers = response.peers;
+        peers.extend(response.peers6);
+        let tracker = Tracker {
+            interval: response.interval,
+            peers,
+            info_hash,
+        };
+        Ok(Arc::new(tracker))
     }
 
     /// Returns the info hash,
@@ -64,33 +92,81 @@ impl TrackerInterface for Tracker {
         self.info_hash.clone()
     }
 
-    /// Takes a vector of bencoded peers and returns a vector of bdecoded peers.
+    /// Returns the peers listed by the tracker.
     fn get_peers(&self) -> Result<Vec<Peer>, TrackerError> {
-        if let Decodification::List(peer_list) = &self.peers {
-            let mut peers = Vec::new();
-            for peer in peer_list.iter() {
-                if let Decodification::Dic(peer_dict) = peer {
-                    let peer_ip = match peer_dict.get(&from_string_to_vec("ip")) {
-                        Some(Decodification::String(ip)) => ip,
-                        _ => return Err(TrackerError::new("missing ip".to_string())),
-                    };
-
-                    let peer_port = match peer_dict.get(&from_string_to_vec("port")) {
-                        Some(Decodification::Int(port)) => *port as u16,
-                        _ => return Err(TrackerError::new("missing port".to_string())),
-                    };
-
-                    let peer = Peer::new(
-                        from_vec_to_string(&from_string_to_vec("default_id")),
-                        from_vec_to_string(peer_ip),
-                        peer_port,
-                    );
-                    peers.push(peer);
-                }
-            }
-            return Ok(peers);
+        Ok(self
+            .peers
+            .iter()
+            .map(|peer| Peer::new("default_id".to_string(), peer.ip.clone(), peer.port))
+            .collect())
+    }
+}
+
+/// What a torrent tells its tracker in an announce.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnounceRequest {
+    pub url: String,
+    pub info_hash: Vec<u8>,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub left: u64,
+    pub event: String,
+}
+
+/// The tracker client of a session, shared by all its torrents. It adds what every announce of the
+/// session has in common: the peer id, the address announced, which the port mapping changes, and the
+/// number of peers wanted.
+#[derive(Debug)]
+pub struct Announcer {
+    peer_id: String,
+    announced: Arc<RwLock<AnnouncedAddress>>,
+    numwant: usize,
+}
+
+impl Announcer {
+    pub fn new(
+        peer_id: String,
+        announced: Arc<RwLock<AnnouncedAddress>>,
+        numwant: usize,
+    ) -> Announcer {
+        Announcer {
+            peer_id,
+            announced,
+            numwant,
         }
-        Err(TrackerError::new("Expected List not found".to_string()))
+    }
+
+    /// Announces the torrent and returns its Tracker with the peers listed.
+    pub fn announce(
+        &self,
+        request: AnnounceRequest,
+        logger_sender: Sender<LogMsg>,
+    ) -> Result<Arc<dyn TrackerInterface + Send>, TrackerError> {
+        Tracker::create(self.params(&request), request.info_hash, logger_sender)
+    }
+
+    /// Returns the params of the announce, with the address announced now.
+    fn params(&self, request: &AnnounceRequest) -> HashMap<String, String> {
+        let announced = *self
+            .announced
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut info = HashMap::new();
+        info.insert(String::from("URL"), request.url.clone());
+        info.insert(String::from("peer_id"), self.peer_id.clone());
+        info.insert(String::from("port"), format!("{}", announced.port));
+        if let Some(ip) = announced.ip {
+            info.insert(String::from("ip"), ip.to_string());
+        }
+        info.insert(String::from("uploaded"), format!("{}", request.uploaded));
+        info.insert(
+            String::from("downloaded"),
+            format!("{}", request.downloaded),
+        );
+        info.insert(String::from("left"), format!("{}", request.left));
+        info.insert(String::from("event"), request.event.clone());
+        info.insert(String::from("numwant"), format!("{}", self.numwant));
+        info
     }
 }
 
@@ -98,18 +174,16 @@ impl TrackerInterface for Tracker {
 fn request_tracker(
     info: HashMap<String, String>,
     info_hash: &[u8],
-) -> Result<Decodification, TrackerError> {
+) -> Result<AnnounceResponse, TrackerError> {
     let mut stream = start_connection(info["URL"].clone(), info["port"].clone())?;
 
-    let url = info["URL"].split("//").collect::<Vec<&str>>()[1]
-        .split('/')
-        .collect::<Vec<&str>>()[0];
+    let url = authority(&info["URL"])?;
 
     let request = format_request(info.clone(), info_hash, url);
     let response = write_and_read_stream(&mut stream, request)?;
     let d: &[u8] = response_splitter(response.as_ref());
 
-    let response = bdecode(d)?;
+    let response = bencode::from_bytes(d)?;
 
     Ok(response)
 }
@@ -119,18 +193,108 @@ fn start_connection(initial_url: String, _port: String) -> Result<TcpStream, Tra
     if initial_url.is_empty() {
         return Err(TrackerError::new("URL not found".to_string()));
     }
-    let url = initial_url.split("//").collect::<Vec<&str>>()[1]
-        .split('/')
-        .collect::<Vec<&str>>()[0];
-
-    let stream = TcpStream::connect(url)?;
+    let stream = TcpStream::connect(authority(&initial_url)?)?;
     Ok(stream)
 }
 
+/// Returns the host and port of the url, an IPv6 host is between brackets.
+fn authority(url: &str) -> Result<&str, TrackerError> {
+    match url.split_once("//") {
+        Some((_, rest)) => Ok(rest.split('/').next().unwrap_or(rest)),
+        None => Err(TrackerError::new(format!("Invalid URL: {}", url))),
+    }
+}
+
+fn deserialize_peers<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<TrackerPeer>, D::Error> {
+    deserializer.deserialize_any(PeersVisitor {
+        entry_len: COMPACT_IPV4_LEN,
+    })
+}
+
+fn deserialize_peers6<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<TrackerPeer>, D::Error> {
+    deserializer.deserialize_any(PeersVisitor {
+        entry_len: COMPACT_IPV6_LEN,
+    })
+}
+
+/// Reads the peers of an announce response, either a list of dictionaries or a compact string
+/// with an address and a port every entry_len bytes.
+struct PeersVisitor {
+    entry_len: usize,
+}
+
+impl<'de> Visitor<'de> for PeersVisitor {
+    type Value = Vec<TrackerPeer>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "a list of peers or a string of {} bytes per peer",
+            self.entry_len
+        )
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut peers = Vec::new();
+        while let Some(peer) = seq.next_element()? {
+            peers.push(peer);
+        }
+        Ok(peers)
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+        parse_compact_peers(bytes, self.entry_len).map_err(|e| E::custom(e.to_string()))
+    }
+}
+
+/// Returns the peers of a compact string, 4 or 16 bytes of address and 2 of port each.
+fn parse_compact_peers(bytes: &[u8], entry_len: usize) -> Result<Vec<TrackerPeer>, TrackerError> {
+    if !bytes.len().is_multiple_of(entry_len) {
+        return Err(TrackerError::new(format!(
+            "TrackerError: {} bytes of compact peers are not entries of {}",
+            bytes.len(),
+            entry_len
+        )));
+    }
+    bytes
+        .chunks(entry_len)
+        .map(|entry| {
+            let (ip, port) = entry.split_at(entry_len - 2);
+            let ip = match (<[u8; 4]>::try_from(ip), <[u8; 16]>::try_from(ip)) {
+                (Ok(octets), _) => IpAddr::from(octets),
+                (_, Ok(octets)) => IpAddr::from(octets),
+                _ => {
+                    return Err(TrackerError::new(format!(
+                        "TrackerError: addresses of {} bytes are neither IPv4 nor IPv6",
+                        ip.len()
+                    )))
+                }
+            };
+            Ok(TrackerPeer {
+                ip: ip.to_string(),
+                port: u16::from_be_bytes([port[0], port[1]]),
+            })
+        })
+        .collect()
+}
+
 /// Returns a String with the formatted tracker request given the info data.
 fn format_request(info: HashMap<String, String>, info_hash: &[u8], url: &str) -> String {
     let url_with_port = format!("{}:{}", url, info["port"]);
-    let request = format!("GET /announce?info_hash={}&peer_id={}&port={}&uploaded={}&downloaded={}&left={}&event={}&numwant={} HTTP/1.1\r\nHost: {}\r\n\r\n",
+    let numwant = match info.get("numwant") {
+        Some(numwant) => numwant.clone(),
+        None => NUMBER_OF_PEERS_TO_ORDER.to_string(),
+    };
+    // the address of the client, when it is not the one the announce comes from
+    let ip = match info.get("ip") {
+        Some(ip) => format!("&ip={}", to_urlencoded(ip.as_bytes())),
+        None => String::new(),
+    };
+    let request = format!("GET /announce?info_hash={}&peer_id={}&port={}&uploaded={}&downloaded={}&left={}&event={}&numwant={}{} HTTP/1.1\r\nHost: {}\r\n\r\n",
                               to_urlencoded(info_hash),
                               info["peer_id"],
                               info["port"],
@@ -138,7 +302,8 @@ fn format_request(info: HashMap<String, String>, info_hash: &[u8], url: &str) ->
                               info["downloaded"],
                               info["left"],
                               info["event"],
-                              NUMBER_OF_PEERS_TO_ORDER,
+                              numwant,
+                              ip,
                               url_with_port);
     request
 }
@@ -147,7 +312,7 @@ fn format_request(info: HashMap<String, String>, info_hash: &[u8], url: &str) ->
 // fn request_tracker(
 //     info: HashMap<String, String>,
 //     info_hash: &[u8],
-// ) -> Result<Decodification, TrackerError> {
+// ) -> Result<Value, TrackerError> {
 //     let mut stream = start_connection("http://localhost:8088/announce".to_string(), "8088".to_string())?;
 
 //     let url = "http://localhost:8088/announce".split("//").collect::<Vec<&str>>()[1]
@@ -188,20 +353,12 @@ fn write_and_read_stream(stream: &mut TcpStream, request: String) -> Result<Vec<
     Ok(response)
 }
 
-/// Splits the response to avoid the html header.
+/// Splits the response to avoid the html header. The body is empty if there is no blank line after the headers.
 fn response_splitter(response: &[u8]) -> &[u8] {
-    let mut pos = 0;
-    for i in 0..response.len() {
-        if response[i] == b'\r'
-            && response[i + 1] == b'\n'
-            && response[i + 2] == b'\r'
-            && response[i + 3] == b'\n'
-        {
-            break;
-        }
-        pos += 1;
+    match response.windows(4).position(|window| window == b"\r\n\r\n") {
+        Some(pos) => &response[pos + 4..],
+        None => &[],
     }
-    &response[pos + 4..]
 }
 
 #[cfg(test)]
@@ -244,6 +401,79 @@ mod tests {
         assert_ne!(peers.len(), 0);
     }
 
+    #[test]
+    fn response_splitter_without_body() {
+        assert_eq!(
+            response_splitter(b"HTTP/1.1 200 OK\r\n\r\nd8:intervali5ee"),
+            b"d8:intervali5ee"
+        );
+        assert!(response_splitter(b"HTTP/1.1 200 OK\r\n\r").is_empty());
+        assert!(response_splitter(b"").is_empty());
+    }
+
+    #[test]
+    fn announce_response_is_parsed() {
+        let response: AnnounceResponse = bencode::from_bytes(
+            b"d8:intervali1800e5:peersld2:ip9:127.0.0.17:peer id4:abcd4:porti6881eeee",
+        )
+        .unwrap();
+        assert_eq!(response.interval, 1800);
+        assert_eq!(
+            response.peers,
+            vec![TrackerPeer {
+                ip: "127.0.0.1".to_string(),
+                port: 6881
+            }]
+        );
+
+        let failed: AnnounceResponse =
+            bencode::from_bytes(b"d14:failure reason15:unknown torrente").unwrap();
+        assert_eq!(failed.failure_reason, Some("unknown torrent".to_string()));
+        assert!(failed.peers.is_empty());
+    }
+
+    #[test]
+    fn compact_peers_and_peers6_are_parsed() {
+        let mut bytes = b"d8:intervali900e5:peers12:".to_vec();
+        bytes.extend([127, 0, 0, 1, 0x1a, 0xe1, 10, 0, 0, 2, 0, 80]);
+        bytes.extend(b"6:peers618:");
+        bytes.extend([0x20, 0x01, 0x0d, 0xb8]);
+        bytes.extend([0; 11]);
+        bytes.extend([1, 0x1a, 0xe1]);
+        bytes.push(b'e');
+        let response: AnnounceResponse = bencode::from_bytes(&bytes).unwrap();
+
+        let peer = |ip: &str, port| TrackerPeer {
+            ip: ip.to_string(),
+            port,
+        };
+        assert_eq!(
+            response.peers,
+            vec![peer("127.0.0.1", 6881), peer("10.0.0.2", 80)]
+        );
+        assert_eq!(response.peers6, vec![peer("2001:db8::1", 6881)]);
+
+        // 18 bytes are three IPv4 peers, 12 bytes are no IPv6 peers
+        assert_eq!(
+            parse_compact_peers(&[0; 18], COMPACT_IPV4_LEN)
+                .unwrap()
+                .len(),
+            3
+        );
+        assert!(parse_compact_peers(&[0; 12], COMPACT_IPV6_LEN).is_err());
+        assert!(bencode::from_bytes::<AnnounceResponse>(b"d5:peers5:abcdee").is_err());
+    }
+
+    #[test]
+    fn url_authority() {
+        assert_eq!(
+            authority("http://[::1]:6969/announce").unwrap(),
+            "[::1]:6969"
+        );
+        assert_eq!(authority("http://tracker:80").unwrap(), "tracker:80");
+        assert!(authority("tracker/announce").is_err());
+    }
+
     #[test]
     fn wrong_url() {
         let mut info = HashMap::new();
@@ -331,6 +561,54 @@ mod tests {
         assert_eq!(format_request(info, &info_hash,"torrent.ubuntu.com:433"), format!("GET /announce?info_hash=%b1%11%81%3c%e6%0fB%91%974%82%3d%f5%ec%20%bd%1e%04%e7%f7&peer_id=12187165419728154321&port=443&uploaded=0&downloaded=0&left=0&event=started&numwant=100 HTTP/1.1\r\nHost: torrent.ubuntu.com:433:443\r\n\r\n"));
     }
 
+    #[test]
+    fn request_announces_the_external_ip() {
+        let mut info = HashMap::new();
+        info.insert(String::from("peer_id"), "12187165419728154321".to_string());
+        info.insert(String::from("port"), format!("{}", 51413));
+        info.insert(String::from("uploaded"), format!("{}", 0));
+        info.insert(String::from("downloaded"), format!("{}", 0));
+        info.insert(String::from("left"), format!("{}", 0));
+        info.insert(String::from("event"), "started".to_string());
+        info.insert(String::from("ip"), "2001:db8::7".to_string());
+
+        let request = format_request(info, &[1; 20], "tracker:80");
+        assert!(request.contains("&port=51413&"));
+        assert!(request.contains("&numwant=100&ip=2001%3adb8%3a%3a7 HTTP/1.1"));
+    }
+
+    #[test]
+    fn announcer_params_follow_the_announced_address() {
+        let announced = Arc::new(RwLock::new(AnnouncedAddress {
+            ip: None,
+            port: 6881,
+        }));
+        let announcer = Announcer::new("12187165419728154321".to_string(), announced.clone(), 30);
+        let request = AnnounceRequest {
+            url: "http://tracker:80/announce".to_string(),
+            info_hash: vec![1; 20],
+            uploaded: 0,
+            downloaded: 0,
+            left: 100,
+            event: "started".to_string(),
+        };
+        let params = announcer.params(&request);
+        assert_eq!(params["peer_id"], "12187165419728154321");
+        assert_eq!(params["port"], "6881");
+        assert_eq!(params["left"], "100");
+        assert_eq!(params["numwant"], "30");
+        assert!(!params.contains_key("ip"));
+
+        // the port mapping changed the address
+        *announced.write().unwrap() = AnnouncedAddress {
+            ip: Some("192.0.2.1".parse().unwrap()),
+            port: 51413,
+        };
+        let params = announcer.params(&request);
+        assert_eq!(params["port"], "51413");
+        assert_eq!(params["ip"], "192.0.2.1");
+    }
+
     #[test]
     fn correct_info_hash_urlencoded() {
         let info_hash = [
@@ -1,15 +0,0 @@
-pub const UPDATE_DOWNLOADED_PIECES: usize = 0;
-pub const UPDATE_ACTIVE_CONNS: usize = 1;
-pub const UPDATE_TOTAL_SIZE: usize = 2;
-pub const UPDATE_FILENAME: usize = 3;
-pub const UPDATE_PEER_ID_IP_PORT: usize = 4;
-pub const UPDATE_UNCHOKE: usize = 5;
-pub const UPDATE_INTERESTED: usize = 6;
-pub const UPDATE_VERIFIED_PIECES: usize = 7;
-pub const UPDATE_PEERS_NUMBER: usize = 8;
-pub const GET_PIECES_QUANTITY: usize = 9;
-pub const DELETE_ONE_ACTIVE_CONNECTION: usize = 10;
-pub const UPDATE_UPSPEED: usize = 11;
-pub const UPDATE_DOWNSPEED: usize = 12;
-pub const UPDATE_TORRENT_VEC: usize = 13;
-pub const UPDATE_INITIAL_DOWNLOADED_PIECES: usize = 14;
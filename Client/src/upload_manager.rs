@@ -1,27 +1,27 @@
 use crate::{
-    download_manager::PieceStatus, errors::upload_manager_error::UploadManagerError,
-    logger::LogMsg, peer_entities::communication_method::CommunicationMethod,
-    peer_entities::peer_connection::fmt_chunk, ui::ui_codes::*, utilities::constants::CHUNK_SIZE,
-    utilities::utils::UiParams,
+    choker::Choker,
+    disk_cache::DiskCache,
+    download_manager::PieceStatus,
+    errors::upload_manager_error::UploadManagerError,
+    events::{ClientEvent, Direction, EventBus},
+    logger::LogMsg,
+    peer_entities::communication_method::CommunicationMethod,
+    peer_entities::peer_connection::fmt_chunk,
+    rate_estimator::Traffic,
 };
-use chrono::{offset::Utc, DateTime};
-use glib::Sender as UISender;
-use std::{
-    io::{Read, Seek},
-    sync::{
-        mpsc::{Receiver, Sender},
-        Arc, Mutex,
-    },
-    time::SystemTime,
+use std::sync::{
+    mpsc::{Receiver, Sender},
+    Arc, Mutex,
 };
 
 /// The UploadManager is responsible for managing the upload process of a piece.
 pub struct UploadManager {
     logger_sender: Sender<LogMsg>,
-    pub download_path: String,
+    pub disk_cache: Arc<DiskCache>,
     pub bitfield: Arc<Vec<Mutex<PieceStatus>>>,
     pub receiver: Arc<Mutex<Receiver<Option<PieceRequest>>>>,
     listener_control_sender: Arc<Mutex<Sender<String>>>,
+    choker: Arc<Choker>,
 }
 
 /// This struct contains the required data to upload a piece.
@@ -37,17 +37,19 @@ impl UploadManager {
     /// Creates a new UploaderManager instance.
     pub fn new(
         logger_sender: Sender<LogMsg>,
-        download_path: String,
+        disk_cache: Arc<DiskCache>,
         bitfield: Arc<Vec<Mutex<PieceStatus>>>,
         receiver: Arc<Mutex<Receiver<Option<PieceRequest>>>>,
         listener_control_sender: Arc<Mutex<Sender<String>>>,
+        choker: Arc<Choker>,
     ) -> Self {
         Self {
             logger_sender,
-            download_path,
+            disk_cache,
             bitfield,
             receiver,
             listener_control_sender,
+            choker,
         }
     }
 
@@ -55,7 +57,7 @@ impl UploadManager {
     #[allow(clippy::type_complexity)]
     pub fn start_uploader(
         &self,
-        sender_client: Arc<Mutex<UISender<Vec<(usize, UiParams, String)>>>>,
+        events: Arc<EventBus>,
         torrent_name: String,
     ) -> Result<(), UploadManagerError> {
         self.logger_sender.send(LogMsg::Info(
@@ -73,40 +75,57 @@ impl UploadManager {
                 if let PieceStatus::NotDownloaded = piece_lock.lock()?.to_owned() {
                     continue;
                 }
+                // choked peers are not uploaded to, even if they keep asking
+                if self.choker.is_choked(&piece_request.peer_id) {
+                    continue;
+                }
                 self.logger_sender.send(LogMsg::Info(format!(
                     "Sending piece: {}, offset:{}, chunk len: {}",
                     piece_index, offset, length
                 )))?;
 
                 let stream = piece_request.stream;
-                let mut piece_data = vec![0; length as usize];
-
-                let mut piece_file = std::fs::File::open(format!(
-                    "{}/piece_{}.txt",
-                    self.download_path, piece_index
-                ))?;
-                piece_file.seek(std::io::SeekFrom::Start(offset as u64))?;
-                let _ = piece_file.read(&mut piece_data)?;
+                let piece_data = self.disk_cache.read_block(piece_index, offset, length)?;
 
-                let system_time = SystemTime::now();
-                let datetime: DateTime<Utc> = system_time.into();
-                let timestamp = datetime.timestamp();
+                let block_length = piece_data.len();
                 let piece_data = &fmt_chunk(piece_index, offset, &piece_data);
-                stream.lock()?.write_all(piece_data)?;
+                // the stream takes the bytes from the limiter when they are written, this only waits for the
+                // blocks sent before. The event loop reads the messages of the peer with the stream locked, so it is
+                // not held while waiting
+                let limiter = stream.lock()?.rate_limiter();
+                if let Some(limiter) = limiter {
+                    limiter.wait_ready(Direction::Upload);
+                }
+                let mut stream = stream.lock()?;
+                if let Err(e) = stream.write_all(piece_data) {
+                    // the peer left after asking
+                    self.logger_sender.send(LogMsg::Info(format!(
+                        "Could not send piece: {}, offset:{}, error: {}",
+                        piece_index, offset, e
+                    )))?;
+                    continue;
+                }
 
-                let system_time2 = SystemTime::now();
-                let datetime2: DateTime<Utc> = system_time2.into();
-                let timestamp2 = datetime2.timestamp();
-                let upload_speed = CHUNK_SIZE as i64 / (timestamp2 - timestamp);
+                if let Some(meter) = stream.rate_meter() {
+                    meter.record(Direction::Upload, Traffic::Payload, block_length);
+                    events.publish(
+                        &torrent_name,
+                        ClientEvent::RateSample {
+                            peer_id: piece_request.peer_id,
+                            direction: Direction::Upload,
+                            bytes_per_second: meter.rates().upload_payload,
+                        },
+                    );
+                }
 
-                sender_client.lock()?.send(vec![(
-                    UPDATE_UPSPEED,
-                    UiParams::Vector(vec![
-                        piece_request.peer_id,
-                        format!("{} bytes / sec", upload_speed),
-                    ]),
-                    torrent_name.clone(),
-                )])?;
+                let cache_stats = self.disk_cache.stats()?;
+                events.publish(
+                    &torrent_name,
+                    ClientEvent::CacheStats {
+                        hits: cache_stats.hits,
+                        misses: cache_stats.misses,
+                    },
+                );
             } else {
                 self.listener_control_sender
                     .lock()?
@@ -41,3 +41,13 @@ pub const ID_LENGTH: usize = 20;
 pub const U8_BYTE_SIZE: u32 = 8;
 pub const MAX_PIECES_TO_DOWNLOAD: usize = 10;
 pub const KEEP_ALIVE_ID: u8 = 23;
+pub const DISK_CACHE_SIZE: usize = 32 * 1024 * 1024;
+pub const MAX_TORRENT_SIZE: usize = 64 * 1024 * 1024;
+pub const LOOKAHEAD_WINDOW: usize = 20;
+pub const HANDSHAKE_LEN: usize = 68;
+pub const MAX_CONNECTIONS: usize = 200;
+pub const MAX_HALF_OPEN: usize = 8;
+pub const MAX_CONNECT_ATTEMPTS: usize = 10;
+pub const PORT_RETRIES: usize = 10;
+pub const TORRENTS_PATH: &str = "src/torrent_files";
+pub const DOWNLOADED_PIECES_PATH: &str = "src/downloaded_pieces";
@@ -0,0 +1,148 @@
+use crate::{
+    disk_cache::DiskCache, errors::data_check_error::DataCheckError,
+    parsing::torrent_parser::Metainfo,
+};
+use sha1::{Digest, Sha1};
+use std::{
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+/// Pieces are written to disk in batches of half of this size.
+const IMPORT_CACHE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Returns whether each piece of the torrent is right in the data at data_path, which is the file of a
+/// single-file torrent or the directory of a multi-file one. Pieces of missing or short files are wrong.
+pub fn check_data(metainfo: &Metainfo, data_path: &str) -> Vec<bool> {
+    let files = data_files(metainfo, data_path);
+    (0..metainfo.info.pieces.len())
+        .map(|index| match read_piece(metainfo, &files, index) {
+            Some(piece) => Sha1::digest(&piece)[..] == metainfo.info.pieces[index][..],
+            None => false,
+        })
+        .collect()
+}
+
+/// Copies the right pieces of the data at data_path to pieces_path, where the client uploads them from.
+/// They are written through a DiskCache, so adjacent pieces are written together. Returns how many pieces
+/// were copied.
+pub fn import_pieces(
+    metainfo: &Metainfo,
+    data_path: &str,
+    pieces_path: &str,
+) -> Result<usize, DataCheckError> {
+    if !Path::new(data_path).exists() {
+        return Err(DataCheckError::new(format!(
+            "DataCheckError: {} does not exist",
+            data_path
+        )));
+    }
+    fs::create_dir_all(pieces_path)?;
+    let files = data_files(metainfo, data_path);
+    let cache = DiskCache::new(
+        pieces_path.to_string(),
+        metainfo.info.piece_length,
+        IMPORT_CACHE_SIZE,
+    );
+    let mut imported = 0;
+    for (index, hash) in metainfo.info.pieces.iter().enumerate() {
+        if let Some(piece) = read_piece(metainfo, &files, index) {
+            if Sha1::digest(&piece)[..] == hash[..] {
+                cache.write_piece(index as u32, piece)?;
+                imported += 1;
+            }
+        }
+    }
+    cache.flush()?;
+    Ok(imported)
+}
+
+/// Returns the path on disk and the length of every file of the torrent, in the order of the pieces.
+fn data_files(metainfo: &Metainfo, data_path: &str) -> Vec<(PathBuf, u64)> {
+    if metainfo.info.length.is_some() {
+        return vec![(PathBuf::from(data_path), metainfo.info.total_length())];
+    }
+    metainfo
+        .info
+        .torrent_files()
+        .into_iter()
+        .map(|file| (Path::new(data_path).join(file.path), file.length))
+        .collect()
+}
+
+/// Reads the piece from the files, None if a file is missing or too short.
+fn read_piece(metainfo: &Metainfo, files: &[(PathBuf, u64)], index: usize) -> Option<Vec<u8>> {
+    let piece_length = metainfo.info.piece_length;
+    let start = index as u64 * piece_length;
+    let end = (start + piece_length).min(metainfo.info.total_length());
+    let mut piece = Vec::with_capacity((end - start) as usize);
+    let mut file_start = 0;
+    for (path, length) in files {
+        let file_end = file_start + length;
+        if file_start < end && file_end > start {
+            let begin = start.max(file_start) - file_start;
+            let finish = end.min(file_end) - file_start;
+            let mut file = File::open(path).ok()?;
+            file.seek(SeekFrom::Start(begin)).ok()?;
+            let mut buffer = vec![0; (finish - begin) as usize];
+            file.read_exact(&mut buffer).ok()?;
+            piece.extend(buffer);
+        }
+        file_start = file_end;
+    }
+    Some(piece)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        disk_cache::stored_pieces, parsing::torrent_builder::TorrentBuilder,
+        test_files::test_helper::test_dir,
+    };
+
+    #[test]
+    fn test_check_finds_the_changed_piece() {
+        let dir = test_dir("data_check", "changed");
+        let content = dir.join("content");
+        fs::create_dir_all(&content).unwrap();
+        fs::write(content.join("a.txt"), vec![1u8; 20000]).unwrap();
+        fs::write(content.join("b.txt"), vec![2u8; 30000]).unwrap();
+        let bytes = TorrentBuilder::new(content.to_str().unwrap(), "http://tracker/announce")
+            .piece_length(16 * 1024)
+            .build()
+            .unwrap();
+        let metainfo = Metainfo::from_bytes(&bytes).unwrap();
+        let data_path = content.to_str().unwrap();
+        assert_eq!(check_data(&metainfo, data_path), vec![true; 4]);
+
+        // the last bytes of b.txt are in the last piece
+        fs::write(content.join("b.txt"), vec![2u8; 29999]).unwrap();
+        assert_eq!(
+            check_data(&metainfo, data_path),
+            vec![true, true, true, false]
+        );
+        let pieces_path = dir.join("pieces");
+        let pieces_path = pieces_path.to_str().unwrap();
+        assert_eq!(import_pieces(&metainfo, data_path, pieces_path).unwrap(), 3);
+        let stored = stored_pieces(pieces_path).unwrap();
+        assert!(stored.contains(&2));
+        assert!(!stored.contains(&3));
+    }
+
+    #[test]
+    fn test_missing_data() {
+        let dir = test_dir("data_check", "missing");
+        let file = dir.join("file.iso");
+        fs::write(&file, vec![3u8; 1000]).unwrap();
+        let bytes = TorrentBuilder::new(file.to_str().unwrap(), "http://tracker/announce")
+            .build()
+            .unwrap();
+        let metainfo = Metainfo::from_bytes(&bytes).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(check_data(&metainfo, file.to_str().unwrap()), vec![false]);
+        assert!(import_pieces(&metainfo, file.to_str().unwrap(), "unused").is_err());
+    }
+}
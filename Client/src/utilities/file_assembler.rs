@@ -1,12 +1,17 @@
 use std::{
-    fs::{File, OpenOptions},
-    io::{Read, Write},
+    fs::{create_dir_all, File, OpenOptions},
+    io::Write,
+    path::{Component, Path, PathBuf},
 };
 
-use crate::errors::download_manager_error::DownloadManagerError;
+use crate::{
+    disk_cache::read_piece,
+    errors::download_manager_error::DownloadManagerError,
+    file_selection::{FilePriority, FileSelection},
+};
 const BUFFERED_PIECE_QUANTITY: usize = 20;
 
-/// Creates a file at given path, and appends quantity files which paths are in the next format ["path/piece_{index}.txt"]
+/// Creates a file at given path, and appends the first quantity pieces stored by the DiskCache at src_dir
 pub fn assemble(
     src_dir: String,
     dst_path: String,
@@ -26,10 +31,7 @@ pub fn assemble(
     let mut buffer: Vec<u8> = Vec::with_capacity(buffer_len * piece_lenght);
 
     for i in 0..quantity {
-        let piece_path = format!("{}/piece_{}.txt", src_dir, i);
-        let mut piece_file = File::open(piece_path)?;
-        let mut piece_content = Vec::new();
-        piece_file.read_to_end(&mut piece_content)?;
+        let piece_content = read_piece(&src_dir, piece_lenght as u64, i as u32)?;
 
         buffer.extend(&piece_content);
 
@@ -47,10 +49,73 @@ pub fn assemble(
     Ok(())
 }
 
+/// Writes every file of a multi-file torrent that is not skipped inside dst_dir, copying its bytes from the pieces at src_dir.
+/// Skipped files are never created, their bytes in the boundary pieces are left in src_dir.
+pub fn assemble_files(
+    src_dir: String,
+    dst_dir: String,
+    selection: &FileSelection,
+    quantity: usize,
+    piece_lenght: usize,
+) -> Result<(), DownloadManagerError> {
+    let priorities = selection.priorities()?;
+    let torrent_end = (quantity * piece_lenght) as u64;
+
+    for (i, torrent_file) in selection.files().iter().enumerate() {
+        if priorities[i] == FilePriority::Skip {
+            continue;
+        }
+        let dst_path = destination(&dst_dir, &torrent_file.path)?;
+        if let Some(parent) = dst_path.parent() {
+            create_dir_all(parent)?;
+        }
+        let mut file = File::create(&dst_path)?;
+
+        let mut start = selection.file_offset(i);
+        let end = (start + torrent_file.length).min(torrent_end);
+        while start < end {
+            let piece_index = (start / piece_lenght as u64) as usize;
+            let piece_content = read_piece(&src_dir, piece_lenght as u64, piece_index as u32)?;
+
+            let piece_start = piece_index as u64 * piece_lenght as u64;
+            let begin = (start - piece_start) as usize;
+            let finish = ((end - piece_start) as usize).min(piece_content.len());
+            if begin >= finish {
+                return Err(DownloadManagerError::new(format!(
+                    "piece {} is shorter than expected",
+                    piece_index
+                )));
+            }
+            file.write_all(&piece_content[begin..finish])?;
+            start = piece_start + finish as u64;
+        }
+        file.flush()?;
+    }
+    Ok(())
+}
+
+/// Returns the path of a file of the torrent inside dst_dir, failing if any part of it is not a plain name.
+fn destination(dst_dir: &str, path: &str) -> Result<PathBuf, DownloadManagerError> {
+    let mut dst_path = PathBuf::from(dst_dir);
+    for component in path.split('/') {
+        match Path::new(component).components().collect::<Vec<_>>()[..] {
+            [Component::Normal(_)] => dst_path.push(component),
+            _ => {
+                return Err(DownloadManagerError::new(format!(
+                    "unsafe path {} in the torrent",
+                    path
+                )))
+            }
+        }
+    }
+    Ok(dst_path)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::{fs::remove_file, path::Path};
+    use crate::disk_cache::store_pieces;
+    use std::{fs::remove_file, io::Read, path::Path};
 
     #[test]
     fn test_assemble_40_pieces() {
@@ -94,4 +159,72 @@ mod tests {
         file.read_to_end(&mut content).unwrap();
         assert_eq!(content.len(), quantity * piece_lenght);
     }
+
+    #[test]
+    fn test_assemble_files_skips_files() {
+        use crate::parsing::torrent_parser::TorrentFile;
+
+        let tmp_dir = std::env::temp_dir().join("crabrave_assemble_files");
+        let _r = std::fs::remove_dir_all(&tmp_dir);
+        let src_dir = tmp_dir.join("pieces");
+        let dst_dir = tmp_dir.join("download");
+        create_dir_all(&src_dir).unwrap();
+        // 3 pieces of 4 bytes holding "aaaaa", "bbb" and "cccc"
+        store_pieces(
+            src_dir.to_str().unwrap(),
+            4,
+            0,
+            &[b"aaaa", b"abbb", b"cccc"],
+        )
+        .unwrap();
+
+        let files = vec![
+            TorrentFile {
+                path: "a.txt".to_string(),
+                length: 5,
+            },
+            TorrentFile {
+                path: "dir/b.txt".to_string(),
+                length: 3,
+            },
+            TorrentFile {
+                path: "c.txt".to_string(),
+                length: 4,
+            },
+        ];
+        let resume_path = tmp_dir.join("test.resume");
+        let selection =
+            FileSelection::new(files, 4, true, resume_path.to_str().unwrap().to_string()).unwrap();
+        selection.set_priority(0, FilePriority::Skip).unwrap();
+
+        assemble_files(
+            src_dir.to_str().unwrap().to_string(),
+            dst_dir.to_str().unwrap().to_string(),
+            &selection,
+            3,
+            4,
+        )
+        .unwrap();
+
+        assert!(!dst_dir.join("a.txt").exists());
+        assert_eq!(std::fs::read(dst_dir.join("dir/b.txt")).unwrap(), b"bbb");
+        assert_eq!(std::fs::read(dst_dir.join("c.txt")).unwrap(), b"cccc");
+    }
+
+    #[test]
+    fn test_destination_stays_inside_the_download_directory() {
+        assert_eq!(
+            destination("download", "dir/b.txt").unwrap(),
+            Path::new("download").join("dir").join("b.txt")
+        );
+        for path in [
+            "../b.txt",
+            "dir/../../b.txt",
+            "/etc/passwd",
+            "dir//b.txt",
+            "./b.txt",
+        ] {
+            assert!(destination("download", path).is_err(), "{}", path);
+        }
+    }
 }
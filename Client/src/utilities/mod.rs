@@ -1,3 +1,4 @@
 pub mod constants;
+pub mod data_check;
 pub mod file_assembler;
 pub mod utils;
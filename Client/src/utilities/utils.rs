@@ -1,17 +1,7 @@
-use crate::utilities::constants::ID_LENGTH;
+use crate::utilities::constants::{DOWNLOADED_PIECES_PATH, ID_LENGTH};
 use chrono::{offset::Utc, DateTime};
 use std::{cmp::Ordering, process, time::SystemTime};
 
-/// This enum represents the different types of messages that can be sent to the UI.
-#[derive(Debug, Clone)]
-pub enum UiParams {
-    Usize(usize),
-    String(String),
-    U64(u64),
-    Vector(Vec<String>),
-    Integer(i64),
-}
-
 /// Converts an u32 to a vector of u8.
 pub fn u32_to_vecu8(number: &u32) -> [u8; 4] {
     let x1: u8 = ((number >> 24) & 0xff) as u8;
@@ -89,6 +79,28 @@ pub fn to_urlencoded(bytes: &[u8]) -> String {
         .collect()
 }
 
+/// Converts a vector of u8 to lowercase hex, the way info hashes are shown.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Converts hex, in either case, to a vector of u8. Returns None if it is not hex.
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Returns the directory where the pieces of the torrent at torrent_path are stored while it is downloaded.
+pub fn pieces_path(torrent_path: &str) -> String {
+    let file_name = torrent_path.rsplit('/').next().unwrap_or(torrent_path);
+    format!("{}/{}", DOWNLOADED_PIECES_PATH, file_name)
+}
+
 /// Converts an u64 to a string that represents the size.
 pub fn to_gb(bytes: u64) -> String {
     let gb = bytes / 1024 / 1024 / 1024;
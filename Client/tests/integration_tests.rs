@@ -2,16 +2,28 @@
 mod peer_mock;
 mod tests {
     use crate::peer_mock::*;
+    use crabrave::choker::Choker;
+    use crabrave::connection_manager::ConnectionManager;
+    use crabrave::disk_cache::{read_piece, DiskCache};
     use crabrave::download_manager::DownloadManager;
+    use crabrave::file_selection::FileSelection;
     use crabrave::listener::Listener;
     use crabrave::logger::LogMsg;
     use crabrave::logger::Logger;
+    use crabrave::parsing::torrent_parser::TorrentFile;
     use crabrave::peer_entities::communication_method::CommunicationMethod;
+    use crabrave::peer_entities::event_loop::EventLoop;
+    use crabrave::piece_picker::{DownloadMode, PiecePicker};
+    use crabrave::rate_estimator::TransferMeter;
+    use crabrave::rate_limiter::RateLimiter;
+    use crabrave::session::{ConnectionLimit, IncomingConnection, TorrentState};
     use crabrave::upload_manager::PieceRequest;
     use crabrave::upload_manager::UploadManager;
     use crabrave::{
-        download_manager::DownloaderInfo, peer_entities::peer::Peer,
-        peer_entities::peer_connection::PeerConnection, utilities::utils::UiParams,
+        download_manager::DownloaderInfo,
+        events::{ClientEvent, EventBus, TorrentEvent},
+        peer_entities::peer::Peer,
+        peer_entities::peer_connection::PeerConnection,
     };
     use sha1::{Digest, Sha1};
     use std::fs::remove_file;
@@ -30,11 +42,11 @@ mod tests {
         let piece_length = 262144;
         let torrent_name = "archivotorrent".to_string();
         let _r = remove_file(Path::new(&format!("{}/{}", download_path, torrent_name))).unwrap(); // delete file if exists
-        let _r = remove_file(Path::new(&format!(
-            "{}/{}",
-            download_pieces_path, "piece_0.txt"
-        )))
-        .unwrap(); // delete piece_0.txt if exists
+        for stored in ["pieces.dat", "pieces.stored"] {
+            let _r =
+                remove_file(Path::new(&format!("{}/{}", download_pieces_path, stored))).unwrap();
+            // delete the stored pieces if they exist
+        }
         let info_hash: Vec<u8> = vec![5; 20];
 
         let mut piece_file =
@@ -67,12 +79,30 @@ mod tests {
         );
 
         let peers_vec = vec![Arc::new(peer_connection1)];
+        let disk_cache = Arc::new(DiskCache::new(
+            download_pieces_path.clone(),
+            piece_length as u64,
+            1024 * 1024,
+        ));
+        let file_selection = Arc::new(
+            FileSelection::new(
+                vec![TorrentFile {
+                    path: torrent_name.clone(),
+                    length: piece_length as u64,
+                }],
+                piece_length as u64,
+                false,
+                format!("{}.resume", download_pieces_path),
+            )
+            .unwrap(),
+        );
 
-        let (sender_client, _receiver): (
-            glib::Sender<Vec<(usize, UiParams, String)>>,
-            glib::Receiver<Vec<(usize, UiParams, String)>>,
-        ) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        let (events_sender, events_receiver): (Sender<TorrentEvent>, Receiver<TorrentEvent>) =
+            channel();
+        let events = EventBus::new();
+        events.subscribe(Box::new(events_sender));
 
+        let state = Arc::new(RwLock::new(TorrentState::Running));
         let downloader_info = DownloaderInfo {
             piece_length: piece_length as u64,
             download_path: download_path.clone(),
@@ -84,32 +114,49 @@ mod tests {
             upload_sender: Arc::new(Mutex::new(sender_upload.clone())),
             torrent_name: "archivotorrent.txt".to_string(),
             file_length: piece_length as u64,
-            ui_sender: Arc::new(Mutex::new(sender_client.clone())),
+            events: events.clone(),
             download_pieces_path: download_pieces_path.clone(),
+            disk_cache: disk_cache.clone(),
+            file_selection,
+            piece_picker: Arc::new(RwLock::new(PiecePicker::new(DownloadMode::RarestFirst))),
+            state: state.clone(),
+            connections: ConnectionLimit::new(10),
+            connection_manager: ConnectionManager::new(10, ConnectionLimit::new(10)),
         };
 
         // Execute
         let download_manager = DownloadManager::new(downloader_info).unwrap();
         let bitfield = download_manager.bitfield.clone();
 
+        let (_incoming_sender, incoming_receiver): (
+            Sender<IncomingConnection>,
+            Receiver<IncomingConnection>,
+        ) = channel();
+        let choker = Choker::new(bitfield.clone());
         let listener = Listener::new(
-            format!("127.0.0.1:{}", 1476).as_str(),
+            Arc::new(Mutex::new(incoming_receiver)),
+            state.clone(),
             bitfield.clone(),
             Arc::new(Mutex::new(listener_control_rx)),
             Arc::new(Mutex::new(sender_logger.clone())),
             Arc::new(Mutex::new(sender_upload.clone())),
             client_id.clone(),
             info_hash.clone(),
-            Arc::new(Mutex::new(sender_client.clone())),
+            events.clone(),
             "test.torrent".to_string(),
+            RateLimiter::new(0, 0),
+            TransferMeter::new(),
+            choker.clone(),
+            EventLoop::new().unwrap(),
         )
         .unwrap();
         let upload_manager = UploadManager::new(
             sender_logger.clone(),
-            download_path.clone(),
+            disk_cache,
             bitfield,
             Arc::new(Mutex::new(receiver_upload)),
             Arc::new(Mutex::new(listener_control_tx.clone())),
+            choker,
         );
         let mut logger = Logger::new(
             "tests/test_files/logs_test.txt".to_string(),
@@ -126,13 +173,26 @@ mod tests {
             let _r = listener.listen();
         });
 
-        let sender_client_cp = Arc::new(Mutex::new(sender_client.clone()));
+        let events_cp = events.clone();
         let torrent_name_cp = "archivotorrent.txt".to_string();
 
         let upload_handle = spawn(move || {
-            let _r = upload_manager.start_uploader(sender_client_cp, torrent_name_cp);
+            let _r = upload_manager.start_uploader(events_cp, torrent_name_cp);
         });
 
+        // once the piece is downloaded the torrent seeds until it is removed
+        let mut received = Vec::new();
+        for torrent_event in events_receiver.iter() {
+            let seeding = matches!(
+                torrent_event.event,
+                ClientEvent::StateChanged(TorrentState::Seeding)
+            );
+            received.push(torrent_event);
+            if seeding {
+                break;
+            }
+        }
+        *state.write().unwrap() = TorrentState::Stopped;
         let _rl = listener_handle.join();
         let _rd = download_handle.join();
         let _ru = upload_handle.join();
@@ -144,14 +204,13 @@ mod tests {
         let mut piece = Vec::new();
         let _r = piece_file.read_to_end(&mut piece).unwrap();
 
-        let mut downloaded_piece_file =
-            File::open("tests/test_files/download_pieces_test/piece_0.txt".to_string()).unwrap();
-        let mut downloaded_piece = Vec::new();
-        let _r = downloaded_piece_file
-            .read_to_end(&mut downloaded_piece)
-            .unwrap();
+        let downloaded_piece = read_piece(&download_pieces_path, piece_length as u64, 0).unwrap();
 
         assert_eq!(piece, downloaded_piece);
+        assert!(received.iter().any(|torrent_event| matches!(
+            torrent_event.event,
+            ClientEvent::PieceVerified { index: 0 }
+        )));
     }
 }
 
@@ -1,4 +1,5 @@
-use crate::{bdecoder::Decodification, errors::announce_error::AnnounceError, peer::Peer};
+use crate::{errors::announce_error::AnnounceError, peer::Peer};
+use bencode::Value;
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -144,27 +145,25 @@ pub fn int_to_hex(i: u32) -> String {
     format!("{:x}", i)
 }
 
-/// Converts a Decodification hashmap to a vector of peers ( only used in tests ).
-pub fn decode_peer_list(current_info_hash: Vec<u8>, peers_decoded: Decodification) -> Vec<Peer> {
+/// Converts a decoded dictionary to a vector of peers ( only used in tests ).
+pub fn decode_peer_list(current_info_hash: Vec<u8>, peers_decoded: Value) -> Vec<Peer> {
     let mut peers = Vec::new();
-    if let Decodification::Dic(mut peer_dic) = peers_decoded {
-        if let Decodification::List(peer_list) =
-            peer_dic.get_mut(&"peers".as_bytes().to_vec()).unwrap()
-        {
+    if let Value::Dict(mut peer_dic) = peers_decoded {
+        if let Value::List(peer_list) = peer_dic.get_mut("peers".as_bytes()).unwrap() {
             for peer in peer_list.iter() {
-                if let Decodification::Dic(peer_dict) = peer {
-                    let peer_ip = match peer_dict.get(&"ip".as_bytes().to_vec()) {
-                        Some(Decodification::String(ip)) => ip,
+                if let Value::Dict(peer_dict) = peer {
+                    let peer_ip = match peer_dict.get("ip".as_bytes()) {
+                        Some(Value::Bytes(ip)) => ip,
                         _ => panic!("ip not found"),
                     };
 
-                    let peer_id = match peer_dict.get(&"id".as_bytes().to_vec()) {
-                        Some(Decodification::String(id)) => id,
+                    let peer_id = match peer_dict.get("id".as_bytes()) {
+                        Some(Value::Bytes(id)) => id,
                         _ => panic!("id not found"),
                     };
 
-                    let peer_port = match peer_dict.get(&"port".as_bytes().to_vec()) {
-                        Some(Decodification::Int(port)) => *port as u16,
+                    let peer_port = match peer_dict.get("port".as_bytes()) {
+                        Some(Value::Int(port)) => *port as u16,
                         _ => panic!("port not found"),
                     };
 
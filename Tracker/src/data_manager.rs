@@ -38,6 +38,7 @@ impl DataManager {
         // Open file if exists, else create it
         let file = OpenOptions::new()
             .create(true)
+            .truncate(false)
             .write(true)
             .read(true)
             .open(path)?;
@@ -171,7 +172,7 @@ mod tests {
                 .get(&vec![1, 2, 3])
                 .unwrap()
                 .peers
-                .get(&"pepe10".to_string())
+                .get("pepe10")
                 .unwrap()
                 .id,
             "pepe10".to_string()
@@ -193,11 +194,7 @@ mod tests {
         let tracker = data_manager.init_tracker().unwrap();
         // save this value to check if it is saved correctly when changes are made
         let new_changes = tracker.lock().unwrap().new_changes;
-        if new_changes {
-            tracker.lock().unwrap().new_changes = false;
-        } else {
-            tracker.lock().unwrap().new_changes = true;
-        };
+        tracker.lock().unwrap().new_changes = !new_changes;
         // save tracker
         let _ = data_manager.save_tracker();
         // check if file has the correct data
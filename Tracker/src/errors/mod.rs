@@ -1,6 +1,5 @@
 pub mod announce_error;
 pub mod app_error;
-pub mod bdecoder_error;
 pub mod data_manager_error;
 pub mod listener_error;
 pub mod logger_error;
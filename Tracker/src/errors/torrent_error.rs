@@ -1,5 +1,6 @@
 use crate::announce_utils::URLParams;
 use crate::logger::LogMsg;
+use bencode::BEncoderError;
 use std::{
     convert::Infallible,
     fmt::Display,
@@ -36,6 +37,14 @@ impl From<Error> for TorrentError {
     }
 }
 
+impl From<BEncoderError> for TorrentError {
+    fn from(error: BEncoderError) -> TorrentError {
+        TorrentError {
+            msg: format!("TorrentError: ({})", error),
+        }
+    }
+}
+
 impl From<Option<&URLParams>> for TorrentError {
     fn from(error: Option<&URLParams>) -> TorrentError {
         TorrentError {
@@ -1,4 +1,5 @@
 use crate::{errors::torrent_error::TorrentError, logger::LogMsg};
+use bencode::BEncoderError;
 use std::{
     fmt::Display,
     io::Error,
@@ -33,6 +34,14 @@ impl From<Error> for TrackerError {
     }
 }
 
+impl From<BEncoderError> for TrackerError {
+    fn from(error: BEncoderError) -> TrackerError {
+        TrackerError {
+            msg: format!("TrackerError: ({})", error),
+        }
+    }
+}
+
 impl From<TorrentError> for TrackerError {
     fn from(error: TorrentError) -> TrackerError {
         TrackerError {
@@ -1,7 +1,5 @@
 pub mod announce_utils;
 pub mod app;
-pub mod bdecoder;
-pub mod bencoder;
 pub mod constants;
 pub mod controller;
 pub mod data_manager;
@@ -1,16 +1,24 @@
 use crate::{
     announce_utils::{int_to_hex, URLParams},
-    bdecoder::Decodification,
     errors::torrent_error::TorrentError,
     logger::LogMsg,
     peer::Peer,
 };
+use bencode::Value;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     sync::{mpsc::Sender, Arc, Mutex},
 };
 
+/// A peer as it is listed in a non compact announce response.
+#[derive(Serialize)]
+struct PeerEntry<'a> {
+    id: &'a str,
+    ip: &'a str,
+    port: i64,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Torrent {
     pub info_hash: Vec<u8>,
@@ -34,35 +42,26 @@ impl Torrent {
     }
 
     /// Returns a Vec of peers with ip, id & port.
-    pub fn get_peers_deco(&self, limit: u32) -> Result<Decodification, TorrentError> {
+    pub fn get_peers_deco(&self, limit: u32) -> Result<Value, TorrentError> {
         //Vec<HashMap<String, String>>
-        let mut peers: Vec<Decodification> = Vec::new();
+        let mut peers: Vec<Value> = Vec::new();
         for (count, (id, peer)) in self.peers.iter().enumerate() {
             if count == limit as usize {
                 break;
             }
-            let mut peer_info: HashMap<Vec<u8>, Decodification> = HashMap::new();
-            peer_info.insert(
-                "ip".as_bytes().to_vec(),
-                Decodification::String(peer.ip.clone().as_bytes().to_vec()),
-            );
-            peer_info.insert(
-                "id".as_bytes().to_vec(),
-                Decodification::String(id.clone().as_bytes().to_vec()),
-            );
-            peer_info.insert(
-                "port".as_bytes().to_vec(),
-                Decodification::Int(peer.port.parse::<i64>()?),
-            );
-            peers.push(Decodification::Dic(peer_info));
+            peers.push(bencode::to_value(&PeerEntry {
+                id,
+                ip: &peer.ip,
+                port: peer.port.parse::<i64>()?,
+            })?);
         }
-        Ok(Decodification::List(peers))
+        Ok(Value::List(peers))
     }
 
     /// Returns a compacted Vec of peers with ip & port.
-    pub fn get_peers_deco_compacted(&self, limit: u32) -> Result<Decodification, TorrentError> {
+    pub fn get_peers_deco_compacted(&self, limit: u32) -> Result<Value, TorrentError> {
         //Vec<HashMap<String, String>>
-        let mut peers: Vec<Decodification> = Vec::new();
+        let mut peers: Vec<Value> = Vec::new();
         for (count, peer) in self.peers.values().enumerate() {
             if count == limit as usize {
                 break;
@@ -76,11 +75,9 @@ impl Torrent {
                 peer_info_compacted.push_str(&*int_to_hex(octect.parse::<u32>()?));
             }
 
-            peers.push(Decodification::String(
-                peer_info_compacted.as_bytes().to_vec(),
-            ));
+            peers.push(Value::Bytes(peer_info_compacted.as_bytes().to_vec()));
         }
-        Ok(Decodification::List(peers))
+        Ok(Value::List(peers))
     }
 
     /// Given a HashMap with the params of the announce, updates the Peer values.
@@ -201,6 +198,7 @@ impl Torrent {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bencode::Dictionary;
     use std::sync::mpsc::{channel, Receiver};
 
     #[test]
@@ -336,29 +334,29 @@ mod tests {
         torrent.add_peer(peer1);
         torrent.add_peer(peer2);
 
-        let first_possible_result = Decodification::List(vec![
-            Decodification::Dic(HashMap::from([
-                (vec![105, 112], Decodification::String(vec![105, 112, 50])),
-                (vec![112, 111, 114, 116], Decodification::Int(4042)),
-                (vec![105, 100], Decodification::String(vec![105, 100, 50])),
+        let first_possible_result = Value::List(vec![
+            Value::Dict(Dictionary::from([
+                (vec![105, 112], Value::Bytes(vec![105, 112, 50])),
+                (vec![112, 111, 114, 116], Value::Int(4042)),
+                (vec![105, 100], Value::Bytes(vec![105, 100, 50])),
             ])),
-            Decodification::Dic(HashMap::from([
-                (vec![112, 111, 114, 116], Decodification::Int(4041)),
-                (vec![105, 112], Decodification::String(vec![105, 112, 49])),
-                (vec![105, 100], Decodification::String(vec![105, 100, 49])),
+            Value::Dict(Dictionary::from([
+                (vec![112, 111, 114, 116], Value::Int(4041)),
+                (vec![105, 112], Value::Bytes(vec![105, 112, 49])),
+                (vec![105, 100], Value::Bytes(vec![105, 100, 49])),
             ])),
         ]);
 
-        let second_possible_result = Decodification::List(vec![
-            Decodification::Dic(HashMap::from([
-                (vec![112, 111, 114, 116], Decodification::Int(4041)),
-                (vec![105, 112], Decodification::String(vec![105, 112, 49])),
-                (vec![105, 100], Decodification::String(vec![105, 100, 49])),
+        let second_possible_result = Value::List(vec![
+            Value::Dict(Dictionary::from([
+                (vec![112, 111, 114, 116], Value::Int(4041)),
+                (vec![105, 112], Value::Bytes(vec![105, 112, 49])),
+                (vec![105, 100], Value::Bytes(vec![105, 100, 49])),
             ])),
-            Decodification::Dic(HashMap::from([
-                (vec![105, 112], Decodification::String(vec![105, 112, 50])),
-                (vec![112, 111, 114, 116], Decodification::Int(4042)),
-                (vec![105, 100], Decodification::String(vec![105, 100, 50])),
+            Value::Dict(Dictionary::from([
+                (vec![105, 112], Value::Bytes(vec![105, 112, 50])),
+                (vec![112, 111, 114, 116], Value::Int(4042)),
+                (vec![105, 100], Value::Bytes(vec![105, 100, 50])),
             ])),
         ]);
 
@@ -400,14 +398,14 @@ mod tests {
         torrent.add_peer(peer1);
         torrent.add_peer(peer2);
 
-        let first_possible_result = Decodification::List(vec![
-            Decodification::String(vec![99, 48, 48, 49, 53, 52]),
-            Decodification::String(vec![98, 102, 50, 49, 54, 50, 97]),
+        let first_possible_result = Value::List(vec![
+            Value::Bytes(vec![99, 48, 48, 49, 53, 52]),
+            Value::Bytes(vec![98, 102, 50, 49, 54, 50, 97]),
         ]);
 
-        let second_possible_result = Decodification::List(vec![
-            Decodification::String(vec![98, 102, 50, 49, 54, 50, 97]),
-            Decodification::String(vec![99, 48, 48, 49, 53, 52]),
+        let second_possible_result = Value::List(vec![
+            Value::Bytes(vec![98, 102, 50, 49, 54, 50, 97]),
+            Value::Bytes(vec![99, 48, 48, 49, 53, 52]),
         ]);
 
         let test_result = torrent.get_peers_deco_compacted(50).unwrap();
@@ -1,11 +1,8 @@
 use crate::{
-    announce_utils::URLParams,
-    bdecoder::Decodification,
-    bencoder::{bencode, BencoderTypes},
-    errors::tracker_error::TrackerError,
-    logger::LogMsg,
+    announce_utils::URLParams, errors::tracker_error::TrackerError, logger::LogMsg,
     torrent::Torrent,
 };
+use bencode::Value;
 use chrono::{offset::Utc, DateTime};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
@@ -26,6 +23,17 @@ pub struct Tracker {
     pub new_changes: bool, // To know if we have to store the changes in the file
 }
 
+/// The bencoded answer to an announce.
+#[derive(Serialize)]
+struct AnnounceResponse {
+    interval: i64,
+    #[serde(rename = "tracker id")]
+    tracker_id: i64,
+    complete: i64,
+    incomplete: i64,
+    peers: Value,
+}
+
 pub trait TrackerInterface {
     fn add_torrent(&mut self, info_hash: Vec<u8>, timestamp: i64);
     fn delete_torrent(&mut self, torrent: Torrent);
@@ -47,7 +55,7 @@ pub trait TrackerInterface {
         info_hash: Vec<u8>,
         compact: u16,
         numwant: u32,
-    ) -> Result<Decodification, TrackerError>;
+    ) -> Result<Value, TrackerError>;
 }
 
 impl TrackerInterface for Tracker {
@@ -153,27 +161,14 @@ impl TrackerInterface for Tracker {
 
         let total_peers_torrent = self.torrents[&info_hash].peers.len();
 
-        let mut response = HashMap::new();
-        response.insert("interval".as_bytes().to_vec(), Decodification::Int(1));
-        response.insert(
-            "tracker id".as_bytes().to_vec(),
-            Decodification::Int(222320),
-        );
-        response.insert(
-            "complete".as_bytes().to_vec(),
-            Decodification::Int(complete as i64),
-        );
-        response.insert(
-            "incomplete".as_bytes().to_vec(),
-            Decodification::Int((total_peers_torrent - complete) as i64),
-        );
-        response.insert(
-            "peers".as_bytes().to_vec(),
-            self.get_announce_peer_list(info_hash, compact, numwant)?,
-        );
-        Ok(bencode(&BencoderTypes::Decodification(
-            Decodification::Dic(response),
-        )))
+        let response = AnnounceResponse {
+            interval: 1,
+            tracker_id: 222320,
+            complete: complete as i64,
+            incomplete: (total_peers_torrent - complete) as i64,
+            peers: self.get_announce_peer_list(info_hash, compact, numwant)?,
+        };
+        Ok(bencode::to_bytes(&response)?)
     }
 
     fn get_announce_peer_list(
@@ -181,9 +176,9 @@ impl TrackerInterface for Tracker {
         info_hash: Vec<u8>,
         compact: u16,
         numwant: u32,
-    ) -> Result<Decodification, TrackerError> {
+    ) -> Result<Value, TrackerError> {
         if self.torrents.get(&info_hash).is_none() {
-            return Ok(Decodification::List(Vec::new()));
+            return Ok(Value::List(Vec::new()));
         }
         let torrent = self.torrents.get(&info_hash).unwrap();
 
@@ -197,7 +192,8 @@ impl TrackerInterface for Tracker {
 
 #[cfg(test)]
 mod tests {
-    use crate::{announce_utils::decode_peer_list, bdecoder::bdecode, peer::Peer};
+    use crate::{announce_utils::decode_peer_list, peer::Peer};
+    use bencode::bdecode;
 
     use super::*;
     use std::{
@@ -5,10 +5,10 @@ mod tests {
     use crate::data_manager_mock::*;
     use crate::listener_mock::*;
 
+    use bencode::bdecode;
     use tracker::announce_utils::decode_peer_list;
     use tracker::announce_utils::response_splitter;
     use tracker::app::initialize_app;
-    use tracker::bdecoder::bdecode;
     use tracker::tracker::Tracker;
     use tracker::{
         constants::{LISTENER_IP, LISTENER_PORT},